@@ -0,0 +1,73 @@
+use criterion::{
+    criterion_group, criterion_main, BenchmarkId, Criterion, Throughput,
+};
+
+use nix_query_tree_viewer::nix_query_tree::parsing::nix_query_tree_parser;
+use nix_query_tree_viewer::nix_query_tree::stream_parsing::read_nix_query_tree;
+
+/// The store paths that package `i` depends on.  Every package is reachable from package 0,
+/// the tree stays shallow, and some packages are depended on more than once, so there are
+/// plenty of repeated `[...]` entries, like in a real closure.
+fn deps(i: usize, packages: usize) -> Vec<usize> {
+    let mut deps: Vec<usize> =
+        vec![2 * i + 1, 2 * i + 2, (i * 7 + 13) % packages];
+    deps.retain(|&dep| dep > i && dep < packages);
+    deps.sort_unstable();
+    deps.dedup();
+    deps
+}
+
+/// Generate `nix-store --query --tree` output for a closure of `packages` store paths.
+fn generate_tree(packages: usize) -> String {
+    let path = |i: usize| format!("/nix/store/{:032}-package-{}", i, i);
+    let root = 0;
+    let mut seen = vec![false; packages];
+    seen[root] = true;
+
+    let mut raw = path(root);
+    raw.push('\n');
+
+    // Each stack entry is a package, and the prefix for the lines of its children.
+    let mut stack: Vec<(usize, String)> = vec![];
+    for &dep in deps(root, packages).iter().rev() {
+        stack.push((dep, String::new()));
+    }
+    while let Some((i, prefix)) = stack.pop() {
+        raw.push_str(&prefix);
+        raw.push_str("+---");
+        raw.push_str(&path(i));
+        if seen[i] {
+            raw.push_str(" [...]\n");
+        } else {
+            raw.push('\n');
+            seen[i] = true;
+            let child_prefix = format!("{}|   ", prefix);
+            for &dep in deps(i, packages).iter().rev() {
+                stack.push((dep, child_prefix.clone()));
+            }
+        }
+    }
+    raw
+}
+
+fn bench_parsers(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    for &packages in &[1_000, 10_000, 100_000] {
+        let raw = generate_tree(packages);
+        group.throughput(Throughput::Bytes(raw.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::new("nom", packages),
+            &raw,
+            |b, raw| b.iter(|| nix_query_tree_parser(raw).unwrap()),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("streaming", packages),
+            &raw,
+            |b, raw| b.iter(|| read_nix_query_tree(raw.as_bytes()).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_parsers);
+criterion_main!(benches);
@@ -0,0 +1,229 @@
+use std::path::Path;
+
+use super::nix_query_tree::drv_diff;
+use super::nix_query_tree::exec_nix_store::{self, KeepRaw, NixStoreRes};
+use super::nix_query_tree::html_report::{self, Annotations};
+use super::nix_query_tree::licenses::{LicenseDb, LicenseReport};
+use super::nix_query_tree::sbom::{Sbom, SbomFormat};
+use super::nix_query_tree::substitutes::{self, Substituter};
+use super::nix_query_tree::NixQueryDrv;
+use super::opts::Opts;
+
+/// Print a report of the differences between two derivations.
+///
+/// Like `diff`, this returns 0 if the derivations are the same, 1 if they are different, and
+/// 2 if there was an error.
+fn diff_drvs(left: &Path, right: &Path) -> i32 {
+    let left_drv = NixQueryDrv::from(left);
+    let right_drv = NixQueryDrv::from(right);
+    match drv_diff::diff_drvs(&left_drv, &right_drv) {
+        Err(err) => {
+            eprintln!("Error comparing derivations: {}", err);
+            2
+        }
+        Ok(drv_diff) => {
+            print!("{}", drv_diff.report());
+            i32::from(!drv_diff.is_empty())
+        }
+    }
+}
+
+/// Run `nix-store --query --tree` for `nix_store_path` in `store_uri`, printing any error.
+/// With `refresh`, the on-disk cache isn't used.
+fn query_tree(
+    nix_store_path: &Path,
+    store_uri: Option<&str>,
+    refresh: bool,
+) -> Option<NixStoreRes> {
+    let exec_nix_store_res = if refresh {
+        exec_nix_store::refresh(nix_store_path, store_uri, KeepRaw::No)
+    } else {
+        exec_nix_store::run(nix_store_path, store_uri, KeepRaw::No)
+    };
+    if let Some(cache_warning) = &exec_nix_store_res.cache_warning {
+        eprintln!("Warning: {}", cache_warning);
+    }
+    match exec_nix_store_res.res {
+        Err(err) => {
+            eprintln!(
+                "Error running `nix-store --query --tree {}`: {}",
+                nix_store_path.display(),
+                err
+            );
+            None
+        }
+        Ok(nix_store_res) => {
+            if let Some(skipped_warning) = nix_store_res.skipped_warning() {
+                eprintln!("Warning: {}", skipped_warning);
+            }
+            Some(nix_store_res)
+        }
+    }
+}
+
+/// Write `contents` to `output_path`, or to stdout if there is no `output_path`.
+///
+/// This returns 0 on success, and 2 if there was an error.
+fn write_output(output_path: Option<&Path>, contents: &str, what: &str) -> i32 {
+    match output_path {
+        None => {
+            print!("{}", contents);
+            0
+        }
+        Some(output_path) => match std::fs::write(output_path, contents) {
+            Err(err) => {
+                eprintln!(
+                    "Error writing {} to {}: {}",
+                    what,
+                    output_path.display(),
+                    err
+                );
+                2
+            }
+            Ok(()) => 0,
+        },
+    }
+}
+
+/// Write a software bill of materials for the closure of `nix_store_path`.
+///
+/// This returns 0 on success, and 2 if there was an error.
+fn export_sbom(opts: &Opts, format: SbomFormat) -> i32 {
+    match query_tree(
+        &opts.nix_store_path,
+        opts.store_uri.as_deref(),
+        opts.refresh,
+    ) {
+        None => 2,
+        Some(nix_store_res) => {
+            let sbom = Sbom::new(&nix_store_res.tree, &nix_store_res.map);
+            let mut rendered = sbom.render(format);
+            rendered.push('\n');
+            write_output(opts.output_path.as_deref(), &rendered, "SBOM")
+        }
+    }
+}
+
+/// Print the licenses of every package in the closure of `nix_store_path`.
+///
+/// This returns 0 if every package has a free license, 1 if any package is unfree or has no
+/// license information, and 2 if there was an error.
+fn license_report(opts: &Opts) -> i32 {
+    let license_db =
+        match opts.license_db_path.as_deref().map(LicenseDb::from_path) {
+            None => None,
+            Some(Ok(license_db)) => Some(license_db),
+            Some(Err(err)) => {
+                eprintln!("Error reading license information: {}", err);
+                return 2;
+            }
+        };
+    match query_tree(
+        &opts.nix_store_path,
+        opts.store_uri.as_deref(),
+        opts.refresh,
+    ) {
+        None => 2,
+        Some(nix_store_res) => {
+            let report = LicenseReport::from_closure(
+                &nix_store_res.map,
+                &nix_store_res.tree.0.item.0,
+                license_db.as_ref(),
+            );
+            print!("{}", report.report());
+            i32::from(report.has_flagged())
+        }
+    }
+}
+
+/// Write a self-contained HTML report of the tree for `nix_store_path`.
+///
+/// This returns 0 on success, and 2 if there was an error.
+fn export_html_report(opts: &Opts) -> i32 {
+    match query_tree(
+        &opts.nix_store_path,
+        opts.store_uri.as_deref(),
+        opts.refresh,
+    ) {
+        None => 2,
+        Some(nix_store_res) => {
+            let mut annotations = Annotations::default();
+            if opts.with_sizes {
+                annotations.add_sizes(&nix_store_res.map);
+            }
+            if opts.with_duplicates {
+                annotations.add_duplicates(&nix_store_res.map);
+            }
+            let html = html_report::render(
+                &nix_store_res.tree,
+                &nix_store_res.map,
+                &annotations,
+            );
+            write_output(opts.output_path.as_deref(), &html, "HTML report")
+        }
+    }
+}
+
+/// Print the store paths in the closure of `nix_store_path` that are missing from the binary
+/// cache given with `--substituter`.
+///
+/// This returns 0 if every store path is in the binary cache, 1 if any are missing, and 2 if
+/// there was an error.
+fn missing_report(opts: &Opts) -> i32 {
+    let substituter = match opts
+        .substituter
+        .as_deref()
+        .map(Substituter::from_uri)
+    {
+        None => {
+            eprintln!("--missing-report needs a binary cache to check against.  Give one with --substituter.");
+            return 2;
+        }
+        Some(Err(err)) => {
+            eprintln!("Error opening the binary cache: {}", err);
+            return 2;
+        }
+        Some(Ok(substituter)) => substituter,
+    };
+    match query_tree(
+        &opts.nix_store_path,
+        opts.store_uri.as_deref(),
+        opts.refresh,
+    ) {
+        None => 2,
+        Some(nix_store_res) => {
+            match substitutes::check(&substituter, &nix_store_res) {
+                Err(err) => {
+                    eprintln!("Error checking the binary cache: {}", err);
+                    2
+                }
+                Ok(report) => {
+                    print!("{}", report.report());
+                    i32::from(!report.missing.is_empty())
+                }
+            }
+        }
+    }
+}
+
+/// Run any of the modes that don't need the GTK GUI.
+///
+/// This returns the exit code for the process, or `None` if no headless mode was asked for
+/// in `opts`.
+pub fn run(opts: &Opts) -> Option<i32> {
+    if let Some(diff_path) = &opts.diff_path {
+        Some(diff_drvs(&opts.nix_store_path, diff_path))
+    } else if let Some(sbom_format) = opts.sbom_format {
+        Some(export_sbom(opts, sbom_format))
+    } else if opts.license_report {
+        Some(license_report(opts))
+    } else if opts.html_report {
+        Some(export_html_report(opts))
+    } else if opts.missing_report {
+        Some(missing_report(opts))
+    } else if opts.tui {
+        Some(super::tui::run(opts))
+    } else {
+        None
+    }
+}
@@ -1,12 +1,44 @@
 #![deny(unsafe_code)]
 #![warn(clippy::all, clippy::pedantic)]
+// The code base consistently passes format arguments positionally.
+#![allow(clippy::uninlined_format_args)]
 
 pub mod nix_query_tree;
 pub mod tree;
 
+mod cli;
 mod opts;
+mod tui;
+#[cfg(feature = "gui")]
 mod ui;
+mod view;
+
+#[cfg(feature = "gui")]
+fn run_gui(opts: opts::Opts) {
+    ui::run(opts);
+}
+
+#[cfg(not(feature = "gui"))]
+fn run_gui(_opts: opts::Opts) {
+    eprintln!("nix-query-tree-viewer was built without the `gui` feature.  Use --tui to browse the tree in the terminal, or one of the headless options like --sbom.");
+    std::process::exit(2);
+}
 
 pub fn default_main() {
-    ui::run();
+    let mut opts = opts::Opts::parse_from_args();
+    let installable = opts.installable();
+    // The GUI evaluates PATH itself once its window is open.
+    if !installable.is_store_path() && !opts.opens_gui() {
+        match installable.resolve(opts.out_path) {
+            Err(err) => {
+                eprintln!("Error evaluating {}: {}", installable, err);
+                std::process::exit(2);
+            }
+            Ok(nix_store_path) => opts.nix_store_path = nix_store_path,
+        }
+    }
+    match cli::run(&opts) {
+        Some(exit_code) => std::process::exit(exit_code),
+        None => run_gui(opts),
+    }
 }
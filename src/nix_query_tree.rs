@@ -1,7 +1,26 @@
+#[cfg(feature = "serde")]
+pub mod cache;
+pub mod derivation;
+pub mod drv_diff;
+pub mod drv_parsing;
+pub mod duplicates;
 pub mod exec_nix_store;
+pub mod gc_roots;
+pub mod html_report;
+pub mod installable;
+pub mod licenses;
+pub mod narinfo;
 pub mod parsing;
+pub mod printing;
+pub mod references;
+pub mod sbom;
+pub mod stream_parsing;
+pub mod store_contents;
+pub mod substitutes;
+pub mod vulnerabilities;
 
 use super::tree::{Path, Tree, TreePathMap};
+use std::cmp::Ordering;
 use std::path::PathBuf;
 use std::str::FromStr;
 
@@ -14,6 +33,7 @@ use std::str::FromStr;
 ///     NixQueryDrv::from("/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10");
 /// ```
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct NixQueryDrv(PathBuf);
 
 impl<T: ?Sized + AsRef<std::ffi::OsStr>> From<&T> for NixQueryDrv {
@@ -35,10 +55,46 @@ impl NixQueryDrv {
         self.0.cmp(&other.0)
     }
 
+    /// Whether or not this store path is a derivation (a `.drv` file).
+    ///
+    /// ```
+    /// use nix_query_tree_viewer::nix_query_tree::NixQueryDrv;
+    ///
+    /// let hello_drv =
+    ///     NixQueryDrv::from("/nix/store/jymg0kanmlgbcv35wxd8d660rw0fawhv-hello-2.10.drv");
+    /// let hello = NixQueryDrv::from("/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10");
+    /// assert!(hello_drv.is_drv());
+    /// assert!(!hello.is_drv());
+    /// ```
+    #[must_use]
+    pub fn is_drv(&self) -> bool {
+        self.0.extension() == Some(std::ffi::OsStr::new("drv"))
+    }
+
     pub fn cmp_drv_name(&self, other: &Self) -> std::cmp::Ordering {
         self.drv_name().cmp(&other.drv_name())
     }
 
+    /// Pull out the package name and version from a `NixQueryDrv`.  The `.drv` extension is
+    /// dropped for derivations.
+    ///
+    /// ```
+    /// use nix_query_tree_viewer::nix_query_tree::NixQueryDrv;
+    ///
+    /// let nix_query_drv =
+    ///     NixQueryDrv::from("/nix/store/jymg0kanmlgbcv35wxd8d660rw0fawhv-hello-2.10.drv");
+    /// assert_eq!(
+    ///     nix_query_drv.pname_and_version(),
+    ///     (String::from("hello"), Some(String::from("2.10")))
+    /// );
+    /// ```
+    pub fn pname_and_version(&self) -> (String, Option<String>) {
+        let drv_name = self.drv_name();
+        let name = drv_name.trim_end_matches(".drv");
+        let (pname, version) = split_name_version(name);
+        (String::from(pname), version.map(String::from))
+    }
+
     /// Pull out the hash and derivation name from a `NixQueryDrv`
     ///
     /// ```
@@ -56,6 +112,35 @@ impl NixQueryDrv {
         String::from((drv_str).trim_start_matches("/nix/store/"))
     }
 
+    /// Pull out the 32-character hash part from a `NixQueryDrv`.
+    ///
+    /// This is the part of the store path that other store paths contain when they reference
+    /// it.
+    ///
+    /// ```
+    /// use nix_query_tree_viewer::nix_query_tree::NixQueryDrv;
+    ///
+    /// let nix_query_drv =
+    ///     NixQueryDrv::from("/nix/store/az4kl5slhbkmmy4vj98z3hzxxkan7zza-gnugrep-3.3");
+    /// assert_eq!(
+    ///     nix_query_drv.hash_part(),
+    ///     Some(String::from("az4kl5slhbkmmy4vj98z3hzxxkan7zza"))
+    /// );
+    /// assert_eq!(NixQueryDrv::from("/tmp/foo").hash_part(), None);
+    /// ```
+    #[must_use]
+    pub fn hash_part(&self) -> Option<String> {
+        let hash_and_drv_name = self.hash_and_drv_name();
+        let hash = hash_and_drv_name.get(0..32)?;
+        if hash.chars().all(|c| c.is_ascii_alphanumeric())
+            && hash_and_drv_name.get(32..33) == Some("-")
+        {
+            Some(String::from(hash))
+        } else {
+            None
+        }
+    }
+
     /// Pull out a truncated hash and derivation name from a `NixQueryDrv`
     ///
     /// ```
@@ -116,6 +201,122 @@ impl NixQueryDrv {
     }
 }
 
+/// Split a derivation name into a package name and version, the same way as nix's
+/// `builtins.parseDrvName`.
+///
+/// The version starts after the first `-` that is not followed by a letter.
+///
+/// ```
+/// use nix_query_tree_viewer::nix_query_tree::split_name_version;
+///
+/// assert_eq!(split_name_version("gnugrep-3.3"), ("gnugrep", Some("3.3")));
+/// assert_eq!(split_name_version("nix-prefetch-git-1.0"), ("nix-prefetch-git", Some("1.0")));
+/// assert_eq!(split_name_version("cc-wrapper.sh"), ("cc-wrapper.sh", None));
+/// ```
+#[must_use]
+pub fn split_name_version(drv_name: &str) -> (&str, Option<&str>) {
+    let mut chars = drv_name.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if let ('-', Some((_, next))) = (c, chars.peek()) {
+            if !next.is_alphabetic() {
+                return (&drv_name[..i], Some(&drv_name[i + 1..]));
+            }
+        }
+    }
+    (drv_name, None)
+}
+
+/// Common names of derivation outputs.  These show up as a suffix on the version of non-`out`
+/// outputs, like `openssl-1.1.1d-dev`.
+const OUTPUT_NAMES: [&str; 12] = [
+    "bin", "debug", "dev", "devdoc", "doc", "info", "lib", "man", "out", "py",
+    "static", "tools",
+];
+
+/// Split the output name off the end of a version.  Versions without a known output name
+/// belong to the `out` output.
+///
+/// ```
+/// use nix_query_tree_viewer::nix_query_tree::split_output;
+///
+/// assert_eq!(split_output("1.1.1d-dev"), ("1.1.1d", "dev"));
+/// assert_eq!(split_output("1.1.1d"), ("1.1.1d", "out"));
+/// ```
+#[must_use]
+pub fn split_output(version: &str) -> (&str, &str) {
+    match version.rfind('-') {
+        Some(i) if OUTPUT_NAMES.contains(&&version[i + 1..]) => {
+            (&version[..i], &version[i + 1..])
+        }
+        _ => (version, "out"),
+    }
+}
+
+/// Split a version into components the same way as nix's `builtins.splitVersion`.
+fn version_components(version: &str) -> Vec<&str> {
+    let mut components = vec![];
+    let mut rest = version;
+    loop {
+        rest = rest.trim_start_matches(['.', '-']);
+        if rest.is_empty() {
+            return components;
+        }
+        let is_digit = rest.starts_with(|c: char| c.is_ascii_digit());
+        let end = rest
+            .find(|c: char| {
+                if is_digit {
+                    !c.is_ascii_digit()
+                } else {
+                    c.is_ascii_digit() || c == '.' || c == '-'
+                }
+            })
+            .unwrap_or(rest.len());
+        components.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+}
+
+fn component_lt(c1: &str, c2: &str) -> bool {
+    let n1 = c1.parse::<u64>().ok();
+    let n2 = c2.parse::<u64>().ok();
+    match (n1, n2) {
+        (Some(n1), Some(n2)) => n1 < n2,
+        _ if c1.is_empty() && n2.is_some() => true,
+        _ if c1 == "pre" && c2 != "pre" => true,
+        _ if c2 == "pre" => false,
+        (Some(_), None) => false,
+        (None, Some(_)) => true,
+        (None, None) => c1 < c2,
+    }
+}
+
+/// Compare two versions the same way as nix's `builtins.compareVersions`.
+///
+/// ```
+/// use nix_query_tree_viewer::nix_query_tree::compare_versions;
+/// use std::cmp::Ordering;
+///
+/// assert_eq!(compare_versions("1.1.1d", "1.1.1g"), Ordering::Less);
+/// assert_eq!(compare_versions("2.27", "2.3"), Ordering::Greater);
+/// assert_eq!(compare_versions("1.0pre1", "1.0"), Ordering::Less);
+/// assert_eq!(compare_versions("4.4-p23", "4.4.p23"), Ordering::Equal);
+/// ```
+#[must_use]
+pub fn compare_versions(v1: &str, v2: &str) -> Ordering {
+    let components1 = version_components(v1);
+    let components2 = version_components(v2);
+    for i in 0..components1.len().max(components2.len()) {
+        let c1 = components1.get(i).copied().unwrap_or("");
+        let c2 = components2.get(i).copied().unwrap_or("");
+        if component_lt(c1, c2) {
+            return Ordering::Less;
+        } else if component_lt(c2, c1) {
+            return Ordering::Greater;
+        }
+    }
+    Ordering::Equal
+}
+
 impl FromStr for NixQueryDrv {
     // This should really be never.
     type Err = ();
@@ -136,6 +337,7 @@ impl std::fmt::Display for NixQueryDrv {
 ///
 /// See `NixQueryEntry`.
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub enum Recurse {
     Yes,
     No,
@@ -155,6 +357,7 @@ pub enum Recurse {
 /// ```
 ///
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct NixQueryEntry(pub NixQueryDrv, pub Recurse);
 
 impl FromStr for NixQueryEntry {
@@ -221,6 +424,7 @@ impl NixQueryEntry {
 /// assert!(nix_query_tree.is_ok());
 /// ```
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct NixQueryTree(pub Tree<NixQueryEntry>);
 
 impl NixQueryTree {
@@ -237,10 +441,10 @@ impl NixQueryTree {
 }
 
 impl FromStr for NixQueryTree {
-    type Err = nom::Err<(String, nom::error::ErrorKind)>;
+    type Err = parsing::ParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        parsing::nix_query_tree_parser(s).map_err(|err| err.to_owned())
+        parsing::nix_query_tree_parser(s)
     }
 }
 
@@ -272,6 +476,7 @@ impl FromStr for NixQueryTree {
 /// assert_eq!(map.lookup_first(&pcre_drv), expected_path.as_ref());
 /// ```
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct NixQueryPathMap(pub TreePathMap<NixQueryDrv>);
 
 impl NixQueryPathMap {
@@ -279,3 +484,26 @@ impl NixQueryPathMap {
         self.0.lookup_first(k)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_components() {
+        assert_eq!(version_components("1.1.1d"), vec!["1", "1", "1", "d"]);
+        assert_eq!(version_components("4.4-p23"), vec!["4", "4", "p", "23"]);
+        assert_eq!(version_components("2.0pre"), vec!["2", "0", "pre"]);
+        assert_eq!(version_components(""), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn test_compare_versions() {
+        assert_eq!(compare_versions("1.0", "1.0"), Ordering::Equal);
+        assert_eq!(compare_versions("1.0", "1.0.1"), Ordering::Less);
+        assert_eq!(compare_versions("1.0a", "1.0"), Ordering::Greater);
+        assert_eq!(compare_versions("1.0pre", "1.0"), Ordering::Less);
+        assert_eq!(compare_versions("10", "9"), Ordering::Greater);
+        assert_eq!(compare_versions("1.a", "1.1"), Ordering::Less);
+    }
+}
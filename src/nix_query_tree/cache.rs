@@ -0,0 +1,534 @@
+//! An on-disk cache of `nix-store --query --tree` results.
+//!
+//! Store paths are immutable, so the references of a store path never change, and the parsed
+//! `NixStoreRes` for a store path can be reused until it is evicted.  Each result is kept as a
+//! JSON file in the cache directory.  When the files in the directory get bigger than the size
+//! limit, the oldest ones are removed.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime};
+
+use super::super::tree::Tree;
+use super::exec_nix_store::NixStoreRes;
+use super::narinfo::NarInfo;
+use super::parsing::SkippedLine;
+use super::{NixQueryDrv, NixQueryEntry, NixQueryTree};
+
+/// The format of the cache files.  This is bumped whenever `CachedNixStoreRes` changes, so
+/// files written by older versions are ignored.
+const CACHE_VERSION: u32 = 2;
+
+/// The size limit for the default cache, 100 MiB.
+pub const DEFAULT_MAX_BYTES: u64 = 100 * 1024 * 1024;
+
+/// Temporary files older than an hour are left over from a write that never finished, like
+/// from a process that was killed, and are removed when evicting.
+const STALE_TMP_AGE: Duration = Duration::from_secs(60 * 60);
+
+/// Counts the temporary files written by this process, so that two threads writing the same
+/// key at once don't use the same temporary file.
+static TMP_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CacheErr {
+    IoErr(String),
+    JsonErr(String),
+}
+
+impl std::fmt::Display for CacheErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            CacheErr::IoErr(string) | CacheErr::JsonErr(string) => string,
+        };
+        write!(f, "{}", string)
+    }
+}
+
+fn io_err(path: &Path, err: &std::io::Error) -> CacheErr {
+    CacheErr::IoErr(format!("{}: {}", path.display(), err))
+}
+
+/// The start of a cache file, which says whether the rest of it can be read.
+#[derive(Deserialize)]
+struct CacheHeader {
+    version: u32,
+    key: String,
+}
+
+/// What is actually written to a cache file.
+#[derive(Deserialize, Serialize)]
+struct CacheEntry {
+    version: u32,
+    key: String,
+    nix_store_res: CachedNixStoreRes,
+}
+
+/// A `NixStoreRes` with the tree flattened.
+///
+/// The serde impls for `Tree` nest two JSON levels for every level of the tree, and
+/// `serde_json` refuses to read anything nested more than 128 levels deep, so a `Tree` deeper
+/// than about 63 levels could be written but never read back.  Large closures are exactly the
+/// ones worth caching, so the tree is written as a flat list instead.  The path map isn't
+/// written at all, since it is quick to rebuild from the tree.
+#[derive(Deserialize, Serialize)]
+struct CachedNixStoreRes {
+    raw: Option<String>,
+    /// Every entry in the tree in pre-order, along with how many levels below the root it
+    /// is.
+    tree: Vec<(usize, NixQueryEntry)>,
+    skipped: Vec<SkippedLine>,
+    narinfos: HashMap<NixQueryDrv, NarInfo>,
+}
+
+impl CachedNixStoreRes {
+    fn new(nix_store_res: &NixStoreRes) -> Self {
+        CachedNixStoreRes {
+            raw: nix_store_res.raw.clone(),
+            tree: nix_store_res
+                .tree
+                .0
+                .pre_order()
+                .map(|(path, entry)| (path.0.len(), entry.clone()))
+                .collect(),
+            skipped: nix_store_res.skipped.clone(),
+            narinfos: nix_store_res.narinfos.clone(),
+        }
+    }
+
+    fn into_nix_store_res(self) -> Result<NixStoreRes, String> {
+        let tree = tree_from_pre_order(self.tree)?;
+        let map = tree.path_map();
+        Ok(NixStoreRes {
+            raw: self.raw,
+            tree,
+            map,
+            skipped: self.skipped,
+            narinfos: self.narinfos,
+        })
+    }
+}
+
+/// Rebuild a tree from its entries in pre-order, along with their depths, as written by
+/// `CachedNixStoreRes::new`.  This keeps the branches that are still open on an explicit
+/// stack, so it works for trees of any depth.
+fn tree_from_pre_order(
+    entries: Vec<(usize, NixQueryEntry)>,
+) -> Result<NixQueryTree, String> {
+    // The open branches from the root down.  The branch at depth n is at index n.
+    let mut stack: Vec<Tree<NixQueryEntry>> = vec![];
+    for (depth, entry) in entries {
+        if depth > stack.len() || (depth == 0) != stack.is_empty() {
+            return Err(format!(
+                "an entry at depth {} can't come after {} open branches",
+                depth,
+                stack.len()
+            ));
+        }
+        while stack.len() > depth {
+            close_branch(&mut stack);
+        }
+        stack.push(Tree::singleton(entry));
+    }
+    while stack.len() > 1 {
+        close_branch(&mut stack);
+    }
+    stack
+        .pop()
+        .map(NixQueryTree)
+        .ok_or_else(|| String::from("the tree is empty"))
+}
+
+/// Pop the last open branch and add it as a child of the branch before it.
+fn close_branch(stack: &mut Vec<Tree<NixQueryEntry>>) {
+    if let Some(tree) = stack.pop() {
+        if let Some(parent) = stack.last_mut() {
+            parent.children.push(tree);
+        }
+    }
+}
+
+/// A directory of cached `NixStoreRes`s.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Cache {
+    dir: PathBuf,
+    max_bytes: u64,
+}
+
+impl Cache {
+    #[must_use]
+    pub fn new(dir: &Path, max_bytes: u64) -> Self {
+        Cache {
+            dir: dir.to_path_buf(),
+            max_bytes,
+        }
+    }
+
+    /// The cache in `$XDG_CACHE_HOME/nix-query-tree-viewer`, or
+    /// `~/.cache/nix-query-tree-viewer` if `XDG_CACHE_HOME` isn't set.
+    ///
+    /// This is `None` if neither `XDG_CACHE_HOME` nor `HOME` is set.
+    pub fn open_default() -> Option<Self> {
+        let cache_home = std::env::var_os("XDG_CACHE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| {
+                std::env::var_os("HOME")
+                    .map(|home| PathBuf::from(home).join(".cache"))
+            })?;
+        Some(Cache::new(
+            &cache_home.join("nix-query-tree-viewer"),
+            DEFAULT_MAX_BYTES,
+        ))
+    }
+
+    /// The file that the result for `key` is cached in.
+    fn entry_path(&self, key: &str) -> PathBuf {
+        // Escape the key so it can be used as a single file name.
+        let file_name: String = key
+            .chars()
+            .map(|c| match c {
+                '%' => String::from("%25"),
+                '/' => String::from("%2F"),
+                _ => c.to_string(),
+            })
+            .collect();
+        self.dir.join(format!("{}.json", file_name))
+    }
+
+    /// Look up the cached result for `key`.
+    ///
+    /// A missing file, or a file from a different version, means there is no cached result.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the file for `key` can't be read, or is from this version but can't be
+    /// decoded.
+    pub fn get(&self, key: &str) -> Result<Option<NixStoreRes>, CacheErr> {
+        let entry_path = self.entry_path(key);
+        let contents = match std::fs::read_to_string(&entry_path) {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(None)
+            }
+            res => res.map_err(|err| io_err(&entry_path, &err))?,
+        };
+        let json_err = |err: String| {
+            CacheErr::JsonErr(format!("{}: {}", entry_path.display(), err))
+        };
+
+        let header: CacheHeader = serde_json::from_str(&contents)
+            .map_err(|err| json_err(err.to_string()))?;
+        if header.version != CACHE_VERSION || header.key != key {
+            return Ok(None);
+        }
+        let entry: CacheEntry = serde_json::from_str(&contents)
+            .map_err(|err| json_err(err.to_string()))?;
+        entry
+            .nix_store_res
+            .into_nix_store_res()
+            .map(Some)
+            .map_err(json_err)
+    }
+
+    /// Cache `nix_store_res` as the result for `key`, and then evict old results if the cache
+    /// is too big.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the result can't be written to the cache directory.
+    pub fn put(
+        &self,
+        key: &str,
+        nix_store_res: &NixStoreRes,
+    ) -> Result<(), CacheErr> {
+        std::fs::create_dir_all(&self.dir)
+            .map_err(|err| io_err(&self.dir, &err))?;
+
+        let entry = CacheEntry {
+            version: CACHE_VERSION,
+            key: String::from(key),
+            nix_store_res: CachedNixStoreRes::new(nix_store_res),
+        };
+        let contents = serde_json::to_string(&entry)
+            .map_err(|err| CacheErr::JsonErr(err.to_string()))?;
+
+        // Write to a temporary file first, so another process never reads a half-written
+        // file.
+        let entry_path = self.entry_path(key);
+        let tmp_path = entry_path.with_extension(format!(
+            "tmp-{}-{}",
+            std::process::id(),
+            TMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::write(&tmp_path, contents)
+            .map_err(|err| io_err(&tmp_path, &err))?;
+        std::fs::rename(&tmp_path, &entry_path)
+            .map_err(|err| io_err(&entry_path, &err))?;
+
+        self.evict()
+    }
+
+    /// Remove the cached result for `key`, if there is one.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the cached result exists but can't be removed.
+    pub fn remove(&self, key: &str) -> Result<(), CacheErr> {
+        remove_if_exists(&self.entry_path(key))
+    }
+
+    /// Remove the oldest cache files until the total size of the cache is at most
+    /// `max_bytes`.  Stale temporary files are removed too.
+    fn evict(&self) -> Result<(), CacheErr> {
+        let mut files = vec![];
+        let read_dir = std::fs::read_dir(&self.dir)
+            .map_err(|err| io_err(&self.dir, &err))?;
+        for dir_entry in read_dir {
+            let dir_entry = dir_entry.map_err(|err| io_err(&self.dir, &err))?;
+            let path = dir_entry.path();
+            let extension = path
+                .extension()
+                .and_then(std::ffi::OsStr::to_str)
+                .unwrap_or("");
+            let is_tmp = extension.starts_with("tmp-");
+            if extension != "json" && !is_tmp {
+                continue;
+            }
+            let metadata =
+                dir_entry.metadata().map_err(|err| io_err(&path, &err))?;
+            let modified =
+                metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            if is_tmp {
+                let age = SystemTime::now()
+                    .duration_since(modified)
+                    .unwrap_or_default();
+                if age > STALE_TMP_AGE {
+                    remove_if_exists(&path)?;
+                }
+                continue;
+            }
+            files.push((modified, metadata.len(), path));
+        }
+
+        let mut total_bytes: u64 = files.iter().map(|(_, len, _)| len).sum();
+        // Oldest first.
+        files.sort();
+        for (_, len, path) in files {
+            if total_bytes <= self.max_bytes {
+                break;
+            }
+            remove_if_exists(&path)?;
+            total_bytes -= len;
+        }
+        Ok(())
+    }
+}
+
+/// Remove a file from the cache.  It is fine if it has already been removed, for instance by
+/// another process evicting it.
+fn remove_if_exists(path: &Path) -> Result<(), CacheErr> {
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+            Err(io_err(path, &err))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The key to cache the result for `nix_store_path` in `store_uri` under.  See
+/// `exec_nix_store::run`.
+///
+/// For the default store, this is the path with all symlinks resolved, so `./result` and the
+/// store path it points to share a result.  Other stores can't be looked at locally, so
+/// `nix_store_path` is used as is, along with the store URI.
+///
+/// This is `None` if the path isn't in the nix store, since only store paths are guaranteed
+/// to never change.
+pub fn cache_key(
+    nix_store_path: &Path,
+    store_uri: Option<&str>,
+) -> Option<String> {
+    let store_dir = std::env::var_os("NIX_STORE_DIR")
+        .map_or_else(|| PathBuf::from("/nix/store"), PathBuf::from);
+    let resolved = match store_uri {
+        None => std::fs::canonicalize(nix_store_path).ok()?,
+        Some(_) => nix_store_path.to_path_buf(),
+    };
+    if !resolved.starts_with(&store_dir) || resolved == store_dir {
+        return None;
+    }
+    let resolved = resolved.to_string_lossy();
+    match store_uri {
+        None => Some(resolved.into_owned()),
+        Some(store_uri) => Some(format!("{} {}", store_uri, resolved)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::nix_query_tree::Recurse;
+
+    fn nix_store_res(name: &str) -> NixStoreRes {
+        let raw = format!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-{}\n\
+             +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27\n",
+            name
+        );
+        let tree: NixQueryTree = raw.parse().unwrap();
+        NixStoreRes::new(&raw, tree)
+    }
+
+    #[test]
+    fn test_get_and_put() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache =
+            Cache::new(&temp_dir.path().join("cache"), DEFAULT_MAX_BYTES);
+        let key = "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10";
+        let res = nix_store_res("hello-2.10");
+
+        assert_eq!(cache.get(key), Ok(None));
+        cache.put(key, &res).unwrap();
+        assert_eq!(cache.get(key), Ok(Some(res)));
+        assert_eq!(cache.get("/nix/store/other"), Ok(None));
+
+        cache.remove(key).unwrap();
+        assert_eq!(cache.get(key), Ok(None));
+        cache.remove(key).unwrap();
+
+        // Files from other versions are ignored.
+        std::fs::write(
+            cache.entry_path(key),
+            format!("{{\"version\": 0, \"key\": \"{}\"}}", key),
+        )
+        .unwrap();
+        assert_eq!(cache.get(key), Ok(None));
+
+        // Files from this version that can't be decoded are reported.
+        std::fs::write(
+            cache.entry_path(key),
+            format!("{{\"version\": {}, \"key\": \"{}\"}}", CACHE_VERSION, key),
+        )
+        .unwrap();
+        assert!(matches!(cache.get(key), Err(CacheErr::JsonErr(_))));
+    }
+
+    #[test]
+    fn test_get_and_put_deep_tree() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(temp_dir.path(), DEFAULT_MAX_BYTES);
+        let key = "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10";
+        let depth = 200;
+        let mut tree = Tree::singleton(NixQueryEntry::new(
+            "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27",
+            Recurse::No,
+        ));
+        for _ in 0..depth {
+            tree = Tree::new(
+                NixQueryEntry::new(key, Recurse::No),
+                vec![
+                    tree,
+                    Tree::singleton(NixQueryEntry::new(key, Recurse::Yes)),
+                ],
+            );
+        }
+        let res = NixStoreRes::new("", NixQueryTree(tree));
+
+        cache.put(key, &res).unwrap();
+        let cached = cache.get(key).unwrap().unwrap();
+        assert_eq!(cached.tree.0.depth(), depth);
+        assert_eq!(cached, res);
+    }
+
+    #[test]
+    fn test_tree_from_pre_order() {
+        let entry = |name: &str| {
+            NixQueryEntry::new(&format!("/nix/store/{}", name), Recurse::No)
+        };
+        assert_eq!(
+            tree_from_pre_order(vec![
+                (0, entry("a")),
+                (1, entry("b")),
+                (2, entry("c")),
+                (1, entry("d")),
+            ]),
+            Ok(NixQueryTree(Tree::new(
+                entry("a"),
+                vec![
+                    Tree::new(entry("b"), vec![Tree::singleton(entry("c"))]),
+                    Tree::singleton(entry("d")),
+                ]
+            )))
+        );
+        assert!(tree_from_pre_order(vec![]).is_err());
+        assert!(tree_from_pre_order(vec![(1, entry("a"))]).is_err());
+        assert!(tree_from_pre_order(vec![(0, entry("a")), (0, entry("b"))])
+            .is_err());
+        assert!(tree_from_pre_order(vec![(0, entry("a")), (2, entry("b"))])
+            .is_err());
+    }
+
+    #[test]
+    fn test_cache_key() {
+        let nix_store_path =
+            Path::new("/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10");
+
+        assert_eq!(
+            cache_key(nix_store_path, Some("file:///tmp/binary-cache")),
+            Some(String::from("file:///tmp/binary-cache /nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10"))
+        );
+        assert_eq!(cache_key(Path::new("/nix/store"), Some("daemon")), None);
+        assert_eq!(cache_key(Path::new("/tmp"), None), None);
+    }
+
+    #[test]
+    fn test_evict() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path().join("cache");
+        let res = nix_store_res("hello-2.10");
+        let entry_bytes = {
+            let cache = Cache::new(&dir, u64::MAX);
+            cache.put("/nix/store/a", &res).unwrap();
+            std::fs::metadata(cache.entry_path("/nix/store/a"))
+                .unwrap()
+                .len()
+        };
+
+        // Room for two entries.
+        let cache = Cache::new(&dir, entry_bytes * 2);
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        cache.put("/nix/store/b", &res).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        cache.put("/nix/store/c", &res).unwrap();
+
+        assert_eq!(cache.get("/nix/store/a"), Ok(None));
+        assert_eq!(cache.get("/nix/store/b"), Ok(Some(res.clone())));
+        assert_eq!(cache.get("/nix/store/c"), Ok(Some(res)));
+    }
+
+    #[test]
+    fn test_evict_stale_tmp_files() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(temp_dir.path(), DEFAULT_MAX_BYTES);
+        let stale = temp_dir.path().join("%2Fnix%2Fstore%2Fa.tmp-1-0");
+        let fresh = temp_dir.path().join("%2Fnix%2Fstore%2Fb.tmp-1-1");
+        std::fs::write(&stale, "").unwrap();
+        std::fs::write(&fresh, "").unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&stale)
+            .unwrap()
+            .set_modified(SystemTime::now() - STALE_TMP_AGE * 2)
+            .unwrap();
+
+        cache
+            .put("/nix/store/c", &nix_store_res("hello-2.10"))
+            .unwrap();
+
+        assert!(!stale.exists());
+        assert!(fresh.exists());
+    }
+}
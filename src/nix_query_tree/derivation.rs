@@ -0,0 +1,103 @@
+use std::path::Path;
+use std::str::FromStr;
+
+use super::drv_parsing;
+use super::NixQueryDrv;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DerivationErr {
+    IoErr(String),
+    ParseErr(String),
+}
+
+impl std::fmt::Display for DerivationErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            DerivationErr::IoErr(string) | DerivationErr::ParseErr(string) => {
+                string
+            }
+        };
+        write!(f, "{}", string)
+    }
+}
+
+/// A single output of a derivation, like `out` or `dev`.
+///
+/// `hash_algo` and `hash` are only non-empty for fixed-output derivations.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct DrvOutput {
+    pub name: String,
+    pub path: NixQueryDrv,
+    pub hash_algo: String,
+    pub hash: String,
+}
+
+/// A derivation this derivation depends on, along with which of its outputs are needed.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct InputDrv {
+    pub drv: NixQueryDrv,
+    pub outputs: Vec<String>,
+}
+
+/// The contents of a `.drv` file in the nix store.
+///
+/// This is parsed from the `ATerm` `Derive(...)` format that nix writes `.drv` files in.  All
+/// fields are kept in the same order they are in the `.drv` file.
+///
+/// ```
+/// use nix_query_tree_viewer::nix_query_tree::derivation::Derivation;
+/// use std::str::FromStr;
+///
+/// let raw_drv = concat!(
+///     r#"Derive([("out","/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10","","")],"#,
+///     r#"[],[],"x86_64-linux","/bin/sh",["-c","echo hello"],"#,
+///     r#"[("name","hello-2.10"),("out","/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10")])"#,
+/// );
+/// let derivation = Derivation::from_str(raw_drv).unwrap();
+///
+/// assert_eq!(derivation.builder, "/bin/sh");
+/// assert_eq!(derivation.env_var("name"), Some("hello-2.10"));
+/// ```
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Derivation {
+    pub outputs: Vec<DrvOutput>,
+    pub input_drvs: Vec<InputDrv>,
+    pub input_srcs: Vec<NixQueryDrv>,
+    pub platform: String,
+    pub builder: String,
+    pub args: Vec<String>,
+    pub env: Vec<(String, String)>,
+}
+
+impl Derivation {
+    /// Read and parse a `.drv` file.
+    ///
+    /// # Errors
+    ///
+    /// This fails if `drv_path` can't be read or isn't a valid `.drv` file.
+    pub fn from_path(drv_path: &Path) -> Result<Derivation, DerivationErr> {
+        let raw = std::fs::read_to_string(drv_path).map_err(|io_err| {
+            DerivationErr::IoErr(format!("{}: {}", drv_path.display(), io_err))
+        })?;
+        drv_parsing::derivation_parser(&raw)
+            .map_err(|nom_err| DerivationErr::ParseErr(nom_err.to_string()))
+    }
+
+    /// Lookup the value of an environment variable that will be set for the builder.
+    #[must_use]
+    pub fn env_var(&self, key: &str) -> Option<&str> {
+        self.env
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+impl FromStr for Derivation {
+    type Err = nom::Err<(String, nom::error::ErrorKind)>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        drv_parsing::derivation_parser(s)
+            .map_err(nom::Err::<(&str, nom::error::ErrorKind)>::to_owned)
+    }
+}
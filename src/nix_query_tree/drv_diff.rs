@@ -0,0 +1,647 @@
+use std::collections::{HashMap, HashSet};
+
+use super::derivation::{Derivation, DerivationErr};
+use super::NixQueryDrv;
+
+/// A single reason two derivations differ.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum DrvChange {
+    Outputs {
+        left: Vec<String>,
+        right: Vec<String>,
+    },
+    Platform {
+        left: String,
+        right: String,
+    },
+    Builder {
+        left: String,
+        right: String,
+    },
+    Args {
+        left: Vec<String>,
+        right: Vec<String>,
+    },
+    Env {
+        key: String,
+        left: Option<String>,
+        right: Option<String>,
+    },
+    InputSrcs {
+        only_left: Vec<NixQueryDrv>,
+        only_right: Vec<NixQueryDrv>,
+    },
+    InputDrvs {
+        only_left: Vec<NixQueryDrv>,
+        only_right: Vec<NixQueryDrv>,
+    },
+    /// An input derivation with the same name on both sides, but a different hash.
+    InputDrv(DrvDiff),
+    /// Same as `InputDrv`, but these two derivations have already been compared somewhere else
+    /// in the `DrvDiff`.
+    InputDrvRepeated {
+        left: NixQueryDrv,
+        right: NixQueryDrv,
+    },
+}
+
+impl DrvChange {
+    /// Whether or not this change is only because of a difference in an input derivation.
+    #[must_use]
+    pub fn is_input_drv(&self) -> bool {
+        matches!(
+            self,
+            DrvChange::InputDrv(_) | DrvChange::InputDrvRepeated { .. }
+        )
+    }
+
+    /// A one line, human-readable description of this change.
+    #[must_use]
+    pub fn description(&self) -> String {
+        match self {
+            DrvChange::Outputs { .. } => {
+                String::from("The set of outputs differ")
+            }
+            DrvChange::Platform { .. } => String::from("The platforms differ"),
+            DrvChange::Builder { .. } => String::from("The builders differ"),
+            DrvChange::Args { .. } => String::from("The arguments differ"),
+            DrvChange::Env { key, .. } => {
+                format!("The environment variable `{}` differs", key)
+            }
+            DrvChange::InputSrcs { .. } => {
+                String::from("The set of input sources differ")
+            }
+            DrvChange::InputDrvs { .. } => {
+                String::from("The set of input derivations differ")
+            }
+            DrvChange::InputDrv(drv_diff) => format!(
+                "The input derivation `{}` differs",
+                drv_diff.left.drv_name()
+            ),
+            DrvChange::InputDrvRepeated { left, .. } => format!(
+                "The input derivation `{}` differs (already compared above)",
+                left.drv_name()
+            ),
+        }
+    }
+
+    /// The lines showing the left (`- `) and right (`+ `) side of this change.
+    ///
+    /// For `DrvChange::InputDrv` this only shows the two derivation paths.  The differences
+    /// between them are in the inner `DrvDiff`.
+    pub fn details(&self) -> Vec<String> {
+        fn left_right(left: &str, right: &str) -> Vec<String> {
+            vec![format!("- {}", left), format!("+ {}", right)]
+        }
+
+        fn only_left_right(
+            only_left: &[NixQueryDrv],
+            only_right: &[NixQueryDrv],
+        ) -> Vec<String> {
+            only_left
+                .iter()
+                .map(|drv| format!("- {}", drv))
+                .chain(only_right.iter().map(|drv| format!("+ {}", drv)))
+                .collect()
+        }
+
+        match self {
+            DrvChange::Outputs { left, right }
+            | DrvChange::Args { left, right } => {
+                left_right(&left.join(" "), &right.join(" "))
+            }
+            DrvChange::Platform { left, right }
+            | DrvChange::Builder { left, right } => left_right(left, right),
+            DrvChange::Env { left, right, .. } => left_right(
+                left.as_ref().map_or("(not set)", String::as_str),
+                right.as_ref().map_or("(not set)", String::as_str),
+            ),
+            DrvChange::InputSrcs {
+                only_left,
+                only_right,
+            }
+            | DrvChange::InputDrvs {
+                only_left,
+                only_right,
+            } => only_left_right(only_left, only_right),
+            DrvChange::InputDrv(DrvDiff { left, right, .. })
+            | DrvChange::InputDrvRepeated { left, right } => {
+                left_right(&left.to_string(), &right.to_string())
+            }
+        }
+    }
+}
+
+/// The differences between two derivations, including the differences between any of their
+/// input derivations.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DrvDiff {
+    pub left: NixQueryDrv,
+    pub right: NixQueryDrv,
+    pub changes: Vec<DrvChange>,
+}
+
+impl DrvDiff {
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Follow differing input derivations down to the first derivation that differs for some
+    /// reason other than one of its inputs differing.
+    ///
+    /// This returns the whole chain of `DrvDiff`s, starting at `self`.  The last element is
+    /// the first point of divergence.
+    #[must_use]
+    pub fn first_divergence(&self) -> Vec<&DrvDiff> {
+        let mut chain = vec![self];
+        let mut curr = self;
+        while !curr.changes.iter().any(|change| !change.is_input_drv()) {
+            let option_next =
+                curr.changes.iter().find_map(|change| match change {
+                    DrvChange::InputDrv(drv_diff) => Some(drv_diff),
+                    _ => None,
+                });
+            match option_next {
+                None => break,
+                Some(next) => {
+                    chain.push(next);
+                    curr = next;
+                }
+            }
+        }
+        chain
+    }
+
+    /// Render this `DrvDiff` as a human-readable, indented text report.
+    #[must_use]
+    pub fn report(&self) -> String {
+        let mut lines =
+            vec![format!("- {}", self.left), format!("+ {}", self.right)];
+
+        if self.is_empty() {
+            lines.push(String::from("The derivations are the same"));
+        } else {
+            let divergence = self.first_divergence();
+            let first = divergence.last().unwrap_or(&self);
+            lines.push(String::new());
+            lines.push(String::from("First point of divergence:"));
+            lines.push(format!("  - {}", first.left));
+            lines.push(format!("  + {}", first.right));
+            lines.push(String::new());
+            self.report_changes(0, &mut lines);
+        }
+
+        let mut report = lines.join("\n");
+        report.push('\n');
+        report
+    }
+
+    fn report_changes(&self, indent: usize, lines: &mut Vec<String>) {
+        let prefix = " ".repeat(indent);
+        for change in &self.changes {
+            lines.push(format!("{}• {}:", prefix, change.description()));
+            for detail in change.details() {
+                lines.push(format!("{}    {}", prefix, detail));
+            }
+            if let DrvChange::InputDrv(drv_diff) = change {
+                drv_diff.report_changes(indent + 4, lines);
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for DrvDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.report())
+    }
+}
+
+/// Return the elements that are only in `left` and only in `right`.
+fn only_left_right(
+    left: &[NixQueryDrv],
+    right: &[NixQueryDrv],
+) -> (Vec<NixQueryDrv>, Vec<NixQueryDrv>) {
+    let left_set: HashSet<&NixQueryDrv> = left.iter().collect();
+    let right_set: HashSet<&NixQueryDrv> = right.iter().collect();
+    let only_left = left
+        .iter()
+        .filter(|drv| !right_set.contains(drv))
+        .cloned()
+        .collect();
+    let only_right = right
+        .iter()
+        .filter(|drv| !left_set.contains(drv))
+        .cloned()
+        .collect();
+    (only_left, only_right)
+}
+
+/// Output paths of the left derivation, paired with the output paths of the right derivation.
+///
+/// When an input derivation differs, all of its output paths differ too, so every
+/// environment variable or argument referring to them would show up as a change.  Replacing
+/// the left output paths with the right output paths before comparing strings hides this
+/// noise.
+type Substitutions = Vec<(String, String)>;
+
+fn output_substitutions(
+    left: &Derivation,
+    right: &Derivation,
+) -> Substitutions {
+    left.outputs
+        .iter()
+        .filter_map(|left_output| {
+            right
+                .outputs
+                .iter()
+                .find(|right_output| right_output.name == left_output.name)
+                .map(|right_output| {
+                    (
+                        left_output.path.to_string(),
+                        right_output.path.to_string(),
+                    )
+                })
+        })
+        .filter(|(left_path, right_path)| {
+            !left_path.is_empty() && left_path != right_path
+        })
+        .collect()
+}
+
+fn same_modulo_substitutions(
+    left: &str,
+    right: &str,
+    substitutions: &[(String, String)],
+) -> bool {
+    let substituted = substitutions
+        .iter()
+        .fold(String::from(left), |acc, (from, to)| acc.replace(from, to));
+    substituted == right
+}
+
+struct Differ<'a> {
+    load: &'a dyn Fn(&NixQueryDrv) -> Result<Derivation, DerivationErr>,
+    cache: HashMap<NixQueryDrv, Derivation>,
+    seen: HashSet<(NixQueryDrv, NixQueryDrv)>,
+}
+
+impl Differ<'_> {
+    fn load(&mut self, drv: &NixQueryDrv) -> Result<Derivation, DerivationErr> {
+        if let Some(derivation) = self.cache.get(drv) {
+            return Ok(derivation.clone());
+        }
+        let derivation = (self.load)(drv)?;
+        self.cache.insert(drv.clone(), derivation.clone());
+        Ok(derivation)
+    }
+
+    /// Pair up the input derivations that only one side has by name, and
+    /// diff each pair.  The outputs of every pair are added to
+    /// `substitutions`.
+    fn diff_input_drvs(
+        &mut self,
+        left: &Derivation,
+        right: &Derivation,
+        substitutions: &mut Substitutions,
+    ) -> Result<Vec<DrvChange>, DerivationErr> {
+        let mut input_changes = vec![];
+
+        let left_input_drvs: Vec<NixQueryDrv> =
+            left.input_drvs.iter().map(|i| i.drv.clone()).collect();
+        let right_input_drvs: Vec<NixQueryDrv> =
+            right.input_drvs.iter().map(|i| i.drv.clone()).collect();
+        let (only_left, mut only_right) =
+            only_left_right(&left_input_drvs, &right_input_drvs);
+        let mut unpaired_left = vec![];
+        for left_input in only_left {
+            let option_pos = only_right.iter().position(|right_input| {
+                right_input.drv_name() == left_input.drv_name()
+            });
+            match option_pos {
+                None => unpaired_left.push(left_input),
+                Some(pos) => {
+                    let right_input = only_right.remove(pos);
+                    let left_input_derivation = self.load(&left_input)?;
+                    let right_input_derivation = self.load(&right_input)?;
+                    substitutions.extend(output_substitutions(
+                        &left_input_derivation,
+                        &right_input_derivation,
+                    ));
+                    let pair = (left_input.clone(), right_input.clone());
+                    if self.seen.contains(&pair) {
+                        input_changes.push(DrvChange::InputDrvRepeated {
+                            left: left_input,
+                            right: right_input,
+                        });
+                    } else {
+                        let input_diff =
+                            self.diff(&left_input, &right_input)?;
+                        input_changes.push(DrvChange::InputDrv(input_diff));
+                    }
+                }
+            }
+        }
+        if !unpaired_left.is_empty() || !only_right.is_empty() {
+            input_changes.push(DrvChange::InputDrvs {
+                only_left: unpaired_left,
+                only_right,
+            });
+        }
+        Ok(input_changes)
+    }
+
+    fn diff(
+        &mut self,
+        left_drv: &NixQueryDrv,
+        right_drv: &NixQueryDrv,
+    ) -> Result<DrvDiff, DerivationErr> {
+        self.seen.insert((left_drv.clone(), right_drv.clone()));
+
+        let left = self.load(left_drv)?;
+        let right = self.load(right_drv)?;
+
+        let mut substitutions = output_substitutions(&left, &right);
+        let input_changes =
+            self.diff_input_drvs(&left, &right, &mut substitutions)?;
+
+        let mut changes = vec![];
+
+        let left_output_names: Vec<String> =
+            left.outputs.iter().map(|o| o.name.clone()).collect();
+        let right_output_names: Vec<String> =
+            right.outputs.iter().map(|o| o.name.clone()).collect();
+        if left_output_names != right_output_names {
+            changes.push(DrvChange::Outputs {
+                left: left_output_names,
+                right: right_output_names,
+            });
+        }
+
+        if left.platform != right.platform {
+            changes.push(DrvChange::Platform {
+                left: left.platform.clone(),
+                right: right.platform.clone(),
+            });
+        }
+
+        if !same_modulo_substitutions(
+            &left.builder,
+            &right.builder,
+            &substitutions,
+        ) {
+            changes.push(DrvChange::Builder {
+                left: left.builder.clone(),
+                right: right.builder.clone(),
+            });
+        }
+
+        let args_same =
+            left.args.len() == right.args.len()
+                && left.args.iter().zip(right.args.iter()).all(|(l, r)| {
+                    same_modulo_substitutions(l, r, &substitutions)
+                });
+        if !args_same {
+            changes.push(DrvChange::Args {
+                left: left.args.clone(),
+                right: right.args.clone(),
+            });
+        }
+
+        let (only_left_srcs, only_right_srcs) =
+            only_left_right(&left.input_srcs, &right.input_srcs);
+        if !only_left_srcs.is_empty() || !only_right_srcs.is_empty() {
+            changes.push(DrvChange::InputSrcs {
+                only_left: only_left_srcs,
+                only_right: only_right_srcs,
+            });
+        }
+
+        let mut env_keys: Vec<&String> = left
+            .env
+            .iter()
+            .chain(right.env.iter())
+            .map(|(key, _)| key)
+            .collect();
+        env_keys.sort();
+        env_keys.dedup();
+        for key in env_keys {
+            let option_left = left.env_var(key);
+            let option_right = right.env_var(key);
+            let same = match (option_left, option_right) {
+                (Some(l), Some(r)) => {
+                    same_modulo_substitutions(l, r, &substitutions)
+                }
+                (None, None) => true,
+                _ => false,
+            };
+            if !same {
+                changes.push(DrvChange::Env {
+                    key: key.clone(),
+                    left: option_left.map(String::from),
+                    right: option_right.map(String::from),
+                });
+            }
+        }
+
+        changes.extend(input_changes);
+
+        Ok(DrvDiff {
+            left: left_drv.clone(),
+            right: right_drv.clone(),
+            changes,
+        })
+    }
+}
+
+/// Compare two derivations, recursively comparing any input derivations with the same name
+/// but different hashes.
+///
+/// Derivations are read with `load`.  This makes it possible to compare derivations that
+/// aren't in the nix store.
+///
+/// # Errors
+///
+/// This fails if `load` fails for any of the derivations being compared.
+pub fn diff_drvs_with(
+    left: &NixQueryDrv,
+    right: &NixQueryDrv,
+    load: &dyn Fn(&NixQueryDrv) -> Result<Derivation, DerivationErr>,
+) -> Result<DrvDiff, DerivationErr> {
+    let mut differ = Differ {
+        load,
+        cache: HashMap::new(),
+        seen: HashSet::new(),
+    };
+    differ.diff(left, right)
+}
+
+/// Compare two `.drv` files in the nix store.
+///
+/// # Errors
+///
+/// This fails if either `.drv` file, or an input derivation being compared, can't be
+/// read or parsed.
+pub fn diff_drvs(
+    left: &NixQueryDrv,
+    right: &NixQueryDrv,
+) -> Result<DrvDiff, DerivationErr> {
+    diff_drvs_with(left, right, &|drv| Derivation::from_path(drv))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::str::FromStr;
+
+    fn drv(
+        out: &str,
+        input_drvs: &[&str],
+        builder: &str,
+        env: &[(&str, &str)],
+    ) -> Derivation {
+        let raw = format!(
+            r#"Derive([("out","{}","","")],[{}],[],"x86_64-linux","{}",[],[{}])"#,
+            out,
+            input_drvs
+                .iter()
+                .map(|i| format!(r#"("{}",["out"])"#, i))
+                .collect::<Vec<String>>()
+                .join(","),
+            builder,
+            env.iter()
+                .map(|(k, v)| format!(r#"("{}","{}")"#, k, v))
+                .collect::<Vec<String>>()
+                .join(","),
+        );
+        Derivation::from_str(&raw).unwrap()
+    }
+
+    fn loader(
+        drvs: Vec<(&str, Derivation)>,
+    ) -> impl Fn(&NixQueryDrv) -> Result<Derivation, DerivationErr> {
+        let map: HashMap<NixQueryDrv, Derivation> = drvs
+            .into_iter()
+            .map(|(path, derivation)| (NixQueryDrv::from(path), derivation))
+            .collect();
+        move |drv| {
+            map.get(drv)
+                .cloned()
+                .ok_or_else(|| DerivationErr::IoErr(drv.to_string()))
+        }
+    }
+
+    const BASH_A: &str =
+        "/nix/store/aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-bash-4.4.drv";
+    const BASH_B: &str =
+        "/nix/store/bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb-bash-4.4.drv";
+    const BASH_A_OUT: &str =
+        "/nix/store/cccccccccccccccccccccccccccccccc-bash-4.4";
+    const BASH_B_OUT: &str =
+        "/nix/store/dddddddddddddddddddddddddddddddd-bash-4.4";
+    const HELLO_A: &str =
+        "/nix/store/eeeeeeeeeeeeeeeeeeeeeeeeeeeeeeee-hello-2.10.drv";
+    const HELLO_B: &str =
+        "/nix/store/ffffffffffffffffffffffffffffffff-hello-2.10.drv";
+    const HELLO_A_OUT: &str =
+        "/nix/store/gggggggggggggggggggggggggggggggg-hello-2.10";
+    const HELLO_B_OUT: &str =
+        "/nix/store/hhhhhhhhhhhhhhhhhhhhhhhhhhhhhhhh-hello-2.10";
+
+    #[test]
+    fn test_diff_drvs_same() {
+        let hello = drv(HELLO_A_OUT, &[], "/bin/sh", &[("name", "hello")]);
+        let load = loader(vec![(HELLO_A, hello)]);
+        let r = diff_drvs_with(&HELLO_A.into(), &HELLO_A.into(), &load);
+        let actual = DrvDiff {
+            left: HELLO_A.into(),
+            right: HELLO_A.into(),
+            changes: vec![],
+        };
+        assert_eq!(r, Ok(actual));
+    }
+
+    #[test]
+    fn test_diff_drvs_env_and_builder() {
+        let hello_a = drv(
+            HELLO_A_OUT,
+            &[],
+            "/bin/sh",
+            &[("name", "hello"), ("out", HELLO_A_OUT)],
+        );
+        let hello_b = drv(
+            HELLO_B_OUT,
+            &[],
+            "/bin/bash",
+            &[("name", "hello2"), ("out", HELLO_B_OUT)],
+        );
+        let load = loader(vec![(HELLO_A, hello_a), (HELLO_B, hello_b)]);
+        let r = diff_drvs_with(&HELLO_A.into(), &HELLO_B.into(), &load);
+        let actual = DrvDiff {
+            left: HELLO_A.into(),
+            right: HELLO_B.into(),
+            changes: vec![
+                DrvChange::Builder {
+                    left: "/bin/sh".into(),
+                    right: "/bin/bash".into(),
+                },
+                DrvChange::Env {
+                    key: "name".into(),
+                    left: Some("hello".into()),
+                    right: Some("hello2".into()),
+                },
+            ],
+        };
+        assert_eq!(r, Ok(actual));
+    }
+
+    #[test]
+    fn test_diff_drvs_recursive_first_divergence() {
+        let bash_a = drv(BASH_A_OUT, &[], "/bin/sh", &[("version", "4.4")]);
+        let bash_b = drv(BASH_B_OUT, &[], "/bin/sh", &[("version", "4.5")]);
+        let hello_a = drv(
+            HELLO_A_OUT,
+            &[BASH_A],
+            &format!("{}/bin/bash", BASH_A_OUT),
+            &[("out", HELLO_A_OUT)],
+        );
+        let hello_b = drv(
+            HELLO_B_OUT,
+            &[BASH_B],
+            &format!("{}/bin/bash", BASH_B_OUT),
+            &[("out", HELLO_B_OUT)],
+        );
+        let load = loader(vec![
+            (BASH_A, bash_a),
+            (BASH_B, bash_b),
+            (HELLO_A, hello_a),
+            (HELLO_B, hello_b),
+        ]);
+        let r =
+            diff_drvs_with(&HELLO_A.into(), &HELLO_B.into(), &load).unwrap();
+        let bash_diff = DrvDiff {
+            left: BASH_A.into(),
+            right: BASH_B.into(),
+            changes: vec![DrvChange::Env {
+                key: "version".into(),
+                left: Some("4.4".into()),
+                right: Some("4.5".into()),
+            }],
+        };
+        let actual = DrvDiff {
+            left: HELLO_A.into(),
+            right: HELLO_B.into(),
+            changes: vec![DrvChange::InputDrv(bash_diff.clone())],
+        };
+        assert_eq!(r, actual);
+        assert_eq!(r.first_divergence(), vec![&actual, &bash_diff]);
+    }
+
+    #[test]
+    fn test_diff_drvs_missing_drv() {
+        let load = loader(vec![]);
+        let r = diff_drvs_with(&HELLO_A.into(), &HELLO_B.into(), &load);
+        assert_eq!(r, Err(DerivationErr::IoErr(HELLO_A.into())));
+    }
+}
@@ -0,0 +1,231 @@
+use nom::character::complete::{char, newline};
+use nom::multi::separated_list;
+use nom::sequence::delimited;
+use nom::{do_parse, eof, named, opt, tag, IResult};
+
+use super::derivation::{Derivation, DrvOutput, InputDrv};
+use super::NixQueryDrv;
+
+/// Parse an `ATerm` string literal, like `"hello\nworld"`.
+///
+/// Nix only ever escapes `"`, `\`, and the `\n`, `\r`, `\t` control characters in `.drv` files.
+fn parse_string(input: &str) -> IResult<&str, String> {
+    let (input, _) = char('"')(input)?;
+    let mut res = String::new();
+    let mut chars = input.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((&input[i + 1..], res)),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => res.push('\n'),
+                Some((_, 'r')) => res.push('\r'),
+                Some((_, 't')) => res.push('\t'),
+                Some((_, escaped)) => res.push(escaped),
+                None => break,
+            },
+            _ => res.push(c),
+        }
+    }
+    Err(nom::Err::Error((input, nom::error::ErrorKind::Eof)))
+}
+
+/// Parse an `ATerm` list like `[a,b,c]`, where each element is parsed with `f`.
+fn parse_list<'a, O>(
+    f: impl Fn(&'a str) -> IResult<&'a str, O>,
+) -> impl Fn(&'a str) -> IResult<&'a str, Vec<O>> {
+    delimited(char('['), separated_list(char(','), f), char(']'))
+}
+
+fn parse_nix_query_drv(input: &str) -> IResult<&str, NixQueryDrv> {
+    let (input, path) = parse_string(input)?;
+    Ok((input, NixQueryDrv::from(&path)))
+}
+
+/// Parse a single output, like `("out","/nix/store/...-hello-2.10","","")`.
+fn parse_drv_output(input: &str) -> IResult<&str, DrvOutput> {
+    let (input, _) = char('(')(input)?;
+    let (input, name) = parse_string(input)?;
+    let (input, _) = char(',')(input)?;
+    let (input, path) = parse_nix_query_drv(input)?;
+    let (input, _) = char(',')(input)?;
+    let (input, hash_algo) = parse_string(input)?;
+    let (input, _) = char(',')(input)?;
+    let (input, hash) = parse_string(input)?;
+    let (input, _) = char(')')(input)?;
+    Ok((
+        input,
+        DrvOutput {
+            name,
+            path,
+            hash_algo,
+            hash,
+        },
+    ))
+}
+
+/// Parse a single input derivation, like `("/nix/store/...-bash-4.4-p23.drv",["out"])`.
+fn parse_input_drv(input: &str) -> IResult<&str, InputDrv> {
+    let (input, _) = char('(')(input)?;
+    let (input, drv) = parse_nix_query_drv(input)?;
+    let (input, _) = char(',')(input)?;
+    let (input, outputs) = parse_list(parse_string)(input)?;
+    let (input, _) = char(')')(input)?;
+    Ok((input, InputDrv { drv, outputs }))
+}
+
+/// Parse a single environment variable, like `("name","hello-2.10")`.
+fn parse_env_var(input: &str) -> IResult<&str, (String, String)> {
+    let (input, _) = char('(')(input)?;
+    let (input, key) = parse_string(input)?;
+    let (input, _) = char(',')(input)?;
+    let (input, value) = parse_string(input)?;
+    let (input, _) = char(')')(input)?;
+    Ok((input, (key, value)))
+}
+
+named!(parse_derive_start<&str, &str>,
+    tag!("Derive("));
+
+fn parse_derivation(input: &str) -> IResult<&str, Derivation> {
+    let (input, _) = parse_derive_start(input)?;
+    let (input, outputs) = parse_list(parse_drv_output)(input)?;
+    let (input, _) = char(',')(input)?;
+    let (input, input_drvs) = parse_list(parse_input_drv)(input)?;
+    let (input, _) = char(',')(input)?;
+    let (input, input_srcs) = parse_list(parse_nix_query_drv)(input)?;
+    let (input, _) = char(',')(input)?;
+    let (input, platform) = parse_string(input)?;
+    let (input, _) = char(',')(input)?;
+    let (input, builder) = parse_string(input)?;
+    let (input, _) = char(',')(input)?;
+    let (input, args) = parse_list(parse_string)(input)?;
+    let (input, _) = char(',')(input)?;
+    let (input, env) = parse_list(parse_env_var)(input)?;
+    let (input, _) = char(')')(input)?;
+    Ok((
+        input,
+        Derivation {
+            outputs,
+            input_drvs,
+            input_srcs,
+            platform,
+            builder,
+            args,
+            env,
+        },
+    ))
+}
+
+named!(parse_derivation_final<&str, Derivation>,
+    do_parse!(
+        derivation: parse_derivation >>
+        opt!(newline) >>
+        eof!() >>
+        (derivation)));
+
+/// Parse the full contents of a `.drv` file.
+///
+/// # Errors
+///
+/// This fails if `input` isn't a single `Derive(...)` expression.
+pub fn derivation_parser(
+    input: &str,
+) -> Result<Derivation, nom::Err<(&str, nom::error::ErrorKind)>> {
+    parse_derivation_final(input).map(|(_, derivation)| derivation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_string_simple() {
+        let r = parse_string(r#""hello-2.10",rest"#);
+        assert_eq!(r, Ok((",rest", String::from("hello-2.10"))));
+    }
+
+    #[test]
+    fn test_parse_string_empty() {
+        let r = parse_string(r#""""#);
+        assert_eq!(r, Ok(("", String::new())));
+    }
+
+    #[test]
+    fn test_parse_string_escapes() {
+        let r = parse_string(r#""a \"b\"\nc\\d\te""#);
+        assert_eq!(r, Ok(("", String::from("a \"b\"\nc\\d\te"))));
+    }
+
+    #[test]
+    fn test_parse_string_unterminated() {
+        let r = parse_string(r#""hello"#);
+        assert!(r.is_err());
+    }
+
+    #[test]
+    fn test_parse_input_drv() {
+        let raw_input = r#"("/nix/store/m3dzp25n0g4fwlygdhvak1kk8xz906n9-bash-4.4-p23.drv",["dev","out"])"#;
+        let actual_input_drv = InputDrv {
+            drv: "/nix/store/m3dzp25n0g4fwlygdhvak1kk8xz906n9-bash-4.4-p23.drv"
+                .into(),
+            outputs: vec!["dev".into(), "out".into()],
+        };
+        let r = parse_input_drv(raw_input);
+        assert_eq!(r, Ok(("", actual_input_drv)));
+    }
+
+    #[test]
+    fn test_parse_derivation() {
+        let raw_input = concat!(
+            r#"Derive([("out","/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10","","")],"#,
+            r#"[("/nix/store/m3dzp25n0g4fwlygdhvak1kk8xz906n9-bash-4.4-p23.drv",["out"])],"#,
+            r#"["/nix/store/9krlzvny65gdc8s7kpb6lkx8cd02c25b-default-builder.sh"],"#,
+            r#""x86_64-linux","/nix/store/ybzmmkk2z0smm1b3xyvw0ssffvxzsrbd-bash-4.4-p23/bin/bash","#,
+            r#"["-e","/nix/store/9krlzvny65gdc8s7kpb6lkx8cd02c25b-default-builder.sh"],"#,
+            r#"[("name","hello-2.10"),("system","x86_64-linux")])"#,
+            "\n"
+        );
+        let actual_derivation = Derivation {
+            outputs: vec![DrvOutput {
+                name: "out".into(),
+                path: "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10"
+                    .into(),
+                hash_algo: String::new(),
+                hash: String::new(),
+            }],
+            input_drvs: vec![InputDrv {
+                drv: "/nix/store/m3dzp25n0g4fwlygdhvak1kk8xz906n9-bash-4.4-p23.drv"
+                    .into(),
+                outputs: vec!["out".into()],
+            }],
+            input_srcs: vec![
+                "/nix/store/9krlzvny65gdc8s7kpb6lkx8cd02c25b-default-builder.sh"
+                    .into(),
+            ],
+            platform: "x86_64-linux".into(),
+            builder:
+                "/nix/store/ybzmmkk2z0smm1b3xyvw0ssffvxzsrbd-bash-4.4-p23/bin/bash"
+                    .into(),
+            args: vec![
+                "-e".into(),
+                "/nix/store/9krlzvny65gdc8s7kpb6lkx8cd02c25b-default-builder.sh"
+                    .into(),
+            ],
+            env: vec![
+                ("name".into(), "hello-2.10".into()),
+                ("system".into(), "x86_64-linux".into()),
+            ],
+        };
+
+        let r = derivation_parser(raw_input);
+        assert_eq!(r, Ok(actual_derivation));
+    }
+
+    #[test]
+    fn test_parse_derivation_trailing_garbage() {
+        let raw_input =
+            r#"Derive([],[],[],"x86_64-linux","/bin/sh",[],[]) foo"#;
+        let r = derivation_parser(raw_input);
+        assert!(r.is_err());
+    }
+}
@@ -0,0 +1,194 @@
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use super::super::tree::Path;
+use super::{compare_versions, split_output, NixQueryDrv, NixQueryPathMap};
+
+/// A single store path for a package that is in the tree more than once.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Variant {
+    pub drv: NixQueryDrv,
+    pub version: Option<String>,
+    /// The first place this store path shows up in the tree.
+    pub first_path: Path,
+    /// How many times this store path shows up in the tree.
+    pub occurrences: usize,
+}
+
+/// A package name that appears in the tree with more than one store path.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Duplicate {
+    pub pname: String,
+    /// All the store paths for this package, sorted by version.
+    pub variants: Vec<Variant>,
+}
+
+/// Find all the package names that appear in the tree with more than one store path, like a
+/// closure that contains two different `glibc`s.
+///
+/// The different outputs of a single derivation (like `openssl-1.1.1d` and
+/// `openssl-1.1.1d-dev`) are not considered duplicates of each other.  A package is only
+/// reported when one of its outputs comes from more than one store path.
+///
+/// ```
+/// use indoc::indoc;
+/// use nix_query_tree_viewer::nix_query_tree::duplicates::find_duplicates;
+/// use nix_query_tree_viewer::nix_query_tree::NixQueryTree;
+/// use std::str::FromStr;
+///
+/// let raw_tree = indoc!(
+///         "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+///         +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+///         +---/nix/store/681354n3k44r8z90m35hm8945vsp95h1-glibc-2.30
+///         "
+///     );
+/// let nix_query_tree = NixQueryTree::from_str(raw_tree).unwrap();
+/// let duplicates = find_duplicates(&nix_query_tree.path_map());
+///
+/// assert_eq!(duplicates.len(), 1);
+/// assert_eq!(duplicates[0].pname, "glibc");
+/// assert_eq!(duplicates[0].variants.len(), 2);
+/// ```
+#[must_use]
+pub fn find_duplicates(path_map: &NixQueryPathMap) -> Vec<Duplicate> {
+    let mut by_pname: BTreeMap<String, Vec<Variant>> = BTreeMap::new();
+
+    for (drv, paths) in path_map.0.iter() {
+        let (pname, version) = drv.pname_and_version();
+        if let Some(first_path) = paths.first() {
+            by_pname.entry(pname).or_default().push(Variant {
+                drv: drv.clone(),
+                version,
+                first_path: first_path.clone(),
+                occurrences: paths.len(),
+            });
+        }
+    }
+
+    by_pname
+        .into_iter()
+        .filter(|(_, variants)| has_duplicate_output(variants))
+        .map(|(pname, mut variants)| {
+            variants.sort_by(|a, b| {
+                compare_option_versions(
+                    a.version.as_deref(),
+                    b.version.as_deref(),
+                )
+                .then_with(|| a.drv.cmp_hash(&b.drv))
+            });
+            Duplicate { pname, variants }
+        })
+        .collect()
+}
+
+/// Compare versions with `compare_versions`, so `2.9` comes before `2.10`.
+/// Store paths without a version come first.
+fn compare_option_versions(v1: Option<&str>, v2: Option<&str>) -> Ordering {
+    match (v1, v2) {
+        (Some(v1), Some(v2)) => compare_versions(v1, v2),
+        _ => v1.cmp(&v2),
+    }
+}
+
+/// Whether or not any single output is provided by more than one of these store paths.
+fn has_duplicate_output(variants: &[Variant]) -> bool {
+    let mut outputs: BTreeMap<&str, usize> = BTreeMap::new();
+    for variant in variants {
+        let output = variant
+            .version
+            .as_ref()
+            .map_or("out", |version| split_output(version).1);
+        *outputs.entry(output).or_default() += 1;
+    }
+    outputs.values().any(|&count| count > 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::NixQueryTree;
+    use indoc::indoc;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_find_duplicates() {
+        let raw_tree = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            +---/nix/store/i6vl5lwlz5jbkg4r6p340dwmj6fha3xq-openssl-1.1.1d
+            +---/nix/store/a6d5lyc3bzakyvxigwbms4lsh97pw3hn-openssl-1.1.1d-dev
+            |   +---/nix/store/i6vl5lwlz5jbkg4r6p340dwmj6fha3xq-openssl-1.1.1d [...]
+            +---/nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-multiple-outputs.sh
+                +---/nix/store/681354n3k44r8z90m35hm8945vsp95h1-glibc-2.30
+                +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            "
+        );
+        let nix_query_tree = NixQueryTree::from_str(raw_tree).unwrap();
+        let duplicates = find_duplicates(&nix_query_tree.path_map());
+
+        assert_eq!(
+            duplicates,
+            vec![Duplicate {
+                pname: String::from("glibc"),
+                variants: vec![
+                    Variant {
+                        drv: NixQueryDrv::from("/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27"),
+                        version: Some(String::from("2.27")),
+                        first_path: Path::from(vec![0]),
+                        occurrences: 3,
+                    },
+                    Variant {
+                        drv: NixQueryDrv::from("/nix/store/681354n3k44r8z90m35hm8945vsp95h1-glibc-2.30"),
+                        version: Some(String::from("2.30")),
+                        first_path: Path::from(vec![3, 0]),
+                        occurrences: 1,
+                    },
+                ],
+            }]
+        );
+    }
+
+    #[test]
+    fn test_find_duplicates_same_version() {
+        let raw_tree = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            +---/nix/store/681354n3k44r8z90m35hm8945vsp95h1-glibc-2.27
+            "
+        );
+        let nix_query_tree = NixQueryTree::from_str(raw_tree).unwrap();
+        let duplicates = find_duplicates(&nix_query_tree.path_map());
+
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(
+            duplicates[0].variants[0].drv,
+            NixQueryDrv::from(
+                "/nix/store/681354n3k44r8z90m35hm8945vsp95h1-glibc-2.27"
+            )
+        );
+    }
+
+    #[test]
+    fn test_find_duplicates_sorted_by_version() {
+        let raw_tree = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.10
+            +---/nix/store/681354n3k44r8z90m35hm8945vsp95h1-glibc-2.9
+            "
+        );
+        let nix_query_tree = NixQueryTree::from_str(raw_tree).unwrap();
+        let duplicates = find_duplicates(&nix_query_tree.path_map());
+
+        let versions: Vec<Option<String>> = duplicates[0]
+            .variants
+            .iter()
+            .map(|variant| variant.version.clone())
+            .collect();
+        assert_eq!(
+            versions,
+            vec![Some(String::from("2.9")), Some(String::from("2.10"))]
+        );
+    }
+}
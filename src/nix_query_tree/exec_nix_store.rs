@@ -1,8 +1,16 @@
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::io::{self, BufReader, Read};
 use std::path::{Path, PathBuf};
-use std::process::{Command, Output};
+use std::process::{Child, Command, Stdio};
+use std::thread;
 
-use super::parsing;
-use super::{NixQueryEntry, NixQueryPathMap, NixQueryTree};
+#[cfg(feature = "serde")]
+use super::cache::{self, Cache};
+use super::narinfo::{self, BinaryCache, NarInfo};
+use super::parsing::{self, SkippedLine};
+use super::stream_parsing::{self, StreamParseErr};
+use super::{NixQueryDrv, NixQueryEntry, NixQueryPathMap, NixQueryTree};
 use crate::tree;
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -10,38 +18,93 @@ pub enum NixStoreErr {
     CommandErr(String),
     Utf8Err(String),
     NixStoreErr(String),
-    ParseErr(String),
+    /// The output from `nix-store` that couldn't be parsed, along with where parsing failed.
+    ParseErr(String, parsing::ParseError),
+    /// The output from `nix-store` couldn't be parsed, and it wasn't kept.  See `KeepRaw`.
+    StreamParseErr(StreamParseErr),
+    /// An error reading the `.narinfo` files of a `file://` binary cache.
+    BinaryCacheErr(String),
+    /// An error evaluating a flake reference or attribute to the store path to query.
+    EvalErr(String),
 }
 
 impl std::fmt::Display for NixStoreErr {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let string = match self {
-            NixStoreErr::CommandErr(string) => string,
-            NixStoreErr::Utf8Err(string) => string,
-            NixStoreErr::NixStoreErr(string) => string,
-            NixStoreErr::ParseErr(string) => string,
+            NixStoreErr::CommandErr(string)
+            | NixStoreErr::Utf8Err(string)
+            | NixStoreErr::NixStoreErr(string)
+            | NixStoreErr::BinaryCacheErr(string)
+            | NixStoreErr::EvalErr(string) => string,
+            NixStoreErr::ParseErr(_, parse_err) => {
+                return write!(f, "Error parsing the output: {}", parse_err);
+            }
+            NixStoreErr::StreamParseErr(stream_parse_err) => {
+                return write!(
+                    f,
+                    "Error parsing the output: {}",
+                    stream_parse_err
+                );
+            }
         };
         write!(f, "{}", string)
     }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct NixStoreRes {
-    pub raw: String,
+    /// The output from `nix-store`, if it was kept.  See `KeepRaw`.
+    pub raw: Option<String>,
     pub tree: NixQueryTree,
     pub map: NixQueryPathMap,
+    /// Lines in `raw` that were skipped because they aren't part of the tree.
+    pub skipped: Vec<SkippedLine>,
+    /// The `.narinfo` of every store path in `tree`, when it was read from a binary cache.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub narinfos: HashMap<NixQueryDrv, NarInfo>,
 }
 
 impl NixStoreRes {
+    #[must_use]
     pub fn new(raw: &str, tree: NixQueryTree) -> Self {
         let map: NixQueryPathMap = tree.path_map();
         NixStoreRes {
-            raw: String::from(raw),
+            raw: Some(String::from(raw)),
             tree,
             map,
+            skipped: vec![],
+            narinfos: HashMap::new(),
         }
     }
 
+    /// A warning about the lines that were skipped when parsing, if there were any.
+    pub fn skipped_warning(&self) -> Option<String> {
+        const MAX_SHOWN: usize = 3;
+
+        if self.skipped.is_empty() {
+            return None;
+        }
+        let shown: Vec<String> = self
+            .skipped
+            .iter()
+            .take(MAX_SHOWN)
+            .map(SkippedLine::to_string)
+            .collect();
+        let more = if self.skipped.len() > MAX_SHOWN {
+            ", ..."
+        } else {
+            ""
+        };
+        Some(format!(
+            "Skipped {} lines that aren't part of the tree: {}{}",
+            self.skipped.len(),
+            shown.join(", "),
+            more
+        ))
+    }
+
+    #[must_use]
     pub fn lookup_first_query_entry(
         &self,
         nix_query_entry: &NixQueryEntry,
@@ -50,46 +113,258 @@ impl NixStoreRes {
     }
 }
 
+/// Whether to keep the raw output from `nix-store` in `NixStoreRes::raw`.
+///
+/// The output for a large closure can be tens of megabytes, so it is only worth keeping when
+/// it is going to be shown.  Without it, the output is parsed one line at a time and never
+/// held in memory as a whole.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum KeepRaw {
+    Yes,
+    No,
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct ExecNixStoreRes {
     pub nix_store_path: PathBuf,
+    /// The store that was queried, if it wasn't the default one.  See `run`.
+    pub store_uri: Option<String>,
     pub res: Result<NixStoreRes, NixStoreErr>,
+    /// Whether `res` came from the on-disk cache instead of running `nix-store`.
+    pub from_cache: bool,
+    /// Why the on-disk cache couldn't be read, if it couldn't.  `res` then comes from running
+    /// `nix-store`.
+    pub cache_warning: Option<String>,
 }
 
 impl ExecNixStoreRes {
+    #[must_use]
     pub fn new(
         nix_store_path: &Path,
         res: Result<NixStoreRes, NixStoreErr>,
     ) -> Self {
         ExecNixStoreRes {
             nix_store_path: nix_store_path.to_path_buf(),
+            store_uri: None,
             res,
+            from_cache: false,
+            cache_warning: None,
         }
     }
 }
 
-fn nix_store_res(nix_store_path: &Path) -> Result<NixStoreRes, NixStoreErr> {
-    let nix_store_output: Output = Command::new("nix-store")
-        .args(&["--query", "--tree", &nix_store_path.to_string_lossy()])
-        .output()
+/// The arguments to pass to `nix-store` to query the tree for `nix_store_path`.
+fn nix_store_args(
+    nix_store_path: &Path,
+    store_uri: Option<&str>,
+) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec![
+        "--query".into(),
+        "--tree".into(),
+        nix_store_path.as_os_str().to_os_string(),
+    ];
+    if let Some(store_uri) = store_uri {
+        args.push("--store".into());
+        args.push(store_uri.into());
+    }
+    args
+}
+
+fn nix_store_res(
+    nix_store_path: &Path,
+    store_uri: Option<&str>,
+    keep_raw: KeepRaw,
+) -> Result<NixStoreRes, NixStoreErr> {
+    let mut child: Child = Command::new("nix-store")
+        .args(nix_store_args(nix_store_path, store_uri))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
         .map_err(|io_err| NixStoreErr::CommandErr(io_err.to_string()))?;
 
-    if nix_store_output.status.success() {
-        let stdout = from_utf8(nix_store_output.stdout)?;
-        parsing::nix_query_tree_parser(&stdout)
-            .map(|nix_query_tree| NixStoreRes::new(&stdout, nix_query_tree))
-            .map_err(|nom_err| NixStoreErr::ParseErr(nom_err.to_string()))
-    } else {
-        let stderr = from_utf8(nix_store_output.stderr)?;
-        Err(NixStoreErr::NixStoreErr(stderr))
+    // stderr is read on its own thread, so that `nix-store` can't block on a full stderr pipe
+    // while stdout is being parsed.
+    let mut child_stderr = child.stderr.take().expect("stderr is piped");
+    let stderr_thread = thread::spawn(move || {
+        let mut stderr = vec![];
+        child_stderr.read_to_end(&mut stderr).map(|_| stderr)
+    });
+
+    let mut stdout =
+        BufReader::new(child.stdout.take().expect("stdout is piped"));
+    let mut option_raw = match keep_raw {
+        KeepRaw::Yes => Some(String::new()),
+        KeepRaw::No => None,
+    };
+    let parse_res = stream_parsing::read_nix_query_tree_lenient(
+        &mut stdout,
+        option_raw.as_mut(),
+    );
+    // Read anything left after a parse error, so `nix-store` can exit and the whole output
+    // can be shown.
+    let rest_res = match &mut option_raw {
+        Some(raw) => stdout.read_to_string(raw).map(|_| ()),
+        None => io::copy(&mut stdout, &mut io::sink()).map(|_| ()),
+    };
+
+    let status = child
+        .wait()
+        .map_err(|io_err| NixStoreErr::CommandErr(io_err.to_string()))?;
+    let stderr = stderr_thread
+        .join()
+        .expect("reading stderr doesn't panic")
+        .map_err(|io_err| NixStoreErr::CommandErr(io_err.to_string()))?;
+
+    if !status.success() {
+        return Err(NixStoreErr::NixStoreErr(from_utf8(stderr)?));
+    }
+    rest_res.map_err(|io_err| NixStoreErr::Utf8Err(io_err.to_string()))?;
+
+    match (parse_res, option_raw) {
+        (Ok((nix_query_tree, skipped)), option_raw) => {
+            let map = nix_query_tree.path_map();
+            Ok(NixStoreRes {
+                raw: option_raw,
+                tree: nix_query_tree,
+                map,
+                skipped,
+                narinfos: HashMap::new(),
+            })
+        }
+        (Err(StreamParseErr::IoErr(string)), _) => {
+            Err(NixStoreErr::Utf8Err(string))
+        }
+        // The streaming parser only knows which line is wrong, so the nom parser is run on
+        // the output to find the column and what was expected there.
+        (Err(StreamParseErr::ParseErr(_, _)), Some(raw)) => {
+            parsing::nix_query_tree_parser_lenient(&raw)
+                .map(|(nix_query_tree, skipped)| NixStoreRes {
+                    skipped,
+                    ..NixStoreRes::new(&raw, nix_query_tree)
+                })
+                .map_err(|parse_err| NixStoreErr::ParseErr(raw, parse_err))
+        }
+        (Err(stream_parse_err), None) => {
+            Err(NixStoreErr::StreamParseErr(stream_parse_err))
+        }
+    }
+}
+
+/// Run `nix-store --query --tree` for the given nix store path, and cache the result if it
+/// succeeds.
+///
+/// Without the `serde` feature there is no cache, and this just runs `nix-store`.
+#[cfg(feature = "serde")]
+fn run_and_cache(
+    nix_store_path: &Path,
+    store_uri: Option<&str>,
+    keep_raw: KeepRaw,
+) -> Result<NixStoreRes, NixStoreErr> {
+    let res = nix_store_res(nix_store_path, store_uri, keep_raw);
+    if let (Ok(nix_store_res), Some(cache), Some(key)) = (
+        &res,
+        Cache::open_default(),
+        cache::cache_key(nix_store_path, store_uri),
+    ) {
+        // Failing to write to the cache only means the next run is slower, so it isn't
+        // reported.
+        let _ = cache.put(&key, nix_store_res);
     }
+    res
+}
+
+#[cfg(not(feature = "serde"))]
+fn run_and_cache(
+    nix_store_path: &Path,
+    store_uri: Option<&str>,
+    keep_raw: KeepRaw,
+) -> Result<NixStoreRes, NixStoreErr> {
+    nix_store_res(nix_store_path, store_uri, keep_raw)
+}
+
+/// Read the tree for `nix_store_path` from the `.narinfo` files in the binary cache in
+/// `dir`.
+fn binary_cache_res(
+    nix_store_path: &Path,
+    dir: &Path,
+) -> Result<NixStoreRes, NixStoreErr> {
+    BinaryCache::open(dir)
+        .and_then(|binary_cache| binary_cache.nix_store_res(nix_store_path))
+        .map_err(|err| NixStoreErr::BinaryCacheErr(err.to_string()))
 }
 
 /// Run `nix-store --query --tree` for the given nix store path.
-pub fn run(nix_store_path: &Path) -> ExecNixStoreRes {
+///
+/// `store_uri` is passed to `nix-store --store`, so it can be any store that `nix-store`
+/// understands, like `ssh://host` or `local?root=/mnt`.  Without it, the default store
+/// (usually the local nix daemon) is queried.
+///
+/// A `file:///path/to/binary-cache` store URI is read directly instead, without running
+/// `nix-store`.  See `narinfo::BinaryCache`.
+///
+/// If there is a result for the same store path in the on-disk cache, it is used instead of
+/// running `nix-store` again.  A cached result without the raw output isn't used when
+/// `keep_raw` asks for it.  If the cached result can't be read, `nix-store` is run and the
+/// reason is put in `cache_warning`.
+pub fn run(
+    nix_store_path: &Path,
+    store_uri: Option<&str>,
+    keep_raw: KeepRaw,
+) -> ExecNixStoreRes {
+    let exec_nix_store_res = |res, from_cache| ExecNixStoreRes {
+        store_uri: store_uri.map(String::from),
+        from_cache,
+        ..ExecNixStoreRes::new(nix_store_path, res)
+    };
+
+    // Reading a local binary cache is about as fast as reading the on-disk cache.
+    if let Some(dir) = store_uri.and_then(narinfo::binary_cache_dir) {
+        return exec_nix_store_res(
+            binary_cache_res(nix_store_path, &dir),
+            false,
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    let cache_warning = match Cache::open_default()
+        .zip(cache::cache_key(nix_store_path, store_uri))
+        .map_or(Ok(None), |(cache, key)| cache.get(&key))
+    {
+        Ok(Some(nix_store_res))
+            if keep_raw == KeepRaw::No || nix_store_res.raw.is_some() =>
+        {
+            return exec_nix_store_res(Ok(nix_store_res), true);
+        }
+        Ok(_) => None,
+        Err(cache_err) => {
+            Some(format!("Couldn't read the cached result: {}", cache_err))
+        }
+    };
+    #[cfg(not(feature = "serde"))]
+    let cache_warning = None;
+    ExecNixStoreRes {
+        cache_warning,
+        ..exec_nix_store_res(
+            run_and_cache(nix_store_path, store_uri, keep_raw),
+            false,
+        )
+    }
+}
+
+/// Like `run`, but always run `nix-store`, even if there is a cached result.  The cached
+/// result is replaced with the new one.
+pub fn refresh(
+    nix_store_path: &Path,
+    store_uri: Option<&str>,
+    keep_raw: KeepRaw,
+) -> ExecNixStoreRes {
+    let res = match store_uri.and_then(narinfo::binary_cache_dir) {
+        Some(dir) => binary_cache_res(nix_store_path, &dir),
+        None => run_and_cache(nix_store_path, store_uri, keep_raw),
+    };
     ExecNixStoreRes {
-        nix_store_path: nix_store_path.to_path_buf(),
-        res: nix_store_res(nix_store_path),
+        store_uri: store_uri.map(String::from),
+        ..ExecNixStoreRes::new(nix_store_path, res)
     }
 }
 
@@ -98,3 +373,33 @@ fn from_utf8(i: Vec<u8>) -> Result<String, NixStoreErr> {
     String::from_utf8(i)
         .map_err(|utf8_err| NixStoreErr::Utf8Err(utf8_err.to_string()))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nix_store_args() {
+        let nix_store_path =
+            Path::new("/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10");
+
+        assert_eq!(
+            nix_store_args(nix_store_path, None),
+            vec![
+                OsString::from("--query"),
+                OsString::from("--tree"),
+                nix_store_path.as_os_str().to_os_string(),
+            ]
+        );
+        assert_eq!(
+            nix_store_args(nix_store_path, Some("file:///tmp/binary-cache")),
+            vec![
+                OsString::from("--query"),
+                OsString::from("--tree"),
+                nix_store_path.as_os_str().to_os_string(),
+                OsString::from("--store"),
+                OsString::from("file:///tmp/binary-cache"),
+            ]
+        );
+    }
+}
@@ -0,0 +1,748 @@
+//! Find the garbage collector roots that keep a store path alive, and the chain of references
+//! from each root down to the store path.
+//!
+//! The roots come from `nix-store --query --roots`.  When that fails (for instance because the
+//! nix daemon isn't reachable), the symlinks in `/nix/var/nix/gcroots` and
+//! `/nix/var/nix/profiles` are scanned instead, the same way the garbage collector finds them.
+//! Entries that can't be read while scanning are skipped with a warning.
+//!
+//! The chains are found in the store paths that refer to the store path, directly or not,
+//! from `nix-store --query --referrers-closure`.  The references between them come from
+//! `nix-store --dump-db`, which lists the references of every store path it is given.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::ffi::OsStr;
+use std::io;
+use std::path::{Component, Path};
+use std::process::Command;
+
+use super::NixQueryDrv;
+
+/// The directories that are scanned for roots when `nix-store --query --roots` fails.
+const ROOT_DIRS: [&str; 2] = ["/nix/var/nix/gcroots", "/nix/var/nix/profiles"];
+
+const STORE_DIR: &str = "/nix/store";
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum GcRootsErr {
+    CommandErr(String),
+    NixStoreErr(String),
+    IoErr(String),
+}
+
+impl std::fmt::Display for GcRootsErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            GcRootsErr::CommandErr(string)
+            | GcRootsErr::NixStoreErr(string)
+            | GcRootsErr::IoErr(string) => string,
+        };
+        write!(f, "{}", string)
+    }
+}
+
+impl From<std::io::Error> for GcRootsErr {
+    fn from(err: std::io::Error) -> Self {
+        GcRootsErr::IoErr(err.to_string())
+    }
+}
+
+/// A garbage collector root.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct GcRoot {
+    /// What holds on to the store path, like `/nix/var/nix/profiles/system-42-link` or the
+    /// `result` symlink of a build.  Running processes show up as something like
+    /// `/proc/1234/maps`, or `{censored}` when they belong to another user.
+    pub link: String,
+    /// The store path that is kept alive directly.
+    pub target: NixQueryDrv,
+}
+
+/// A garbage collector root, and the chain of store paths from it to the store path that it
+/// keeps alive.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RootChain {
+    pub root: GcRoot,
+    /// The store paths from `root.target` down to the store path being kept alive, each one
+    /// referencing the next.
+    pub chain: Vec<NixQueryDrv>,
+}
+
+/// The garbage collector roots keeping a store path alive.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GcRootsRes {
+    pub root_chains: Vec<RootChain>,
+    /// The entries under the root directories that couldn't be read, and why, like
+    /// `/nix/var/nix/gcroots/per-user/bob: Permission denied (os error 13)`, and the roots
+    /// that no chain could be found for.
+    pub warnings: Vec<String>,
+}
+
+impl GcRootsRes {
+    /// A warning about the entries and roots that were skipped, if there were any.
+    pub fn skipped_warning(&self) -> Option<String> {
+        const MAX_SHOWN: usize = 3;
+
+        if self.warnings.is_empty() {
+            return None;
+        }
+        let shown: Vec<&str> = self
+            .warnings
+            .iter()
+            .take(MAX_SHOWN)
+            .map(String::as_str)
+            .collect();
+        let more = if self.warnings.len() > MAX_SHOWN {
+            ", ..."
+        } else {
+            ""
+        };
+        Some(format!(
+            "Skipped {} entries that couldn't be read or followed: {}{}",
+            self.warnings.len(),
+            shown.join(", "),
+            more
+        ))
+    }
+}
+
+impl RootChain {
+    /// The chain in one line, like `/home/me/result → hello-2.10 → glibc-2.27`.
+    #[must_use]
+    pub fn summary(&self) -> String {
+        let mut summary = self.root.link.clone();
+        for drv in &self.chain {
+            summary.push_str(" → ");
+            summary.push_str(&drv.drv_name());
+        }
+        summary
+    }
+}
+
+/// Sort roots by their link, and remove the duplicates.
+fn sort_roots(roots: &mut Vec<GcRoot>) {
+    roots.sort_by(|a, b| {
+        a.link
+            .cmp(&b.link)
+            .then_with(|| a.target.cmp_hash(&b.target))
+    });
+    roots.dedup();
+}
+
+/// The store path that `path` is in, if it is in the store.  `/nix/store/xxx-foo/bin/foo` is
+/// in `/nix/store/xxx-foo`.
+fn store_path_of(path: &Path) -> Option<NixQueryDrv> {
+    let rest = path.strip_prefix(STORE_DIR).ok()?;
+    match rest.components().next() {
+        Some(Component::Normal(name)) => {
+            Some(NixQueryDrv::from(&Path::new(STORE_DIR).join(name)))
+        }
+        _ => None,
+    }
+}
+
+/// Parse the output of `nix-store --query --roots`.  Each line looks like
+/// `/nix/var/nix/profiles/default-1-link -> /nix/store/...-user-environment`.
+///
+/// ```
+/// use nix_query_tree_viewer::nix_query_tree::gc_roots::parse_roots;
+///
+/// let roots = parse_roots(
+///     "/home/me/result -> /nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10\n",
+/// );
+/// assert_eq!(roots[0].link, "/home/me/result");
+/// assert_eq!(
+///     roots[0].target.to_string(),
+///     "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10"
+/// );
+/// ```
+#[must_use]
+pub fn parse_roots(output: &str) -> Vec<GcRoot> {
+    let mut roots: Vec<GcRoot> = output
+        .lines()
+        .filter_map(|line| {
+            let (link, target) = match line.rfind(" -> ") {
+                Some(i) => (&line[..i], &line[i + 4..]),
+                // Older versions of nix only print the store paths that are roots.
+                None => (line, line),
+            };
+            Some(GcRoot {
+                link: String::from(link),
+                target: store_path_of(Path::new(target))?,
+            })
+        })
+        .collect();
+    sort_roots(&mut roots);
+    roots
+}
+
+/// Parse the output of `nix-store --dump-db`, returning the references of each store path
+/// in it.  Each store path is followed by its hash, size, deriver, and number of references,
+/// and then by the references themselves, each on its own line.
+fn parse_dump_db(
+    output: &str,
+) -> Result<HashMap<NixQueryDrv, Vec<NixQueryDrv>>, GcRootsErr> {
+    let parse_err = |msg: &str| {
+        GcRootsErr::NixStoreErr(format!(
+            "couldn't parse the output of nix-store --dump-db: {}",
+            msg
+        ))
+    };
+    let mut lines = output.lines();
+    let mut references = HashMap::new();
+    while let Some(line) = lines.next() {
+        let drv = store_path_of(Path::new(line)).ok_or_else(|| {
+            parse_err(&format!("expecting a store path at {}", line))
+        })?;
+        // The hash, size and deriver aren't needed.
+        let ref_count: usize = lines
+            .nth(3)
+            .and_then(|line| line.parse().ok())
+            .ok_or_else(|| {
+                parse_err(&format!(
+                    "expecting the number of references of {}",
+                    line
+                ))
+            })?;
+        let drv_references = lines
+            .by_ref()
+            .take(ref_count)
+            .map(|line| store_path_of(Path::new(line)))
+            .collect::<Option<Vec<NixQueryDrv>>>()
+            .filter(|drv_references| drv_references.len() == ref_count)
+            .ok_or_else(|| {
+                parse_err(&format!(
+                    "expecting {} references of {}",
+                    ref_count, line
+                ))
+            })?;
+        references.insert(drv, drv_references);
+    }
+    Ok(references)
+}
+
+/// Run `nix-store` with `args`, returning its output.
+fn run_nix_store<I, S>(
+    args: I,
+    store_uri: Option<&str>,
+) -> Result<String, GcRootsErr>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let mut command = Command::new("nix-store");
+    command.args(args);
+    if let Some(store_uri) = store_uri {
+        command.arg("--store").arg(store_uri);
+    }
+    let output = command
+        .output()
+        .map_err(|io_err| GcRootsErr::CommandErr(io_err.to_string()))?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(GcRootsErr::NixStoreErr(
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ))
+    }
+}
+
+/// Run `nix-store --query --roots` for `drv`.
+///
+/// # Errors
+///
+/// This fails if `nix-store` can't be run, or exits with an error.
+pub fn query_roots(
+    drv: &NixQueryDrv,
+    store_uri: Option<&str>,
+) -> Result<Vec<GcRoot>, GcRootsErr> {
+    let output = run_nix_store(
+        [
+            OsStr::new("--query"),
+            OsStr::new("--roots"),
+            drv.as_os_str(),
+        ],
+        store_uri,
+    )?;
+    Ok(parse_roots(&output))
+}
+
+/// Run `nix-store --query --referrers-closure` for `drv`, returning the store paths that
+/// refer to `drv`, directly or not, including `drv` itself.
+///
+/// # Errors
+///
+/// This fails if `nix-store` can't be run, or exits with an error.
+pub fn query_referrers_closure(
+    drv: &NixQueryDrv,
+    store_uri: Option<&str>,
+) -> Result<HashSet<NixQueryDrv>, GcRootsErr> {
+    let output = run_nix_store(
+        [
+            OsStr::new("--query"),
+            OsStr::new("--referrers-closure"),
+            drv.as_os_str(),
+        ],
+        store_uri,
+    )?;
+    Ok(output
+        .lines()
+        .filter_map(|line| store_path_of(Path::new(line)))
+        .collect())
+}
+
+/// Run `nix-store --dump-db` for `drvs`, returning the references of each of them.
+fn query_references(
+    drvs: &HashSet<NixQueryDrv>,
+    store_uri: Option<&str>,
+) -> Result<HashMap<NixQueryDrv, Vec<NixQueryDrv>>, GcRootsErr> {
+    let args = std::iter::once(OsStr::new("--dump-db"))
+        .chain(drvs.iter().map(|drv| drv.as_os_str()));
+    parse_dump_db(&run_nix_store(args, store_uri)?)
+}
+
+/// Whether scanning should carry on after `err`.  Entries can disappear while they are
+/// being scanned, and the roots of other users can't always be read.
+fn is_skippable(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::NotFound | io::ErrorKind::PermissionDenied
+    )
+}
+
+/// Find the roots under `path` the same way the garbage collector does.  Directories are
+/// searched recursively.  A symlink into the store is a root, and so is a symlink to another
+/// symlink into the store, like the links in `gcroots/auto` that point to `result` symlinks.
+///
+/// Entries that can't be read are added to `warnings` and skipped.
+fn find_roots(
+    path: &Path,
+    roots: &mut Vec<GcRoot>,
+    warnings: &mut Vec<String>,
+) -> io::Result<()> {
+    match find_roots_unchecked(path, roots, warnings) {
+        Err(err) if is_skippable(&err) => {
+            warnings.push(format!("{}: {}", path.display(), err));
+            Ok(())
+        }
+        res => res,
+    }
+}
+
+fn find_roots_unchecked(
+    path: &Path,
+    roots: &mut Vec<GcRoot>,
+    warnings: &mut Vec<String>,
+) -> io::Result<()> {
+    let metadata = std::fs::symlink_metadata(path)?;
+    if metadata.is_dir() {
+        for dir_entry in std::fs::read_dir(path)? {
+            find_roots(&dir_entry?.path(), roots, warnings)?;
+        }
+    } else if metadata.file_type().is_symlink() {
+        let target = std::fs::read_link(path)?;
+        if let Some(drv) = store_path_of(&target) {
+            roots.push(GcRoot {
+                link: path.display().to_string(),
+                target: drv,
+            });
+        } else {
+            let target = path
+                .parent()
+                .map_or(target.clone(), |dir| dir.join(&target));
+            // Stale links to `result` symlinks that have since been deleted are skipped.
+            let is_symlink = std::fs::symlink_metadata(&target)
+                .is_ok_and(|metadata| metadata.file_type().is_symlink());
+            if is_symlink {
+                if let Some(drv) = store_path_of(&std::fs::read_link(&target)?)
+                {
+                    roots.push(GcRoot {
+                        link: target.display().to_string(),
+                        target: drv,
+                    });
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Scan `dirs` for garbage collector roots, like `/nix/var/nix/gcroots`.  Directories that
+/// don't exist are skipped.  This returns the roots, and a warning for each entry that
+/// couldn't be read.
+///
+/// # Errors
+///
+/// This fails on errors other than an entry being missing or unreadable.
+pub fn scan_root_dirs(
+    dirs: &[&Path],
+) -> Result<(Vec<GcRoot>, Vec<String>), GcRootsErr> {
+    let mut roots = vec![];
+    let mut warnings = vec![];
+    for dir in dirs {
+        if dir.exists() {
+            find_roots(dir, &mut roots, &mut warnings)?;
+        }
+    }
+    sort_roots(&mut roots);
+    Ok((roots, warnings))
+}
+
+/// For each store path that refers to `drv`, directly or not, the next store path on the
+/// shortest chain of references from it down to `drv`.  `references` holds the references of
+/// each store path.
+fn next_in_chains(
+    drv: &NixQueryDrv,
+    references: &HashMap<NixQueryDrv, Vec<NixQueryDrv>>,
+) -> HashMap<NixQueryDrv, NixQueryDrv> {
+    let mut referrers: HashMap<&NixQueryDrv, Vec<&NixQueryDrv>> =
+        HashMap::new();
+    for (referrer, drv_references) in references {
+        for reference in drv_references {
+            referrers.entry(reference).or_default().push(referrer);
+        }
+    }
+    // Sorted, so that the same chain is picked every time when there are several that are
+    // equally short.
+    for drv_referrers in referrers.values_mut() {
+        drv_referrers.sort_by(|a, b| a.cmp_hash(b));
+    }
+
+    // A breadth-first search up from `drv`, so each store path is reached by a shortest
+    // chain first.
+    let mut next = HashMap::new();
+    let mut queue = VecDeque::from(vec![drv]);
+    while let Some(reference) = queue.pop_front() {
+        for &referrer in referrers.get(reference).into_iter().flatten() {
+            if referrer != drv && !next.contains_key(referrer) {
+                next.insert(referrer.clone(), reference.clone());
+                queue.push_back(referrer);
+            }
+        }
+    }
+    next
+}
+
+/// The chain of store paths from `target` down to `drv`, following `next_in_chains`, or
+/// `None` if `target` doesn't refer to `drv`.
+fn chain_from(
+    target: &NixQueryDrv,
+    drv: &NixQueryDrv,
+    next: &HashMap<NixQueryDrv, NixQueryDrv>,
+) -> Option<Vec<NixQueryDrv>> {
+    let mut chain = vec![target.clone()];
+    let mut curr = target;
+    while curr != drv {
+        curr = next.get(curr)?;
+        chain.push(curr.clone());
+    }
+    Some(chain)
+}
+
+/// Find the garbage collector roots that keep `drv` alive, along with the shortest chain of
+/// references from each root to `drv`.
+///
+/// The chains are found by running `nix-store` twice, no matter how many roots there are.
+/// Roots found by scanning that don't keep `drv` alive are left out.  A root that no chain
+/// can be found for is skipped with a warning.
+///
+/// # Errors
+///
+/// This fails if the roots can't be found, or the store paths referring to `drv` or their
+/// references can't be queried.
+pub fn root_chains(
+    drv: &NixQueryDrv,
+    store_uri: Option<&str>,
+) -> Result<GcRootsRes, GcRootsErr> {
+    let (roots, mut warnings) = match query_roots(drv, store_uri) {
+        Ok(roots) => (roots, vec![]),
+        Err(_) if store_uri.is_none() => {
+            let dirs: Vec<&Path> = ROOT_DIRS.iter().map(Path::new).collect();
+            scan_root_dirs(&dirs)?
+        }
+        Err(err) => return Err(err),
+    };
+
+    let referrers = query_referrers_closure(drv, store_uri)?;
+    let roots: Vec<GcRoot> = roots
+        .into_iter()
+        .filter(|root| referrers.contains(&root.target))
+        .collect();
+    if roots.is_empty() {
+        return Ok(GcRootsRes {
+            root_chains: vec![],
+            warnings,
+        });
+    }
+    let next = next_in_chains(drv, &query_references(&referrers, store_uri)?);
+
+    let mut root_chains = vec![];
+    for root in roots {
+        match chain_from(&root.target, drv, &next) {
+            Some(chain) => root_chains.push(RootChain { root, chain }),
+            None => warnings.push(format!(
+                "{}: no chain of references from {} to {}",
+                root.link, root.target, drv
+            )),
+        }
+    }
+    Ok(GcRootsRes {
+        root_chains,
+        warnings,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use indoc::indoc;
+
+    #[test]
+    fn test_parse_roots() {
+        let output = indoc!(
+            "/nix/var/nix/profiles/default-1-link -> /nix/store/xa0pvm3qfhvgmjm1rmnk8z3pz9i05l9j-user-environment
+            /proc/1234/maps -> /nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27/lib/libc.so.6
+            {censored} -> /nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            "
+        );
+        assert_eq!(
+            parse_roots(output),
+            vec![
+                GcRoot {
+                    link: String::from("/nix/var/nix/profiles/default-1-link"),
+                    target: NixQueryDrv::from(
+                        "/nix/store/xa0pvm3qfhvgmjm1rmnk8z3pz9i05l9j-user-environment"
+                    ),
+                },
+                GcRoot {
+                    link: String::from("/proc/1234/maps"),
+                    target: NixQueryDrv::from(
+                        "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27"
+                    ),
+                },
+                GcRoot {
+                    link: String::from("{censored}"),
+                    target: NixQueryDrv::from(
+                        "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27"
+                    ),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_dump_db() {
+        let output = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            0a1b2c3d4e5f
+            206000
+            /nix/store/0p8pb3lbgdyvakiihnzvvh6cl8r9chbq-hello-2.10.drv
+            2
+            /nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            /nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            /nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            5f4e3d2c1b0a
+            28000000
+
+            0
+            "
+        );
+        let drv = |s: &str| NixQueryDrv::from(s);
+        let hello =
+            drv("/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10");
+        let glibc =
+            drv("/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27");
+        assert_eq!(
+            parse_dump_db(output),
+            Ok(vec![
+                (hello.clone(), vec![glibc.clone(), hello]),
+                (glibc, vec![]),
+            ]
+            .into_iter()
+            .collect())
+        );
+
+        // A store path with fewer references than it says it has.
+        assert!(parse_dump_db(indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            0a1b2c3d4e5f
+            206000
+
+            2
+            /nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            "
+        ))
+        .is_err());
+        assert!(parse_dump_db("hello\n").is_err());
+    }
+
+    #[test]
+    fn test_chains() {
+        let drv = |s: &str| NixQueryDrv::from(s);
+        let env =
+            drv("/nix/store/xa0pvm3qfhvgmjm1rmnk8z3pz9i05l9j-user-environment");
+        let hello =
+            drv("/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10");
+        let acl = drv("/nix/store/a9n6ip5d1m7b3blkxmmg0dd5z4cbqgvf-acl-2.2.53");
+        let glibc =
+            drv("/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27");
+        let bash = drv("/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-bash-4.4");
+        let references: HashMap<NixQueryDrv, Vec<NixQueryDrv>> = vec![
+            (env.clone(), vec![hello.clone()]),
+            (
+                hello.clone(),
+                vec![acl.clone(), glibc.clone(), hello.clone()],
+            ),
+            (acl.clone(), vec![glibc.clone()]),
+            (glibc.clone(), vec![glibc.clone()]),
+        ]
+        .into_iter()
+        .collect();
+        let next = next_in_chains(&glibc, &references);
+
+        // The direct reference from hello is shorter than the one through acl.
+        assert_eq!(
+            chain_from(&env, &glibc, &next),
+            Some(vec![env, hello, glibc.clone()])
+        );
+        assert_eq!(
+            chain_from(&glibc, &glibc, &next),
+            Some(vec![glibc.clone()])
+        );
+        assert_eq!(chain_from(&bash, &glibc, &next), None);
+    }
+
+    #[test]
+    fn test_scan_root_dirs() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let gcroots = dir.join("gcroots");
+        std::fs::create_dir_all(gcroots.join("auto")).unwrap();
+        let hello = "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10";
+        std::os::unix::fs::symlink(hello, gcroots.join("hello")).unwrap();
+        // An indirect root, like the ones `nix-build` makes for `result` symlinks.
+        let result = dir.join("result");
+        let glibc = "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27";
+        std::os::unix::fs::symlink(format!("{}/lib", glibc), &result).unwrap();
+        std::os::unix::fs::symlink(&result, gcroots.join("auto").join("abc"))
+            .unwrap();
+        // A stale indirect root.
+        std::os::unix::fs::symlink(
+            dir.join("deleted-result"),
+            gcroots.join("auto").join("def"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            scan_root_dirs(&[&gcroots, &dir.join("missing")]),
+            Ok((
+                vec![
+                    GcRoot {
+                        link: gcroots.join("hello").display().to_string(),
+                        target: NixQueryDrv::from(hello),
+                    },
+                    GcRoot {
+                        link: result.display().to_string(),
+                        target: NixQueryDrv::from(glibc),
+                    },
+                ],
+                vec![]
+            ))
+        );
+    }
+
+    #[test]
+    fn test_scan_root_dirs_unreadable() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let gcroots = dir.path().join("gcroots");
+        let per_user = gcroots.join("per-user");
+        std::fs::create_dir_all(&per_user).unwrap();
+        let hello = "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10";
+        std::os::unix::fs::symlink(hello, gcroots.join("hello")).unwrap();
+        std::fs::set_permissions(
+            &per_user,
+            std::fs::Permissions::from_mode(0o000),
+        )
+        .unwrap();
+        // root can read the directory anyway, so there is nothing to skip.
+        let readable = std::fs::read_dir(&per_user).is_ok();
+
+        let res = scan_root_dirs(&[&gcroots]);
+        std::fs::set_permissions(
+            &per_user,
+            std::fs::Permissions::from_mode(0o755),
+        )
+        .unwrap();
+
+        let (roots, warnings) = res.unwrap();
+        assert_eq!(
+            roots,
+            vec![GcRoot {
+                link: gcroots.join("hello").display().to_string(),
+                target: NixQueryDrv::from(hello),
+            }]
+        );
+        if !readable {
+            assert_eq!(warnings.len(), 1);
+            assert!(
+                warnings[0].starts_with(&format!("{}: ", per_user.display()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_find_roots_skips_missing() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing");
+        let mut roots = vec![];
+        let mut warnings = vec![];
+        assert!(find_roots(&missing, &mut roots, &mut warnings).is_ok());
+        assert!(roots.is_empty());
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with(&format!("{}: ", missing.display())));
+    }
+
+    #[test]
+    fn test_skipped_warning() {
+        let res = |warnings: Vec<&str>| GcRootsRes {
+            root_chains: vec![],
+            warnings: warnings.into_iter().map(String::from).collect(),
+        };
+        assert_eq!(res(vec![]).skipped_warning(), None);
+        assert_eq!(
+            res(vec!["/a: Permission denied", "/b: Permission denied"])
+                .skipped_warning(),
+            Some(String::from(
+                "Skipped 2 entries that couldn't be read or followed: /a: Permission denied, /b: Permission denied"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_summary() {
+        let root_chain = RootChain {
+            root: GcRoot {
+                link: String::from("/home/me/result"),
+                target: NixQueryDrv::from(
+                    "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10",
+                ),
+            },
+            chain: vec![
+                NixQueryDrv::from(
+                    "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10",
+                ),
+                NixQueryDrv::from(
+                    "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27",
+                ),
+            ],
+        };
+        assert_eq!(
+            root_chain.summary(),
+            "/home/me/result → hello-2.10 → glibc-2.27"
+        );
+    }
+}
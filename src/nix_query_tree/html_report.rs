@@ -0,0 +1,391 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use super::super::tree::{Path, Tree};
+use super::duplicates;
+use super::store_contents;
+use super::{
+    NixQueryDrv, NixQueryEntry, NixQueryPathMap, NixQueryTree, Recurse,
+};
+
+/// Extra information to show next to each store path in an HTML report.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Annotations {
+    /// The size of each store path.  Store paths that aren't in here don't get a size.
+    pub sizes: HashMap<NixQueryDrv, u64>,
+    /// A description of the other store paths for the same package, for each store path for
+    /// a package that appears in the tree with more than one store path.
+    pub duplicates: HashMap<NixQueryDrv, String>,
+}
+
+impl Annotations {
+    /// Find the size of every store path in the tree from the files on disk.  Store paths
+    /// that can't be read are skipped.
+    pub fn add_sizes(&mut self, path_map: &NixQueryPathMap) {
+        for (drv, _) in path_map.0.iter() {
+            if let Ok(size) = store_contents::disk_usage(drv) {
+                self.sizes.insert(drv.clone(), size);
+            }
+        }
+    }
+
+    /// Mark every store path for a package that appears with more than one store path.
+    pub fn add_duplicates(&mut self, path_map: &NixQueryPathMap) {
+        for duplicate in duplicates::find_duplicates(path_map) {
+            for variant in &duplicate.variants {
+                let others: Vec<String> = duplicate
+                    .variants
+                    .iter()
+                    .filter(|other| other.drv != variant.drv)
+                    .map(|other| other.drv.drv_name())
+                    .collect();
+                self.duplicates.insert(
+                    variant.drv.clone(),
+                    format!("also in the tree as {}", others.join(", ")),
+                );
+            }
+        }
+    }
+}
+
+const STYLE: &str = r"
+body { font-family: sans-serif; margin: 1em 2em; }
+header { position: sticky; top: 0; background: white; padding: 0.5em 0; border-bottom: 1px solid #ccc; }
+#filter { width: 30em; font-size: 1em; }
+#tree, #tree ul { list-style: none; padding-left: 1.5em; }
+#tree { padding-left: 0; font-family: monospace; }
+#tree li { margin: 0.1em 0; }
+#tree li.leaf { padding-left: 1.1em; }
+#tree summary { cursor: pointer; }
+#tree li:target > details > summary, #tree li:target > .drv { background: #ffe97f; }
+.size { color: #666; }
+.duplicate { color: #b35900; }
+.goto { color: #3366cc; }
+.filter-match > details > summary .drv, .filter-match > .drv { font-weight: bold; }
+";
+
+const SCRIPT: &str = r##"
+function openAncestors(element) {
+  for (let e = element; e; e = e.parentElement) {
+    if (e.tagName === "DETAILS") { e.open = true; }
+  }
+}
+function gotoHash() {
+  const element = document.getElementById(decodeURIComponent(location.hash.slice(1)));
+  if (element) { openAncestors(element); element.scrollIntoView(); }
+}
+window.addEventListener("hashchange", gotoHash);
+window.addEventListener("DOMContentLoaded", function () {
+  gotoHash();
+  // Items in reverse document order, so children always come before their parents.
+  const items = Array.from(document.querySelectorAll("#tree li")).reverse();
+  document.getElementById("filter").addEventListener("input", function (event) {
+    const query = event.target.value.toLowerCase();
+    for (const item of items) { item.childMatches = false; }
+    for (const item of items) {
+      const matches = query !== "" && item.dataset.path.toLowerCase().includes(query);
+      const visible = query === "" || matches || item.childMatches;
+      item.hidden = !visible;
+      item.classList.toggle("filter-match", matches);
+      const details = item.querySelector(":scope > details");
+      if (details && query !== "") { details.open = item.childMatches; }
+      const parent = item.parentElement.closest("li");
+      if (visible && parent) { parent.childMatches = true; }
+    }
+  });
+});
+"##;
+
+/// Escape text for including in HTML.
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The id of the HTML element for the node at `path` in the tree.
+fn node_id(path: &Path) -> String {
+    let mut id = String::from("n");
+    for index in &path.0 {
+        id.push('-');
+        id.push_str(&index.to_string());
+    }
+    id
+}
+
+struct Renderer<'a> {
+    path_map: &'a NixQueryPathMap,
+    annotations: &'a Annotations,
+    html: String,
+}
+
+impl Renderer<'_> {
+    fn write(&mut self, args: std::fmt::Arguments<'_>) {
+        self.html
+            .write_fmt(args)
+            .expect("writing to a String never fails");
+    }
+
+    /// Render `tree` and all of its descendants.  This walks the tree with an explicit stack
+    /// instead of recursing, so deep closures can't overflow the stack.
+    fn render_tree(&mut self, tree: &Tree<NixQueryEntry>) {
+        // The nodes from the root down to the current node, along with the index of the next
+        // child of each one to render.  `path` is the `Path` to the current node.
+        let mut stack: Vec<(&Tree<NixQueryEntry>, usize)> = vec![(tree, 0)];
+        let mut path = Path::new();
+        self.open_node(tree, &path);
+        while let Some((tree, next_child)) = stack.last_mut() {
+            if let Some(child) = tree.children.get(*next_child) {
+                path.push_back(*next_child);
+                *next_child += 1;
+                self.open_node(child, &path);
+                stack.push((child, 0));
+            } else {
+                let is_leaf = tree.children.is_empty();
+                stack.pop();
+                path.0.pop_back();
+                self.close_node(is_leaf);
+            }
+        }
+    }
+
+    /// Render the start of the node at `path`, up to where its children go.
+    fn open_node(&mut self, tree: &Tree<NixQueryEntry>, path: &Path) {
+        let drv: &NixQueryDrv = &tree.item.0;
+        let drv_str = escape(&drv.to_string());
+        let is_leaf = tree.children.is_empty();
+
+        self.write(format_args!(
+            "<li id=\"{}\" data-path=\"{}\"{}>",
+            node_id(path),
+            drv_str,
+            if is_leaf { " class=\"leaf\"" } else { "" }
+        ));
+        if !is_leaf {
+            // Only the root is expanded to start with.
+            let open = if path.0.is_empty() { " open" } else { "" };
+            self.write(format_args!("<details{}><summary>", open));
+        }
+
+        self.write(format_args!("<span class=\"drv\">{}</span>", drv_str));
+        self.render_annotations(drv);
+        if tree.item.1 == Recurse::Yes {
+            if let Some(first_path) = self.path_map.lookup_first(drv) {
+                self.write(format_args!(
+                    " <a class=\"goto\" href=\"#{}\" title=\"go to first instance\">[...]</a>",
+                    node_id(first_path)
+                ));
+            } else {
+                self.html.push_str(" [...]");
+            }
+        }
+
+        if !is_leaf {
+            self.html.push_str("</summary><ul>\n");
+        }
+    }
+
+    /// Render the end of a node, after its children.
+    fn close_node(&mut self, is_leaf: bool) {
+        if !is_leaf {
+            self.html.push_str("</ul></details>");
+        }
+        self.html.push_str("</li>\n");
+    }
+
+    fn render_annotations(&mut self, drv: &NixQueryDrv) {
+        if let Some(size) = self.annotations.sizes.get(drv) {
+            self.write(format_args!(
+                " <span class=\"size\">{}</span>",
+                escape(&store_contents::human_size(*size))
+            ));
+        }
+        if let Some(description) = self.annotations.duplicates.get(drv) {
+            self.write(format_args!(
+                " <span class=\"duplicate\" title=\"{}\">duplicate</span>",
+                escape(description)
+            ));
+        }
+    }
+}
+
+/// Render a `NixQueryTree` as a single, self-contained HTML page.
+///
+/// The page has a collapsible tree, links from each `[...]` entry to the first instance of
+/// that store path, and a box for filtering the tree by store path.  It doesn't load anything
+/// from the network, so it can be attached to a PR or an email.
+///
+/// ```
+/// use indoc::indoc;
+/// use nix_query_tree_viewer::nix_query_tree::html_report::{render, Annotations};
+/// use nix_query_tree_viewer::nix_query_tree::NixQueryTree;
+/// use std::str::FromStr;
+///
+/// let raw_tree = indoc!(
+///         "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+///         +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+///         |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+///         +---/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10 [...]
+///         "
+///     );
+/// let nix_query_tree = NixQueryTree::from_str(raw_tree).unwrap();
+/// let html = render(&nix_query_tree, &nix_query_tree.path_map(), &Annotations::default());
+///
+/// assert!(html.starts_with("<!DOCTYPE html>"));
+/// assert!(html.contains(r##"<a class="goto" href="#n-0" title="go to first instance">[...]</a>"##));
+/// ```
+#[must_use]
+pub fn render(
+    tree: &NixQueryTree,
+    path_map: &NixQueryPathMap,
+    annotations: &Annotations,
+) -> String {
+    let root = escape(&tree.0.item.0.to_string());
+    let unique_paths = path_map.0.iter().count();
+
+    let mut renderer = Renderer {
+        path_map,
+        annotations,
+        html: String::new(),
+    };
+    renderer.render_tree(&tree.0);
+
+    format!(
+        concat!(
+            "<!DOCTYPE html>\n",
+            "<html lang=\"en\">\n",
+            "<head>\n",
+            "<meta charset=\"utf-8\">\n",
+            "<title>{root}</title>\n",
+            "<style>{style}</style>\n",
+            "<script>{script}</script>\n",
+            "</head>\n",
+            "<body>\n",
+            "<header>\n",
+            "<h1>{root}</h1>\n",
+            "<p>{unique_paths} unique store paths.  Generated by {name} {version}.</p>\n",
+            "<input id=\"filter\" type=\"search\" placeholder=\"Filter store paths\" autofocus>\n",
+            "</header>\n",
+            "<ul id=\"tree\">\n{tree}</ul>\n",
+            "</body>\n",
+            "</html>\n",
+        ),
+        root = root,
+        style = STYLE,
+        script = SCRIPT,
+        unique_paths = unique_paths,
+        name = env!("CARGO_PKG_NAME"),
+        version = env!("CARGO_PKG_VERSION"),
+        tree = renderer.html,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use indoc::indoc;
+    use std::str::FromStr;
+
+    fn nix_query_tree() -> NixQueryTree {
+        let raw_tree = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            +---/nix/store/681354n3k44r8z90m35hm8945vsp95h1-glibc-2.30
+            "
+        );
+        NixQueryTree::from_str(raw_tree).unwrap()
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            escape(r#"<a href="x">'&'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;&#39;&amp;&#39;&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn test_render_tree() {
+        let nix_query_tree = nix_query_tree();
+        let html = render(
+            &nix_query_tree,
+            &nix_query_tree.path_map(),
+            &Annotations::default(),
+        );
+
+        assert!(html.contains(
+            "<li id=\"n\" data-path=\"/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10\"><details open><summary>"
+        ));
+        assert!(html.contains(
+            "<li id=\"n-0\" data-path=\"/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27\"><details><summary>"
+        ));
+        assert!(html.contains(
+            "<li id=\"n-0-0\" data-path=\"/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27\" class=\"leaf\"><span class=\"drv\">/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27</span> <a class=\"goto\" href=\"#n-0\" title=\"go to first instance\">[...]</a></li>"
+        ));
+        assert!(!html.contains("class=\"duplicate\""));
+        assert!(html.contains("<p>3 unique store paths."));
+    }
+
+    #[test]
+    fn test_render_annotations() {
+        let nix_query_tree = nix_query_tree();
+        let path_map = nix_query_tree.path_map();
+        let mut annotations = Annotations::default();
+        annotations.add_duplicates(&path_map);
+        annotations.sizes.insert(
+            NixQueryDrv::from(
+                "/nix/store/681354n3k44r8z90m35hm8945vsp95h1-glibc-2.30",
+            ),
+            2048,
+        );
+        let html = render(&nix_query_tree, &path_map, &annotations);
+
+        assert_eq!(annotations.duplicates.len(), 2);
+        assert!(html.contains(
+            "<span class=\"drv\">/nix/store/681354n3k44r8z90m35hm8945vsp95h1-glibc-2.30</span> <span class=\"size\">2.0 KiB</span> <span class=\"duplicate\" title=\"also in the tree as glibc-2.27\">duplicate</span></li>"
+        ));
+    }
+
+    #[test]
+    fn test_render_deep_tree() {
+        let depth = 3_000;
+        let mut tree = Tree::singleton(NixQueryEntry::new(
+            "/nix/store/681354n3k44r8z90m35hm8945vsp95h1-glibc-2.30",
+            Recurse::No,
+        ));
+        for _ in 0..depth {
+            tree = Tree::new(
+                NixQueryEntry::new(
+                    "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10",
+                    Recurse::No,
+                ),
+                vec![tree],
+            );
+        }
+        let nix_query_tree = NixQueryTree(tree);
+        let html = render(
+            &nix_query_tree,
+            &nix_query_tree.path_map(),
+            &Annotations::default(),
+        );
+
+        assert_eq!(html.matches("<li ").count(), depth + 1);
+        assert_eq!(html.matches("</li>").count(), depth + 1);
+        assert_eq!(html.matches("</details>").count(), depth);
+        assert!(html.contains(&format!(
+            "<li id=\"{}\" data-path=\"/nix/store/681354n3k44r8z90m35hm8945vsp95h1-glibc-2.30\" class=\"leaf\">",
+            node_id(&Path::from(vec![0; depth]))
+        )));
+    }
+}
@@ -0,0 +1,270 @@
+//! Things that can be given instead of a store path, like the flake reference `nixpkgs#hello`
+//! or `<nixpkgs> -A hello`.  These are evaluated with `nix eval` or `nix-instantiate` to find
+//! the store path to query.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum InstallableErr {
+    CommandErr(String),
+    /// `nix` or `nix-instantiate` failed, or didn't output a store path.
+    EvalErr(String),
+}
+
+impl std::fmt::Display for InstallableErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            InstallableErr::CommandErr(string)
+            | InstallableErr::EvalErr(string) => string,
+        };
+        write!(f, "{}", string)
+    }
+}
+
+/// Something to find the tree of.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Installable {
+    /// A path in the nix store, or a symlink to one like `./result`.
+    StorePath(PathBuf),
+    /// A flake output, like `nixpkgs#hello` or `.#packages.x86_64-linux.myapp`, or the default
+    /// package of a flake, like `nixpkgs` or `.`.
+    Flake(String),
+    /// An attribute of a nix expression, like `-A hello` of `<nixpkgs>`.
+    Attr { file: String, attr: String },
+}
+
+/// Whether `input` should be taken as a store path instead of a flake reference.  Flake
+/// references can be directories, like `.`, but not files.
+fn is_store_path(input: &str) -> bool {
+    let path = Path::new(input);
+    !input.contains('#')
+        && (path.is_absolute()
+            || std::fs::symlink_metadata(path)
+                .is_ok_and(|metadata| !metadata.is_dir()))
+}
+
+impl Installable {
+    /// The installable for `input`, with the attribute given with `-A`, if any.  Without an
+    /// attribute, `input` is a store path if it is an absolute path, or a file or symlink
+    /// that exists, like `./result`.  Anything else is a flake reference, and `nix eval`
+    /// reports it if it isn't a valid one.
+    ///
+    /// ```
+    /// use nix_query_tree_viewer::nix_query_tree::installable::Installable;
+    ///
+    /// assert_eq!(
+    ///     Installable::new("nixpkgs#hello", None),
+    ///     Installable::Flake(String::from("nixpkgs#hello"))
+    /// );
+    /// assert_eq!(
+    ///     Installable::new("nixpkgs", None),
+    ///     Installable::Flake(String::from("nixpkgs"))
+    /// );
+    /// assert_eq!(
+    ///     Installable::new("<nixpkgs>", Some("hello")),
+    ///     Installable::Attr {
+    ///         file: String::from("<nixpkgs>"),
+    ///         attr: String::from("hello"),
+    ///     }
+    /// );
+    /// ```
+    #[must_use]
+    pub fn new(input: &str, attr: Option<&str>) -> Self {
+        match attr {
+            Some(attr) => Installable::Attr {
+                file: String::from(input),
+                attr: String::from(attr),
+            },
+            None if is_store_path(input) => {
+                Installable::StorePath(PathBuf::from(input))
+            }
+            None => Installable::Flake(String::from(input)),
+        }
+    }
+
+    /// The installable typed into a search box, where an attribute is given like
+    /// `<nixpkgs> -A hello`.
+    #[must_use]
+    pub fn from_input(input: &str) -> Self {
+        let input = input.trim();
+        match input.find(" -A ") {
+            Some(i) => Installable::new(
+                input[..i].trim_end(),
+                Some(input[i + 4..].trim_start()),
+            ),
+            None => Installable::new(input, None),
+        }
+    }
+
+    #[must_use]
+    pub fn is_store_path(&self) -> bool {
+        matches!(self, Installable::StorePath(_))
+    }
+
+    /// Evaluate this to a store path with the `nix` and `nix-instantiate` on the `PATH`.  See
+    /// `Evaluator::resolve`.
+    ///
+    /// # Errors
+    ///
+    /// This fails in the same cases as `Evaluator::resolve`.
+    pub fn resolve(&self, out_path: bool) -> Result<PathBuf, InstallableErr> {
+        Evaluator::default().resolve(self, out_path)
+    }
+}
+
+impl std::fmt::Display for Installable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Installable::StorePath(path) => write!(f, "{}", path.display()),
+            Installable::Flake(flake_ref) => write!(f, "{}", flake_ref),
+            Installable::Attr { file, attr } => {
+                write!(f, "{} -A {}", file, attr)
+            }
+        }
+    }
+}
+
+/// The commands used to evaluate installables.  These can be pointed at fake executables in
+/// tests.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Evaluator {
+    pub nix: PathBuf,
+    pub nix_instantiate: PathBuf,
+}
+
+impl Default for Evaluator {
+    fn default() -> Self {
+        Evaluator {
+            nix: PathBuf::from("nix"),
+            nix_instantiate: PathBuf::from("nix-instantiate"),
+        }
+    }
+}
+
+/// Run `program` with `args`, returning its trimmed stdout.
+fn output(program: &Path, args: &[&str]) -> Result<String, InstallableErr> {
+    let output =
+        Command::new(program)
+            .args(args)
+            .output()
+            .map_err(|io_err| {
+                InstallableErr::CommandErr(format!(
+                    "Error running {}: {}",
+                    program.display(),
+                    io_err
+                ))
+            })?;
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        Err(InstallableErr::EvalErr(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ))
+    }
+}
+
+impl Evaluator {
+    /// Evaluate `installable` to the `.drv` file of the derivation, or with `out_path`, to its
+    /// output path.  The output path only has a tree once it has been built.
+    ///
+    /// Flake references are evaluated with `nix eval --raw --apply`, and attributes with
+    /// `nix-instantiate`.  Store paths are returned as they are.
+    ///
+    /// # Errors
+    ///
+    /// This fails if `nix` or `nix-instantiate` can't be run, exits with an error, or doesn't
+    /// print a store path.
+    pub fn resolve(
+        &self,
+        installable: &Installable,
+        out_path: bool,
+    ) -> Result<PathBuf, InstallableErr> {
+        let attr_name = if out_path { "outPath" } else { "drvPath" };
+        let stdout = match installable {
+            Installable::StorePath(path) => return Ok(path.clone()),
+            Installable::Flake(flake_ref) => output(
+                &self.nix,
+                &[
+                    "--extra-experimental-features",
+                    "nix-command flakes",
+                    "eval",
+                    "--raw",
+                    flake_ref,
+                    "--apply",
+                    &format!("pkg: pkg.{}", attr_name),
+                ],
+            )?,
+            Installable::Attr { file, attr } if out_path => {
+                let json = output(
+                    &self.nix_instantiate,
+                    &[
+                        "--eval",
+                        "--json",
+                        file,
+                        "-A",
+                        &format!("{}.{}", attr, attr_name),
+                    ],
+                )?;
+                serde_json::from_str(&json).map_err(|json_err| {
+                    InstallableErr::EvalErr(json_err.to_string())
+                })?
+            }
+            // This also writes the `.drv` file to the store, so it can be queried.
+            Installable::Attr { file, attr } => {
+                output(&self.nix_instantiate, &[file, "-A", attr])?
+            }
+        };
+
+        match stdout.lines().next() {
+            Some(line) if line.starts_with('/') => Ok(PathBuf::from(line)),
+            _ => Err(InstallableErr::EvalErr(format!(
+                "{} didn't evaluate to a store path: {:?}",
+                installable, stdout
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_input() {
+        assert_eq!(
+            Installable::from_input(
+                " /nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10 "
+            ),
+            Installable::StorePath(PathBuf::from(
+                "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10"
+            ))
+        );
+        assert_eq!(
+            Installable::from_input(".#packages.x86_64-linux.myapp"),
+            Installable::Flake(String::from(".#packages.x86_64-linux.myapp"))
+        );
+        assert_eq!(
+            Installable::from_input("."),
+            Installable::Flake(String::from("."))
+        );
+        assert_eq!(
+            Installable::from_input("github:NixOS/nixpkgs"),
+            Installable::Flake(String::from("github:NixOS/nixpkgs"))
+        );
+        // A file that exists, like a `result` symlink.
+        assert_eq!(
+            Installable::from_input("Cargo.toml"),
+            Installable::StorePath(PathBuf::from("Cargo.toml"))
+        );
+        let attr = Installable::from_input("./default.nix  -A  hello");
+        assert_eq!(
+            attr,
+            Installable::Attr {
+                file: String::from("./default.nix"),
+                attr: String::from("hello"),
+            }
+        );
+        assert_eq!(attr.to_string(), "./default.nix -A hello");
+    }
+}
@@ -0,0 +1,555 @@
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::str::FromStr;
+
+use super::derivation::Derivation;
+use super::{split_output, NixQueryDrv, NixQueryPathMap};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LicenseDbErr {
+    IoErr(String),
+    JsonErr(String),
+    /// The JSON is valid, but it is not in a format that has license information.
+    FormatErr(String),
+}
+
+impl std::fmt::Display for LicenseDbErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            LicenseDbErr::IoErr(string)
+            | LicenseDbErr::JsonErr(string)
+            | LicenseDbErr::FormatErr(string) => string,
+        };
+        write!(f, "{}", string)
+    }
+}
+
+/// The `shortName`s of the licenses in nixpkgs that are unfree, for license information that
+/// doesn't come with a `free` attribute.
+const UNFREE_LICENSES: [&str; 5] = [
+    "unfree",
+    "unfreeRedistributable",
+    "unfreeRedistributableFirmware",
+    "issl",
+    "databricks",
+];
+
+/// A single license, as found in `meta.license` in nixpkgs.
+#[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct License {
+    /// The SPDX identifier of the license if it has one, otherwise its short name.
+    pub id: String,
+    pub full_name: Option<String>,
+    pub free: bool,
+}
+
+impl License {
+    fn from_id(id: &str) -> Self {
+        License {
+            id: id.to_string(),
+            full_name: None,
+            free: !UNFREE_LICENSES.contains(&id),
+        }
+    }
+}
+
+impl std::fmt::Display for License {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.id)
+    }
+}
+
+/// Parse the value of `meta.license`.  This is either a single license or a list of
+/// licenses, where each license is either a string or an attribute set like
+/// `{ "spdxId": "MIT", "fullName": "MIT License", "free": true }`.
+///
+/// This returns `None` if there is no license information in `value`.
+///
+/// ```
+/// use nix_query_tree_viewer::nix_query_tree::licenses::parse_licenses;
+/// use serde_json::json;
+///
+/// let licenses = parse_licenses(&json!([
+///     {"spdxId": "MIT", "shortName": "mit", "fullName": "MIT License", "free": true},
+///     "unfree",
+/// ]))
+/// .unwrap();
+///
+/// assert_eq!(licenses[0].id, "MIT");
+/// assert!(licenses[0].free);
+/// assert_eq!(licenses[1].id, "unfree");
+/// assert!(!licenses[1].free);
+/// ```
+pub fn parse_licenses(value: &Value) -> Option<Vec<License>> {
+    match value {
+        Value::String(id) if !id.is_empty() => Some(vec![License::from_id(id)]),
+        Value::Object(attrs) => {
+            let get = |key: &str| attrs.get(key).and_then(Value::as_str);
+            let id = get("spdxId").or_else(|| get("shortName"))?;
+            let mut license = License::from_id(id);
+            license.full_name = get("fullName").map(String::from);
+            if let Some(free) = attrs.get("free").and_then(Value::as_bool) {
+                license.free = free;
+            }
+            Some(vec![license])
+        }
+        Value::Array(values) => {
+            let licenses: Vec<License> =
+                values.iter().filter_map(parse_licenses).flatten().collect();
+            if licenses.is_empty() {
+                None
+            } else {
+                Some(licenses)
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Read the license information exposed in the environment of a derivation.
+///
+/// Derivations don't normally have their `meta` in their environment, but it can be passed
+/// through as a `meta` or `license` attribute, or as part of `__json` for derivations that use
+/// `__structuredAttrs`.
+#[must_use]
+pub fn licenses_from_derivation(
+    derivation: &Derivation,
+) -> Option<Vec<License>> {
+    let from_json = |key: &str, pointer: &str| {
+        let json: Value =
+            serde_json::from_str(derivation.env_var(key)?).ok()?;
+        parse_licenses(json.pointer(pointer)?)
+    };
+    from_json("__json", "/meta/license")
+        .or_else(|| from_json("meta", "/license"))
+        .or_else(|| {
+            let license = derivation.env_var("license")?;
+            serde_json::from_str(license)
+                .ok()
+                .and_then(|json: Value| parse_licenses(&json))
+                .or_else(|| {
+                    let licenses: Vec<License> = license
+                        .split_whitespace()
+                        .map(License::from_id)
+                        .collect();
+                    if licenses.is_empty() {
+                        None
+                    } else {
+                        Some(licenses)
+                    }
+                })
+        })
+}
+
+/// License information for store paths, read from a JSON file produced by `nix eval`.
+///
+/// The JSON is either an object mapping store paths or package names to `meta.license`
+/// values, or a list of objects with a `license` (or `meta.license`) and any of `drvPath`,
+/// `outPath`, `path`, `name`, and `pname` to identify the package.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct LicenseDb {
+    pub licenses: HashMap<String, Vec<License>>,
+}
+
+impl LicenseDb {
+    /// Read and parse license information from disk.
+    ///
+    /// # Errors
+    ///
+    /// This fails if `path` can't be read, or isn't valid JSON in the expected format.
+    pub fn from_path(path: &Path) -> Result<LicenseDb, LicenseDbErr> {
+        let raw = std::fs::read_to_string(path).map_err(|io_err| {
+            LicenseDbErr::IoErr(format!("{}: {}", path.display(), io_err))
+        })?;
+        LicenseDb::from_str(&raw)
+    }
+
+    /// Lookup the licenses for a store path.  This tries the full store path, then the
+    /// name, then the name without the output, and then just the package name.
+    #[must_use]
+    pub fn lookup(&self, drv: &NixQueryDrv) -> Option<&Vec<License>> {
+        let drv_name = drv.drv_name();
+        let name = drv_name.trim_end_matches(".drv");
+        let (pname, version) = drv.pname_and_version();
+        let name_without_output = version
+            .as_ref()
+            .map(|version| format!("{}-{}", pname, split_output(version).0));
+
+        self.licenses
+            .get(&drv.to_string())
+            .or_else(|| self.licenses.get(name))
+            .or_else(|| {
+                name_without_output.and_then(|name| self.licenses.get(&name))
+            })
+            .or_else(|| self.licenses.get(&pname))
+    }
+}
+
+impl FromStr for LicenseDb {
+    type Err = LicenseDbErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let json: Value = serde_json::from_str(s)
+            .map_err(|err| LicenseDbErr::JsonErr(err.to_string()))?;
+        let mut licenses = HashMap::new();
+        match &json {
+            Value::Object(attrs) => {
+                for (key, value) in attrs {
+                    if let Some(parsed) = parse_licenses(value) {
+                        licenses.insert(key.clone(), parsed);
+                    }
+                }
+            }
+            Value::Array(entries) => {
+                for entry in entries {
+                    let license = entry
+                        .get("license")
+                        .or_else(|| entry.pointer("/meta/license"))
+                        .and_then(parse_licenses);
+                    if let Some(parsed) = license {
+                        for key in
+                            &["drvPath", "outPath", "path", "name", "pname"]
+                        {
+                            if let Some(value) =
+                                entry.get(key).and_then(Value::as_str)
+                            {
+                                licenses
+                                    .insert(value.to_string(), parsed.clone());
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {
+                return Err(LicenseDbErr::FormatErr(String::from(
+                    "expecting an object or a list of license information",
+                )))
+            }
+        }
+        Ok(LicenseDb { licenses })
+    }
+}
+
+/// All the store paths in a closure with the same license.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LicenseGroup {
+    /// `None` for the store paths without any license information.
+    pub license: Option<License>,
+    /// Sorted by store path.
+    pub drvs: Vec<NixQueryDrv>,
+}
+
+impl LicenseGroup {
+    #[must_use]
+    pub fn is_unknown(&self) -> bool {
+        self.license.is_none()
+    }
+
+    #[must_use]
+    pub fn is_unfree(&self) -> bool {
+        self.license.as_ref().is_some_and(|license| !license.free)
+    }
+}
+
+/// The licenses of every package in a closure.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct LicenseReport {
+    /// The licenses of each store path.  Store paths without any license information are
+    /// not in here.
+    pub licenses: HashMap<NixQueryDrv, Vec<License>>,
+    /// The store paths without any license information.
+    pub unknown: Vec<NixQueryDrv>,
+}
+
+impl LicenseReport {
+    /// Find the licenses of every package in the closure with `lookup`.
+    ///
+    /// When the root of the tree is a `.drv` file, only the `.drv` files in the tree are
+    /// counted as packages.  The other paths are sources and build scripts.
+    pub fn new<F>(
+        path_map: &NixQueryPathMap,
+        root: &NixQueryDrv,
+        lookup: F,
+    ) -> Self
+    where
+        F: Fn(&NixQueryDrv) -> Option<Vec<License>>,
+    {
+        let mut report = LicenseReport::default();
+        for (drv, _) in path_map.0.iter() {
+            if root.is_drv() && !drv.is_drv() {
+                continue;
+            }
+            match lookup(drv) {
+                Some(licenses) => {
+                    report.licenses.insert(drv.clone(), licenses);
+                }
+                None => report.unknown.push(drv.clone()),
+            }
+        }
+        report.unknown.sort_by(NixQueryDrv::cmp_hash);
+        report
+    }
+
+    /// Find the licenses of every package in the closure, first from `license_db`, and then
+    /// from the environment of each `.drv` file.
+    #[must_use]
+    pub fn from_closure(
+        path_map: &NixQueryPathMap,
+        root: &NixQueryDrv,
+        license_db: Option<&LicenseDb>,
+    ) -> Self {
+        LicenseReport::new(path_map, root, |drv| {
+            license_db
+                .and_then(|license_db| license_db.lookup(drv).cloned())
+                .or_else(|| {
+                    if drv.is_drv() {
+                        Derivation::from_path(drv).ok().and_then(|derivation| {
+                            licenses_from_derivation(&derivation)
+                        })
+                    } else {
+                        None
+                    }
+                })
+        })
+    }
+
+    /// Group the store paths by license.  Store paths with more than one license show up in
+    /// more than one group.  The groups are sorted by license, with the group of store paths
+    /// without license information last.
+    #[must_use]
+    pub fn summary(&self) -> Vec<LicenseGroup> {
+        let mut groups: BTreeMap<&License, Vec<NixQueryDrv>> = BTreeMap::new();
+        for (drv, licenses) in &self.licenses {
+            for license in licenses {
+                groups.entry(license).or_default().push(drv.clone());
+            }
+        }
+        let mut summary: Vec<LicenseGroup> = groups
+            .into_iter()
+            .map(|(license, mut drvs)| {
+                drvs.sort_by(NixQueryDrv::cmp_hash);
+                LicenseGroup {
+                    license: Some(license.clone()),
+                    drvs,
+                }
+            })
+            .collect();
+        if !self.unknown.is_empty() {
+            summary.push(LicenseGroup {
+                license: None,
+                drvs: self.unknown.clone(),
+            });
+        }
+        summary
+    }
+
+    /// A mapping from each store path to a description of its licenses, for showing in a
+    /// column next to the store path.
+    #[must_use]
+    pub fn license_names(&self) -> HashMap<NixQueryDrv, String> {
+        let mut names: HashMap<NixQueryDrv, String> = self
+            .licenses
+            .iter()
+            .map(|(drv, licenses)| {
+                let ids: Vec<String> =
+                    licenses.iter().map(ToString::to_string).collect();
+                (drv.clone(), ids.join(", "))
+            })
+            .collect();
+        for drv in &self.unknown {
+            names.insert(drv.clone(), String::from("unknown"));
+        }
+        names
+    }
+
+    /// Whether or not any package is unfree or has no license information.
+    #[must_use]
+    pub fn has_flagged(&self) -> bool {
+        self.summary()
+            .iter()
+            .any(|group| group.is_unfree() || group.is_unknown())
+    }
+
+    /// A human-readable summary of the licenses in the closure.
+    ///
+    /// ```
+    /// use indoc::indoc;
+    /// use nix_query_tree_viewer::nix_query_tree::licenses::{License, LicenseReport};
+    /// use nix_query_tree_viewer::nix_query_tree::NixQueryTree;
+    /// use std::str::FromStr;
+    ///
+    /// let raw_tree = indoc!(
+    ///         "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+    ///         +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+    ///         "
+    ///     );
+    /// let nix_query_tree = NixQueryTree::from_str(raw_tree).unwrap();
+    /// let root = &nix_query_tree.0.item.0;
+    /// let report = LicenseReport::new(&nix_query_tree.path_map(), root, |drv| {
+    ///     if drv.drv_name().starts_with("hello") {
+    ///         Some(vec![License { id: String::from("GPL-3.0-or-later"), full_name: None, free: true }])
+    ///     } else {
+    ///         None
+    ///     }
+    /// });
+    ///
+    /// assert_eq!(
+    ///     report.report(),
+    ///     indoc!(
+    ///         "GPL-3.0-or-later (1 store paths)
+    ///              /nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+    ///          UNKNOWN: no license information (1 store paths)
+    ///              /nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+    ///         "
+    ///     )
+    /// );
+    /// ```
+    #[must_use]
+    pub fn report(&self) -> String {
+        let mut lines = vec![];
+        for group in self.summary() {
+            let title = match &group.license {
+                None => String::from("UNKNOWN: no license information"),
+                Some(license) if !license.free => {
+                    format!("UNFREE: {}", license)
+                }
+                Some(license) => license.to_string(),
+            };
+            lines.push(format!("{} ({} store paths)", title, group.drvs.len()));
+            for drv in &group.drvs {
+                lines.push(format!("    {}", drv));
+            }
+        }
+
+        let mut report = lines.join("\n");
+        report.push('\n');
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use super::super::NixQueryTree;
+    use indoc::indoc;
+    use serde_json::json;
+
+    #[test]
+    fn test_licenses_from_derivation() {
+        let mut derivation = Derivation {
+            outputs: vec![],
+            input_drvs: vec![],
+            input_srcs: vec![],
+            platform: String::from("x86_64-linux"),
+            builder: String::from("/bin/sh"),
+            args: vec![],
+            env: vec![(String::from("name"), String::from("hello-2.10"))],
+        };
+        assert_eq!(licenses_from_derivation(&derivation), None);
+
+        derivation.env.push((
+            String::from("__json"),
+            json!({"meta": {"license": {"spdxId": "GPL-3.0-or-later", "free": true}}})
+                .to_string(),
+        ));
+        assert_eq!(
+            licenses_from_derivation(&derivation),
+            Some(vec![License::from_id("GPL-3.0-or-later")])
+        );
+
+        derivation.env =
+            vec![(String::from("license"), String::from("mit unfree"))];
+        assert_eq!(
+            licenses_from_derivation(&derivation),
+            Some(vec![License::from_id("mit"), License::from_id("unfree")])
+        );
+    }
+
+    #[test]
+    fn test_license_db_lookup() {
+        let license_db = LicenseDb::from_str(
+            &json!([
+                {"drvPath": "/nix/store/jymg0kanmlgbcv35wxd8d660rw0fawhv-hello-2.10.drv", "license": "gpl3Plus"},
+                {"name": "openssl-1.1.1d", "meta": {"license": {"spdxId": "OpenSSL"}}},
+                {"pname": "unrar", "license": {"shortName": "unfreeRedistributable", "free": false}},
+                {"name": "no-license-1.0", "license": null},
+            ])
+            .to_string(),
+        )
+        .unwrap();
+
+        let lookup = |path: &str| {
+            license_db
+                .lookup(&NixQueryDrv::from(path))
+                .map(|licenses| licenses[0].id.as_str())
+        };
+        assert_eq!(
+            lookup(
+                "/nix/store/jymg0kanmlgbcv35wxd8d660rw0fawhv-hello-2.10.drv"
+            ),
+            Some("gpl3Plus")
+        );
+        assert_eq!(
+            lookup("/nix/store/a6d5lyc3bzakyvxigwbms4lsh97pw3hn-openssl-1.1.1d-dev"),
+            Some("OpenSSL")
+        );
+        assert_eq!(
+            lookup("/nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-unrar-5.9.2"),
+            Some("unfreeRedistributable")
+        );
+        assert_eq!(
+            lookup(
+                "/nix/store/5jzbjvnrz85n454inlyxcpgap9i6k6la-no-license-1.0"
+            ),
+            None
+        );
+        assert!(LicenseDb::from_str("\"MIT\"").is_err());
+    }
+
+    #[test]
+    fn test_license_report_summary() {
+        let raw_tree = indoc!(
+            "/nix/store/jymg0kanmlgbcv35wxd8d660rw0fawhv-hello-2.10.drv
+            +---/nix/store/9krlzvny65gdc8s7kpb6lkx8cd02c25b-default-builder.sh
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27.drv
+            +---/nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-unrar-5.9.2.drv
+            "
+        );
+        let nix_query_tree = NixQueryTree::from_str(raw_tree).unwrap();
+        let license_db = LicenseDb::from_str(
+            &json!({
+                "hello": "gpl3Plus",
+                "glibc": ["lgpl2Plus", "gpl2"],
+                "unrar": "unfree",
+            })
+            .to_string(),
+        )
+        .unwrap();
+        let report = LicenseReport::new(
+            &nix_query_tree.path_map(),
+            &nix_query_tree.0.item.0,
+            |drv| license_db.lookup(drv).cloned(),
+        );
+        let summary = report.summary();
+
+        // The builder script is not a package, so it isn't counted as unknown.
+        assert!(report.unknown.is_empty());
+        assert_eq!(
+            summary
+                .iter()
+                .map(|group| group.license.as_ref().unwrap().id.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["gpl2", "gpl3Plus", "lgpl2Plus", "unfree"]
+        );
+        assert!(summary[3].is_unfree());
+        assert!(report.has_flagged());
+        assert_eq!(
+            report.license_names()[&NixQueryDrv::from(
+                "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27.drv"
+            )],
+            "lgpl2Plus, gpl2"
+        );
+    }
+}
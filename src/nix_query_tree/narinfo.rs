@@ -0,0 +1,459 @@
+//! Read store paths and their references straight from the `.narinfo` files of a flat-file
+//! binary cache, like one made with `nix copy --to file:///path/to/binary-cache`.
+//!
+//! Every store path in the cache has a `<hash>.narinfo` file next to a `nix-cache-info` file.
+//! The `References:` field of each `.narinfo` file is enough to build the same tree that
+//! `nix-store --query --tree` prints, so no nix binary is needed.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use super::super::tree::Tree;
+use super::exec_nix_store::NixStoreRes;
+use super::printing::{self, TreeStyle};
+use super::store_contents::human_size;
+use super::{NixQueryDrv, NixQueryEntry, NixQueryTree, Recurse};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum NarInfoErr {
+    /// The store path isn't in the binary cache.
+    NotFound(String),
+    IoErr(String),
+    ParseErr(String),
+}
+
+impl std::fmt::Display for NarInfoErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NarInfoErr::NotFound(path) => {
+                write!(f, "{} is not in the binary cache", path)
+            }
+            NarInfoErr::IoErr(string) | NarInfoErr::ParseErr(string) => {
+                write!(f, "{}", string)
+            }
+        }
+    }
+}
+
+fn io_err(path: &Path, err: &std::io::Error) -> NarInfoErr {
+    NarInfoErr::IoErr(format!("{}: {}", path.display(), err))
+}
+
+/// The fields of a `.narinfo` file.  Fields that this doesn't use are ignored.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct NarInfo {
+    pub store_path: PathBuf,
+    /// Where the compressed NAR is, relative to the binary cache.
+    pub url: Option<String>,
+    pub compression: Option<String>,
+    /// The size of the compressed NAR.
+    pub file_size: Option<u64>,
+    pub nar_hash: Option<String>,
+    /// The size of the uncompressed NAR.
+    pub nar_size: Option<u64>,
+    /// The base names of the store paths this references, like
+    /// `pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27`.
+    pub references: Vec<String>,
+    pub deriver: Option<String>,
+    /// The signatures, in the form `key-name:signature`.
+    pub sigs: Vec<String>,
+}
+
+fn parse_size(key: &str, value: &str) -> Result<u64, NarInfoErr> {
+    value.parse().map_err(|_| {
+        NarInfoErr::ParseErr(format!("{} is not a size: {}", key, value))
+    })
+}
+
+impl FromStr for NarInfo {
+    type Err = NarInfoErr;
+
+    /// Parse the contents of a `.narinfo` file.
+    ///
+    /// ```
+    /// use nix_query_tree_viewer::nix_query_tree::narinfo::NarInfo;
+    ///
+    /// let narinfo: NarInfo = "StorePath: /nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+    /// Compression: xz
+    /// FileSize: 41232
+    /// NarSize: 206000
+    /// References: pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+    /// Sig: cache.example.org-1:c2lnbmF0dXJl
+    /// "
+    ///     .parse()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(narinfo.compression, Some(String::from("xz")));
+    /// assert_eq!(narinfo.nar_size, Some(206000));
+    /// assert_eq!(narinfo.references.len(), 2);
+    /// assert_eq!(narinfo.signers(), vec!["cache.example.org-1"]);
+    /// ```
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut store_path = None;
+        let mut narinfo = NarInfo::default();
+        for line in s.lines().filter(|line| !line.trim().is_empty()) {
+            let (key, value) = match line.find(':') {
+                None => {
+                    return Err(NarInfoErr::ParseErr(format!(
+                        "Expected a line like \"Key: value\", but got: {}",
+                        line
+                    )))
+                }
+                Some(i) => (&line[..i], line[i + 1..].trim()),
+            };
+            match key {
+                "StorePath" => store_path = Some(PathBuf::from(value)),
+                "URL" => narinfo.url = Some(String::from(value)),
+                "Compression" => {
+                    narinfo.compression = Some(String::from(value));
+                }
+                "FileSize" => narinfo.file_size = Some(parse_size(key, value)?),
+                "NarHash" => narinfo.nar_hash = Some(String::from(value)),
+                "NarSize" => narinfo.nar_size = Some(parse_size(key, value)?),
+                "References" => {
+                    narinfo.references =
+                        value.split_whitespace().map(String::from).collect();
+                }
+                "Deriver" => narinfo.deriver = Some(String::from(value)),
+                "Sig" => narinfo.sigs.push(String::from(value)),
+                _ => {}
+            }
+        }
+        narinfo.store_path = store_path.ok_or_else(|| {
+            NarInfoErr::ParseErr(String::from("There is no StorePath field"))
+        })?;
+        Ok(narinfo)
+    }
+}
+
+impl NarInfo {
+    /// The names of the keys this is signed with.
+    #[must_use]
+    pub fn signers(&self) -> Vec<&str> {
+        self.sigs
+            .iter()
+            .map(|sig| sig.split(':').next().unwrap_or(sig))
+            .collect()
+    }
+
+    /// A one line summary of the sizes, compression, and signatures, like
+    /// `NAR 201.2 KiB, xz 40.3 KiB, signed by cache.example.org-1`.
+    #[must_use]
+    pub fn summary(&self) -> String {
+        let mut parts = vec![];
+        if let Some(nar_size) = self.nar_size {
+            parts.push(format!("NAR {}", human_size(nar_size)));
+        }
+        match (&self.compression, self.file_size) {
+            (Some(compression), Some(file_size)) => {
+                parts.push(format!(
+                    "{} {}",
+                    compression,
+                    human_size(file_size)
+                ));
+            }
+            (Some(compression), None) => parts.push(compression.clone()),
+            (None, Some(file_size)) => {
+                parts.push(format!("file {}", human_size(file_size)));
+            }
+            (None, None) => {}
+        }
+        if self.sigs.is_empty() {
+            parts.push(String::from("unsigned"));
+        } else {
+            parts.push(format!("signed by {}", self.signers().join(", ")));
+        }
+        parts.join(", ")
+    }
+}
+
+/// The directory of a `file://` store URI, or `None` for any other kind of store.  Query
+/// parameters like `?compression=zstd` are ignored.
+///
+/// ```
+/// use nix_query_tree_viewer::nix_query_tree::narinfo::binary_cache_dir;
+/// use std::path::PathBuf;
+///
+/// assert_eq!(
+///     binary_cache_dir("file:///tmp/binary-cache?compression=zstd"),
+///     Some(PathBuf::from("/tmp/binary-cache"))
+/// );
+/// assert_eq!(binary_cache_dir("ssh://builder"), None);
+/// ```
+#[must_use]
+pub fn binary_cache_dir(store_uri: &str) -> Option<PathBuf> {
+    let path = store_uri.strip_prefix("file://")?;
+    let path = path.split('?').next().unwrap_or(path);
+    Some(PathBuf::from(path))
+}
+
+/// A flat-file binary cache directory.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BinaryCache {
+    dir: PathBuf,
+    /// The nix store that the store paths in the cache are from, usually `/nix/store`.
+    store_dir: PathBuf,
+}
+
+impl BinaryCache {
+    /// Open the binary cache in `dir`.  This fails if there is no `nix-cache-info` file in
+    /// `dir`.
+    ///
+    /// # Errors
+    ///
+    /// This fails if `nix-cache-info` can't be read or parsed.
+    pub fn open(dir: &Path) -> Result<Self, NarInfoErr> {
+        let cache_info_path = dir.join("nix-cache-info");
+        let cache_info = std::fs::read_to_string(&cache_info_path)
+            .map_err(|err| io_err(&cache_info_path, &err))?;
+        let store_dir = cache_info
+            .lines()
+            .find_map(|line| line.strip_prefix("StoreDir:"))
+            .map_or_else(
+                || PathBuf::from("/nix/store"),
+                |store_dir| PathBuf::from(store_dir.trim()),
+            );
+        Ok(BinaryCache {
+            dir: dir.to_path_buf(),
+            store_dir,
+        })
+    }
+
+    /// Read the `.narinfo` file for `store_path`.
+    ///
+    /// # Errors
+    ///
+    /// This fails if there is no `.narinfo` for `store_path`, or it can't be parsed.
+    pub fn narinfo(&self, store_path: &Path) -> Result<NarInfo, NarInfoErr> {
+        let not_found =
+            || NarInfoErr::NotFound(store_path.display().to_string());
+        let hash_part = NixQueryDrv::from(store_path)
+            .hash_part()
+            .ok_or_else(not_found)?;
+        let narinfo_path = self.dir.join(format!("{}.narinfo", hash_part));
+        let contents = match std::fs::read_to_string(&narinfo_path) {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Err(not_found())
+            }
+            res => res.map_err(|err| io_err(&narinfo_path, &err))?,
+        };
+        contents.parse().map_err(|err| match err {
+            NarInfoErr::ParseErr(string) => NarInfoErr::ParseErr(format!(
+                "{}: {}",
+                narinfo_path.display(),
+                string
+            )),
+            err => err,
+        })
+    }
+
+    /// Read the `.narinfo` files for `store_path` and everything it references, directly
+    /// or indirectly.
+    fn closure(
+        &self,
+        store_path: &Path,
+    ) -> Result<HashMap<PathBuf, NarInfo>, NarInfoErr> {
+        let mut narinfos = HashMap::new();
+        let mut todo = vec![store_path.to_path_buf()];
+        while let Some(path) = todo.pop() {
+            if narinfos.contains_key(&path) {
+                continue;
+            }
+            let narinfo = self.narinfo(&path)?;
+            for reference in &narinfo.references {
+                todo.push(self.store_dir.join(reference));
+            }
+            narinfos.insert(path, narinfo);
+        }
+        Ok(narinfos)
+    }
+
+    /// The references of `store_path`, in the order that `nix-store --query --tree` prints
+    /// them: a store path comes after everything it references.
+    fn sorted_references(
+        &self,
+        store_path: &Path,
+        narinfos: &HashMap<PathBuf, NarInfo>,
+    ) -> Vec<PathBuf> {
+        let mut references: Vec<PathBuf> = narinfos[store_path]
+            .references
+            .iter()
+            .map(|reference| self.store_dir.join(reference))
+            .collect();
+        references.sort();
+        references.dedup();
+        let in_set: HashSet<&PathBuf> = references.iter().collect();
+
+        // A depth-first search only through the references of `store_path`, outputting each
+        // path after the paths it references.
+        let mut sorted = vec![];
+        let mut visited: HashSet<&PathBuf> = HashSet::new();
+        for start in &references {
+            if !visited.insert(start) {
+                continue;
+            }
+            let mut stack: Vec<(&PathBuf, usize)> = vec![(start, 0)];
+            while let Some((path, next)) = stack.last_mut() {
+                let path: &PathBuf = path;
+                let refs = &narinfos[path].references;
+                match refs.get(*next) {
+                    None => {
+                        sorted.push(path.clone());
+                        stack.pop();
+                    }
+                    Some(reference) => {
+                        *next += 1;
+                        let reference = self.store_dir.join(reference);
+                        if let Some(&reference) = in_set.get(&reference) {
+                            if reference != path && visited.insert(reference) {
+                                stack.push((reference, 0));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        sorted
+    }
+
+    /// Build the same tree as `nix-store --query --tree` for `store_path`, along with the
+    /// `.narinfo` of every store path in it.
+    ///
+    /// Like `nix-store`, only the first instance of a store path has children, and later
+    /// instances are marked with `Recurse::Yes`.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the `.narinfo` of `store_path`, or of anything it references, can't be
+    /// read.
+    #[allow(clippy::missing_panics_doc)] // The root stays on the stack until the end.
+    pub fn query_tree(
+        &self,
+        store_path: &Path,
+    ) -> Result<(NixQueryTree, HashMap<NixQueryDrv, NarInfo>), NarInfoErr> {
+        let narinfos = self.closure(store_path)?;
+
+        let entry = |path: &Path, recurse| {
+            NixQueryEntry(NixQueryDrv::from(path), recurse)
+        };
+        let mut done: HashSet<PathBuf> = HashSet::new();
+        done.insert(store_path.to_path_buf());
+
+        // Each stack entry is a tree that is still being built, and its references that
+        // haven't been added to it yet.
+        let mut stack: Vec<(Tree<NixQueryEntry>, std::vec::IntoIter<PathBuf>)> =
+            vec![(
+                Tree::singleton(entry(store_path, Recurse::No)),
+                self.sorted_references(store_path, &narinfos).into_iter(),
+            )];
+        let root = loop {
+            let (tree, references) =
+                stack.last_mut().expect("the stack is never empty here");
+            match references.next() {
+                Some(reference) if !done.insert(reference.clone()) => {
+                    tree.children
+                        .push(Tree::singleton(entry(&reference, Recurse::Yes)));
+                }
+                Some(reference) => {
+                    let references =
+                        self.sorted_references(&reference, &narinfos);
+                    stack.push((
+                        Tree::singleton(entry(&reference, Recurse::No)),
+                        references.into_iter(),
+                    ));
+                }
+                None => {
+                    let (finished, _) =
+                        stack.pop().expect("the stack is never empty here");
+                    match stack.last_mut() {
+                        None => break finished,
+                        Some((parent, _)) => parent.children.push(finished),
+                    }
+                }
+            }
+        };
+
+        let narinfos = narinfos
+            .into_iter()
+            .map(|(path, narinfo)| (NixQueryDrv::from(&path), narinfo))
+            .collect();
+        Ok((NixQueryTree(root), narinfos))
+    }
+
+    /// Like `query_tree`, but as a `NixStoreRes`.  The raw output is what
+    /// `nix-store --query --tree` would have printed.
+    ///
+    /// # Errors
+    ///
+    /// This fails in the same cases as `query_tree`.
+    pub fn nix_store_res(
+        &self,
+        store_path: &Path,
+    ) -> Result<NixStoreRes, NarInfoErr> {
+        let (tree, narinfos) = self.query_tree(store_path)?;
+        let raw = printing::print_nix_query_tree(&tree, TreeStyle::Ascii);
+        Ok(NixStoreRes {
+            narinfos,
+            ..NixStoreRes::new(&raw, tree)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use indoc::indoc;
+
+    #[test]
+    fn test_parse_narinfo() {
+        let narinfo: NarInfo = indoc!(
+            "StorePath: /nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            URL: nar/1w1fff338fvdw53sqgamddn1b2xgds473pv6y13gizdbqjv4i5p3.nar.xz
+            Compression: xz
+            FileSize: 4083012
+            NarHash: sha256:0wv4ynb2qblkbx6bb4bsl3vv8m2dgm4rrhdz9gm8mgh6fxlfm1jh
+            NarSize: 16945456
+            References: pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            Deriver: 7x7x2pljmqsa3nr1ipmypn0gdvf3w0ph-glibc-2.27.drv
+            Sig: cache.example.org-1:c2lnbmF0dXJlLTE=
+            Sig: other.example.org-1:c2lnbmF0dXJlLTI=
+            "
+        )
+        .parse()
+        .unwrap();
+
+        assert_eq!(
+            narinfo.store_path,
+            PathBuf::from(
+                "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27"
+            )
+        );
+        assert_eq!(narinfo.file_size, Some(4_083_012));
+        assert_eq!(
+            narinfo.deriver,
+            Some(String::from(
+                "7x7x2pljmqsa3nr1ipmypn0gdvf3w0ph-glibc-2.27.drv"
+            ))
+        );
+        assert_eq!(
+            narinfo.summary(),
+            "NAR 16.2 MiB, xz 3.9 MiB, signed by cache.example.org-1, other.example.org-1"
+        );
+
+        assert_eq!(
+            "URL: nar/foo.nar".parse::<NarInfo>(),
+            Err(NarInfoErr::ParseErr(String::from(
+                "There is no StorePath field"
+            )))
+        );
+        assert_eq!(
+            "StorePath: /nix/store/foo\nNarSize: big".parse::<NarInfo>(),
+            Err(NarInfoErr::ParseErr(String::from(
+                "NarSize is not a size: big"
+            )))
+        );
+    }
+}
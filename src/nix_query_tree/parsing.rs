@@ -1,13 +1,13 @@
 use nom::character::complete::{newline, space1};
 use nom::combinator::complete;
 use nom::multi::{many0, many_m_n};
-use nom::{alt, do_parse, eof, map, named, opt, tag, take_till, IResult};
+use nom::{alt, do_parse, eof, map, named, opt, tag, take_till1, IResult};
 
 use super::super::tree::Tree;
 use super::{NixQueryDrv, NixQueryEntry, NixQueryTree, Recurse};
 
 named!(parse_nix_query_drv<&str, NixQueryDrv>,
-    map!(take_till!(char::is_whitespace), NixQueryDrv::from));
+    map!(take_till1!(char::is_whitespace), NixQueryDrv::from));
 
 named!(parse_recurse<&str, &str>,
     tag!("[...]"));
@@ -24,6 +24,11 @@ named!(parse_nix_query_entry<&str, NixQueryEntry>,
         (NixQueryEntry(drv, opt_recurse.unwrap_or(Recurse::No)))
     ));
 
+/// Parse a single entry, like `/nix/store/...-glibc-2.27 [...]`.
+///
+/// # Errors
+///
+/// This fails if `input` doesn't start with a store path.
 pub fn nix_query_entry_parser(
     input: &str,
 ) -> Result<NixQueryEntry, nom::Err<(&str, nom::error::ErrorKind)>> {
@@ -49,7 +54,7 @@ fn parse_single_branch(
     level: usize,
 ) -> impl Fn(&str) -> IResult<&str, NixQueryEntry> {
     move |input| {
-        let (input, _) = parse_extra_levels(level)(input)?;
+        let (input, ()) = parse_extra_levels(level)(input)?;
         let (input, _) = parse_branch_start(input)?;
         let (input, nix_query_entry) = parse_nix_query_entry(input)?;
         let (input, _) = newline(input)?;
@@ -93,11 +98,274 @@ named!(parse_nix_query_tree_final<&str, NixQueryTree>,
         eof!() >>
         (nix_query_tree)));
 
+/// What the parser was expecting to find where it failed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Expected {
+    /// A nix store path, like `/nix/store/...-hello-2.10`.
+    NixStorePath,
+    /// The start of a branch, like `+---` or `├───`.
+    BranchStart,
+    /// A branch nested at most this many levels deep.  This happens when a branch is nested
+    /// more than one level deeper than the branch before it.
+    IndentationLevel(usize),
+    /// `[...]` after a nix store path.
+    Recurse,
+    /// The end of the line.
+    Newline,
+}
+
+impl std::fmt::Display for Expected {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expected::NixStorePath => write!(f, "a nix store path"),
+            Expected::BranchStart => {
+                write!(f, "the start of a branch, like \"+---\"")
+            }
+            Expected::IndentationLevel(level) => {
+                write!(f, "a branch nested at most {} levels deep", level)
+            }
+            Expected::Recurse => write!(f, "\"[...]\" or the end of the line"),
+            Expected::Newline => write!(f, "a newline"),
+        }
+    }
+}
+
+/// An error from parsing the output of `nix-store --query --tree`.
+///
+/// ```
+/// use nix_query_tree_viewer::nix_query_tree::parsing::{nix_query_tree_parser, Expected};
+///
+/// let raw_tree = "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+/// +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [..]
+/// ";
+/// let err = nix_query_tree_parser(raw_tree).unwrap_err();
+///
+/// assert_eq!(err.line, 2);
+/// assert_eq!(err.column, 60);
+/// assert_eq!(err.expected, Expected::Recurse);
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ParseError {
+    /// The line the error is on, starting from 1.
+    pub line: usize,
+    /// The column the error is at in characters, starting from 1.
+    pub column: usize,
+    /// The text of the line the error is on, without the newline.
+    pub line_text: String,
+    pub expected: Expected,
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: expecting {}\n\n{}\n{}^",
+            self.line,
+            self.column,
+            self.expected,
+            self.line_text,
+            " ".repeat(self.column - 1)
+        )
+    }
+}
+
+/// Figure out what is wrong with the first line of output, which should only be a nix store
+/// path.  This returns the byte offset of the problem in the line.
+fn diagnose_root(line: &str) -> (usize, Expected) {
+    match line.find(char::is_whitespace) {
+        Some(0) => (0, Expected::NixStorePath),
+        None if line.is_empty() => (0, Expected::NixStorePath),
+        Some(drv_len) => (drv_len, Expected::Newline),
+        None => (line.len(), Expected::Newline),
+    }
+}
+
+/// The number of levels that a branch line is nested, along with the byte offset of the end
+/// of each level.
+fn branch_levels(line: &str) -> Vec<usize> {
+    let mut offsets = vec![];
+    let mut rest = line;
+    while let Ok((new_rest, _)) = parse_extra_level(rest) {
+        rest = new_rest;
+        offsets.push(line.len() - rest.len());
+    }
+    offsets
+}
+
+/// Figure out what is wrong with a line that should be a branch nested at most `max_level`
+/// levels deep.  This returns the byte offset of the problem in the line.
+fn diagnose_branch(line: &str, max_level: usize) -> (usize, Expected) {
+    let level_offsets = branch_levels(line);
+    let level = level_offsets.len();
+    let start_offset = level_offsets.last().copied().unwrap_or(0);
+
+    let entry = match parse_branch_start(&line[start_offset..]) {
+        Err(_) => return (start_offset, Expected::BranchStart),
+        Ok(_) if level > max_level => {
+            let offset =
+                max_level.checked_sub(1).map_or(0, |i| level_offsets[i]);
+            return (offset, Expected::IndentationLevel(max_level));
+        }
+        Ok((entry, _)) => entry,
+    };
+    let entry_offset = line.len() - entry.len();
+
+    let drv_len = entry.find(char::is_whitespace).unwrap_or(entry.len());
+    if drv_len == 0 {
+        return (entry_offset, Expected::NixStorePath);
+    }
+    let after_drv = &entry[drv_len..];
+    let recurse = after_drv.trim_start();
+    if after_drv.is_empty() || recurse == "[...]" {
+        // The line itself is fine, so it must be missing its newline.
+        (line.len(), Expected::Newline)
+    } else {
+        (line.len() - recurse.len(), Expected::Recurse)
+    }
+}
+
+/// Turn the place where the nom parser stopped into a `ParseError` that says what was wrong.
+///
+/// The parser backtracks to the start of a line when it can't parse a branch, so this looks
+/// at that whole line again to find the column and what was expected.
+fn parse_error(input: &str, rest: &str) -> ParseError {
+    let offset = input.len() - rest.len();
+    let line_start = input[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = input[line_start..]
+        .find('\n')
+        .map_or(input.len(), |i| line_start + i);
+    let line_text = &input[line_start..line_end];
+
+    let (column_offset, expected) = if line_start == 0 {
+        diagnose_root(line_text)
+    } else {
+        let prev_line_start =
+            input[..line_start - 1].rfind('\n').map_or(0, |i| i + 1);
+        let max_level = if prev_line_start == 0 {
+            0
+        } else {
+            branch_levels(&input[prev_line_start..line_start - 1]).len() + 1
+        };
+        diagnose_branch(line_text, max_level)
+    };
+
+    ParseError {
+        line: input[..line_start].matches('\n').count() + 1,
+        column: line_text[..column_offset].chars().count() + 1,
+        line_text: String::from(line_text),
+        expected,
+    }
+}
+
 /// Parse all output from `nix-store --query --tree`.
-pub fn nix_query_tree_parser(
+///
+/// All of the input must be parsed, so anything after the tree is an error.
+///
+/// # Errors
+///
+/// This fails with the line and column of the first thing that isn't part of the tree.
+pub fn nix_query_tree_parser(input: &str) -> Result<NixQueryTree, ParseError> {
+    match parse_nix_query_tree_final(input) {
+        Ok((_, nix_query_tree)) => Ok(nix_query_tree),
+        Err(nom::Err::Error((rest, _)) | nom::Err::Failure((rest, _))) => {
+            Err(parse_error(input, rest))
+        }
+        // This only happens when the input ends in the middle of the first line.
+        Err(nom::Err::Incomplete(_)) => Err(parse_error(input, "")),
+    }
+}
+
+/// A line that `nix_query_tree_parser_lenient` skipped because it isn't part of the tree.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
+pub struct SkippedLine {
+    /// The line number in the original input, starting from 1.
+    pub line: usize,
+    pub line_text: String,
+}
+
+impl std::fmt::Display for SkippedLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: \"{}\"", self.line, self.line_text)
+    }
+}
+
+/// Whether `line` could be the first line of the tree, which is only a nix store path.
+pub(super) fn is_root_line(line: &str) -> bool {
+    line.starts_with('/') && !line.contains(char::is_whitespace)
+}
+
+/// Whether `line` looks like a branch of the tree, like `|   +---/nix/store/...`.
+fn is_branch_line(line: &str) -> bool {
+    let start_offset = branch_levels(line).last().copied().unwrap_or(0);
+    parse_branch_start(&line[start_offset..]).is_ok()
+}
+
+/// Parse output from `nix-store --query --tree` that may have other things mixed in.
+///
+/// This is like `nix_query_tree_parser`, but lines that aren't part of the tree (like
+/// `warning: ...` lines from `nix`) are skipped, CRLF line endings are accepted, and the last
+/// line doesn't need a newline.  The skipped lines are returned along with the tree.  Lines
+/// that look like part of the tree but can't be parsed are still errors.
+///
+/// ```
+/// use nix_query_tree_viewer::nix_query_tree::parsing::nix_query_tree_parser_lenient;
+///
+/// let raw_tree = "warning: unknown setting 'foo'\r
+/// /nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10\r
+/// +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27";
+/// let (nix_query_tree, skipped) = nix_query_tree_parser_lenient(raw_tree).unwrap();
+///
+/// assert_eq!(nix_query_tree.0.children.len(), 1);
+/// assert_eq!(skipped.len(), 1);
+/// assert_eq!(skipped[0].line, 1);
+/// ```
+///
+/// # Errors
+///
+/// This fails if no line of `input` looks like the root of a tree, or the tree is
+/// malformed.
+pub fn nix_query_tree_parser_lenient(
     input: &str,
-) -> Result<NixQueryTree, nom::Err<(&str, nom::error::ErrorKind)>> {
-    parse_nix_query_tree(input).map(|(_, nix_query_tree)| nix_query_tree)
+) -> Result<(NixQueryTree, Vec<SkippedLine>), ParseError> {
+    let mut skipped = vec![];
+    let mut tree_text = String::new();
+    // The line number in `input` of each line in `tree_text`.
+    let mut line_numbers = vec![];
+
+    for (i, line) in input.lines().enumerate() {
+        let is_tree_line = if line_numbers.is_empty() {
+            is_root_line(line)
+        } else {
+            is_branch_line(line)
+        };
+        if is_tree_line {
+            tree_text.push_str(line);
+            tree_text.push('\n');
+            line_numbers.push(i + 1);
+        } else {
+            skipped.push(SkippedLine {
+                line: i + 1,
+                line_text: String::from(line),
+            });
+        }
+    }
+
+    if line_numbers.is_empty() {
+        return Err(ParseError {
+            line: 1,
+            column: 1,
+            line_text: String::from(input.lines().next().unwrap_or("")),
+            expected: Expected::NixStorePath,
+        });
+    }
+
+    nix_query_tree_parser(&tree_text)
+        .map(|nix_query_tree| (nix_query_tree, skipped))
+        .map_err(|parse_err| ParseError {
+            line: line_numbers[parse_err.line - 1],
+            ..parse_err
+        })
 }
 
 #[cfg(test)]
@@ -352,4 +620,108 @@ mod tests {
         let r = parse_nix_query_tree(raw_input);
         assert_eq!(r, Ok(("", NixQueryTree(actual_tree))));
     }
+
+    fn parse_err(raw_input: &str) -> (usize, usize, Expected) {
+        let err = nix_query_tree_parser(raw_input).unwrap_err();
+        (err.line, err.column, err.expected)
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse_err(""), (1, 1, Expected::NixStorePath));
+        assert_eq!(
+            parse_err("/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10"),
+            (1, 55, Expected::Newline)
+        );
+
+        let raw_input = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            |   |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            "
+        );
+        assert_eq!(parse_err(raw_input), (3, 5, Expected::IndentationLevel(1)));
+
+        let raw_input = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            |   /nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            "
+        );
+        assert_eq!(parse_err(raw_input), (2, 5, Expected::BranchStart));
+
+        let raw_input = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            ├───/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            │   └─── [...]
+            "
+        );
+        assert_eq!(parse_err(raw_input), (3, 9, Expected::NixStorePath));
+    }
+
+    #[test]
+    fn test_parse_error_trailing_garbage() {
+        let raw_input = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            warning: unknown setting 'foo'
+            "
+        );
+        let err = nix_query_tree_parser(raw_input).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "line 3, column 1: expecting the start of a branch, like \"+---\"\n\nwarning: unknown setting 'foo'\n^"
+        );
+    }
+
+    #[test]
+    fn test_parse_lenient() {
+        let raw_input = "warning: unknown setting 'foo'\r\n\
+            /nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10\r\n\
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27\r\n\
+            \r\n\
+            |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]\r\n\
+            +---/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10 [...]";
+        let strict_input = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            +---/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10 [...]
+            "
+        );
+
+        let (nix_query_tree, skipped) =
+            nix_query_tree_parser_lenient(raw_input).unwrap();
+        assert_eq!(Ok(nix_query_tree), nix_query_tree_parser(strict_input));
+        assert_eq!(
+            skipped,
+            vec![
+                SkippedLine {
+                    line: 1,
+                    line_text: String::from("warning: unknown setting 'foo'")
+                },
+                SkippedLine {
+                    line: 4,
+                    line_text: String::new()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_lenient_errors() {
+        let raw_input = indoc!(
+            "warning: unknown setting 'foo'
+            /nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            |   |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            "
+        );
+        let err = nix_query_tree_parser_lenient(raw_input).unwrap_err();
+        assert_eq!(
+            (err.line, err.column, err.expected),
+            (3, 1, Expected::IndentationLevel(0))
+        );
+
+        let err = nix_query_tree_parser_lenient("warning: foo\n").unwrap_err();
+        assert_eq!(err.expected, Expected::NixStorePath);
+    }
 }
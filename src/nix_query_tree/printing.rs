@@ -0,0 +1,144 @@
+//! Print a `NixQueryTree` back out in the same format as `nix-store --query --tree`.
+//!
+//! This is the inverse of `parsing`, so it can be used to write out a tree that has been
+//! filtered or pruned in a form that other tools (and this one) can read.
+
+use super::super::tree::Tree;
+use super::{NixQueryEntry, NixQueryTree, Recurse};
+
+/// The characters used to draw the branches of the tree.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum TreeStyle {
+    /// `+---` and `|   `, like older versions of `nix-store`.
+    #[default]
+    Ascii,
+    /// `├───`, `└───`, and `│   `, like newer versions of `nix-store`.
+    Unicode,
+}
+
+impl TreeStyle {
+    /// The start of a branch, depending on whether it is the last child of its parent.
+    fn branch_start(self, is_last: bool) -> &'static str {
+        match (self, is_last) {
+            (TreeStyle::Ascii, _) => "+---",
+            (TreeStyle::Unicode, false) => "├───",
+            (TreeStyle::Unicode, true) => "└───",
+        }
+    }
+
+    /// The indentation below a branch for its children, depending on whether the branch is the
+    /// last child of its parent.
+    fn extra_level(self, is_last: bool) -> &'static str {
+        match (self, is_last) {
+            (_, true) => "    ",
+            (TreeStyle::Ascii, false) => "|   ",
+            (TreeStyle::Unicode, false) => "│   ",
+        }
+    }
+}
+
+fn push_entry(output: &mut String, entry: &NixQueryEntry) {
+    output.push_str(&entry.0.to_string());
+    if entry.1 == Recurse::Yes {
+        output.push_str(" [...]");
+    }
+    output.push('\n');
+}
+
+/// A branch still to be printed, whether it is the last child of its parent, and the
+/// indentation of its parent's children.
+type Branch<'a> = (&'a Tree<NixQueryEntry>, bool, String);
+
+/// Push the children of `tree` onto `stack` so that the first child is popped first.
+fn push_children<'a>(
+    stack: &mut Vec<Branch<'a>>,
+    tree: &'a Tree<NixQueryEntry>,
+    prefix: &str,
+) {
+    let last_index = tree.children.len().saturating_sub(1);
+    for (i, child) in tree.children.iter().enumerate().rev() {
+        stack.push((child, i == last_index, String::from(prefix)));
+    }
+}
+
+/// Print a `NixQueryTree` in the format of `nix-store --query --tree`.
+///
+/// This doesn't recurse, so very deep trees can be printed.
+///
+/// ```
+/// use indoc::indoc;
+/// use nix_query_tree_viewer::nix_query_tree::printing::{print_nix_query_tree, TreeStyle};
+/// use nix_query_tree_viewer::nix_query_tree::NixQueryTree;
+/// use std::str::FromStr;
+///
+/// let raw_tree = indoc!(
+///         "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+///         +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+///         |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+///         +---/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10 [...]
+///         "
+///     );
+/// let nix_query_tree = NixQueryTree::from_str(raw_tree).unwrap();
+///
+/// assert_eq!(print_nix_query_tree(&nix_query_tree, TreeStyle::Ascii), raw_tree);
+/// assert_eq!(
+///     print_nix_query_tree(&nix_query_tree, TreeStyle::Unicode),
+///     indoc!(
+///         "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+///         ├───/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+///         │   └───/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+///         └───/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10 [...]
+///         "
+///     )
+/// );
+/// ```
+#[must_use]
+pub fn print_nix_query_tree(
+    nix_query_tree: &NixQueryTree,
+    style: TreeStyle,
+) -> String {
+    let root: &Tree<NixQueryEntry> = &nix_query_tree.0;
+    let mut output = String::new();
+    output.push_str(&root.item.0.to_string());
+    output.push('\n');
+
+    let mut stack = vec![];
+    push_children(&mut stack, root, "");
+
+    while let Some((tree, is_last, prefix)) = stack.pop() {
+        output.push_str(&prefix);
+        output.push_str(style.branch_start(is_last));
+        push_entry(&mut output, &tree.item);
+
+        let child_prefix = prefix + style.extra_level(is_last);
+        push_children(&mut stack, tree, &child_prefix);
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use indoc::indoc;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_print_nested_unicode() {
+        let raw_tree = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            ├───/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            │   ├───/nix/store/a9n6ip5d1m7b3blkxmmg0dd5z4cbqgvf-acl-2.2.53
+            │   │   └───/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            │   └───/nix/store/5jzbjvnrz85n454inlyxcpgap9i6k6la-pcre-8.43
+            │       └───/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            └───/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10 [...]
+            "
+        );
+        let nix_query_tree = NixQueryTree::from_str(raw_tree).unwrap();
+        assert_eq!(
+            print_nix_query_tree(&nix_query_tree, TreeStyle::Unicode),
+            raw_tree
+        );
+    }
+}
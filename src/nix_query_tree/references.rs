@@ -0,0 +1,281 @@
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use super::super::tree::Tree;
+use super::store_contents::{self, FileEntry, FileKind, StoreContentsErr};
+use super::NixQueryDrv;
+
+/// How many bytes to show on each side of a reference.
+const CONTEXT_LEN: usize = 32;
+
+/// How many bytes to read from a file at a time.
+const CHUNK_LEN: usize = 64 * 1024;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ReferenceKind {
+    /// The hash is in the contents of a regular file.
+    Contents,
+    /// The hash is in the target of a symlink.
+    SymlinkTarget,
+}
+
+/// A single place inside a store path where the hash of another store path appears.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Reference {
+    pub file: PathBuf,
+    pub kind: ReferenceKind,
+    /// The byte offset of the start of the hash, either in the file contents or in the
+    /// symlink target.
+    pub offset: u64,
+    /// The bytes surrounding the hash, with unprintable bytes replaced by `.`.
+    pub context: String,
+}
+
+/// Render bytes as a string, replacing anything that is not printable ASCII with `.`.
+///
+/// ```
+/// use nix_query_tree_viewer::nix_query_tree::references::printable;
+///
+/// assert_eq!(printable(b"\x00/nix/store\n"), "./nix/store.");
+/// ```
+#[must_use]
+pub fn printable(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&byte| {
+            if byte.is_ascii_graphic() || byte == b' ' {
+                byte as char
+            } else {
+                '.'
+            }
+        })
+        .collect()
+}
+
+fn context(bytes: &[u8], start: usize, needle_len: usize) -> String {
+    let context_start = start.saturating_sub(CONTEXT_LEN);
+    let context_end = (start + needle_len + CONTEXT_LEN).min(bytes.len());
+    printable(&bytes[context_start..context_end])
+}
+
+fn find_all(haystack: &[u8], needle: &[u8]) -> Vec<usize> {
+    if needle.is_empty() {
+        return vec![];
+    }
+    haystack
+        .windows(needle.len())
+        .enumerate()
+        .filter(|(_, window)| *window == needle)
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Find every occurrence of `needle` in `reader`, reading `chunk_len` bytes at a time.
+///
+/// This returns the offset of each occurrence along with its context.
+fn scan_reader<R: Read>(
+    mut reader: R,
+    needle: &[u8],
+    chunk_len: usize,
+) -> std::io::Result<Vec<(u64, String)>> {
+    // Enough bytes are kept from the previous chunk so that an occurrence spanning two chunks,
+    // along with its context on both sides, is always found in one piece.
+    let keep_len = needle.len() + 2 * CONTEXT_LEN;
+    let mut res = vec![];
+    let mut buf: Vec<u8> = vec![];
+    let mut buf_offset: u64 = 0;
+    let mut next_offset: u64 = 0;
+    let mut chunk = vec![0; chunk_len];
+
+    loop {
+        let bytes_read = reader.read(&mut chunk)?;
+        let eof = bytes_read == 0;
+        buf.extend_from_slice(&chunk[..bytes_read]);
+
+        for i in find_all(&buf, needle) {
+            let offset = buf_offset + i as u64;
+            let has_full_context = i + needle.len() + CONTEXT_LEN <= buf.len();
+            if offset >= next_offset && (eof || has_full_context) {
+                res.push((offset, context(&buf, i, needle.len())));
+                next_offset = offset + 1;
+            }
+        }
+
+        if eof {
+            return Ok(res);
+        }
+
+        if buf.len() > keep_len {
+            let drop_len = buf.len() - keep_len;
+            buf.drain(..drop_len);
+            buf_offset += drop_len as u64;
+        }
+    }
+}
+
+fn find_in_file(
+    file_entry: &FileEntry,
+    hash: &str,
+) -> Result<Vec<Reference>, StoreContentsErr> {
+    let path = &file_entry.path;
+    match &file_entry.kind {
+        FileKind::Directory => Ok(vec![]),
+        FileKind::Symlink { target } => {
+            let target_str = target.to_string_lossy();
+            Ok(find_all(target_str.as_bytes(), hash.as_bytes())
+                .into_iter()
+                .map(|i| Reference {
+                    file: path.clone(),
+                    kind: ReferenceKind::SymlinkTarget,
+                    offset: i as u64,
+                    context: target_str.clone().into_owned(),
+                })
+                .collect())
+        }
+        FileKind::Regular { .. } => {
+            let io_err = |err: std::io::Error| {
+                StoreContentsErr::IoErr(format!("{}: {}", path.display(), err))
+            };
+            let file = std::fs::File::open(path).map_err(io_err)?;
+            let found = scan_reader(file, hash.as_bytes(), CHUNK_LEN)
+                .map_err(io_err)?;
+            Ok(found
+                .into_iter()
+                .map(|(offset, context)| Reference {
+                    file: path.clone(),
+                    kind: ReferenceKind::Contents,
+                    offset,
+                    context,
+                })
+                .collect())
+        }
+    }
+}
+
+fn find_in_tree(
+    tree: &Tree<FileEntry>,
+    hash: &str,
+    res: &mut Vec<Reference>,
+) -> Result<(), StoreContentsErr> {
+    res.extend(find_in_file(&tree.item, hash)?);
+    for child in &tree.children {
+        find_in_tree(child, hash, res)?;
+    }
+    Ok(())
+}
+
+/// Find every place in the files under `path` that contains `hash`.
+///
+/// Both file contents and symlink targets are searched.  Symlinks are never followed.
+///
+/// # Errors
+///
+/// This fails if `path`, or anything under it, can't be read.
+pub fn find_references(
+    path: &Path,
+    hash: &str,
+) -> Result<Vec<Reference>, StoreContentsErr> {
+    let contents = store_contents::read_store_contents(path, usize::MAX)?;
+    let mut res = vec![];
+    find_in_tree(&contents.tree, hash, &mut res)?;
+    Ok(res)
+}
+
+/// Find every place in the files of `parent` that references `child`.
+///
+/// This is similar to `nix why-depends --precise`.
+///
+/// # Errors
+///
+/// This fails if the files of `parent` can't be read.
+pub fn find_references_to(
+    parent: &NixQueryDrv,
+    child: &NixQueryDrv,
+) -> Result<Vec<Reference>, StoreContentsErr> {
+    let hash = child.hash_part().ok_or_else(|| {
+        StoreContentsErr::IoErr(format!("{} is not a nix store path", child))
+    })?;
+    find_references(parent, &hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "az4kl5slhbkmmy4vj98z3hzxxkan7zza";
+
+    #[test]
+    fn test_scan_reader() {
+        let mut bytes = vec![b'x'; 100];
+        bytes.extend_from_slice(HASH.as_bytes());
+        bytes.extend_from_slice(&[0; 10]);
+        bytes.extend_from_slice(HASH.as_bytes());
+
+        let r = scan_reader(bytes.as_slice(), HASH.as_bytes(), 1024).unwrap();
+        let expected_context =
+            format!("{}{}{}", "x".repeat(CONTEXT_LEN), HASH, ".".repeat(10));
+        assert_eq!(r.len(), 2);
+        assert_eq!(r[0].0, 100);
+        assert_eq!(r[0].1, format!("{}{}", expected_context, &HASH[..22]));
+        assert_eq!(r[1].0, 142);
+    }
+
+    #[test]
+    fn test_scan_reader_across_chunks() {
+        let mut bytes = vec![b'x'; 100];
+        bytes.extend_from_slice(HASH.as_bytes());
+        bytes.extend_from_slice(&[b'y'; 100]);
+
+        // Reading in tiny chunks gives the same results as reading everything at once.
+        for chunk_len in &[1, 7, 33, 1024] {
+            let r = scan_reader(bytes.as_slice(), HASH.as_bytes(), *chunk_len)
+                .unwrap();
+            assert_eq!(
+                r,
+                vec![(
+                    100,
+                    format!(
+                        "{}{}{}",
+                        "x".repeat(CONTEXT_LEN),
+                        HASH,
+                        "y".repeat(CONTEXT_LEN)
+                    )
+                )]
+            );
+        }
+    }
+
+    #[test]
+    fn test_find_references() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        std::fs::create_dir(dir.join("bin")).unwrap();
+        std::fs::write(
+            dir.join("bin").join("hello"),
+            format!("#!/nix/store/{}-bash/bin/sh\n", HASH),
+        )
+        .unwrap();
+        std::fs::write(dir.join("README"), "nothing here").unwrap();
+        let target = format!("/nix/store/{}-bash/bin/bash", HASH);
+        std::os::unix::fs::symlink(&target, dir.join("sh")).unwrap();
+
+        let r = find_references(dir, HASH).unwrap();
+        assert_eq!(
+            r,
+            vec![
+                Reference {
+                    file: dir.join("bin").join("hello"),
+                    kind: ReferenceKind::Contents,
+                    offset: 13,
+                    context: format!("#!/nix/store/{}-bash/bin/sh.", HASH),
+                },
+                Reference {
+                    file: dir.join("sh"),
+                    kind: ReferenceKind::SymlinkTarget,
+                    offset: 11,
+                    context: target,
+                },
+            ]
+        );
+    }
+}
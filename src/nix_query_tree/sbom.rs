@@ -0,0 +1,494 @@
+use serde_json::{json, Value};
+use std::fmt::Write;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{NixQueryDrv, NixQueryPathMap, NixQueryTree};
+
+/// The software bill of materials formats that can be exported.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SbomFormat {
+    /// [CycloneDX](https://cyclonedx.org/) 1.4 JSON.
+    CycloneDx,
+    /// [SPDX](https://spdx.dev/) 2.3 JSON.
+    Spdx,
+}
+
+impl SbomFormat {
+    /// The file extension conventionally used for this format.
+    #[must_use]
+    pub fn extension(self) -> &'static str {
+        match self {
+            SbomFormat::CycloneDx => "cdx.json",
+            SbomFormat::Spdx => "spdx.json",
+        }
+    }
+}
+
+impl FromStr for SbomFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "cyclonedx" | "cdx" => Ok(SbomFormat::CycloneDx),
+            "spdx" => Ok(SbomFormat::Spdx),
+            _ => Err(format!(
+                "unknown SBOM format \"{}\", expecting \"cyclonedx\" or \"spdx\"",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for SbomFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SbomFormat::CycloneDx => write!(f, "cyclonedx"),
+            SbomFormat::Spdx => write!(f, "spdx"),
+        }
+    }
+}
+
+/// A single store path in the bill of materials.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Component {
+    pub drv: NixQueryDrv,
+    pub pname: String,
+    pub version: Option<String>,
+    /// The hash part of the store path.
+    pub hash: Option<String>,
+}
+
+impl Component {
+    #[must_use]
+    pub fn new(drv: &NixQueryDrv) -> Self {
+        let (pname, version) = drv.pname_and_version();
+        Component {
+            drv: drv.clone(),
+            pname,
+            version,
+            hash: drv.hash_part(),
+        }
+    }
+
+    /// A [package URL](https://github.com/package-url/purl-spec) for this component.  The
+    /// hash part of the store path is carried in the `hash` qualifier, so the purl identifies
+    /// this exact store path.
+    ///
+    /// ```
+    /// use nix_query_tree_viewer::nix_query_tree::sbom::Component;
+    /// use nix_query_tree_viewer::nix_query_tree::NixQueryDrv;
+    ///
+    /// let drv = NixQueryDrv::from("/nix/store/a6d5lyc3bzakyvxigwbms4lsh97pw3hn-gtk+3-3.24.14");
+    ///
+    /// assert_eq!(
+    ///     Component::new(&drv).purl(),
+    ///     "pkg:nix/gtk%2B3@3.24.14?hash=a6d5lyc3bzakyvxigwbms4lsh97pw3hn"
+    /// );
+    /// ```
+    #[must_use]
+    pub fn purl(&self) -> String {
+        let mut purl = format!("pkg:nix/{}", percent_encode(&self.pname));
+        if let Some(version) = &self.version {
+            purl.push('@');
+            purl.push_str(&percent_encode(version));
+        }
+        if let Some(hash) = &self.hash {
+            purl.push_str("?hash=");
+            purl.push_str(hash);
+        }
+        purl
+    }
+
+    /// An identifier for this component that is valid as an SPDX element id.
+    fn spdx_id(&self) -> String {
+        let id: String = self
+            .drv
+            .hash_and_drv_name()
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                    c
+                } else {
+                    '-'
+                }
+            })
+            .collect();
+        format!("SPDXRef-{}", id)
+    }
+}
+
+/// A software bill of materials for the closure of a single store path.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Sbom {
+    pub root: Component,
+    /// Every distinct store path in the tree (including the root), sorted by store path.
+    pub components: Vec<Component>,
+    /// The direct dependencies of every store path that has any, sorted by store path.  Store
+    /// paths that refer to themselves are not counted as depending on themselves.
+    pub dependencies: Vec<(NixQueryDrv, Vec<NixQueryDrv>)>,
+    /// When the bill of materials was created, as an RFC 3339 UTC timestamp.
+    pub timestamp: String,
+}
+
+impl Sbom {
+    /// Create a bill of materials from a `NixQueryTree`.
+    ///
+    /// Store paths show up many times in a `NixQueryTree`, but only the first instance of
+    /// each has its children listed.  `path_map` is used to find that first instance, so
+    /// every store path becomes a single component with a single list of dependencies.
+    ///
+    /// ```
+    /// use indoc::indoc;
+    /// use nix_query_tree_viewer::nix_query_tree::sbom::Sbom;
+    /// use nix_query_tree_viewer::nix_query_tree::NixQueryTree;
+    /// use std::str::FromStr;
+    ///
+    /// let raw_tree = indoc!(
+    ///         "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+    ///         +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+    ///         |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+    ///         +---/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10 [...]
+    ///         "
+    ///     );
+    /// let nix_query_tree = NixQueryTree::from_str(raw_tree).unwrap();
+    /// let sbom = Sbom::new(&nix_query_tree, &nix_query_tree.path_map());
+    ///
+    /// assert_eq!(sbom.root.pname, "hello");
+    /// assert_eq!(sbom.components.len(), 2);
+    /// assert_eq!(sbom.dependencies.len(), 1);
+    /// ```
+    #[must_use]
+    pub fn new(tree: &NixQueryTree, path_map: &NixQueryPathMap) -> Self {
+        let mut components: Vec<Component> = path_map
+            .0
+            .iter()
+            .map(|(drv, _)| Component::new(drv))
+            .collect();
+        components.sort_by(|a, b| a.drv.cmp_hash(&b.drv));
+
+        let mut dependencies: Vec<(NixQueryDrv, Vec<NixQueryDrv>)> = path_map
+            .0
+            .iter()
+            .filter_map(|(drv, paths)| {
+                let first_path = paths.first()?.clone();
+                let subtree = tree.0.lookup_tree(first_path)?;
+                let mut children: Vec<NixQueryDrv> = subtree
+                    .children
+                    .iter()
+                    .map(|child| child.item.0.clone())
+                    .filter(|child| child != drv)
+                    .collect();
+                children.sort_by(NixQueryDrv::cmp_hash);
+                children.dedup();
+                if children.is_empty() {
+                    None
+                } else {
+                    Some((drv.clone(), children))
+                }
+            })
+            .collect();
+        dependencies.sort_by(|(a, _), (b, _)| a.cmp_hash(b));
+
+        Sbom {
+            root: Component::new(&tree.0.item.0),
+            components,
+            dependencies,
+            timestamp: now_rfc3339(),
+        }
+    }
+
+    /// Render this bill of materials as a `CycloneDX` JSON document.
+    pub fn to_cyclonedx(&self) -> Value {
+        json!({
+            "bomFormat": "CycloneDX",
+            "specVersion": "1.4",
+            "version": 1,
+            "metadata": {
+                "timestamp": self.timestamp,
+                "tools": [{
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                }],
+                "component": cyclonedx_component(&self.root),
+            },
+            "components": self
+                .components
+                .iter()
+                .filter(|component| **component != self.root)
+                .map(cyclonedx_component)
+                .collect::<Vec<Value>>(),
+            "dependencies": self
+                .dependencies
+                .iter()
+                .map(|(drv, children)| json!({
+                    "ref": drv.to_string(),
+                    "dependsOn": children
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect::<Vec<String>>(),
+                }))
+                .collect::<Vec<Value>>(),
+        })
+    }
+
+    /// Render this bill of materials as an SPDX JSON document.
+    pub fn to_spdx(&self) -> Value {
+        let spdx_ids: std::collections::HashMap<&NixQueryDrv, String> = self
+            .components
+            .iter()
+            .map(|component| (&component.drv, component.spdx_id()))
+            .collect();
+
+        let mut relationships = vec![json!({
+            "spdxElementId": "SPDXRef-DOCUMENT",
+            "relationshipType": "DESCRIBES",
+            "relatedSpdxElement": self.root.spdx_id(),
+        })];
+        for (drv, children) in &self.dependencies {
+            for child in children {
+                relationships.push(json!({
+                    "spdxElementId": spdx_ids[drv],
+                    "relationshipType": "DEPENDS_ON",
+                    "relatedSpdxElement": spdx_ids[child],
+                }));
+            }
+        }
+
+        json!({
+            "spdxVersion": "SPDX-2.3",
+            "dataLicense": "CC0-1.0",
+            "SPDXID": "SPDXRef-DOCUMENT",
+            "name": self.root.drv.drv_name(),
+            "documentNamespace": format!(
+                "https://nixos.org/spdxdocs/{}",
+                self.root.drv.hash_and_drv_name()
+            ),
+            "creationInfo": {
+                "created": self.timestamp,
+                "creators": [format!(
+                    "Tool: {}-{}",
+                    env!("CARGO_PKG_NAME"),
+                    env!("CARGO_PKG_VERSION")
+                )],
+            },
+            "packages": self
+                .components
+                .iter()
+                .map(spdx_package)
+                .collect::<Vec<Value>>(),
+            "relationships": relationships,
+        })
+    }
+
+    /// Render this bill of materials as a pretty-printed JSON document in the given format.
+    #[must_use]
+    #[allow(clippy::missing_panics_doc)] // Serializing a `Value` can't fail.
+    pub fn render(&self, format: SbomFormat) -> String {
+        let value = match format {
+            SbomFormat::CycloneDx => self.to_cyclonedx(),
+            SbomFormat::Spdx => self.to_spdx(),
+        };
+        serde_json::to_string_pretty(&value)
+            .expect("serializing a serde_json::Value should never fail")
+    }
+}
+
+/// Percent-encode everything except the characters that are never encoded in a purl.
+fn percent_encode(s: &str) -> String {
+    let mut res = String::new();
+    for byte in s.bytes() {
+        if byte.is_ascii_alphanumeric() || b".-_~".contains(&byte) {
+            res.push(char::from(byte));
+        } else {
+            write!(res, "%{:02X}", byte)
+                .expect("writing to a String never fails");
+        }
+    }
+    res
+}
+
+// The hash part of a store path is a hash of the store path's name and inputs (or of its
+// contents for fixed-output paths), truncated to 160 bits and written in nix's own base32.
+// It isn't a digest of anything a consumer could download and check, so it can't go in a
+// CycloneDX `hashes` or SPDX `checksums` entry, which have to be a full SHA-1, SHA-256, etc.
+// of the package.  It is carried in the purl instead.
+
+fn cyclonedx_component(component: &Component) -> Value {
+    let mut properties = vec![
+        json!({"name": "nix:store-path", "value": component.drv.to_string()}),
+    ];
+    if let Some(hash) = &component.hash {
+        properties.push(json!({"name": "nix:hash", "value": hash}));
+    }
+    json!({
+        "type": "application",
+        "bom-ref": component.drv.to_string(),
+        "name": component.pname,
+        "version": component.version.clone().unwrap_or_default(),
+        "purl": component.purl(),
+        "properties": properties,
+    })
+}
+
+fn spdx_package(component: &Component) -> Value {
+    json!({
+        "SPDXID": component.spdx_id(),
+        "name": component.pname,
+        "versionInfo": component.version.clone().unwrap_or_else(|| String::from("NOASSERTION")),
+        "downloadLocation": "NOASSERTION",
+        "filesAnalyzed": false,
+        "externalRefs": [
+            {
+                "referenceCategory": "PACKAGE-MANAGER",
+                "referenceType": "purl",
+                "referenceLocator": component.purl(),
+            },
+            {
+                "referenceCategory": "OTHER",
+                "referenceType": "nix-store-path",
+                "referenceLocator": component.drv.to_string(),
+            },
+        ],
+    })
+}
+
+/// The current time as an RFC 3339 UTC timestamp, like `2020-03-01T12:34:56Z`.
+fn now_rfc3339() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    rfc3339(secs)
+}
+
+/// Format seconds since the Unix epoch as an RFC 3339 UTC timestamp.
+///
+/// This uses the days-to-civil-date algorithm from
+/// <http://howardhinnant.github.io/date_algorithms.html>.
+#[allow(clippy::cast_possible_wrap, clippy::cast_sign_loss)]
+fn rfc3339(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let secs_of_day = secs % 86400;
+
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day / 60 % 60,
+        secs_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use indoc::indoc;
+
+    fn sbom() -> Sbom {
+        let raw_tree = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            +---/nix/store/i6vl5lwlz5jbkg4r6p340dwmj6fha3xq-openssl-1.1.1d
+            |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            +---/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10 [...]
+            "
+        );
+        let nix_query_tree = NixQueryTree::from_str(raw_tree).unwrap();
+        let mut sbom = Sbom::new(&nix_query_tree, &nix_query_tree.path_map());
+        sbom.timestamp = rfc3339(0);
+        sbom
+    }
+
+    #[test]
+    fn test_rfc3339() {
+        assert_eq!(rfc3339(0), "1970-01-01T00:00:00Z");
+        assert_eq!(rfc3339(1_583_066_096), "2020-03-01T12:34:56Z");
+        assert_eq!(rfc3339(951_782_400), "2000-02-29T00:00:00Z");
+    }
+
+    #[test]
+    fn test_sbom_dependencies() {
+        let glibc = NixQueryDrv::from(
+            "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27",
+        );
+        let openssl = NixQueryDrv::from(
+            "/nix/store/i6vl5lwlz5jbkg4r6p340dwmj6fha3xq-openssl-1.1.1d",
+        );
+        let hello = NixQueryDrv::from(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10",
+        );
+
+        assert_eq!(
+            sbom().dependencies,
+            vec![
+                (openssl.clone(), vec![glibc.clone()]),
+                (hello, vec![openssl, glibc]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_sbom_cyclonedx() {
+        let cyclonedx = sbom().to_cyclonedx();
+
+        assert_eq!(cyclonedx["bomFormat"], "CycloneDX");
+        assert_eq!(cyclonedx["metadata"]["timestamp"], "1970-01-01T00:00:00Z");
+        assert_eq!(cyclonedx["metadata"]["component"]["name"], "hello");
+        assert_eq!(cyclonedx["components"].as_array().unwrap().len(), 2);
+        assert_eq!(
+            cyclonedx["components"][0],
+            json!({
+                "type": "application",
+                "bom-ref": "/nix/store/i6vl5lwlz5jbkg4r6p340dwmj6fha3xq-openssl-1.1.1d",
+                "name": "openssl",
+                "version": "1.1.1d",
+                "purl": "pkg:nix/openssl@1.1.1d?hash=i6vl5lwlz5jbkg4r6p340dwmj6fha3xq",
+                "properties": [
+                    {"name": "nix:store-path", "value": "/nix/store/i6vl5lwlz5jbkg4r6p340dwmj6fha3xq-openssl-1.1.1d"},
+                    {"name": "nix:hash", "value": "i6vl5lwlz5jbkg4r6p340dwmj6fha3xq"},
+                ],
+            })
+        );
+        assert_eq!(cyclonedx["dependencies"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_sbom_spdx() {
+        let spdx = sbom().to_spdx();
+
+        assert_eq!(spdx["spdxVersion"], "SPDX-2.3");
+        assert_eq!(spdx["packages"].as_array().unwrap().len(), 3);
+        assert_eq!(
+            spdx["relationships"][0],
+            json!({
+                "spdxElementId": "SPDXRef-DOCUMENT",
+                "relationshipType": "DESCRIBES",
+                "relatedSpdxElement": "SPDXRef-qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10",
+            })
+        );
+        assert_eq!(
+            spdx["packages"][0]["externalRefs"][0],
+            json!({
+                "referenceCategory": "PACKAGE-MANAGER",
+                "referenceType": "purl",
+                "referenceLocator": "pkg:nix/openssl@1.1.1d?hash=i6vl5lwlz5jbkg4r6p340dwmj6fha3xq",
+            })
+        );
+        // One for the document, and one for each of the three dependency edges.
+        assert_eq!(spdx["relationships"].as_array().unwrap().len(), 4);
+    }
+}
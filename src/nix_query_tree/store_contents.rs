@@ -0,0 +1,377 @@
+use std::fmt::Write;
+use std::io::Read;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use super::super::tree::Tree;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum StoreContentsErr {
+    /// The path doesn't exist.  Most likely it has been garbage collected.
+    NotFound(String),
+    IoErr(String),
+}
+
+impl std::fmt::Display for StoreContentsErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StoreContentsErr::NotFound(path) => write!(
+                f,
+                "{} does not exist.  It may have been garbage collected.",
+                path
+            ),
+            StoreContentsErr::IoErr(string) => write!(f, "{}", string),
+        }
+    }
+}
+
+fn io_err(path: &Path, err: &std::io::Error) -> StoreContentsErr {
+    if err.kind() == std::io::ErrorKind::NotFound {
+        StoreContentsErr::NotFound(path.display().to_string())
+    } else {
+        StoreContentsErr::IoErr(format!("{}: {}", path.display(), err))
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FileKind {
+    Directory,
+    Regular { executable: bool },
+    Symlink { target: PathBuf },
+}
+
+/// A single file, directory, or symlink inside a store path.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FileEntry {
+    pub name: String,
+    pub path: PathBuf,
+    pub size: u64,
+    pub kind: FileKind,
+}
+
+impl FileEntry {
+    fn from_path(path: &Path) -> Result<FileEntry, StoreContentsErr> {
+        let metadata = std::fs::symlink_metadata(path)
+            .map_err(|err| io_err(path, &err))?;
+        let file_type = metadata.file_type();
+        let kind = if file_type.is_symlink() {
+            let target =
+                std::fs::read_link(path).map_err(|err| io_err(path, &err))?;
+            FileKind::Symlink { target }
+        } else if file_type.is_dir() {
+            FileKind::Directory
+        } else {
+            FileKind::Regular {
+                executable: metadata.permissions().mode() & 0o111 != 0,
+            }
+        };
+        let name = path.file_name().map_or_else(
+            || path.display().to_string(),
+            |file_name| file_name.to_string_lossy().into_owned(),
+        );
+        Ok(FileEntry {
+            name,
+            path: path.to_path_buf(),
+            size: metadata.len(),
+            kind,
+        })
+    }
+}
+
+/// The directory tree of a store path.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StoreContents {
+    pub tree: Tree<FileEntry>,
+    /// Whether or not reading the directory tree was stopped early because it had too many
+    /// entries.
+    pub truncated: bool,
+}
+
+/// Read the whole directory tree under `path`, reading at most `max_entries` entries.
+///
+/// Symlinks are never followed.  The children of each directory are sorted by name.
+///
+/// # Errors
+///
+/// This fails if `path`, or anything under it, can't be read.
+pub fn read_store_contents(
+    path: &Path,
+    max_entries: usize,
+) -> Result<StoreContents, StoreContentsErr> {
+    let mut entries_left = max_entries;
+    let mut truncated = false;
+    let tree = read_tree(path, &mut entries_left, &mut truncated)?;
+    Ok(StoreContents { tree, truncated })
+}
+
+/// The total size of all the files and symlinks under `path`, similar to
+/// `du --apparent-size`.  Directories themselves are not counted.
+///
+/// # Errors
+///
+/// This fails if `path`, or anything under it, can't be read.
+pub fn disk_usage(path: &Path) -> Result<u64, StoreContentsErr> {
+    let store_contents = read_store_contents(path, usize::MAX)?;
+    Ok(store_contents
+        .tree
+        .pre_order()
+        .map(|(_, file_entry)| match file_entry.kind {
+            FileKind::Directory => 0,
+            _ => file_entry.size,
+        })
+        .sum())
+}
+
+/// Read the directory tree under `path`, counting each entry against `entries_left`.
+/// `truncated` is set if an entry is skipped because there are no entries left.
+fn read_tree(
+    path: &Path,
+    entries_left: &mut usize,
+    truncated: &mut bool,
+) -> Result<Tree<FileEntry>, StoreContentsErr> {
+    let entry = FileEntry::from_path(path)?;
+    *entries_left = entries_left.saturating_sub(1);
+    let mut children = vec![];
+    if entry.kind == FileKind::Directory {
+        let mut child_paths = std::fs::read_dir(path)
+            .map_err(|err| io_err(path, &err))?
+            .map(|dir_entry| dir_entry.map(|d| d.path()))
+            .collect::<Result<Vec<PathBuf>, std::io::Error>>()
+            .map_err(|err| io_err(path, &err))?;
+        child_paths.sort();
+        for child_path in child_paths {
+            if *entries_left == 0 {
+                *truncated = true;
+                break;
+            }
+            children.push(read_tree(&child_path, entries_left, truncated)?);
+        }
+    }
+    Ok(Tree::new(entry, children))
+}
+
+/// A preview of the contents of a single file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FilePreview {
+    Text(String),
+    /// A `hexdump -C` style dump of a binary file.
+    Hex(String),
+    /// The file is bigger than the maximum preview size.  This holds the size of the file.
+    TooLarge(u64),
+    /// The path is a directory or symlink.
+    NotAFile,
+}
+
+/// Read a preview of the file at `path` if it is not bigger than `max_size` bytes.
+///
+/// Files that are valid UTF-8 and don't contain any NUL bytes are shown as text.  All other
+/// files are shown as a hex dump.
+///
+/// # Errors
+///
+/// This fails if `path` isn't a regular file, or can't be read.
+pub fn preview_file(
+    path: &Path,
+    max_size: u64,
+) -> Result<FilePreview, StoreContentsErr> {
+    let metadata =
+        std::fs::symlink_metadata(path).map_err(|err| io_err(path, &err))?;
+    if !metadata.file_type().is_file() {
+        return Ok(FilePreview::NotAFile);
+    }
+    if metadata.len() > max_size {
+        return Ok(FilePreview::TooLarge(metadata.len()));
+    }
+    let mut bytes = vec![];
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut bytes))
+        .map_err(|err| io_err(path, &err))?;
+    if bytes.contains(&0) {
+        Ok(FilePreview::Hex(hex_dump(&bytes)))
+    } else {
+        match String::from_utf8(bytes) {
+            Ok(text) => Ok(FilePreview::Text(text)),
+            Err(utf8_err) => {
+                Ok(FilePreview::Hex(hex_dump(utf8_err.as_bytes())))
+            }
+        }
+    }
+}
+
+/// Render bytes in the same format as `hexdump -C`.
+///
+/// ```
+/// use nix_query_tree_viewer::nix_query_tree::store_contents::hex_dump;
+///
+/// assert_eq!(
+///     hex_dump(b"\x7fELF\x02\x01\x01\x00"),
+///     "00000000  7f 45 4c 46 02 01 01 00                           |.ELF....|\n"
+/// );
+/// ```
+#[must_use]
+pub fn hex_dump(bytes: &[u8]) -> String {
+    let mut res = String::new();
+    // Writing to a `String` never fails.
+    for (line_num, chunk) in bytes.chunks(16).enumerate() {
+        let _ = write!(res, "{:08x} ", line_num * 16);
+        for i in 0..16 {
+            if i == 8 {
+                res.push(' ');
+            }
+            match chunk.get(i) {
+                Some(byte) => {
+                    let _ = write!(res, " {:02x}", byte);
+                }
+                None => res.push_str("   "),
+            }
+        }
+        res.push_str("  |");
+        for &byte in chunk {
+            if byte.is_ascii_graphic() || byte == b' ' {
+                res.push(byte as char);
+            } else {
+                res.push('.');
+            }
+        }
+        res.push_str("|\n");
+    }
+    res
+}
+
+/// Render a size in bytes in a human-readable way.
+///
+/// ```
+/// use nix_query_tree_viewer::nix_query_tree::store_contents::human_size;
+///
+/// assert_eq!(human_size(100), "100 B");
+/// assert_eq!(human_size(2048), "2.0 KiB");
+/// assert_eq!(human_size(5 * 1024 * 1024 + 1024 * 512), "5.5 MiB");
+/// ```
+#[must_use]
+pub fn human_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if size < 1024 {
+        return format!("{} B", size);
+    }
+    #[allow(clippy::cast_precision_loss)]
+    let mut value = size as f64 / 1024.0;
+    let mut unit = UNITS[0];
+    for next_unit in &UNITS[1..] {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next_unit;
+    }
+    format!("{:.1} {}", value, unit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_store_contents() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        std::fs::create_dir(dir.join("bin")).unwrap();
+        std::fs::write(dir.join("bin").join("hello"), "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(
+            dir.join("bin").join("hello"),
+            std::fs::Permissions::from_mode(0o555),
+        )
+        .unwrap();
+        std::os::unix::fs::symlink("bin/hello", dir.join("hi")).unwrap();
+
+        let r = read_store_contents(dir, 100).unwrap();
+        assert!(!r.truncated);
+        assert_eq!(r.tree.item.kind, FileKind::Directory);
+        assert_eq!(r.tree.children.len(), 2);
+
+        let bin = &r.tree.children[0];
+        assert_eq!(bin.item.name, "bin");
+        assert_eq!(
+            bin.children[0].item,
+            FileEntry {
+                name: "hello".into(),
+                path: dir.join("bin").join("hello"),
+                size: 10,
+                kind: FileKind::Regular { executable: true },
+            }
+        );
+
+        let hi = &r.tree.children[1];
+        assert_eq!(
+            hi.item.kind,
+            FileKind::Symlink {
+                target: "bin/hello".into()
+            }
+        );
+        assert_eq!(disk_usage(dir), Ok(10 + "bin/hello".len() as u64));
+    }
+
+    #[test]
+    fn test_read_store_contents_truncated() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        for i in 0..5 {
+            std::fs::write(dir.join(i.to_string()), "").unwrap();
+        }
+
+        let r = read_store_contents(dir, 3).unwrap();
+        assert!(r.truncated);
+        assert_eq!(r.tree.children.len(), 2);
+    }
+
+    #[test]
+    fn test_read_store_contents_exact_limit() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        std::fs::write(dir.join("file"), "").unwrap();
+
+        let r = read_store_contents(&dir.join("file"), 1).unwrap();
+        assert!(!r.truncated);
+
+        let r = read_store_contents(dir, 2).unwrap();
+        assert!(!r.truncated);
+        assert_eq!(r.tree.children.len(), 1);
+
+        let r = read_store_contents(dir, 1).unwrap();
+        assert!(r.truncated);
+        assert_eq!(r.tree.children.len(), 0);
+    }
+
+    #[test]
+    fn test_read_store_contents_not_found() {
+        let path =
+            Path::new("/nix/store/00000000000000000000000000000000-gone");
+        let r = read_store_contents(path, 100);
+        assert_eq!(
+            r,
+            Err(StoreContentsErr::NotFound(path.display().to_string()))
+        );
+    }
+
+    #[test]
+    fn test_preview_file() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path();
+        std::fs::write(dir.join("text"), "hello\n").unwrap();
+        std::fs::write(dir.join("binary"), b"\x00\x01").unwrap();
+        std::fs::write(dir.join("large"), "0123456789").unwrap();
+
+        assert_eq!(
+            preview_file(&dir.join("text"), 5),
+            Ok(FilePreview::TooLarge(6))
+        );
+        assert_eq!(
+            preview_file(&dir.join("text"), 100),
+            Ok(FilePreview::Text("hello\n".into()))
+        );
+        assert_eq!(
+            preview_file(&dir.join("binary"), 100),
+            Ok(FilePreview::Hex(hex_dump(b"\x00\x01")))
+        );
+        assert_eq!(preview_file(dir, 100), Ok(FilePreview::NotAFile));
+    }
+}
@@ -0,0 +1,429 @@
+//! A line-oriented parser for `nix-store --query --tree` output.
+//!
+//! Unlike the parser in `parsing`, this reads from a `BufRead` one line at a time, so only a
+//! single line of the raw output is held in memory, and it keeps the branches that are still
+//! open on an explicit stack instead of recursing, so very deep trees don't overflow the stack.
+
+use std::io::BufRead;
+
+use super::super::tree::Tree;
+use super::parsing::{is_root_line, SkippedLine};
+use super::{NixQueryEntry, NixQueryTree, Recurse};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum StreamParseErr {
+    IoErr(String),
+    /// A line that couldn't be parsed, with the line number (starting from 1) and a message.
+    ParseErr(usize, String),
+}
+
+impl std::fmt::Display for StreamParseErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StreamParseErr::IoErr(string) => write!(f, "{}", string),
+            StreamParseErr::ParseErr(line, string) => {
+                write!(f, "line {}: {}", line, string)
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for StreamParseErr {
+    fn from(err: std::io::Error) -> Self {
+        StreamParseErr::IoErr(err.to_string())
+    }
+}
+
+const EXTRA_LEVELS: [&str; 3] = ["|   ", "    ", "│   "];
+
+const BRANCH_STARTS: [&str; 3] = ["+---", "├───", "└───"];
+
+/// Split a line like `|   +---/nix/store/...` into the level of the branch (the number of
+/// `|   ` before the `+---`) and the rest of the line.
+fn split_branch(line: &str) -> Option<(usize, &str)> {
+    let mut level = 0;
+    let mut rest = line;
+    loop {
+        if let Some(entry) = BRANCH_STARTS
+            .iter()
+            .find_map(|start| rest.strip_prefix(start))
+        {
+            return Some((level, entry));
+        }
+        rest = EXTRA_LEVELS
+            .iter()
+            .find_map(|extra| rest.strip_prefix(extra))?;
+        level += 1;
+    }
+}
+
+/// Parse a nix store path, optionally followed by ` [...]`, that makes up the rest of a line.
+fn parse_entry(
+    line_number: usize,
+    raw_entry: &str,
+) -> Result<NixQueryEntry, StreamParseErr> {
+    let (raw_drv, recurse) = match raw_entry.strip_suffix(" [...]") {
+        Some(raw_drv) => (raw_drv, Recurse::Yes),
+        None => (raw_entry, Recurse::No),
+    };
+    if raw_drv.is_empty() || raw_drv.contains(char::is_whitespace) {
+        Err(StreamParseErr::ParseErr(
+            line_number,
+            format!("expecting a nix store path, but got \"{}\"", raw_entry),
+        ))
+    } else {
+        Ok(NixQueryEntry::new(raw_drv, recurse))
+    }
+}
+
+/// Strip the newline from the end of line `line_number`.  Like the parser in `parsing`, every
+/// line has to end in a newline, including the last one.
+fn strip_newline(
+    line_number: usize,
+    line: &str,
+) -> Result<&str, StreamParseErr> {
+    line.strip_suffix('\n').ok_or_else(|| {
+        StreamParseErr::ParseErr(
+            line_number,
+            format!("expecting a newline after \"{}\"", line),
+        )
+    })
+}
+
+/// The branches of a tree that haven't been closed yet.  The root is at index 0, and a branch
+/// at level n is at index n + 1.
+struct OpenBranches(Vec<Tree<NixQueryEntry>>);
+
+impl OpenBranches {
+    fn new(root: NixQueryEntry) -> Self {
+        OpenBranches(vec![Tree::singleton(root)])
+    }
+
+    /// Pop the last open branch and add it as a child of the branch before it.
+    fn close_branch(&mut self) {
+        if let Some(tree) = self.0.pop() {
+            if let Some(parent) = self.0.last_mut() {
+                parent.children.push(tree);
+            }
+        }
+    }
+
+    /// Add the branch from line `line_number`, which was split into `level` and `raw_entry`
+    /// by `split_branch`.  Every open branch nested at least as deep is closed first.
+    fn push_branch(
+        &mut self,
+        line_number: usize,
+        level: usize,
+        raw_entry: &str,
+    ) -> Result<(), StreamParseErr> {
+        if level >= self.0.len() {
+            return Err(StreamParseErr::ParseErr(
+                line_number,
+                format!(
+                    "expecting a branch nested at most {} levels deep, but got one nested {} levels deep",
+                    self.0.len() - 1,
+                    level
+                ),
+            ));
+        }
+        let entry = parse_entry(line_number, raw_entry)?;
+
+        while self.0.len() > level + 1 {
+            self.close_branch();
+        }
+        self.0.push(Tree::singleton(entry));
+        Ok(())
+    }
+
+    fn into_nix_query_tree(mut self) -> NixQueryTree {
+        while self.0.len() > 1 {
+            self.close_branch();
+        }
+        let tree = self
+            .0
+            .pop()
+            .expect("the root is never popped from the stack");
+        NixQueryTree(tree)
+    }
+}
+
+/// Parse all output from `nix-store --query --tree`, reading it one line at a time.
+///
+/// ```
+/// use nix_query_tree_viewer::nix_query_tree::stream_parsing::read_nix_query_tree;
+/// use nix_query_tree_viewer::nix_query_tree::{NixQueryDrv, Recurse};
+/// use nix_query_tree_viewer::tree::Path;
+///
+/// let raw_tree = "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+/// +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+/// |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+/// +---/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10 [...]
+/// ";
+/// let nix_query_tree = read_nix_query_tree(raw_tree.as_bytes()).unwrap();
+/// let entry = nix_query_tree.lookup(Path::from(vec![0, 0])).unwrap();
+///
+/// assert_eq!(
+///     entry.0,
+///     NixQueryDrv::from("/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27")
+/// );
+/// assert_eq!(entry.1, Recurse::Yes);
+/// ```
+///
+/// # Errors
+///
+/// This fails if `reader` can't be read, or a line isn't part of the tree.
+pub fn read_nix_query_tree<R: BufRead>(
+    mut reader: R,
+) -> Result<NixQueryTree, StreamParseErr> {
+    let mut line = String::new();
+
+    if reader.read_line(&mut line)? == 0 {
+        return Err(StreamParseErr::ParseErr(
+            1,
+            String::from("expecting a nix store path, but got no output"),
+        ));
+    }
+    let raw_root = strip_newline(1, &line)?;
+    let root = parse_entry(1, raw_root)?;
+    // Only branches can refer back to an earlier entry.
+    if root.1 == Recurse::Yes {
+        return Err(StreamParseErr::ParseErr(
+            1,
+            format!(
+                "expecting a nix store path without \"[...]\", but got \"{}\"",
+                raw_root
+            ),
+        ));
+    }
+
+    let mut open_branches = OpenBranches::new(root);
+    let mut line_number = 1;
+
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        line_number += 1;
+        let raw_line = strip_newline(line_number, &line)?;
+
+        let (level, raw_entry) = split_branch(raw_line).ok_or_else(|| {
+            StreamParseErr::ParseErr(
+                line_number,
+                format!(
+                    "expecting a branch like \"+---\", but got \"{}\"",
+                    raw_line
+                ),
+            )
+        })?;
+        open_branches.push_branch(line_number, level, raw_entry)?;
+    }
+
+    Ok(open_branches.into_nix_query_tree())
+}
+
+/// Read the next line from `reader`, including the newline.  The line is appended to `raw` if
+/// it is given, and read into `line` otherwise.  Return `None` at the end of the input.
+fn read_line<'a, R: BufRead>(
+    reader: &mut R,
+    raw: Option<&'a mut String>,
+    line: &'a mut String,
+) -> std::io::Result<Option<&'a str>> {
+    if let Some(raw) = raw {
+        let line_start = raw.len();
+        if reader.read_line(raw)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(&raw[line_start..]))
+    } else {
+        line.clear();
+        if reader.read_line(line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(line.as_str()))
+    }
+}
+
+/// Parse output from `nix-store --query --tree` that may have other things mixed in, reading
+/// it one line at a time.
+///
+/// This is the streaming version of `parsing::nix_query_tree_parser_lenient`.  Lines that
+/// aren't part of the tree are skipped and returned along with the tree, and CRLF line
+/// endings are accepted.  If `raw` is given, every line that is read, including the skipped
+/// ones, is appended to it, so the output can still be shown when parsing fails.  Otherwise
+/// only a single line is held in memory at a time.
+///
+/// ```
+/// use nix_query_tree_viewer::nix_query_tree::stream_parsing::read_nix_query_tree_lenient;
+///
+/// let raw_tree = "warning: unknown setting 'foo'\r
+/// /nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10\r
+/// +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27";
+/// let mut raw = String::new();
+/// let (nix_query_tree, skipped) =
+///     read_nix_query_tree_lenient(raw_tree.as_bytes(), Some(&mut raw)).unwrap();
+///
+/// assert_eq!(nix_query_tree.0.children.len(), 1);
+/// assert_eq!(skipped.len(), 1);
+/// assert_eq!(skipped[0].line, 1);
+/// assert_eq!(raw, raw_tree);
+/// ```
+///
+/// # Errors
+///
+/// This fails if `reader` can't be read, no line looks like the root of a tree, or the
+/// tree is malformed.
+pub fn read_nix_query_tree_lenient<R: BufRead>(
+    mut reader: R,
+    mut raw: Option<&mut String>,
+) -> Result<(NixQueryTree, Vec<SkippedLine>), StreamParseErr> {
+    let mut skipped = vec![];
+    let mut option_open_branches: Option<OpenBranches> = None;
+    let mut line_number = 0;
+    let mut line = String::new();
+
+    while let Some(next_line) =
+        read_line(&mut reader, raw.as_deref_mut(), &mut line)?
+    {
+        line_number += 1;
+        let raw_line = next_line.trim_end_matches('\n').trim_end_matches('\r');
+
+        match &mut option_open_branches {
+            None if is_root_line(raw_line) => {
+                let root = parse_entry(line_number, raw_line)?;
+                option_open_branches = Some(OpenBranches::new(root));
+            }
+            Some(open_branches) => match split_branch(raw_line) {
+                Some((level, raw_entry)) => {
+                    open_branches.push_branch(line_number, level, raw_entry)?;
+                }
+                None => skipped.push(SkippedLine {
+                    line: line_number,
+                    line_text: String::from(raw_line),
+                }),
+            },
+            None => skipped.push(SkippedLine {
+                line: line_number,
+                line_text: String::from(raw_line),
+            }),
+        }
+    }
+
+    match option_open_branches {
+        Some(open_branches) => {
+            Ok((open_branches.into_nix_query_tree(), skipped))
+        }
+        None => Err(StreamParseErr::ParseErr(
+            1,
+            String::from("expecting a nix store path, but got no output"),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use indoc::indoc;
+
+    use crate::nix_query_tree::parsing::{
+        nix_query_tree_parser, nix_query_tree_parser_lenient,
+    };
+
+    #[test]
+    fn test_same_as_nom_parser() {
+        let raw_input = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            ├───/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            │   ├───/nix/store/a9n6ip5d1m7b3blkxmmg0dd5z4cbqgvf-acl-2.2.53
+            │   │   └───/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            │   └───/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            └───/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10 [...]
+            "
+        );
+        assert_eq!(
+            read_nix_query_tree(raw_input.as_bytes()),
+            Ok(nix_query_tree_parser(raw_input).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_deep_tree() {
+        let depth = 2_000;
+        let mut raw_input = String::from("/nix/store/aaaa-root\n");
+        for level in 0..depth {
+            raw_input.push_str(&"|   ".repeat(level));
+            raw_input.push_str("+---/nix/store/aaaa-child\n");
+        }
+
+        let tree = read_nix_query_tree(raw_input.as_bytes()).unwrap();
+        let mut node = &tree.0;
+        let mut found_depth = 0;
+        while let Some(child) = node.children.first() {
+            node = child;
+            found_depth += 1;
+        }
+        assert_eq!(found_depth, depth);
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            read_nix_query_tree("".as_bytes()),
+            Err(StreamParseErr::ParseErr(
+                1,
+                String::from("expecting a nix store path, but got no output")
+            ))
+        );
+
+        let raw_input = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            |   |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            "
+        );
+        assert!(matches!(
+            read_nix_query_tree(raw_input.as_bytes()),
+            Err(StreamParseErr::ParseErr(3, _))
+        ));
+
+        let raw_input = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [..]
+            "
+        );
+        assert!(matches!(
+            read_nix_query_tree(raw_input.as_bytes()),
+            Err(StreamParseErr::ParseErr(2, _))
+        ));
+    }
+
+    #[test]
+    fn test_same_as_nom_parser_lenient() {
+        let raw_input = "warning: unknown setting 'foo'\r\n\
+            /nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10\r\n\
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27\r\n\
+            \r\n\
+            |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]\r\n\
+            +---/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10 [...]";
+        let mut raw = String::new();
+        assert_eq!(
+            read_nix_query_tree_lenient(raw_input.as_bytes(), Some(&mut raw)),
+            Ok(nix_query_tree_parser_lenient(raw_input).unwrap())
+        );
+        assert_eq!(raw, raw_input);
+        assert_eq!(
+            read_nix_query_tree_lenient(raw_input.as_bytes(), None),
+            Ok(nix_query_tree_parser_lenient(raw_input).unwrap())
+        );
+
+        let mut raw = String::new();
+        assert!(matches!(
+            read_nix_query_tree_lenient(
+                "warning: foo\n".as_bytes(),
+                Some(&mut raw)
+            ),
+            Err(StreamParseErr::ParseErr(1, _))
+        ));
+    }
+}
@@ -0,0 +1,585 @@
+//! Check which store paths in a tree are already in a binary cache, and how much would have to
+//! be uploaded for the rest.
+//!
+//! The binary cache can be a local directory (or `file://` URI) like the ones `narinfo` reads,
+//! or a plain `http://` endpoint serving the same `<hash>.narinfo` files.  A store path is
+//! substitutable when the binary cache has a `.narinfo` file for it.
+
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use super::super::tree::{Path as TreePath, Tree};
+use super::exec_nix_store::NixStoreRes;
+use super::narinfo::{self, BinaryCache, NarInfo, NarInfoErr};
+use super::store_contents::{self, human_size};
+use super::{NixQueryDrv, NixQueryEntry};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SubstituteErr {
+    /// The URI isn't for a kind of binary cache that can be checked.
+    UriErr(String),
+    IoErr(String),
+    HttpErr(String),
+    NarInfoErr(String),
+}
+
+impl std::fmt::Display for SubstituteErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            SubstituteErr::UriErr(string)
+            | SubstituteErr::IoErr(string)
+            | SubstituteErr::HttpErr(string)
+            | SubstituteErr::NarInfoErr(string) => string,
+        };
+        write!(f, "{}", string)
+    }
+}
+
+impl From<NarInfoErr> for SubstituteErr {
+    fn from(err: NarInfoErr) -> Self {
+        SubstituteErr::NarInfoErr(err.to_string())
+    }
+}
+
+/// How long to wait for an HTTP binary cache to accept a connection, or to send or receive
+/// data, before giving up.
+const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A binary cache served over plain HTTP, like `http://localhost:8080/cache`.
+#[derive(Clone)]
+pub struct HttpCache {
+    host: String,
+    port: u16,
+    /// The path of the binary cache on the server, without a trailing `/`.
+    prefix: String,
+    /// The connection that the server kept open after the last response, so checking a whole
+    /// tree doesn't need a new connection for every store path.
+    connection: Arc<Mutex<Option<BufReader<TcpStream>>>>,
+}
+
+impl std::fmt::Debug for HttpCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HttpCache")
+            .field("host", &self.host)
+            .field("port", &self.port)
+            .field("prefix", &self.prefix)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Two `HttpCache`s are equal when they are for the same binary cache, whether or not they
+/// have a connection open.
+impl PartialEq for HttpCache {
+    fn eq(&self, other: &Self) -> bool {
+        self.host == other.host
+            && self.port == other.port
+            && self.prefix == other.prefix
+    }
+}
+
+impl Eq for HttpCache {}
+
+fn http_err(err: &std::io::Error) -> SubstituteErr {
+    SubstituteErr::HttpErr(err.to_string())
+}
+
+/// Read a body with `Transfer-Encoding: chunked` into `body`.
+fn read_chunked<R: BufRead>(
+    reader: &mut R,
+    body: &mut Vec<u8>,
+) -> std::io::Result<()> {
+    let bad_chunk = || {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, "Bad chunk size")
+    };
+    let mut line = String::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line)?;
+        let size_str = line.trim().split(';').next().unwrap_or("");
+        let size =
+            usize::from_str_radix(size_str, 16).map_err(|_| bad_chunk())?;
+        if size == 0 {
+            // Skip any trailers, up to the empty line that ends the body.
+            loop {
+                line.clear();
+                if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+                    return Ok(());
+                }
+            }
+        }
+        let start = body.len();
+        body.resize(start + size, 0);
+        reader.read_exact(&mut body[start..])?;
+        // The CRLF after the chunk.
+        line.clear();
+        reader.read_line(&mut line)?;
+    }
+}
+
+impl HttpCache {
+    fn new(host: &str, port: u16, prefix: &str) -> Self {
+        HttpCache {
+            host: String::from(host),
+            port,
+            prefix: String::from(prefix.trim_end_matches('/')),
+            connection: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn from_uri(uri: &str) -> Result<Self, SubstituteErr> {
+        let rest = uri.strip_prefix("http://").ok_or_else(|| {
+            SubstituteErr::UriErr(format!("Not an http:// URI: {}", uri))
+        })?;
+        let (authority, prefix) = match rest.find('/') {
+            None => (rest, ""),
+            Some(i) => (&rest[..i], &rest[i..]),
+        };
+        let (host, port) = match authority.rfind(':') {
+            None => (authority, 80),
+            Some(i) => {
+                let port = authority[i + 1..].parse().map_err(|_| {
+                    SubstituteErr::UriErr(format!("Bad port in {}", uri))
+                })?;
+                (&authority[..i], port)
+            }
+        };
+        if host.is_empty() {
+            return Err(SubstituteErr::UriErr(format!("No host in {}", uri)));
+        }
+        Ok(HttpCache::new(host, port, prefix))
+    }
+
+    /// The value of the `Host` header.  The port is left out when it is the default one.
+    fn host_header(&self) -> String {
+        if self.port == 80 {
+            self.host.clone()
+        } else {
+            format!("{}:{}", self.host, self.port)
+        }
+    }
+
+    /// Open a new connection to the server, trying each of its addresses in turn.
+    fn connect(&self) -> Result<BufReader<TcpStream>, SubstituteErr> {
+        let addrs = (self.host.as_str(), self.port)
+            .to_socket_addrs()
+            .map_err(|err| http_err(&err))?;
+        let mut last_err = SubstituteErr::HttpErr(format!(
+            "{} doesn't have any addresses",
+            self.host
+        ));
+        for addr in addrs {
+            match TcpStream::connect_timeout(&addr, HTTP_TIMEOUT) {
+                Ok(stream) => {
+                    stream
+                        .set_read_timeout(Some(HTTP_TIMEOUT))
+                        .and_then(|()| {
+                            stream.set_write_timeout(Some(HTTP_TIMEOUT))
+                        })
+                        .map_err(|err| http_err(&err))?;
+                    return Ok(BufReader::new(stream));
+                }
+                Err(err) => last_err = http_err(&err),
+            }
+        }
+        Err(last_err)
+    }
+
+    /// `GET` a file from the binary cache, returning the status code and the body.
+    ///
+    /// The connection is kept open for the next request if the server allows it.  A server
+    /// may close a kept-open connection at any time, so if a request on one fails, it is sent
+    /// again on a new connection.
+    fn get(&self, file_name: &str) -> Result<(u16, String), SubstituteErr> {
+        let mut connection = self.connection.lock().unwrap();
+        if let Some(mut reader) = connection.take() {
+            if let Ok((response, keep_alive)) =
+                self.request(&mut reader, file_name)
+            {
+                if keep_alive {
+                    *connection = Some(reader);
+                }
+                return Ok(response);
+            }
+        }
+        let mut reader = self.connect()?;
+        let (response, keep_alive) = self.request(&mut reader, file_name)?;
+        if keep_alive {
+            *connection = Some(reader);
+        }
+        Ok(response)
+    }
+
+    /// Send a `GET` request for `file_name` over `reader`'s connection and read the response.
+    /// This also returns whether the server left the connection open for another request.
+    fn request(
+        &self,
+        reader: &mut BufReader<TcpStream>,
+        file_name: &str,
+    ) -> Result<((u16, String), bool), SubstituteErr> {
+        let bad_response = || {
+            SubstituteErr::HttpErr(format!(
+                "Bad response from {}:{}",
+                self.host, self.port
+            ))
+        };
+        let request = format!(
+            "GET {}/{} HTTP/1.1\r\nHost: {}\r\nAccept: text/x-nix-narinfo\r\n\r\n",
+            self.prefix,
+            file_name,
+            self.host_header()
+        );
+        reader
+            .get_mut()
+            .write_all(request.as_bytes())
+            .map_err(|err| http_err(&err))?;
+
+        let mut line = String::new();
+        reader.read_line(&mut line).map_err(|err| http_err(&err))?;
+        let mut status_line = line.split_whitespace();
+        let http_version = status_line.next().unwrap_or("");
+        let status = status_line
+            .next()
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(bad_response)?;
+
+        // HTTP/1.1 connections stay open unless the server says otherwise, and HTTP/1.0 ones
+        // are closed unless the server says otherwise.
+        let mut keep_alive = http_version == "HTTP/1.1";
+        let mut content_length: Option<usize> = None;
+        let mut chunked = false;
+        loop {
+            line.clear();
+            if reader.read_line(&mut line).map_err(|err| http_err(&err))? == 0 {
+                return Err(bad_response());
+            }
+            let header = line.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = header.split_once(':') {
+                let value = value.trim();
+                match name.trim().to_ascii_lowercase().as_str() {
+                    "content-length" => {
+                        content_length =
+                            Some(value.parse().map_err(|_| bad_response())?);
+                    }
+                    "transfer-encoding" => {
+                        chunked = value.eq_ignore_ascii_case("chunked");
+                    }
+                    "connection" => {
+                        keep_alive = value.eq_ignore_ascii_case("keep-alive");
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut body = vec![];
+        if chunked {
+            read_chunked(reader, &mut body)
+        } else if let Some(content_length) = content_length {
+            body.resize(content_length, 0);
+            reader.read_exact(&mut body)
+        } else {
+            // Without a length, the body ends when the server closes the connection.
+            keep_alive = false;
+            reader.read_to_end(&mut body).map(|_| ())
+        }
+        .map_err(|err| http_err(&err))?;
+        let body = String::from_utf8(body).map_err(|_| bad_response())?;
+        Ok(((status, body), keep_alive))
+    }
+
+    fn narinfo(
+        &self,
+        drv: &NixQueryDrv,
+    ) -> Result<Option<NarInfo>, SubstituteErr> {
+        let Some(hash_part) = drv.hash_part() else {
+            return Ok(None);
+        };
+        match self.get(&format!("{}.narinfo", hash_part))? {
+            (200, body) => Ok(Some(body.parse()?)),
+            // Caches in S3 buckets answer 403 for files that don't exist.
+            (403 | 404, _) => Ok(None),
+            (status, _) => Err(SubstituteErr::HttpErr(format!(
+                "Unexpected status {} from {}:{} for {}",
+                status, self.host, self.port, drv
+            ))),
+        }
+    }
+}
+
+/// A binary cache to check store paths against.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Substituter {
+    BinaryCache(BinaryCache),
+    Http(HttpCache),
+}
+
+impl Substituter {
+    /// The binary cache at `uri`.  This can be a `file://` URI, a plain directory, or an
+    /// `http://` URI.  `https://` isn't supported, but a local server can stand in for a
+    /// remote cache.
+    ///
+    /// # Errors
+    ///
+    /// This fails if `uri` is an unsupported or malformed URI, or the directory isn't a
+    /// binary cache.
+    pub fn from_uri(uri: &str) -> Result<Self, SubstituteErr> {
+        if uri.starts_with("http://") {
+            HttpCache::from_uri(uri).map(Substituter::Http)
+        } else if uri.contains("://") && !uri.starts_with("file://") {
+            Err(SubstituteErr::UriErr(format!(
+                "Only local directories, file:// URIs, and http:// URIs can be checked, not {}",
+                uri
+            )))
+        } else {
+            let dir = narinfo::binary_cache_dir(uri)
+                .unwrap_or_else(|| Path::new(uri).to_path_buf());
+            Ok(Substituter::BinaryCache(BinaryCache::open(&dir)?))
+        }
+    }
+
+    /// The `.narinfo` for `drv` in the binary cache, or `None` if it isn't there.
+    ///
+    /// # Errors
+    ///
+    /// This fails if the binary cache can't be read or reached, or the `.narinfo` can't be
+    /// parsed.
+    pub fn lookup(
+        &self,
+        drv: &NixQueryDrv,
+    ) -> Result<Option<NarInfo>, SubstituteErr> {
+        match self {
+            Substituter::BinaryCache(binary_cache) => {
+                match binary_cache.narinfo(drv) {
+                    Ok(narinfo) => Ok(Some(narinfo)),
+                    Err(NarInfoErr::NotFound(_)) => Ok(None),
+                    Err(err) => Err(err.into()),
+                }
+            }
+            Substituter::Http(http_cache) => http_cache.narinfo(drv),
+        }
+    }
+}
+
+/// A store path that isn't in the binary cache.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MissingPath {
+    pub drv: NixQueryDrv,
+    /// The size of the NAR that would have to be uploaded, if it is known.
+    pub size: Option<u64>,
+}
+
+/// Which store paths in a tree are in a binary cache.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SubstituteReport {
+    /// The store paths that are in the binary cache.
+    pub cached: HashSet<NixQueryDrv>,
+    /// The store paths that aren't, sorted with `NixQueryDrv::cmp_hash`.  `is_cached` relies
+    /// on this order.
+    pub missing: Vec<MissingPath>,
+}
+
+/// The size of the NAR for `drv`.  This comes from the `.narinfo` when the tree was read from
+/// a binary cache, and otherwise from the size of the files in the local store, which is close
+/// to the NAR size.
+fn nar_size(nix_store_res: &NixStoreRes, drv: &NixQueryDrv) -> Option<u64> {
+    match nix_store_res.narinfos.get(drv) {
+        Some(narinfo) => narinfo.nar_size,
+        None => store_contents::disk_usage(drv).ok(),
+    }
+}
+
+/// Check every store path in `nix_store_res` against `substituter`.
+///
+/// # Errors
+///
+/// This fails on the first store path that can't be looked up in `substituter`.
+pub fn check(
+    substituter: &Substituter,
+    nix_store_res: &NixStoreRes,
+) -> Result<SubstituteReport, SubstituteErr> {
+    let mut drvs: Vec<&NixQueryDrv> =
+        nix_store_res.map.0.iter().map(|(drv, _)| drv).collect();
+    drvs.sort_by(|a, b| a.cmp_hash(b));
+
+    let mut report = SubstituteReport::default();
+    for drv in drvs {
+        if substituter.lookup(drv)?.is_some() {
+            report.cached.insert(drv.clone());
+        } else {
+            report.missing.push(MissingPath {
+                drv: drv.clone(),
+                size: nar_size(nix_store_res, drv),
+            });
+        }
+    }
+    Ok(report)
+}
+
+impl SubstituteReport {
+    /// Whether `drv` is in the binary cache, or `None` if it wasn't checked.
+    #[must_use]
+    pub fn is_cached(&self, drv: &NixQueryDrv) -> Option<bool> {
+        if self.cached.contains(drv) {
+            Some(true)
+        } else if self
+            .missing
+            .binary_search_by(|missing| missing.drv.cmp_hash(drv))
+            .is_ok()
+        {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// The total size of the missing store paths whose size is known.
+    #[must_use]
+    pub fn bytes_to_upload(&self) -> u64 {
+        self.missing.iter().filter_map(|missing| missing.size).sum()
+    }
+
+    /// A one line summary, like
+    /// `2 of 4 store paths are missing from the binary cache, 1.2 MiB to upload.`
+    #[must_use]
+    pub fn summary(&self) -> String {
+        let total = self.cached.len() + self.missing.len();
+        if self.missing.is_empty() {
+            return format!(
+                "All {} store paths are in the binary cache.",
+                total
+            );
+        }
+        let unknown = self
+            .missing
+            .iter()
+            .filter(|missing| missing.size.is_none())
+            .count();
+        let unknown_str = if unknown == 0 {
+            String::new()
+        } else {
+            format!(", plus {} store paths of unknown size", unknown)
+        };
+        format!(
+            "{} of {} store paths are missing from the binary cache, {} to upload{}.",
+            self.missing.len(),
+            total,
+            human_size(self.bytes_to_upload()),
+            unknown_str
+        )
+    }
+
+    /// The summary, followed by each missing store path and its size.
+    pub fn report(&self) -> String {
+        let mut report = self.summary();
+        report.push('\n');
+        for missing in &self.missing {
+            let size =
+                missing.size.map_or_else(|| String::from("?"), human_size);
+            // Writing to a `String` never fails.
+            let _ = writeln!(report, "{}  {}", missing.drv, size);
+        }
+        report
+    }
+
+    /// The store paths that are missing from the binary cache.
+    #[must_use]
+    pub fn missing_drvs(&self) -> HashSet<&NixQueryDrv> {
+        self.missing.iter().map(|missing| &missing.drv).collect()
+    }
+
+    /// The `Path`s of the rows in `tree` for store paths missing from the binary cache, along
+    /// with all their ancestors and the root.  These are the rows to show when only the
+    /// missing store paths are shown.
+    #[must_use]
+    pub fn missing_paths(
+        &self,
+        tree: &Tree<NixQueryEntry>,
+    ) -> HashSet<TreePath> {
+        let missing = self.missing_drvs();
+        let mut visible = HashSet::new();
+        for (path, entry) in tree.pre_order() {
+            if missing.contains(&entry.0) {
+                let mut ancestor = Some(path);
+                while let Some(path) = ancestor {
+                    ancestor = path.parent();
+                    if !visible.insert(path) {
+                        break;
+                    }
+                }
+            }
+        }
+        // The root is always shown.
+        visible.insert(TreePath::new());
+        visible
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_http_cache_from_uri() {
+        assert_eq!(
+            HttpCache::from_uri("http://localhost:8080/cache/"),
+            Ok(HttpCache::new("localhost", 8080, "/cache"))
+        );
+        assert_eq!(
+            HttpCache::from_uri("http://cache.example.org"),
+            Ok(HttpCache::new("cache.example.org", 80, ""))
+        );
+        assert_eq!(
+            HttpCache::new("localhost", 8080, "").host_header(),
+            "localhost:8080"
+        );
+        assert_eq!(
+            HttpCache::new("cache.example.org", 80, "").host_header(),
+            "cache.example.org"
+        );
+        assert!(HttpCache::from_uri("http://localhost:http/").is_err());
+        assert!(Substituter::from_uri("https://cache.nixos.org").is_err());
+    }
+
+    #[test]
+    fn test_summary() {
+        let drv = |name: &str| {
+            NixQueryDrv::from(&format!(
+                "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-{}",
+                name
+            ))
+        };
+        let mut report = SubstituteReport::default();
+        report.cached.insert(drv("hello-2.10"));
+        assert_eq!(
+            report.summary(),
+            "All 1 store paths are in the binary cache."
+        );
+
+        report.missing = vec![
+            MissingPath {
+                drv: drv("acl-2.2.53"),
+                size: None,
+            },
+            MissingPath {
+                drv: drv("glibc-2.27"),
+                size: Some(2048),
+            },
+        ];
+        assert_eq!(
+            report.summary(),
+            "2 of 3 store paths are missing from the binary cache, 2.0 KiB to upload, plus 1 store paths of unknown size."
+        );
+        assert_eq!(report.is_cached(&drv("hello-2.10")), Some(true));
+        assert_eq!(report.is_cached(&drv("acl-2.2.53")), Some(false));
+        assert_eq!(report.is_cached(&drv("glibc-2.27")), Some(false));
+        assert_eq!(report.is_cached(&drv("bash-4.4")), None);
+    }
+}
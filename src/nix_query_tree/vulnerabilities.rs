@@ -0,0 +1,484 @@
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+
+use super::{compare_versions, split_output, NixQueryDrv, NixQueryPathMap};
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum VulnDbErr {
+    IoErr(String),
+    JsonErr(String),
+    /// The JSON is valid, but it is not an OSV or NVD feed.
+    FormatErr(String),
+}
+
+impl std::fmt::Display for VulnDbErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            VulnDbErr::IoErr(string)
+            | VulnDbErr::JsonErr(string)
+            | VulnDbErr::FormatErr(string) => string,
+        };
+        write!(f, "{}", string)
+    }
+}
+
+/// One end of a `VersionRange`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Bound {
+    pub version: String,
+    pub inclusive: bool,
+}
+
+/// A range of affected versions.  A missing bound means the range is unbounded on that side.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct VersionRange {
+    pub start: Option<Bound>,
+    pub end: Option<Bound>,
+}
+
+impl VersionRange {
+    #[must_use]
+    pub fn contains(&self, version: &str) -> bool {
+        let after_start = self.start.as_ref().is_none_or(|start| {
+            match compare_versions(version, &start.version) {
+                Ordering::Greater => true,
+                Ordering::Equal => start.inclusive,
+                Ordering::Less => false,
+            }
+        });
+        let before_end = self.end.as_ref().is_none_or(|end| {
+            match compare_versions(version, &end.version) {
+                Ordering::Less => true,
+                Ordering::Equal => end.inclusive,
+                Ordering::Greater => false,
+            }
+        });
+        after_start && before_end
+    }
+}
+
+/// A package affected by an `Advisory`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AffectedPackage {
+    pub pname: String,
+    /// Exact versions that are affected.
+    pub versions: Vec<String>,
+    pub ranges: Vec<VersionRange>,
+}
+
+impl AffectedPackage {
+    fn matches(&self, pname: &str, version: &str) -> bool {
+        normalize_pname(&self.pname) == normalize_pname(pname)
+            && (self.versions.iter().any(|v| v == version)
+                || self.ranges.iter().any(|range| range.contains(version)))
+    }
+}
+
+/// A single vulnerability, like a CVE.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Advisory {
+    pub id: String,
+    /// The severity, like `HIGH`, or a CVSS vector if there is no textual severity.
+    pub severity: Option<String>,
+    pub summary: Option<String>,
+    pub affected: Vec<AffectedPackage>,
+}
+
+impl Advisory {
+    #[must_use]
+    pub fn matches(&self, pname: &str, version: &str) -> bool {
+        self.affected
+            .iter()
+            .any(|affected| affected.matches(pname, version))
+    }
+}
+
+/// A database of vulnerabilities loaded from a local feed.
+///
+/// Both OSV JSON (a single advisory, a list of advisories, or an object with a `vulns` list)
+/// and NVD JSON 1.1 feeds (an object with a `CVE_Items` list) are supported.  NVD entries are
+/// matched using the product name from their CPEs.
+///
+/// ```
+/// use nix_query_tree_viewer::nix_query_tree::vulnerabilities::VulnDb;
+/// use std::str::FromStr;
+///
+/// let raw_feed = r#"{
+///     "id": "CVE-2020-1967",
+///     "affected": [{
+///         "package": { "name": "openssl" },
+///         "ranges": [{ "type": "ECOSYSTEM", "events": [{ "introduced": "1.1.1d" }, { "fixed": "1.1.1g" }] }]
+///     }]
+/// }"#;
+/// let vuln_db = VulnDb::from_str(raw_feed).unwrap();
+///
+/// assert_eq!(vuln_db.lookup("openssl", "1.1.1f").len(), 1);
+/// assert!(vuln_db.lookup("openssl", "1.1.1g").is_empty());
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct VulnDb {
+    pub advisories: Vec<Advisory>,
+}
+
+impl VulnDb {
+    /// Read and parse a feed from disk.
+    ///
+    /// # Errors
+    ///
+    /// This fails if `path` can't be read, or isn't an OSV or NVD JSON feed.
+    pub fn from_path(path: &Path) -> Result<VulnDb, VulnDbErr> {
+        let raw = std::fs::read_to_string(path).map_err(|io_err| {
+            VulnDbErr::IoErr(format!("{}: {}", path.display(), io_err))
+        })?;
+        VulnDb::from_str(&raw)
+    }
+
+    /// All the advisories that affect this version of the package `pname`.
+    #[must_use]
+    pub fn lookup(&self, pname: &str, version: &str) -> Vec<&Advisory> {
+        self.advisories
+            .iter()
+            .filter(|advisory| advisory.matches(pname, version))
+            .collect()
+    }
+}
+
+impl FromStr for VulnDb {
+    type Err = VulnDbErr;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let json: Value = serde_json::from_str(s)
+            .map_err(|err| VulnDbErr::JsonErr(err.to_string()))?;
+        let advisories = if let Some(cve_items) = json.get("CVE_Items") {
+            as_array(cve_items, "CVE_Items")?
+                .iter()
+                .map(parse_nvd_item)
+                .collect::<Result<_, _>>()?
+        } else if let Some(vulns) = json.get("vulns") {
+            as_array(vulns, "vulns")?
+                .iter()
+                .map(parse_osv)
+                .collect::<Result<_, _>>()?
+        } else if let Value::Array(vulns) = &json {
+            vulns.iter().map(parse_osv).collect::<Result<_, _>>()?
+        } else {
+            vec![parse_osv(&json)?]
+        };
+        Ok(VulnDb { advisories })
+    }
+}
+
+fn as_array<'a>(
+    value: &'a Value,
+    name: &str,
+) -> Result<&'a Vec<Value>, VulnDbErr> {
+    value.as_array().ok_or_else(|| {
+        VulnDbErr::FormatErr(format!("`{}` is not a list", name))
+    })
+}
+
+fn get_str(value: &Value, pointer: &str) -> Option<String> {
+    value
+        .pointer(pointer)
+        .and_then(Value::as_str)
+        .map(String::from)
+}
+
+fn parse_osv(value: &Value) -> Result<Advisory, VulnDbErr> {
+    let id = get_str(value, "/id").ok_or_else(|| {
+        VulnDbErr::FormatErr(String::from("OSV entry without an `id`"))
+    })?;
+    let severity = get_str(value, "/database_specific/severity")
+        .or_else(|| get_str(value, "/severity/0/score"));
+    let summary =
+        get_str(value, "/summary").or_else(|| get_str(value, "/details"));
+    let affected = value
+        .get("affected")
+        .and_then(Value::as_array)
+        .map(|affected| {
+            affected.iter().filter_map(parse_osv_affected).collect()
+        })
+        .unwrap_or_default();
+    Ok(Advisory {
+        id,
+        severity,
+        summary,
+        affected,
+    })
+}
+
+fn parse_osv_affected(value: &Value) -> Option<AffectedPackage> {
+    let pname = get_str(value, "/package/name")?;
+    let versions = value
+        .get("versions")
+        .and_then(Value::as_array)
+        .map(|versions| {
+            versions
+                .iter()
+                .filter_map(Value::as_str)
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default();
+    let ranges = value
+        .get("ranges")
+        .and_then(Value::as_array)
+        .map(|ranges| ranges.iter().flat_map(parse_osv_range).collect())
+        .unwrap_or_default();
+    Some(AffectedPackage {
+        pname,
+        versions,
+        ranges,
+    })
+}
+
+/// Turn the `events` of an OSV range into `VersionRange`s.
+///
+/// Only `ECOSYSTEM` and `SEMVER` ranges are about package versions.  Other ranges, like
+/// `GIT` ranges of commit hashes, are skipped.
+fn parse_osv_range(value: &Value) -> Vec<VersionRange> {
+    match value.get("type").and_then(Value::as_str) {
+        Some("ECOSYSTEM" | "SEMVER") => {}
+        _ => return vec![],
+    }
+    let Some(events) = value.get("events").and_then(Value::as_array) else {
+        return vec![];
+    };
+    let mut ranges = vec![];
+    let mut option_current: Option<VersionRange> = None;
+    for event in events {
+        if let Some(introduced) = get_str(event, "/introduced") {
+            let start = if introduced == "0" {
+                None
+            } else {
+                Some(Bound {
+                    version: introduced,
+                    inclusive: true,
+                })
+            };
+            option_current = Some(VersionRange { start, end: None });
+        } else if let Some(fixed) = get_str(event, "/fixed") {
+            let mut range = option_current.take().unwrap_or_default();
+            range.end = Some(Bound {
+                version: fixed,
+                inclusive: false,
+            });
+            ranges.push(range);
+        } else if let Some(last_affected) = get_str(event, "/last_affected") {
+            let mut range = option_current.take().unwrap_or_default();
+            range.end = Some(Bound {
+                version: last_affected,
+                inclusive: true,
+            });
+            ranges.push(range);
+        }
+    }
+    ranges.extend(option_current);
+    ranges
+}
+
+fn parse_nvd_item(value: &Value) -> Result<Advisory, VulnDbErr> {
+    let id = get_str(value, "/cve/CVE_data_meta/ID").ok_or_else(|| {
+        VulnDbErr::FormatErr(String::from("NVD entry without a CVE ID"))
+    })?;
+    let severity = get_str(value, "/impact/baseMetricV3/cvssV3/baseSeverity")
+        .or_else(|| get_str(value, "/impact/baseMetricV2/severity"));
+    let summary = get_str(value, "/cve/description/description_data/0/value");
+    let mut affected = vec![];
+    if let Some(nodes) = value
+        .pointer("/configurations/nodes")
+        .and_then(Value::as_array)
+    {
+        for node in nodes {
+            parse_nvd_node(node, &mut affected);
+        }
+    }
+    Ok(Advisory {
+        id,
+        severity,
+        summary,
+        affected,
+    })
+}
+
+fn parse_nvd_node(node: &Value, affected: &mut Vec<AffectedPackage>) {
+    if let Some(cpe_matches) = node.get("cpe_match").and_then(Value::as_array) {
+        for cpe_match in cpe_matches {
+            let vulnerable = cpe_match
+                .get("vulnerable")
+                .and_then(Value::as_bool)
+                .unwrap_or(true);
+            if vulnerable {
+                affected.extend(parse_nvd_cpe_match(cpe_match));
+            }
+        }
+    }
+    if let Some(children) = node.get("children").and_then(Value::as_array) {
+        for child in children {
+            parse_nvd_node(child, affected);
+        }
+    }
+}
+
+fn parse_nvd_cpe_match(cpe_match: &Value) -> Option<AffectedPackage> {
+    // cpe:2.3:a:vendor:product:version:...
+    let cpe = get_str(cpe_match, "/cpe23Uri")?;
+    let fields: Vec<&str> = cpe.split(':').collect();
+    let pname = String::from(*fields.get(4)?);
+    let version = fields.get(5).copied().unwrap_or("*");
+
+    let bound = |key: &str, inclusive: bool| {
+        get_str(cpe_match, &format!("/{}", key))
+            .map(|version| Bound { version, inclusive })
+    };
+    let start = bound("versionStartIncluding", true)
+        .or_else(|| bound("versionStartExcluding", false));
+    let end = bound("versionEndIncluding", true)
+        .or_else(|| bound("versionEndExcluding", false));
+
+    let (versions, ranges) = if start.is_some() || end.is_some() {
+        (vec![], vec![VersionRange { start, end }])
+    } else if version == "*" || version == "-" {
+        // Every version is affected.
+        (vec![], vec![VersionRange::default()])
+    } else {
+        (vec![String::from(version)], vec![])
+    };
+    Some(AffectedPackage {
+        pname,
+        versions,
+        ranges,
+    })
+}
+
+fn normalize_pname(pname: &str) -> String {
+    pname.to_lowercase().replace('_', "-")
+}
+
+/// A store path in the closure that is affected by at least one advisory.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Finding {
+    pub drv: NixQueryDrv,
+    pub pname: String,
+    pub version: String,
+    pub advisories: Vec<Advisory>,
+}
+
+/// Check every store path in the tree against `vuln_db`.
+///
+/// The output name is dropped from versions before matching, so `openssl-1.1.1d-dev` is
+/// checked as version `1.1.1d` of `openssl`.  Store paths without a version are never
+/// matched.  The findings are sorted by package name and then by version.
+#[must_use]
+pub fn scan(vuln_db: &VulnDb, path_map: &NixQueryPathMap) -> Vec<Finding> {
+    let mut findings: Vec<Finding> = path_map
+        .0
+        .iter()
+        .filter_map(|(drv, _)| {
+            let (pname, option_version) = drv.pname_and_version();
+            let version = split_output(&option_version?).0.to_string();
+            let advisories: Vec<Advisory> = vuln_db
+                .lookup(&pname, &version)
+                .into_iter()
+                .cloned()
+                .collect();
+            if advisories.is_empty() {
+                None
+            } else {
+                Some(Finding {
+                    drv: drv.clone(),
+                    pname,
+                    version,
+                    advisories,
+                })
+            }
+        })
+        .collect();
+    findings.sort_by(|a, b| {
+        a.pname
+            .cmp(&b.pname)
+            .then_with(|| compare_versions(&a.version, &b.version))
+            .then_with(|| a.drv.cmp_hash(&b.drv))
+    });
+    findings
+}
+
+/// A mapping from each affected store path to the IDs of the advisories that affect it.
+#[must_use]
+pub fn advisory_ids(findings: &[Finding]) -> HashMap<NixQueryDrv, String> {
+    findings
+        .iter()
+        .map(|finding| {
+            let ids: Vec<&str> = finding
+                .advisories
+                .iter()
+                .map(|advisory| advisory.id.as_str())
+                .collect();
+            (finding.drv.clone(), ids.join(", "))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_range_contains() {
+        let range = VersionRange {
+            start: Some(Bound {
+                version: String::from("1.1.1"),
+                inclusive: true,
+            }),
+            end: Some(Bound {
+                version: String::from("1.1.1g"),
+                inclusive: false,
+            }),
+        };
+        assert!(range.contains("1.1.1"));
+        assert!(range.contains("1.1.1d"));
+        assert!(!range.contains("1.1.1g"));
+        assert!(!range.contains("1.1.0l"));
+        assert!(VersionRange::default().contains("0.1"));
+    }
+
+    #[test]
+    fn test_parse_osv_range() {
+        let range: Value = serde_json::from_str(
+            r#"{"type": "ECOSYSTEM", "events": [
+                {"introduced": "0"}, {"fixed": "1.0"},
+                {"introduced": "2.0"}, {"last_affected": "2.5"},
+                {"introduced": "3.0"}
+            ]}"#,
+        )
+        .unwrap();
+        let ranges = parse_osv_range(&range);
+        assert_eq!(ranges.len(), 3);
+        assert!(ranges[0].contains("0.9"));
+        assert!(!ranges[0].contains("1.0"));
+        assert!(ranges[1].contains("2.5"));
+        assert!(!ranges[1].contains("2.6"));
+        assert!(ranges[2].contains("3.1"));
+        assert!(!ranges[2].contains("2.9"));
+    }
+
+    #[test]
+    fn test_parse_osv_range_git() {
+        let range: Value = serde_json::from_str(
+            r#"{"type": "GIT", "repo": "https://github.com/openssl/openssl", "events": [
+                {"introduced": "0"}, {"fixed": "8ed2ec0e3b2a6c40a68b80d9e6e5e1ad8b8e5c9a"}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(parse_osv_range(&range), vec![]);
+
+        let range: Value = serde_json::from_str(
+            r#"{"events": [{"introduced": "0"}, {"fixed": "1.0"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(parse_osv_range(&range), vec![]);
+    }
+}
@@ -1,16 +1,140 @@
 use std::path::PathBuf;
 use structopt::StructOpt;
 
+use super::nix_query_tree::installable::Installable;
+use super::nix_query_tree::sbom::SbomFormat;
+use super::tree;
+
 #[derive(Debug, StructOpt)]
+// The doc comments are the `--help` text, and each flag is a `bool`.
+#[allow(clippy::doc_markdown, clippy::struct_excessive_bools)]
 #[structopt(about = "GUI viewer for `nix store --query --tree` output.")]
 pub struct Opts {
-    /// PATH in /nix/store to view references of
+    /// PATH in /nix/store to view references of.  This can also be a flake
+    /// reference like `nixpkgs#hello`, or a nix file to evaluate with --attr
     #[structopt(name = "PATH", parse(from_os_str))]
     pub nix_store_path: PathBuf,
+
+    /// Evaluate the attribute ATTR of the nix expression in PATH with
+    /// `nix-instantiate`, like `nix-build '<nixpkgs>' -A hello`, and view
+    /// the resulting derivation
+    #[structopt(long = "attr", short = "A", name = "ATTR")]
+    pub attr: Option<String>,
+
+    /// With a flake reference or --attr, view the output path instead of the
+    /// `.drv` file.  The output path has to be built already
+    #[structopt(long = "out-path")]
+    pub out_path: bool,
+
+    /// Compare the derivation PATH with the derivation OTHER_PATH, and print
+    /// the differences instead of opening the GUI
+    #[structopt(long = "diff", name = "OTHER_PATH", parse(from_os_str))]
+    pub diff_path: Option<PathBuf>,
+
+    /// Check every path in the tree against this local OSV or NVD JSON
+    /// vulnerability feed
+    #[structopt(long = "vulnerability-db", name = "FEED", parse(from_os_str))]
+    #[cfg_attr(not(feature = "gui"), allow(dead_code))]
+    pub vuln_db_path: Option<PathBuf>,
+
+    /// Print a software bill of materials for PATH in FORMAT ("cyclonedx" or
+    /// "spdx") instead of opening the GUI
+    #[structopt(long = "sbom", name = "FORMAT")]
+    pub sbom_format: Option<SbomFormat>,
+
+    /// Print the licenses of every package in PATH, grouped by license,
+    /// instead of opening the GUI
+    #[structopt(long = "license-report")]
+    pub license_report: bool,
+
+    /// Read license information from this JSON file produced by `nix eval`,
+    /// in addition to the environment of each derivation
+    #[structopt(
+        long = "license-data",
+        name = "LICENSE_FILE",
+        parse(from_os_str)
+    )]
+    pub license_db_path: Option<PathBuf>,
+
+    /// Write a self-contained HTML report of the tree for PATH instead of
+    /// opening the GUI
+    #[structopt(long = "html-report")]
+    pub html_report: bool,
+
+    /// In the HTML report, show the size of each store path
+    #[structopt(long = "with-sizes")]
+    pub with_sizes: bool,
+
+    /// In the HTML report, mark packages that appear with more than one store
+    /// path
+    #[structopt(long = "with-duplicates")]
+    pub with_duplicates: bool,
+
+    /// Check every path in the tree against the binary cache at CACHE_URI,
+    /// which can be a local directory, a `file://` URI, or an `http://` URI
+    #[structopt(long = "substituter", name = "CACHE_URI")]
+    pub substituter: Option<String>,
+
+    /// Print the paths in PATH that are missing from the binary cache given
+    /// with --substituter, and how much would have to be uploaded, instead of
+    /// opening the GUI
+    #[structopt(long = "missing-report")]
+    pub missing_report: bool,
+
+    /// Browse the tree for PATH in the terminal instead of opening the GUI,
+    /// for example over SSH
+    #[structopt(long = "tui")]
+    pub tui: bool,
+
+    /// Run `nix-store` even if there is a cached result for PATH, and replace
+    /// the cached result
+    #[structopt(long = "refresh")]
+    pub refresh: bool,
+
+    /// Query the nix store at URI instead of the default one.  This is passed
+    /// to `nix-store --store`, so it can be something like `ssh://host`,
+    /// `local?root=/mnt`, or `file:///path/to/binary-cache`
+    #[structopt(long = "store", name = "URI")]
+    pub store_uri: Option<String>,
+
+    /// Select the row at ROW when the tree is shown.  ROW is the index of
+    /// each child on the way down from the root, separated by dots, like
+    /// "2.0.1".  The path of the selected row is shown in the statusbar
+    #[structopt(long = "select", name = "ROW")]
+    pub select: Option<tree::Path>,
+
+    /// Write the output of a headless mode (like --sbom) to FILE instead of
+    /// stdout
+    #[structopt(
+        long = "output",
+        short = "o",
+        name = "FILE",
+        parse(from_os_str)
+    )]
+    pub output_path: Option<PathBuf>,
 }
 
 impl Opts {
     pub fn parse_from_args() -> Self {
         Opts::from_args()
     }
+
+    /// Whether to open the GUI, instead of printing something like --sbom or running --tui.
+    /// This has to agree with `cli::run`.
+    pub fn opens_gui(&self) -> bool {
+        !(self.diff_path.is_some()
+            || self.sbom_format.is_some()
+            || self.license_report
+            || self.html_report
+            || self.missing_report
+            || self.tui)
+    }
+
+    /// What PATH and --attr say to view.
+    pub fn installable(&self) -> Installable {
+        Installable::new(
+            &self.nix_store_path.to_string_lossy(),
+            self.attr.as_deref(),
+        )
+    }
 }
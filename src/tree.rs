@@ -1,12 +1,56 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::TryFrom;
 use std::hash::Hash;
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+/// A rose tree.
+///
+/// Dropping, cloning, comparing and looking up a `Tree` don't recurse, so they work for trees
+/// of any depth.  `Debug` and the serde impls do recurse, so they can overflow the stack for
+/// very deep trees.
+///
+/// Because `Tree` implements `Drop`, its fields can't be moved out by destructuring.  Use
+/// `std::mem::take` on `children` instead.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct Tree<T> {
     pub item: T,
     pub children: Vec<Tree<T>>,
 }
 
+impl<T> Drop for Tree<T> {
+    fn drop(&mut self) {
+        // Move every descendant onto this stack, so each one is dropped once its children
+        // have been taken away, instead of recursing down the tree.
+        let mut stack: Vec<Tree<T>> = std::mem::take(&mut self.children);
+        while let Some(mut tree) = stack.pop() {
+            stack.append(&mut tree.children);
+        }
+    }
+}
+
+impl<T: Clone> Clone for Tree<T> {
+    fn clone(&self) -> Self {
+        self.map(T::clone)
+    }
+}
+
+impl<T: PartialEq> PartialEq for Tree<T> {
+    fn eq(&self, other: &Self) -> bool {
+        let mut stack: Vec<(&Tree<T>, &Tree<T>)> = vec![(self, other)];
+        while let Some((tree, other_tree)) = stack.pop() {
+            if tree.item != other_tree.item
+                || tree.children.len() != other_tree.children.len()
+            {
+                return false;
+            }
+            stack.extend(tree.children.iter().zip(&other_tree.children));
+        }
+        true
+    }
+}
+
+impl<T: Eq> Eq for Tree<T> {}
+
 impl<T> Tree<T> {
     pub fn new(item: T, children: Vec<Tree<T>>) -> Tree<T> {
         Tree { item, children }
@@ -18,13 +62,16 @@ impl<T> Tree<T> {
 
     /// Lookup the item in the `Tree` that corresponds to the given `Path`.
     pub fn lookup(&self, path: Path) -> Option<&T> {
-        match path.split_front() {
-            None => Some(&self.item),
-            Some((index, child_path)) => match self.children.get(index) {
-                None => None,
-                Some(child_tree) => child_tree.lookup(child_path),
-            },
+        self.lookup_tree(path).map(|tree| &tree.item)
+    }
+
+    /// Lookup the subtree in the `Tree` that is rooted at the given `Path`.
+    pub fn lookup_tree(&self, path: Path) -> Option<&Tree<T>> {
+        let mut tree = self;
+        for index in path.0 {
+            tree = tree.children.get(index)?;
         }
+        Some(tree)
     }
 
     /// Similar to `path_map`, but take a function for mapping an item in the tree to an
@@ -37,11 +84,250 @@ impl<T> Tree<T> {
         U: Eq + Hash,
     {
         let mut map = TreePathMap::new();
-        let root_path = Path::new();
-        map.insert(f(&self.item), root_path.clone());
-        map.insert_children_map(&self.children, &root_path, f);
+        for (path, item) in self.pre_order() {
+            map.insert(f(item), path);
+        }
         map
     }
+
+    /// Iterate over every item in the `Tree` along with its `Path`, visiting each node before
+    /// its children.
+    ///
+    /// ```
+    /// use nix_query_tree_viewer::tree::{Path, Tree};
+    ///
+    /// let tree = Tree::new(
+    ///     "root",
+    ///     vec![Tree::new("0", vec![Tree::singleton("0-0")]), Tree::singleton("1")],
+    /// );
+    /// let items: Vec<(Path, &str)> =
+    ///     tree.pre_order().map(|(path, item)| (path, *item)).collect();
+    ///
+    /// assert_eq!(
+    ///     items,
+    ///     vec![
+    ///         (Path::new(), "root"),
+    ///         (vec![0].into(), "0"),
+    ///         (vec![0, 0].into(), "0-0"),
+    ///         (vec![1].into(), "1"),
+    ///     ]
+    /// );
+    /// ```
+    pub fn pre_order(&self) -> PreOrder<'_, T> {
+        PreOrder {
+            stack: vec![(self, Path::new())],
+        }
+    }
+
+    /// Iterate over every item in the `Tree` along with its `Path`, visiting each node after
+    /// its children.
+    pub fn post_order(&self) -> PostOrder<'_, T> {
+        PostOrder {
+            stack: vec![(self, 0)],
+            path: Path::new(),
+        }
+    }
+
+    /// Iterate over every item in the `Tree` along with its `Path`, visiting all the nodes at
+    /// one depth before any of the nodes at the next depth.
+    pub fn breadth_first(&self) -> BreadthFirst<'_, T> {
+        BreadthFirst {
+            queue: vec![(self, Path::new())].into(),
+        }
+    }
+
+    /// Combine the items in the `Tree` from the leaves up.  `f` is called for each node with
+    /// its item and the results for its children, in order.
+    ///
+    /// ```
+    /// use nix_query_tree_viewer::tree::Tree;
+    ///
+    /// let tree = Tree::new(1, vec![Tree::new(2, vec![Tree::singleton(3)]), Tree::singleton(4)]);
+    /// let sum = tree.fold(|item, children: Vec<i32>| item + children.iter().sum::<i32>());
+    ///
+    /// assert_eq!(sum, 10);
+    /// ```
+    #[allow(clippy::missing_panics_doc)] // The `expect`s only check how the stack is used.
+    pub fn fold<B, F>(&self, mut f: F) -> B
+    where
+        F: FnMut(&T, Vec<B>) -> B,
+    {
+        // Each entry is a node that hasn't been folded yet, and the results for the children
+        // of that node that have been folded so far.
+        let mut stack: Vec<(&Tree<T>, Vec<B>)> =
+            vec![(self, Vec::with_capacity(self.children.len()))];
+        loop {
+            let (tree, results) = stack
+                .last()
+                .expect("the stack is only empty after the root is folded");
+            if let Some(child) = tree.children.get(results.len()) {
+                stack.push((child, Vec::with_capacity(child.children.len())));
+            } else {
+                let (tree, results) = stack.pop().expect("checked above");
+                let result = f(&tree.item, results);
+                match stack.last_mut() {
+                    None => return result,
+                    Some((_, parent_results)) => parent_results.push(result),
+                }
+            }
+        }
+    }
+
+    /// Create a new `Tree` with the same shape by applying `f` to every item.  The items are
+    /// visited in post-order.
+    pub fn map<U, F>(&self, mut f: F) -> Tree<U>
+    where
+        F: FnMut(&T) -> U,
+    {
+        self.fold(|item, children| Tree::new(f(item), children))
+    }
+
+    /// Create a new `Tree` with only the nodes whose item satisfies `predicate`, along with
+    /// all of their ancestors so they can still be reached.  Return `None` if nothing in the
+    /// `Tree` satisfies `predicate`.
+    ///
+    /// ```
+    /// use nix_query_tree_viewer::tree::Tree;
+    ///
+    /// let tree = Tree::new(
+    ///     "root",
+    ///     vec![Tree::new("0", vec![Tree::singleton("0-0")]), Tree::singleton("1")],
+    /// );
+    ///
+    /// assert_eq!(
+    ///     tree.filter(|item| *item == "0-0"),
+    ///     Some(Tree::new("root", vec![Tree::new("0", vec![Tree::singleton("0-0")])]))
+    /// );
+    /// assert_eq!(tree.filter(|item| *item == "2"), None);
+    /// ```
+    pub fn filter<F>(&self, mut predicate: F) -> Option<Tree<T>>
+    where
+        T: Clone,
+        F: FnMut(&T) -> bool,
+    {
+        self.fold(|item, children: Vec<Option<Tree<T>>>| {
+            let children: Vec<Tree<T>> =
+                children.into_iter().flatten().collect();
+            if predicate(item) || !children.is_empty() {
+                Some(Tree::new(item.clone(), children))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Remove every subtree whose root item doesn't satisfy `keep`.  The root of the `Tree`
+    /// itself is always kept.
+    pub fn prune<F>(&mut self, mut keep: F)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let mut stack: Vec<&mut Tree<T>> = vec![self];
+        while let Some(tree) = stack.pop() {
+            tree.children.retain(|child| keep(&child.item));
+            stack.extend(tree.children.iter_mut());
+        }
+    }
+
+    /// The number of levels below the root.  A `Tree` without children has a depth of 0.
+    pub fn depth(&self) -> usize {
+        self.fold(|_, children: Vec<usize>| {
+            children.into_iter().max().map_or(0, |depth| depth + 1)
+        })
+    }
+
+    /// The number of nodes in the `Tree`, including the root.
+    pub fn size(&self) -> usize {
+        self.fold(|_, children: Vec<usize>| 1 + children.iter().sum::<usize>())
+    }
+
+    /// Find the first item in pre-order that satisfies `predicate`, along with its `Path`.
+    pub fn find<F>(&self, mut predicate: F) -> Option<(Path, &T)>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.pre_order().find(|(_, item)| predicate(item))
+    }
+
+    /// The `Path`s of the nodes that have children and are fewer than `max_depth` levels
+    /// below the root, or of every node with children if `max_depth` is `None`.  Expanding
+    /// these shows every node down to `max_depth` levels.
+    ///
+    /// The `Path`s are in breadth-first order, so each one comes after its parent.
+    ///
+    /// ```
+    /// use nix_query_tree_viewer::tree::{Path, Tree};
+    ///
+    /// let tree = Tree::new(
+    ///     "root",
+    ///     vec![Tree::new("0", vec![Tree::singleton("0-0")]), Tree::singleton("1")],
+    /// );
+    ///
+    /// assert_eq!(tree.expandable_paths(Some(1)), vec![Path::new()]);
+    /// assert_eq!(tree.expandable_paths(None), vec![Path::new(), vec![0].into()]);
+    /// ```
+    pub fn expandable_paths(&self, max_depth: Option<usize>) -> Vec<Path> {
+        let mut paths = Vec::new();
+        let mut queue: VecDeque<(&Tree<T>, Path)> =
+            vec![(self, Path::new())].into();
+        while let Some((tree, path)) = queue.pop_front() {
+            let too_deep = max_depth.is_some_and(|max| path.0.len() >= max);
+            if tree.children.is_empty() || too_deep {
+                continue;
+            }
+            for (i, child) in tree.children.iter().enumerate() {
+                let mut child_path = path.clone();
+                child_path.push_back(i);
+                queue.push_back((child, child_path));
+            }
+            paths.push(path);
+        }
+        paths
+    }
+
+    /// The `Path`s of every node above a node whose item satisfies `predicate`.  Expanding
+    /// these shows all the matching nodes, and nothing else that isn't needed to reach them.
+    ///
+    /// Like `expandable_paths`, each `Path` comes after its parent.
+    pub fn ancestor_paths<F>(&self, predicate: F) -> Vec<Path>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        self.ancestor_paths_with_matches(predicate).0
+    }
+
+    /// Like `ancestor_paths`, but this also returns the number of nodes whose item satisfies
+    /// `predicate`, and the `Path` of the first one in pre-order.  `predicate` is only called
+    /// once for each node.
+    pub fn ancestor_paths_with_matches<F>(
+        &self,
+        mut predicate: F,
+    ) -> (Vec<Path>, usize, Option<Path>)
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let mut ancestors: HashSet<Path> = HashSet::new();
+        let mut paths = Vec::new();
+        let mut match_count = 0;
+        let mut first_match = None;
+        for (path, _) in self.pre_order().filter(|(_, item)| predicate(item)) {
+            match_count += 1;
+            if first_match.is_none() {
+                first_match = Some(path.clone());
+            }
+            let mut option_parent = path.parent();
+            while let Some(parent) = option_parent {
+                // Everything above an ancestor that was already found has been found too.
+                if !ancestors.insert(parent.clone()) {
+                    break;
+                }
+                option_parent = parent.parent();
+                paths.push(parent);
+            }
+        }
+        paths.sort_by_key(|path| path.0.len());
+        (paths, match_count, first_match)
+    }
 }
 
 impl<T> Tree<T>
@@ -54,26 +340,283 @@ where
     }
 }
 
+/// A pre-order iterator over a `Tree`, created with `Tree::pre_order`.
+pub struct PreOrder<'a, T> {
+    stack: Vec<(&'a Tree<T>, Path)>,
+}
+
+impl<'a, T> Iterator for PreOrder<'a, T> {
+    type Item = (Path, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (tree, path) = self.stack.pop()?;
+        for (i, child) in tree.children.iter().enumerate().rev() {
+            let mut child_path = path.clone();
+            child_path.push_back(i);
+            self.stack.push((child, child_path));
+        }
+        Some((path, &tree.item))
+    }
+}
+
+/// A post-order iterator over a `Tree`, created with `Tree::post_order`.
+pub struct PostOrder<'a, T> {
+    /// The nodes from the root down to the current node, along with the index of the next
+    /// child of each one to visit.
+    stack: Vec<(&'a Tree<T>, usize)>,
+    /// The `Path` to the current node.  This is shared between all the nodes on the stack,
+    /// so deep trees don't need a separate `Path` for each level.
+    path: Path,
+}
+
+impl<'a, T> Iterator for PostOrder<'a, T> {
+    type Item = (Path, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (tree, next_child) = self.stack.last_mut()?;
+            if let Some(child) = tree.children.get(*next_child) {
+                self.path.push_back(*next_child);
+                *next_child += 1;
+                self.stack.push((child, 0));
+            } else {
+                let (tree, _) = self.stack.pop()?;
+                let path = self.path.clone();
+                self.path.0.pop_back();
+                return Some((path, &tree.item));
+            }
+        }
+    }
+}
+
+/// A breadth-first iterator over a `Tree`, created with `Tree::breadth_first`.
+pub struct BreadthFirst<'a, T> {
+    queue: VecDeque<(&'a Tree<T>, Path)>,
+}
+
+impl<'a, T> Iterator for BreadthFirst<'a, T> {
+    type Item = (Path, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (tree, path) = self.queue.pop_front()?;
+        for (i, child) in tree.children.iter().enumerate() {
+            let mut child_path = path.clone();
+            child_path.push_back(i);
+            self.queue.push_back((child, child_path));
+        }
+        Some((path, &tree.item))
+    }
+}
+
 /// This represents the path through a `Tree<T>` to a given node.
-#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct Path(pub VecDeque<usize>);
 
 impl Path {
+    #[must_use]
     pub fn split_front(mut self) -> Option<(usize, Path)> {
         let option_front_elem: Option<usize> = self.0.pop_front();
-        match option_front_elem {
-            None => None,
-            Some(i) => Some((i, self)),
-        }
+        option_front_elem.map(|i| (i, self))
     }
 
     pub fn push_back(&mut self, value: usize) {
         self.0.push_back(value);
     }
 
+    #[must_use]
     pub fn new() -> Self {
         Path(VecDeque::new())
     }
+
+    /// The `Path` to the parent of this node, or `None` if this is the root.
+    #[must_use]
+    pub fn parent(&self) -> Option<Path> {
+        let mut parent = self.clone();
+        parent.0.pop_back()?;
+        Some(parent)
+    }
+
+    /// Whether this is the `Path` to a node above `other` in the tree.  A `Path` is not an
+    /// ancestor of itself.
+    ///
+    /// ```
+    /// use nix_query_tree_viewer::tree::Path;
+    ///
+    /// let path: Path = "2.0".parse().unwrap();
+    ///
+    /// assert!(path.is_ancestor_of(&"2.0.1".parse().unwrap()));
+    /// assert!(Path::new().is_ancestor_of(&path));
+    /// assert!(!path.is_ancestor_of(&path));
+    /// assert!(!path.is_ancestor_of(&"2.1.0".parse().unwrap()));
+    /// ```
+    #[must_use]
+    pub fn is_ancestor_of(&self, other: &Path) -> bool {
+        self.0.len() < other.0.len()
+            && self.0.iter().zip(other.0.iter()).all(|(a, b)| a == b)
+    }
+
+    /// The `Path` to the deepest node that is above or equal to both this node and `other`.
+    #[must_use]
+    pub fn common_ancestor(&self, other: &Path) -> Path {
+        Path(
+            self.0
+                .iter()
+                .zip(other.0.iter())
+                .take_while(|(a, b)| a == b)
+                .map(|(&a, _)| a)
+                .collect(),
+        )
+    }
+
+    /// The indices of the `gtk::TreePath` for this node, for `gtk::TreePath::new_from_indicesv`.
+    ///
+    /// The `gtk::TreeStore` only has a single row at the top level for the root of the tree,
+    /// so the `gtk::TreePath` always starts with an extra `0`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an index doesn't fit in an `i32`, which GTK uses for indices.
+    #[must_use]
+    pub fn to_gtk_indices(&self) -> Vec<i32> {
+        std::iter::once(0)
+            .chain(self.0.iter().map(|&index| {
+                i32::try_from(index).expect("a tree::Path index fits in an i32")
+            }))
+            .collect()
+    }
+
+    /// The inverse of `to_gtk_indices`, for the indices from `gtk::TreePath::get_indices`.
+    /// The extra `0` at the start for the root is dropped.
+    ///
+    /// ```
+    /// use nix_query_tree_viewer::tree::Path;
+    ///
+    /// let path = Path::from_gtk_indices(&[0, 2, 0, 1]);
+    ///
+    /// assert_eq!(path.to_string(), "2.0.1");
+    /// assert_eq!(path.to_gtk_indices(), vec![0, 2, 0, 1]);
+    /// assert_eq!(Path::from_gtk_indices(&[0]), Path::new());
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if an index is negative, which GTK never returns.
+    #[must_use]
+    pub fn from_gtk_indices(indices: &[i32]) -> Path {
+        Path(
+            indices
+                .iter()
+                .skip(1)
+                .map(|&index| {
+                    usize::try_from(index)
+                        .expect("a gtk::TreePath index is never negative")
+                })
+                .collect(),
+        )
+    }
+
+    /// The string form of the `gtk::TreePath` for this node, for
+    /// `gtk::TreePath::new_from_string`.  Like `to_gtk_indices`, it starts with an extra `0`
+    /// for the root row.
+    ///
+    /// ```
+    /// use nix_query_tree_viewer::tree::Path;
+    ///
+    /// assert_eq!(Path::from(vec![2, 0, 1]).to_gtk_string(), "0:2:0:1");
+    /// assert_eq!(Path::new().to_gtk_string(), "0");
+    /// ```
+    #[must_use]
+    pub fn to_gtk_string(&self) -> String {
+        let indices: Vec<String> = std::iter::once(0)
+            .chain(self.0.iter().copied())
+            .map(|index| index.to_string())
+            .collect();
+        indices.join(":")
+    }
+
+    /// The inverse of `to_gtk_string`, for the string from `gtk::TreePath::to_string`.
+    ///
+    /// ```
+    /// use nix_query_tree_viewer::tree::Path;
+    ///
+    /// assert_eq!(Path::from_gtk_string("0:2:0:1"), Ok(Path::from(vec![2, 0, 1])));
+    /// assert_eq!(Path::from_gtk_string("0"), Ok(Path::new()));
+    /// assert!(Path::from_gtk_string("1:2").is_err());
+    /// assert!(Path::from_gtk_string("0:x").is_err());
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// This fails if `s` isn't indices separated by colons, starting with the `0` for the
+    /// root row.
+    pub fn from_gtk_string(s: &str) -> Result<Path, String> {
+        let mut indices = s.split(':');
+        if indices.next() != Some("0") {
+            return Err(format!(
+                "invalid gtk::TreePath \"{}\", expecting it to start with the root row \"0\"",
+                s
+            ));
+        }
+        parse_indices(s, indices, "0:2:0:1")
+    }
+}
+
+/// Parse `indices`, which were split out of `s`.  `example` is shown in the error message.
+fn parse_indices<'a>(
+    s: &str,
+    indices: impl Iterator<Item = &'a str>,
+    example: &str,
+) -> Result<Path, String> {
+    indices
+        .map(|index| {
+            index.parse::<usize>().map_err(|_| {
+                format!(
+                    "invalid tree path \"{}\", expecting numbers like \"{}\"",
+                    s, example
+                )
+            })
+        })
+        .collect::<Result<VecDeque<usize>, String>>()
+        .map(Path)
+}
+
+/// Write a `Path` as its indices separated by dots, like `2.0.1`.  The root is written as an
+/// empty string.
+impl std::fmt::Display for Path {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, index) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ".")?;
+            }
+            write!(f, "{}", index)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a `Path` in the format written by `Display`.
+///
+/// ```
+/// use nix_query_tree_viewer::tree::Path;
+///
+/// let path: Path = "2.0.1".parse().unwrap();
+///
+/// assert_eq!(path, Path::from(vec![2, 0, 1]));
+/// assert_eq!(path.to_string(), "2.0.1");
+/// assert_eq!("".parse::<Path>(), Ok(Path::new()));
+/// assert!("2..1".parse::<Path>().is_err());
+/// ```
+impl std::str::FromStr for Path {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            Ok(Path::new())
+        } else {
+            parse_indices(s, s.split('.'), "2.0.1")
+        }
+    }
 }
 
 impl<T> From<T> for Path
@@ -88,6 +631,7 @@ where
 /// This is a mapping of items in `Tree` to their `Path`s.  A single item in the `Tree` can have
 /// multiple `Path`s to it if it is in the `Tree` multiple times.
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize, serde::Serialize))]
 pub struct TreePathMap<U>(HashMap<U, Vec<Path>>)
 where
     U: Eq + Hash;
@@ -96,6 +640,7 @@ impl<U> TreePathMap<U>
 where
     U: Eq + Hash,
 {
+    #[must_use]
     pub fn new() -> TreePathMap<U> {
         TreePathMap(HashMap::new())
     }
@@ -113,27 +658,11 @@ where
         let option_paths: Option<&Vec<Path>> = self.0.get(k);
         option_paths.and_then(|vec: &Vec<Path>| vec.first())
     }
-}
 
-impl<U> TreePathMap<U>
-where
-    U: Eq + Hash,
-{
-    /// Insert child `Tree`s starting at `Path`.
-    ///
-    /// The function `f` will map the items in the `T` to an alternative type.
-    fn insert_children_map<T>(
-        &mut self,
-        children: &[Tree<T>],
-        path: &Path,
-        f: &dyn Fn(&T) -> U,
-    ) {
-        for (i, child) in children.iter().enumerate() {
-            let mut child_path = path.clone();
-            child_path.push_back(i);
-            self.insert(f(&child.item), child_path.clone());
-            self.insert_children_map(&child.children, &child_path, f);
-        }
+    /// Iterate over every distinct item along with all of its `Path`s.  The order is
+    /// unspecified.
+    pub fn iter(&self) -> impl Iterator<Item = (&U, &Vec<Path>)> {
+        self.0.iter()
     }
 }
 
@@ -182,6 +711,31 @@ mod tests {
         assert_eq!(res, actual);
     }
 
+    #[test]
+    fn test_path_parent_and_common_ancestor() {
+        let path: Path = vec![2, 0, 1].into();
+
+        assert_eq!(path.parent(), Some(vec![2, 0].into()));
+        assert_eq!(Path::new().parent(), None);
+        assert_eq!(
+            path.common_ancestor(&vec![2, 0, 3, 4].into()),
+            vec![2, 0].into()
+        );
+        assert_eq!(path.common_ancestor(&vec![1].into()), Path::new());
+        assert_eq!(path.common_ancestor(&path), path);
+    }
+
+    #[test]
+    fn test_path_gtk_indices() {
+        let path: Path = vec![2, 0, 1].into();
+
+        assert_eq!(path.to_gtk_indices(), vec![0, 2, 0, 1]);
+        assert_eq!(Path::new().to_gtk_indices(), vec![0]);
+        assert_eq!(Path::from_gtk_indices(&[0, 2, 0, 1]), path);
+        assert_eq!(Path::from_gtk_indices(&[0]), Path::new());
+        assert_eq!(Path::from_gtk_indices(&[]), Path::new());
+    }
+
     #[test]
     fn test_lookup_no_item() {
         let tree = Tree::new(
@@ -253,6 +807,23 @@ mod tests {
         assert_eq!(tree.lookup(path2_1_1).map(String::deref), Some("2-1-1"));
     }
 
+    #[test]
+    fn test_lookup_tree() {
+        let subtree: Tree<String> = Tree::new(
+            "2".into(),
+            vec![Tree::singleton("2-0".into()), Tree::singleton("2-1".into())],
+        );
+        let tree: Tree<String> = Tree::new(
+            "root".into(),
+            vec![Tree::singleton("0".into()), subtree.clone()],
+        );
+
+        assert_eq!(tree.lookup_tree(vec![].into()), Some(&tree));
+        assert_eq!(tree.lookup_tree(vec![1].into()), Some(&subtree));
+        assert_eq!(tree.lookup_tree(vec![2].into()), None);
+        assert_eq!(tree.lookup_tree(vec![1, 0, 0].into()), None);
+    }
+
     #[test]
     fn test_tree_path_map_from_tree_all_unique() {
         let tree: Tree<String> = Tree::new(
@@ -333,4 +904,178 @@ mod tests {
 
         assert_eq!(res_tree_path_map, TreePathMap(actual_tree_path_map));
     }
+
+    fn example_tree() -> Tree<&'static str> {
+        Tree::new(
+            "root",
+            vec![
+                Tree::singleton("0"),
+                Tree::new(
+                    "1",
+                    vec![
+                        Tree::singleton("1-0"),
+                        Tree::new("1-1", vec![Tree::singleton("1-1-0")]),
+                    ],
+                ),
+                Tree::singleton("2"),
+            ],
+        )
+    }
+
+    fn items<'a>(
+        iter: impl Iterator<Item = (Path, &'a &'static str)>,
+    ) -> Vec<&'static str> {
+        iter.map(|(path, item)| {
+            assert_eq!(example_tree().lookup(path), Some(item));
+            *item
+        })
+        .collect()
+    }
+
+    #[test]
+    fn test_traversal_orders() {
+        let tree = example_tree();
+
+        assert_eq!(
+            items(tree.pre_order()),
+            vec!["root", "0", "1", "1-0", "1-1", "1-1-0", "2"]
+        );
+        assert_eq!(
+            items(tree.post_order()),
+            vec!["0", "1-0", "1-1-0", "1-1", "1", "2", "root"]
+        );
+        assert_eq!(
+            items(tree.breadth_first()),
+            vec!["root", "0", "1", "2", "1-0", "1-1", "1-1-0"]
+        );
+    }
+
+    #[test]
+    fn test_map_and_fold() {
+        let tree = example_tree();
+        let lengths: Tree<usize> = tree.map(|item| item.len());
+
+        assert_eq!(lengths.lookup(vec![1, 1, 0].into()), Some(&5));
+        assert_eq!(
+            lengths.fold(|len, children: Vec<usize>| len
+                + children.iter().sum::<usize>()),
+            18
+        );
+        assert_eq!(tree.size(), 7);
+        assert_eq!(tree.depth(), 3);
+        assert_eq!(Tree::singleton("root").depth(), 0);
+    }
+
+    #[test]
+    fn test_filter_and_prune() {
+        let tree = example_tree();
+
+        assert_eq!(
+            tree.filter(|item| item.ends_with('0')),
+            Some(Tree::new(
+                "root",
+                vec![
+                    Tree::singleton("0"),
+                    Tree::new(
+                        "1",
+                        vec![
+                            Tree::singleton("1-0"),
+                            Tree::new("1-1", vec![Tree::singleton("1-1-0")]),
+                        ],
+                    ),
+                ],
+            ))
+        );
+
+        let mut pruned = tree.clone();
+        pruned.prune(|item| !item.starts_with("1-"));
+        assert_eq!(
+            pruned,
+            Tree::new(
+                "root",
+                vec![
+                    Tree::singleton("0"),
+                    Tree::singleton("1"),
+                    Tree::singleton("2"),
+                ],
+            )
+        );
+    }
+
+    #[test]
+    fn test_find() {
+        let tree = example_tree();
+
+        assert_eq!(
+            tree.find(|item| item.starts_with("1-")),
+            Some((vec![1, 0].into(), &"1-0"))
+        );
+        assert_eq!(tree.find(|item| *item == "3"), None);
+    }
+
+    #[test]
+    fn test_paths_to_expand() {
+        let tree = example_tree();
+        let paths = |paths: Vec<Path>| -> Vec<String> {
+            paths.iter().map(Path::to_string).collect()
+        };
+
+        assert_eq!(paths(tree.expandable_paths(Some(0))), Vec::<String>::new());
+        assert_eq!(paths(tree.expandable_paths(Some(2))), vec!["", "1"]);
+        assert_eq!(paths(tree.expandable_paths(None)), vec!["", "1", "1.1"]);
+
+        assert_eq!(
+            paths(tree.ancestor_paths(|item| item.ends_with('0'))),
+            vec!["", "1", "1.1"]
+        );
+        assert_eq!(paths(tree.ancestor_paths(|item| *item == "2")), vec![""]);
+        assert_eq!(
+            paths(tree.ancestor_paths(|item| *item == "root")),
+            Vec::<String>::new()
+        );
+
+        let mut calls = 0;
+        let (ancestors, match_count, first_match) = tree
+            .ancestor_paths_with_matches(|item| {
+                calls += 1;
+                item.ends_with('0')
+            });
+        assert_eq!(paths(ancestors), vec!["", "1", "1.1"]);
+        assert_eq!(match_count, 3);
+        assert_eq!(first_match, Some(vec![0].into()));
+        assert_eq!(calls, tree.size());
+        assert_eq!(
+            tree.ancestor_paths_with_matches(|item| *item == "3"),
+            (vec![], 0, None)
+        );
+    }
+
+    #[test]
+    fn test_deep_tree() {
+        let depth = 10_000;
+        let mut tree = Tree::singleton(depth);
+        for i in (0..depth).rev() {
+            tree = Tree::new(i, vec![tree]);
+        }
+
+        assert_eq!(tree.depth(), depth);
+        assert_eq!(tree.size(), depth + 1);
+        assert_eq!(
+            tree.post_order().next().map(|(_, item)| *item),
+            Some(depth)
+        );
+        assert_eq!(tree.breadth_first().count(), depth + 1);
+        assert_eq!(
+            tree.find(|item| *item == depth)
+                .map(|(path, _)| path.0.len()),
+            Some(depth)
+        );
+        let mut mapped = tree.map(|item| item + 1);
+        mapped.prune(|item| *item <= 10);
+        assert_eq!(mapped.size(), 10);
+        assert_eq!(tree.clone(), tree);
+        assert_ne!(tree.map(|item| item + 1), tree);
+        assert_eq!(tree.lookup(Path::from(vec![0; depth])), Some(&depth));
+        assert_eq!(tree.lookup(Path::from(vec![0; depth + 1])), None);
+    }
 }
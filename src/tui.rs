@@ -0,0 +1,352 @@
+mod state;
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{
+    self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use crossterm::{execute, queue};
+use std::convert::TryFrom;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use super::nix_query_tree::exec_nix_store::{self, KeepRaw, NixStoreRes};
+use super::nix_query_tree::substitutes::{self, Substituter};
+use super::opts::Opts;
+use state::State;
+
+const HELP: &str = "q quit  ←/→ collapse/expand  t go to tree instance  s search for this  r refresh  / find  n/N next/prev  o sort  v view  m only missing";
+
+/// Whether the keyboard is moving around the tree, or typing a search query.
+enum Mode {
+    Browse,
+    Search(String),
+}
+
+/// What the event loop should do after a key press.
+enum Action {
+    Continue,
+    Quit,
+    /// Run `nix-store --query --tree` on this path and show the result.
+    SearchForThis(PathBuf),
+    /// Run `nix-store --query --tree` on the current path again, without using the cache.
+    Refresh,
+}
+
+/// Run `nix-store --query --tree` on `nix_store_path` in `store_uri`.  With `refresh`, the
+/// on-disk cache isn't used.  Along with the result, this returns why the on-disk cache
+/// couldn't be read, if it couldn't.
+fn query_tree(
+    nix_store_path: &Path,
+    store_uri: Option<&str>,
+    refresh: bool,
+) -> Result<(NixStoreRes, Option<String>), String> {
+    let exec_nix_store_res = if refresh {
+        exec_nix_store::refresh(nix_store_path, store_uri, KeepRaw::No)
+    } else {
+        exec_nix_store::run(nix_store_path, store_uri, KeepRaw::No)
+    };
+    let cache_warning = exec_nix_store_res.cache_warning;
+    exec_nix_store_res
+        .res
+        .map(|nix_store_res| (nix_store_res, cache_warning))
+        .map_err(|err| {
+            format!(
+                "Error running `nix-store --query --tree {}`: {}",
+                nix_store_path.display(),
+                err
+            )
+        })
+}
+
+/// Mark which rows are in the binary cache at `substituter_uri`, or show an error in the status
+/// line.
+fn check_substitutes(state: &mut State, substituter_uri: &str) {
+    let res = Substituter::from_uri(substituter_uri).and_then(|substituter| {
+        substitutes::check(&substituter, state.nix_store_res())
+    });
+    match res {
+        Err(err) => {
+            state.message = Some(format!(
+                "Error checking the binary cache {}: {}",
+                substituter_uri, err
+            ));
+        }
+        Ok(report) => state.set_substitutes(report),
+    }
+}
+
+fn draw(
+    stdout: &mut io::Stdout,
+    state: &State,
+    mode: &Mode,
+    width: u16,
+    height: u16,
+) -> crossterm::Result<()> {
+    let width = usize::from(width);
+    queue!(stdout, Clear(ClearType::All))?;
+
+    let body_height = height.saturating_sub(1);
+    let visible_rows = state.rows().iter().enumerate().skip(state.top());
+    for (y, (index, row)) in (0..body_height).zip(visible_rows) {
+        let line: String = state.line(row).chars().take(width).collect();
+        queue!(stdout, MoveTo(0, y))?;
+        if index == state.cursor() {
+            queue!(
+                stdout,
+                SetAttribute(Attribute::Reverse),
+                Print(line),
+                SetAttribute(Attribute::Reset)
+            )?;
+        } else {
+            queue!(stdout, Print(line))?;
+        }
+    }
+
+    // The path of the selected row, in the same form that `--select` takes.
+    let selected_path = match state.selected_row() {
+        Some(row) if !row.path.0.is_empty() => format!("  row {}", row.path),
+        _ => String::new(),
+    };
+    let nar_info = state
+        .selected_narinfo()
+        .map_or_else(String::new, |narinfo| {
+            format!("  ({})", narinfo.summary())
+        });
+    let status = match mode {
+        Mode::Search(query) => format!("/{}", query),
+        Mode::Browse => match &state.message {
+            Some(message) => message.clone(),
+            None => format!(
+                "{}{}{}  [{}, {}]  {}",
+                state.nix_store_path.display(),
+                selected_path,
+                nar_info,
+                state.sort_order,
+                state.view_style,
+                HELP
+            ),
+        },
+    };
+    let status: String = status.chars().take(width).collect();
+    queue!(
+        stdout,
+        MoveTo(0, height.saturating_sub(1)),
+        SetAttribute(Attribute::Bold),
+        Print(status),
+        SetAttribute(Attribute::Reset)
+    )?;
+    stdout.flush()?;
+    Ok(())
+}
+
+fn search(state: &mut State, query: &str, forward: bool) {
+    if !state.search(query, forward) {
+        state.message = Some(format!("No match for \"{}\"", query));
+    }
+}
+
+fn goto_first_instance(state: &mut State) {
+    if !state.goto_first_instance() {
+        state.message = Some(String::from("This is already the tree instance"));
+    }
+}
+
+fn handle_browse_key(
+    state: &mut State,
+    mode: &mut Mode,
+    key: KeyEvent,
+    page_height: isize,
+) -> Action {
+    state.message = None;
+    match key.code {
+        KeyCode::Char('q') | KeyCode::Esc => return Action::Quit,
+        KeyCode::Char('c') if key.modifiers == KeyModifiers::CONTROL => {
+            return Action::Quit
+        }
+        KeyCode::Up | KeyCode::Char('k') => state.move_cursor(-1),
+        KeyCode::Down | KeyCode::Char('j') => state.move_cursor(1),
+        KeyCode::PageUp => state.move_cursor(-page_height),
+        KeyCode::PageDown => state.move_cursor(page_height),
+        KeyCode::Home | KeyCode::Char('g') => state.move_to_start(),
+        KeyCode::End | KeyCode::Char('G') => state.move_to_end(),
+        KeyCode::Right | KeyCode::Char('l') => state.expand(),
+        KeyCode::Left | KeyCode::Char('h') => state.collapse(),
+        KeyCode::Enter | KeyCode::Char(' ') => state.toggle(),
+        KeyCode::Char('t') => goto_first_instance(state),
+        KeyCode::Char('s') => {
+            if let Some(entry) = state.selected_entry() {
+                return Action::SearchForThis(entry.0.to_path_buf());
+            }
+        }
+        KeyCode::Char('r') => return Action::Refresh,
+        KeyCode::Char('/') => *mode = Mode::Search(String::new()),
+        KeyCode::Char(c @ ('n' | 'N')) => {
+            if let Some(query) = state.last_search.clone() {
+                search(state, &query, c == 'n');
+            }
+        }
+        KeyCode::Char('o') => state.set_sort_order(state.sort_order.next()),
+        KeyCode::Char('v') => state.set_view_style(state.view_style.next()),
+        KeyCode::Char('m') => {
+            let toggled = state.toggle_only_missing();
+            if !toggled {
+                state.message = Some(String::from(
+                    "There is no binary cache to compare against; use --substituter",
+                ));
+            }
+        }
+        KeyCode::Char('?') => state.message = Some(String::from(HELP)),
+        _ => {}
+    }
+    Action::Continue
+}
+
+fn handle_search_key(state: &mut State, mode: &mut Mode, key: KeyEvent) {
+    if let Mode::Search(query) = mode {
+        match key.code {
+            KeyCode::Esc => *mode = Mode::Browse,
+            KeyCode::Enter => {
+                let query = query.clone();
+                *mode = Mode::Browse;
+                if !query.is_empty() {
+                    search(state, &query, true);
+                    state.last_search = Some(query);
+                }
+            }
+            KeyCode::Backspace => {
+                query.pop();
+            }
+            KeyCode::Char(c) => query.push(c),
+            _ => {}
+        }
+    }
+}
+
+/// Run `nix-store --query --tree` on `nix_store_path` and show the result, or an error in the
+/// status line.
+fn run_query(
+    stdout: &mut io::Stdout,
+    state: &mut State,
+    mode: &Mode,
+    nix_store_path: &Path,
+    opts: &Opts,
+    refresh: bool,
+) -> crossterm::Result<()> {
+    state.message = Some(format!(
+        "Running `nix-store --query --tree {}`...",
+        nix_store_path.display()
+    ));
+    let (width, height) = terminal::size()?;
+    draw(stdout, state, mode, width, height)?;
+    match query_tree(nix_store_path, opts.store_uri.as_deref(), refresh) {
+        Err(err) => state.message = Some(err),
+        Ok((nix_store_res, cache_warning)) => {
+            state.replace(nix_store_path, nix_store_res);
+            if state.message.is_none() {
+                state.message = cache_warning;
+            }
+            if let Some(substituter_uri) = &opts.substituter {
+                check_substitutes(state, substituter_uri);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn event_loop(
+    stdout: &mut io::Stdout,
+    state: &mut State,
+    opts: &Opts,
+) -> crossterm::Result<()> {
+    let mut mode = Mode::Browse;
+    loop {
+        let (width, height) = terminal::size()?;
+        let body_height = usize::from(height.saturating_sub(1));
+        state.scroll_to_cursor(body_height);
+        draw(stdout, state, &mode, width, height)?;
+
+        if let Event::Key(key) = event::read()? {
+            let action = match mode {
+                Mode::Browse => handle_browse_key(
+                    state,
+                    &mut mode,
+                    key,
+                    isize::try_from(body_height).unwrap_or(isize::MAX),
+                ),
+                Mode::Search(_) => {
+                    handle_search_key(state, &mut mode, key);
+                    Action::Continue
+                }
+            };
+            match action {
+                Action::Continue => {}
+                Action::Quit => return Ok(()),
+                Action::SearchForThis(nix_store_path) => {
+                    run_query(
+                        stdout,
+                        state,
+                        &mode,
+                        &nix_store_path,
+                        opts,
+                        false,
+                    )?;
+                }
+                Action::Refresh => {
+                    let nix_store_path = state.nix_store_path.clone();
+                    run_query(
+                        stdout,
+                        state,
+                        &mode,
+                        &nix_store_path,
+                        opts,
+                        true,
+                    )?;
+                }
+            }
+        }
+    }
+}
+
+/// Browse the tree for `nix_store_path` in the terminal.
+///
+/// This returns 0 on success, and 2 if there was an error.
+pub fn run(opts: &Opts) -> i32 {
+    let store_uri = opts.store_uri.as_deref();
+    let (nix_store_res, cache_warning) =
+        match query_tree(&opts.nix_store_path, store_uri, opts.refresh) {
+            Err(err) => {
+                eprintln!("{}", err);
+                return 2;
+            }
+            Ok(res) => res,
+        };
+    let mut state = State::new(&opts.nix_store_path, nix_store_res);
+    if state.message.is_none() {
+        state.message = cache_warning;
+    }
+    if let Some(substituter_uri) = &opts.substituter {
+        check_substitutes(&mut state, substituter_uri);
+    }
+    if let Some(path) = &opts.select {
+        if !state.goto(path) {
+            state.message = Some(format!("There is no row {} to select", path));
+        }
+    }
+
+    let mut stdout = io::stdout();
+    let res = terminal::enable_raw_mode()
+        .and_then(|()| execute!(stdout, EnterAlternateScreen, Hide))
+        .and_then(|()| event_loop(&mut stdout, &mut state, opts));
+    let cleanup = execute!(stdout, Show, LeaveAlternateScreen)
+        .and_then(|()| terminal::disable_raw_mode());
+
+    match res.and(cleanup) {
+        Err(err) => {
+            eprintln!("Error in the terminal UI: {}", err);
+            2
+        }
+        Ok(()) => 0,
+    }
+}
@@ -0,0 +1,560 @@
+use std::collections::HashSet;
+use std::path::{Path as FilePath, PathBuf};
+
+use super::super::nix_query_tree::exec_nix_store::NixStoreRes;
+use super::super::nix_query_tree::narinfo::NarInfo;
+use super::super::nix_query_tree::substitutes::SubstituteReport;
+use super::super::nix_query_tree::{NixQueryEntry, Recurse};
+use super::super::tree::{Path, Tree};
+use super::super::view::{SortOrder, ViewStyle};
+
+/// A single line of the tree that is currently visible in the terminal.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Row {
+    pub path: Path,
+    pub depth: usize,
+    pub has_children: bool,
+    pub expanded: bool,
+}
+
+/// Everything the terminal UI knows about.  This doesn't touch the terminal at all, so the
+/// navigation logic can be tested on its own.
+#[derive(Clone, Debug)]
+pub struct State {
+    pub nix_store_path: PathBuf,
+    nix_store_res: NixStoreRes,
+    pub sort_order: SortOrder,
+    pub view_style: ViewStyle,
+    expanded: HashSet<Path>,
+    rows: Vec<Row>,
+    /// Index into `rows` of the selected row.
+    cursor: usize,
+    /// Index into `rows` of the first row shown on the screen.
+    top: usize,
+    /// The last query searched for with `/`.
+    pub last_search: Option<String>,
+    /// A message to show in the status line.
+    pub message: Option<String>,
+    /// Which store paths are in the binary cache given with `--substituter`.
+    substitutes: Option<SubstituteReport>,
+    /// When only the store paths missing from the binary cache are shown, the rows for them
+    /// and their ancestors.
+    only_missing: Option<HashSet<Path>>,
+}
+
+impl State {
+    pub fn new(nix_store_path: &FilePath, nix_store_res: NixStoreRes) -> Self {
+        let mut state = State {
+            nix_store_path: nix_store_path.to_path_buf(),
+            nix_store_res,
+            sort_order: SortOrder::default(),
+            view_style: ViewStyle::default(),
+            expanded: HashSet::new(),
+            rows: vec![],
+            cursor: 0,
+            top: 0,
+            last_search: None,
+            message: None,
+            substitutes: None,
+            only_missing: None,
+        };
+        state.message = state.nix_store_res.skipped_warning();
+        state.expanded.insert(Path::new());
+        state.rebuild_rows();
+        state
+    }
+
+    /// Replace the tree with the output of `nix-store` for a different path.  This is used for
+    /// "search for this".
+    pub fn replace(&mut self, nix_store_path: &FilePath, res: NixStoreRes) {
+        let sort_order = self.sort_order;
+        let view_style = self.view_style;
+        *self = State::new(nix_store_path, res);
+        self.sort_order = sort_order;
+        self.view_style = view_style;
+        self.rebuild_rows();
+    }
+
+    pub fn nix_store_res(&self) -> &NixStoreRes {
+        &self.nix_store_res
+    }
+
+    fn tree(&self) -> &Tree<NixQueryEntry> {
+        &self.nix_store_res.tree.0
+    }
+
+    pub fn rows(&self) -> &[Row] {
+        &self.rows
+    }
+
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn top(&self) -> usize {
+        self.top
+    }
+
+    pub fn selected_row(&self) -> Option<&Row> {
+        self.rows.get(self.cursor)
+    }
+
+    pub fn selected_entry(&self) -> Option<&NixQueryEntry> {
+        self.selected_row()
+            .and_then(|row| self.tree().lookup(row.path.clone()))
+    }
+
+    /// The `.narinfo` of the selected store path, when the tree was read from a binary cache.
+    pub fn selected_narinfo(&self) -> Option<&NarInfo> {
+        self.selected_entry()
+            .and_then(|entry| self.nix_store_res.narinfos.get(&entry.0))
+    }
+
+    /// Mark every row as cached or missing from a binary cache.
+    pub fn set_substitutes(&mut self, report: SubstituteReport) {
+        self.message = Some(report.summary());
+        self.substitutes = Some(report);
+        self.only_missing = None;
+        self.rebuild_rows();
+    }
+
+    /// Switch between showing every row, and only the rows for store paths missing from the
+    /// binary cache (and their ancestors).  The missing rows are expanded so they can all be
+    /// seen.
+    ///
+    /// This returns `false` if there is no binary cache to compare against.
+    pub fn toggle_only_missing(&mut self) -> bool {
+        let Some(report) = &self.substitutes else {
+            return false;
+        };
+        if self.only_missing.take().is_none() {
+            let visible = report.missing_paths(self.tree());
+            for path in &visible {
+                if let Some(parent) = path.parent() {
+                    self.expanded.insert(parent);
+                }
+            }
+            self.only_missing = Some(visible);
+        }
+        self.rebuild_rows();
+        true
+    }
+
+    /// Whether the row at `path` is shown when only missing store paths are shown.
+    fn is_visible(&self, path: &Path) -> bool {
+        self.only_missing
+            .as_ref()
+            .is_none_or(|visible| visible.contains(path))
+    }
+
+    /// The indices of the children of `tree`, in the current sort order.
+    fn sorted_children(&self, tree: &Tree<NixQueryEntry>) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..tree.children.len()).collect();
+        indices.sort_by(|&a, &b| {
+            self.sort_order
+                .compare(&tree.children[a].item, &tree.children[b].item)
+        });
+        indices
+    }
+
+    /// Every `Path` in the tree in display order, whether or not it is expanded.  If
+    /// `only_expanded` is set, the children of collapsed nodes are skipped.
+    fn paths_in_order(&self, only_expanded: bool) -> Vec<(Path, usize)> {
+        let mut paths = vec![];
+        let mut stack = vec![(Path::new(), 0, self.tree())];
+        while let Some((path, depth, tree)) = stack.pop() {
+            if !only_expanded || self.expanded.contains(&path) {
+                for &i in self.sorted_children(tree).iter().rev() {
+                    let mut child_path = path.clone();
+                    child_path.push_back(i);
+                    if self.is_visible(&child_path) {
+                        stack.push((child_path, depth + 1, &tree.children[i]));
+                    }
+                }
+            }
+            paths.push((path, depth));
+        }
+        paths
+    }
+
+    /// Recompute the visible rows, keeping the same row selected if it is still visible.
+    fn rebuild_rows(&mut self) {
+        let selected_path = self.selected_row().map(|row| row.path.clone());
+        let tree = self.tree();
+        self.rows = self
+            .paths_in_order(true)
+            .into_iter()
+            .map(|(path, depth)| {
+                let has_children =
+                    tree.lookup_tree(path.clone()).is_some_and(|subtree| {
+                        (0..subtree.children.len()).any(|i| {
+                            let mut child_path = path.clone();
+                            child_path.push_back(i);
+                            self.is_visible(&child_path)
+                        })
+                    });
+                let expanded = has_children && self.expanded.contains(&path);
+                Row {
+                    path,
+                    depth,
+                    has_children,
+                    expanded,
+                }
+            })
+            .collect();
+        self.cursor = selected_path
+            .and_then(|path| self.rows.iter().position(|row| row.path == path))
+            .unwrap_or(self.cursor)
+            .min(self.rows.len().saturating_sub(1));
+    }
+
+    /// Move the cursor up (negative) or down (positive), stopping at either end.
+    pub fn move_cursor(&mut self, delta: isize) {
+        let last = self.rows.len().saturating_sub(1);
+        self.cursor = if delta < 0 {
+            self.cursor.saturating_sub(delta.unsigned_abs())
+        } else {
+            (self.cursor + delta.unsigned_abs()).min(last)
+        };
+    }
+
+    pub fn move_to_start(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn move_to_end(&mut self) {
+        self.cursor = self.rows.len().saturating_sub(1);
+    }
+
+    /// Make sure the cursor is on screen, given the number of rows that fit on the screen.
+    pub fn scroll_to_cursor(&mut self, height: usize) {
+        let height = height.max(1);
+        if self.cursor < self.top {
+            self.top = self.cursor;
+        } else if self.cursor >= self.top + height {
+            self.top = self.cursor + 1 - height;
+        }
+    }
+
+    /// Expand the selected row, or move to its first child if it is already expanded.
+    pub fn expand(&mut self) {
+        if let Some(row) = self.selected_row().cloned() {
+            if row.expanded {
+                self.move_cursor(1);
+            } else if row.has_children {
+                self.expanded.insert(row.path);
+                self.rebuild_rows();
+            }
+        }
+    }
+
+    /// Collapse the selected row, or move to its parent if it is already collapsed.
+    pub fn collapse(&mut self) {
+        if let Some(row) = self.selected_row().cloned() {
+            if row.expanded {
+                self.expanded.remove(&row.path);
+                self.rebuild_rows();
+            } else {
+                let mut parent = row.path;
+                if parent.0.pop_back().is_some() {
+                    self.goto(&parent);
+                }
+            }
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        match self.selected_row() {
+            Some(row) if row.expanded => self.collapse(),
+            _ => self.expand(),
+        }
+    }
+
+    /// Expand all the ancestors of `path` and select it.
+    ///
+    /// This returns `false` if there is no row at `path`.
+    pub fn goto(&mut self, path: &Path) -> bool {
+        if self.tree().lookup_tree(path.clone()).is_none() {
+            return false;
+        }
+        let mut ancestor = Path::new();
+        for &i in &path.0 {
+            self.expanded.insert(ancestor.clone());
+            ancestor.push_back(i);
+        }
+        self.rebuild_rows();
+        if let Some(index) = self.rows.iter().position(|row| &row.path == path)
+        {
+            self.cursor = index;
+        }
+        true
+    }
+
+    /// If the selected row is a `[...]` entry, jump to the first instance of it in the tree.
+    ///
+    /// This returns `false` if there was nothing to jump to.
+    pub fn goto_first_instance(&mut self) -> bool {
+        let option_path = match self.selected_entry() {
+            Some(entry) if entry.1 == Recurse::Yes => {
+                self.nix_store_res.lookup_first_query_entry(entry).cloned()
+            }
+            _ => None,
+        };
+        match option_path {
+            None => false,
+            Some(path) => self.goto(&path),
+        }
+    }
+
+    /// Select the next store path (or the previous one, if `forward` is `false`) whose full
+    /// path contains `query`, ignoring case.  This looks through the whole tree, not just the
+    /// expanded rows.
+    ///
+    /// This returns `false` if nothing matches.
+    pub fn search(&mut self, query: &str, forward: bool) -> bool {
+        let query = query.to_lowercase();
+        let tree = self.tree();
+        let mut paths: Vec<Path> = self
+            .paths_in_order(false)
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        if !forward {
+            paths.reverse();
+        }
+        let start = self
+            .selected_row()
+            .and_then(|row| paths.iter().position(|path| path == &row.path))
+            .map_or(0, |index| index + 1);
+        let found = paths
+            .iter()
+            .cycle()
+            .skip(start)
+            .take(paths.len())
+            .find(|path| {
+                tree.lookup((*path).clone()).is_some_and(|entry| {
+                    entry.0.to_string().to_lowercase().contains(&query)
+                })
+            })
+            .cloned();
+        match found {
+            None => false,
+            Some(path) => {
+                self.goto(&path);
+                true
+            }
+        }
+    }
+
+    pub fn set_sort_order(&mut self, sort_order: SortOrder) {
+        self.sort_order = sort_order;
+        self.rebuild_rows();
+    }
+
+    pub fn set_view_style(&mut self, view_style: ViewStyle) {
+        self.view_style = view_style;
+    }
+
+    /// The text for a row, with indentation and a marker for whether it is expanded.
+    pub fn line(&self, row: &Row) -> String {
+        let marker = if !row.has_children {
+            "  "
+        } else if row.expanded {
+            "▾ "
+        } else {
+            "▸ "
+        };
+        let mut line = "  ".repeat(row.depth);
+        line.push_str(marker);
+        if let Some(entry) = self.tree().lookup(row.path.clone()) {
+            line.push_str(&self.view_style.render(&entry.0));
+            if entry.1 == Recurse::Yes {
+                line.push_str(" [...]");
+            }
+            let option_cached = self
+                .substitutes
+                .as_ref()
+                .and_then(|report| report.is_cached(&entry.0));
+            match option_cached {
+                Some(true) => line.push_str("  [cached]"),
+                Some(false) => line.push_str("  [missing]"),
+                None => {}
+            }
+        }
+        line
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use indoc::indoc;
+    use std::str::FromStr;
+
+    use crate::nix_query_tree::substitutes::MissingPath;
+    use crate::nix_query_tree::{NixQueryDrv, NixQueryTree};
+
+    fn state() -> State {
+        let raw = indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            +---/nix/store/a9n6ip5d1m7b3blkxmmg0dd5z4cbqgvf-acl-2.2.53
+            |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            +---/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10 [...]
+            "
+        );
+        let tree = NixQueryTree::from_str(raw).unwrap();
+        State::new(
+            FilePath::new(
+                "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10",
+            ),
+            NixStoreRes::new(raw, tree),
+        )
+    }
+
+    fn paths(state: &State) -> Vec<Path> {
+        state.rows().iter().map(|row| row.path.clone()).collect()
+    }
+
+    #[test]
+    fn test_expand_and_collapse() {
+        let mut state = state();
+        assert_eq!(
+            paths(&state),
+            vec![
+                Path::new(),
+                Path::from(vec![0]),
+                Path::from(vec![1]),
+                Path::from(vec![2])
+            ]
+        );
+
+        state.move_cursor(2);
+        state.expand();
+        assert_eq!(state.rows().len(), 5);
+        assert_eq!(state.selected_row().unwrap().path, Path::from(vec![1]));
+
+        // Expanding an expanded row moves to its first child.
+        state.expand();
+        assert_eq!(state.selected_row().unwrap().path, Path::from(vec![1, 0]));
+
+        // Collapsing a leaf moves to its parent, and collapsing again hides the children.
+        state.collapse();
+        assert_eq!(state.selected_row().unwrap().path, Path::from(vec![1]));
+        state.collapse();
+        assert_eq!(state.rows().len(), 4);
+    }
+
+    #[test]
+    fn test_goto_first_instance() {
+        let mut state = state();
+        state.move_to_end();
+        assert!(state.goto_first_instance());
+        assert_eq!(state.selected_row().unwrap().path, Path::new());
+
+        state.search("acl", true);
+        state.expand();
+        state.move_cursor(1);
+        assert!(state.goto_first_instance());
+        assert_eq!(state.selected_row().unwrap().path, Path::from(vec![0]));
+
+        // Not a `[...]` entry.
+        assert!(!state.goto_first_instance());
+    }
+
+    #[test]
+    fn test_goto() {
+        let mut state = state();
+        assert!(state.goto(&"0.0".parse().unwrap()));
+        assert_eq!(state.selected_row().unwrap().path, Path::from(vec![0, 0]));
+
+        assert!(!state.goto(&"0.7".parse().unwrap()));
+        assert_eq!(state.selected_row().unwrap().path, Path::from(vec![0, 0]));
+    }
+
+    #[test]
+    fn test_search_wraps_and_expands() {
+        let mut state = state();
+        assert!(state.search("GLIBC", true));
+        assert_eq!(state.selected_row().unwrap().path, Path::from(vec![0]));
+        assert!(state.search("glibc", true));
+        assert_eq!(state.selected_row().unwrap().path, Path::from(vec![0, 0]));
+        assert!(state.search("glibc", true));
+        assert_eq!(state.selected_row().unwrap().path, Path::from(vec![1, 0]));
+        assert!(state.search("glibc", true));
+        assert_eq!(state.selected_row().unwrap().path, Path::from(vec![0]));
+        assert!(state.search("glibc", false));
+        assert_eq!(state.selected_row().unwrap().path, Path::from(vec![1, 0]));
+        assert!(!state.search("openssl", true));
+    }
+
+    #[test]
+    fn test_sort_order_and_lines() {
+        let mut state = state();
+        state.set_sort_order(SortOrder::AlphabeticalDrvName);
+        state.set_view_style(ViewStyle::OnlyDrvName);
+        let lines: Vec<String> =
+            state.rows().iter().map(|row| state.line(row)).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "▾ hello-2.10",
+                "  ▸ acl-2.2.53",
+                "  ▸ glibc-2.27",
+                "    hello-2.10 [...]"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_only_missing() {
+        let mut state = state();
+        assert!(!state.toggle_only_missing());
+
+        let hello = NixQueryDrv::from(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10",
+        );
+        let glibc = NixQueryDrv::from(
+            "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27",
+        );
+        state.set_substitutes(SubstituteReport {
+            cached: vec![hello].into_iter().collect(),
+            missing: vec![MissingPath {
+                drv: glibc,
+                size: None,
+            }],
+        });
+        state.set_view_style(ViewStyle::OnlyDrvName);
+
+        // Only glibc and its ancestors are shown, expanded.
+        assert!(state.toggle_only_missing());
+        let lines: Vec<String> =
+            state.rows().iter().map(|row| state.line(row)).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "▾ hello-2.10  [cached]",
+                "  ▾ glibc-2.27  [missing]",
+                "      glibc-2.27 [...]  [missing]",
+                "  ▾ acl-2.2.53",
+                "      glibc-2.27 [...]  [missing]",
+            ]
+        );
+
+        assert!(state.toggle_only_missing());
+        assert_eq!(state.rows().len(), 6);
+    }
+
+    #[test]
+    fn test_scroll_to_cursor() {
+        let mut state = state();
+        state.move_to_end();
+        state.scroll_to_cursor(2);
+        assert_eq!(state.top(), 2);
+        state.move_to_start();
+        state.scroll_to_cursor(2);
+        assert_eq!(state.top(), 0);
+    }
+}
@@ -14,7 +14,24 @@ use glib::clone;
 use std::path::Path;
 use std::thread;
 
-use super::nix_query_tree::exec_nix_store::NixStoreErr;
+use super::nix_query_tree::derivation::DerivationErr;
+use super::nix_query_tree::drv_diff;
+use super::nix_query_tree::exec_nix_store::{
+    self, ExecNixStoreRes, KeepRaw, NixStoreErr,
+};
+use super::nix_query_tree::gc_roots;
+use super::nix_query_tree::html_report::{self, Annotations};
+use super::nix_query_tree::installable::Installable;
+use super::nix_query_tree::licenses::{LicenseDb, LicenseDbErr, LicenseReport};
+use super::nix_query_tree::references;
+use super::nix_query_tree::sbom::{Sbom, SbomFormat};
+use super::nix_query_tree::substitutes::{
+    self, SubstituteErr, SubstituteReport, Substituter,
+};
+use super::nix_query_tree::vulnerabilities::{VulnDb, VulnDbErr};
+use super::nix_query_tree::NixQueryDrv;
+use super::opts::Opts;
+use super::tree;
 
 use prelude::*;
 
@@ -31,6 +48,10 @@ fn render_nix_store_err(
         ),
     );
 
+    if let NixStoreErr::ParseErr(raw, parse_err) = nix_store_err {
+        stack::show_parse_error(state, raw, parse_err);
+    }
+
     let error_dialog: gtk::MessageDialog = state.get_error_dialog();
     let error_msg = &format!(
         "Error running `nix-store --query --tree {}`:\n\n{}",
@@ -42,7 +63,169 @@ fn render_nix_store_err(
     error_dialog.hide();
 }
 
+fn render_drv_diff_err(
+    state: &State,
+    left: &NixQueryDrv,
+    right: &NixQueryDrv,
+    derivation_err: &DerivationErr,
+) {
+    statusbar::show_msg(
+        state,
+        &format!("Error comparing {} and {}", left, right),
+    );
+
+    let error_dialog: gtk::MessageDialog = state.get_error_dialog();
+    let error_msg = &format!(
+        "Error comparing {} and {}:\n\n{}",
+        left, right, derivation_err
+    );
+    error_dialog.set_property_secondary_text(Some(error_msg));
+    error_dialog.run();
+    error_dialog.hide();
+}
+
+fn render_vuln_db_err(state: &State, path: &Path, vuln_db_err: &VulnDbErr) {
+    statusbar::show_msg(
+        state,
+        &format!("Error loading vulnerability database {}", path.display()),
+    );
+
+    let error_dialog: gtk::MessageDialog = state.get_error_dialog();
+    let error_msg = &format!(
+        "Error loading vulnerability database {}:\n\n{}",
+        path.display(),
+        vuln_db_err
+    );
+    error_dialog.set_property_secondary_text(Some(error_msg));
+    error_dialog.run();
+    error_dialog.hide();
+}
+
+fn render_license_db_err(
+    state: &State,
+    path: &Path,
+    license_db_err: &LicenseDbErr,
+) {
+    statusbar::show_msg(
+        state,
+        &format!("Error loading license data {}", path.display()),
+    );
+
+    let error_dialog: gtk::MessageDialog = state.get_error_dialog();
+    let error_msg = &format!(
+        "Error loading license data {}:\n\n{}",
+        path.display(),
+        license_db_err
+    );
+    error_dialog.set_property_secondary_text(Some(error_msg));
+    error_dialog.run();
+    error_dialog.hide();
+}
+
+fn render_substitute_err(
+    state: &State,
+    substituter: &str,
+    substitute_err: &SubstituteErr,
+) {
+    statusbar::show_msg(
+        state,
+        &format!("Error checking the binary cache {}", substituter),
+    );
+
+    let error_dialog: gtk::MessageDialog = state.get_error_dialog();
+    let error_msg = &format!(
+        "Error checking the binary cache {}:\n\n{}",
+        substituter, substitute_err
+    );
+    error_dialog.set_property_secondary_text(Some(error_msg));
+    error_dialog.run();
+    error_dialog.hide();
+}
+
+/// Show an error for saving `what` (like "SBOM") to `path`.
+fn render_save_err(state: &State, what: &str, path: &Path, err: &str) {
+    statusbar::show_msg(
+        state,
+        &format!("Error saving {} to {}", what, path.display()),
+    );
+
+    let error_dialog: gtk::MessageDialog = state.get_error_dialog();
+    let error_msg =
+        &format!("Error saving {} to {}:\n\n{}", what, path.display(), err);
+    error_dialog.set_property_secondary_text(Some(error_msg));
+    error_dialog.run();
+    error_dialog.hide();
+}
+
+/// Search for `nix_store_path` in the store from the store entry in the toolbar.
 fn search_for(state: &State, nix_store_path: &Path) {
+    let store_uri = toolbar::store_uri(state);
+    query_nix_store(state, nix_store_path, store_uri, false);
+}
+
+/// Evaluate `installable` to a store path in another thread, and then search for it in
+/// `store_uri`.  With `--out-path`, this is the output path instead of the `.drv` file.
+fn evaluate_and_search_for(
+    state: &State,
+    installable: Installable,
+    store_uri: Option<String>,
+    refresh: bool,
+) {
+    disable(state);
+
+    statusbar::show_msg(state, &format!("Evaluating {}...", installable));
+
+    let out_path = *state.read_out_path();
+    thread::spawn(clone!(@strong state.sender as sender => move || {
+        let exec_nix_store_res = match installable.resolve(out_path) {
+            Err(err) => ExecNixStoreRes::new(
+                Path::new(&installable.to_string()),
+                Err(NixStoreErr::EvalErr(format!(
+                    "Error evaluating {}: {}",
+                    installable, err
+                ))),
+            ),
+            Ok(nix_store_path) => {
+                run_nix_store(&nix_store_path, store_uri.as_deref(), refresh)
+            }
+        };
+
+        sender
+            .send(Message::Display(exec_nix_store_res))
+            .expect("sender is already closed.  This should never happen");
+    }));
+}
+
+/// Run `nix-store` again for the store path that is currently shown, without using the
+/// on-disk cache.
+pub fn refresh(state: &State) {
+    let option_nix_store_path = state.read_nix_store_path().clone();
+    if let Some(nix_store_path) = option_nix_store_path {
+        let store_uri = state.read_store_uri().clone();
+        query_nix_store(state, &nix_store_path, store_uri, true);
+    }
+}
+
+/// Run `nix-store --query --tree` for `nix_store_path`.  With `refresh`, the on-disk cache
+/// isn't used.  The raw output is kept for the "Raw" tab.
+fn run_nix_store(
+    nix_store_path: &Path,
+    store_uri: Option<&str>,
+    refresh: bool,
+) -> ExecNixStoreRes {
+    if refresh {
+        exec_nix_store::refresh(nix_store_path, store_uri, KeepRaw::Yes)
+    } else {
+        exec_nix_store::run(nix_store_path, store_uri, KeepRaw::Yes)
+    }
+}
+
+fn query_nix_store(
+    state: &State,
+    nix_store_path: &Path,
+    store_uri: Option<String>,
+    refresh: bool,
+) {
     // nix-store --query --tree /nix/store/jymg0kanmlgbcv35wxd8d660rw0fawhv-hello-2.10.drv
     // nix-store --query --tree /nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
 
@@ -56,7 +239,7 @@ fn search_for(state: &State, nix_store_path: &Path) {
     let nix_store_path_buf = nix_store_path.to_path_buf();
     thread::spawn(clone!(@strong state.sender as sender => move || {
         let exec_nix_store_res =
-            super::nix_query_tree::exec_nix_store::run(&nix_store_path_buf);
+            run_nix_store(&nix_store_path_buf, store_uri.as_deref(), refresh);
 
         sender
             .send(Message::Display(exec_nix_store_res))
@@ -64,6 +247,235 @@ fn search_for(state: &State, nix_store_path: &Path) {
     }));
 }
 
+fn diff_drvs(state: &State, left: &NixQueryDrv, right: &NixQueryDrv) {
+    disable(state);
+
+    statusbar::show_msg(state, &format!("Comparing {} and {}...", left, right));
+
+    let left = left.clone();
+    let right = right.clone();
+    thread::spawn(clone!(@strong state.sender as sender => move || {
+        let res = drv_diff::diff_drvs(&left, &right);
+
+        sender
+            .send(Message::DisplayDrvDiff(left, right, res))
+            .expect("sender is already closed.  This should never happen");
+    }));
+}
+
+fn find_references(state: &State, parent: &NixQueryDrv, child: &NixQueryDrv) {
+    disable(state);
+
+    statusbar::show_msg(
+        state,
+        &format!("Searching for references to {} in {}...", child, parent),
+    );
+
+    let parent = parent.clone();
+    let child = child.clone();
+    thread::spawn(clone!(@strong state.sender as sender => move || {
+        let res = references::find_references_to(&parent, &child);
+
+        sender
+            .send(Message::DisplayReferences(parent, child, res))
+            .expect("sender is already closed.  This should never happen");
+    }));
+}
+
+/// Find the garbage collector roots keeping `drv` alive in another thread.
+fn find_gc_roots(state: &State, drv: &NixQueryDrv) {
+    disable(state);
+
+    statusbar::show_msg(
+        state,
+        &format!("Searching for the GC roots of {}...", drv),
+    );
+
+    let drv = drv.clone();
+    let store_uri = state.read_store_uri().clone();
+    thread::spawn(clone!(@strong state.sender as sender => move || {
+        let res = gc_roots::root_chains(&drv, store_uri.as_deref());
+
+        sender
+            .send(Message::DisplayGcRoots(drv, res))
+            .expect("sender is already closed.  This should never happen");
+    }));
+}
+
+fn load_vuln_db(state: &State, path: &Path) {
+    statusbar::show_msg(
+        state,
+        &format!("Loading vulnerability database {}...", path.display()),
+    );
+
+    let path_buf = path.to_path_buf();
+    thread::spawn(clone!(@strong state.sender as sender => move || {
+        let res = VulnDb::from_path(&path_buf);
+
+        sender
+            .send(Message::LoadVulnDb(path_buf, res))
+            .expect("sender is already closed.  This should never happen");
+    }));
+}
+
+fn load_license_db(state: &State, path: &Path) {
+    statusbar::show_msg(
+        state,
+        &format!("Loading license data {}...", path.display()),
+    );
+
+    let path_buf = path.to_path_buf();
+    thread::spawn(clone!(@strong state.sender as sender => move || {
+        let res = LicenseDb::from_path(&path_buf);
+
+        sender
+            .send(Message::LoadLicenseDb(path_buf, res))
+            .expect("sender is already closed.  This should never happen");
+    }));
+}
+
+/// Find the licenses of everything in the tree in the background.  This reads every `.drv`
+/// file in the tree, so it can take a while for big closures.
+fn find_licenses(state: &State) {
+    let (path_map, root) = match &*state.read_nix_store_res() {
+        None => return,
+        Some(nix_store_res) => (
+            nix_store_res.map.clone(),
+            nix_store_res.tree.0.item.0.clone(),
+        ),
+    };
+    let license_db = state.read_license_db().clone();
+
+    thread::spawn(clone!(@strong state.sender as sender => move || {
+        let report = LicenseReport::from_closure(
+            &path_map,
+            &root,
+            license_db.as_ref(),
+        );
+
+        sender
+            .send(Message::DisplayLicenses(report))
+            .expect("sender is already closed.  This should never happen");
+    }));
+}
+
+/// Check which store paths in the tree are in the binary cache from `--substituter` or File →
+/// Check Binary Cache… in the background.  This makes a request for every store path in the
+/// tree when the binary cache is served over HTTP.
+fn check_substitutes(state: &State) {
+    let substituter_uri = match &*state.read_substituter() {
+        None => return,
+        Some(substituter_uri) => substituter_uri.clone(),
+    };
+    let nix_store_path = match &*state.read_nix_store_path() {
+        None => return,
+        Some(nix_store_path) => nix_store_path.clone(),
+    };
+    let nix_store_res = match &*state.read_nix_store_res() {
+        None => return,
+        Some(nix_store_res) => nix_store_res.clone(),
+    };
+
+    thread::spawn(clone!(@strong state.sender as sender => move || {
+        let res = Substituter::from_uri(&substituter_uri).and_then(
+            |substituter| substitutes::check(&substituter, &nix_store_res),
+        );
+
+        sender
+            .send(Message::DisplaySubstitutes(
+                nix_store_path,
+                substituter_uri,
+                res,
+            ))
+            .expect("sender is already closed.  This should never happen");
+    }));
+}
+
+/// Check the tree against the binary cache at `substituter_uri`, and every tree shown after
+/// it.
+fn set_substituter(state: &State, substituter_uri: &str) {
+    state.write_substituter(String::from(substituter_uri));
+    clear_missing_paths(state);
+    stack::redisplay_data(state);
+    check_substitutes(state);
+}
+
+/// Forget which store paths are missing from the binary cache, and show every row of the tree
+/// view again until the tree has been checked against the binary cache.
+fn clear_missing_paths(state: &State) {
+    state.write_missing_paths(None);
+    let only_missing_menu_item = state.get_only_missing_menu_item();
+    only_missing_menu_item.set_active(false);
+    only_missing_menu_item.set_sensitive(false);
+}
+
+/// Remember which store paths are missing from the binary cache according to `report`, so
+/// View → Show Only Missing Paths can show just them.
+fn set_missing_paths(state: &State, report: &SubstituteReport) {
+    let option_missing_paths = state
+        .read_nix_store_res()
+        .as_ref()
+        .map(|nix_store_res| report.missing_paths(&nix_store_res.tree.0));
+    state.write_missing_paths(option_missing_paths);
+    state.get_only_missing_menu_item().set_sensitive(true);
+}
+
+/// Show or hide the rows of the tree view for store paths that are in the binary cache.
+fn refilter(state: &State) {
+    stack::refilter(state);
+}
+
+/// Write a software bill of materials for the tree currently being shown to `path`.
+fn save_sbom(state: &State, path: &Path, format: SbomFormat) {
+    let res = match &*state.read_nix_store_res() {
+        None => return,
+        Some(nix_store_res) => {
+            let sbom = Sbom::new(&nix_store_res.tree, &nix_store_res.map);
+            std::fs::write(path, sbom.render(format))
+        }
+    };
+
+    match res {
+        Err(io_err) => {
+            render_save_err(state, "SBOM", path, &io_err.to_string());
+        }
+        Ok(()) => statusbar::show_msg(
+            state,
+            &format!("Saved {} SBOM to {}", format, path.display()),
+        ),
+    }
+}
+
+/// Write an HTML report for the tree currently being shown to `path`.  This happens in the
+/// background, since finding the size of every store path can take a while.
+fn save_html_report(state: &State, path: &Path) {
+    let (tree, path_map) = match &*state.read_nix_store_res() {
+        None => return,
+        Some(nix_store_res) => {
+            (nix_store_res.tree.clone(), nix_store_res.map.clone())
+        }
+    };
+
+    statusbar::show_msg(
+        state,
+        &format!("Saving HTML report to {}...", path.display()),
+    );
+
+    let path_buf = path.to_path_buf();
+    thread::spawn(clone!(@strong state.sender as sender => move || {
+        let mut annotations = Annotations::default();
+        annotations.add_sizes(&path_map);
+        annotations.add_duplicates(&path_map);
+        let html = html_report::render(&tree, &path_map, &annotations);
+        let res = std::fs::write(&path_buf, html)
+            .map_err(|io_err| io_err.to_string());
+
+        sender
+            .send(Message::SavedHtmlReport(path_buf, res))
+            .expect("sender is already closed.  This should never happen");
+    }));
+}
+
 fn set_sort_order(state: &State, new_sort_order: SortOrder) {
     state.write_sort_order(new_sort_order);
 
@@ -76,6 +488,72 @@ pub fn set_view_style(state: &State, new_view_style: ViewStyle) {
     stack::change_view_style(state);
 }
 
+fn expand_all(state: &State) {
+    stack::expand_all(state);
+}
+
+fn collapse_all(state: &State) {
+    stack::collapse_all(state);
+}
+
+fn expand_to_depth(state: &State, depth: usize) {
+    stack::expand_to_depth(state, depth);
+}
+
+/// Only show the rows of the tree view leading to store paths that contain `query`.  An
+/// empty `query` collapses everything.
+fn expand_to_matches(state: &State, query: &str) {
+    let query = query.trim();
+    if query.is_empty() {
+        collapse_all(state);
+    } else {
+        stack::expand_to_matches(state, query);
+    }
+}
+
+/// Show the path of the row selected in the tree view in the statusbar, in the same form that
+/// `--select` takes.
+pub fn show_selected_path(state: &State) {
+    let nix_store_res = &*state.read_nix_store_res();
+    let option_selected = nix_store_res.as_ref().and_then(|nix_store_res| {
+        let path = stack::selected_path(state)?;
+        let nix_query_entry = nix_store_res.tree.lookup(path.clone())?;
+        let option_narinfo = nix_store_res.narinfos.get(&nix_query_entry.0);
+        Some((path, nix_query_entry, option_narinfo))
+    });
+    if let Some((path, nix_query_entry, option_narinfo)) = option_selected {
+        if !path.0.is_empty() {
+            let nar_info = option_narinfo.map_or_else(String::new, |narinfo| {
+                format!(" ({})", narinfo.summary())
+            });
+            statusbar::show_msg(
+                state,
+                &format!("Row {}: {}{}", path, nix_query_entry.0, nar_info),
+            );
+        }
+    }
+}
+
+/// Select the row at `path` in the tree view, or say in the statusbar that there is no such
+/// row.
+fn select_path(state: &State, path: &tree::Path) {
+    let exists = state
+        .read_nix_store_res()
+        .as_ref()
+        .and_then(|nix_store_res| {
+            nix_store_res.tree.0.lookup_tree(path.clone())
+        })
+        .is_some();
+    if exists {
+        stack::goto(state, path);
+    } else {
+        statusbar::show_msg(
+            state,
+            &format!("There is no row {} to select", path),
+        );
+    }
+}
+
 fn redisplay_data(state: &State) {
     statusbar::clear(state);
     stack::redisplay_data(state);
@@ -104,14 +582,115 @@ fn handle_msg_recv(state: &State, msg: Message) {
                 );
             }
             Ok(nix_store_res) => {
+                let skipped_warning = nix_store_res.skipped_warning();
                 state.write_nix_store_res(nix_store_res);
+                state.write_nix_store_path(exec_nix_store_res.nix_store_path);
+                state.write_store_uri(exec_nix_store_res.store_uri);
+                clear_missing_paths(state);
                 redisplay_data(state);
+                if let Some(skipped_warning) = skipped_warning {
+                    statusbar::show_msg(state, &skipped_warning);
+                } else if let Some(cache_warning) =
+                    &exec_nix_store_res.cache_warning
+                {
+                    statusbar::show_msg(state, cache_warning);
+                } else if exec_nix_store_res.from_cache {
+                    statusbar::show_msg(
+                        state,
+                        "Loaded from the cache.  Click Refresh to run nix-store again.",
+                    );
+                }
+                if let Some(path) = state.take_pending_select() {
+                    select_path(state, &path);
+                }
+                find_licenses(state);
+                check_substitutes(state);
+            }
+        },
+        Message::DisplayDrvDiff(left, right, res) => match res {
+            Err(derivation_err) => {
+                render_drv_diff_err(state, &left, &right, &derivation_err);
+            }
+            Ok(drv_diff) => {
+                statusbar::clear(state);
+                stack::display_drv_diff(state, &drv_diff);
+            }
+        },
+        Message::LoadVulnDb(path, res) => match res {
+            Err(vuln_db_err) => {
+                render_vuln_db_err(state, &path, &vuln_db_err);
+            }
+            Ok(vuln_db) => {
+                statusbar::show_msg(
+                    state,
+                    &format!(
+                        "Loaded {} advisories from {}",
+                        vuln_db.advisories.len(),
+                        path.display()
+                    ),
+                );
+                state.write_vuln_db(vuln_db);
+                stack::redisplay_vulnerabilities(state);
+            }
+        },
+        Message::LoadLicenseDb(path, res) => match res {
+            Err(license_db_err) => {
+                render_license_db_err(state, &path, &license_db_err);
+            }
+            Ok(license_db) => {
+                statusbar::show_msg(
+                    state,
+                    &format!(
+                        "Loaded license data for {} packages from {}",
+                        license_db.licenses.len(),
+                        path.display()
+                    ),
+                );
+                state.write_license_db(license_db);
+                find_licenses(state);
             }
         },
+        Message::SavedHtmlReport(path, res) => match res {
+            Err(err) => render_save_err(state, "HTML report", &path, &err),
+            Ok(()) => statusbar::show_msg(
+                state,
+                &format!("Saved HTML report to {}", path.display()),
+            ),
+        },
+        Message::DisplaySubstitutes(nix_store_path, substituter, res) => {
+            // The tree or the binary cache changed while this was being checked, and the
+            // new one is already being checked.
+            if state.read_nix_store_path().as_ref() != Some(&nix_store_path)
+                || state.read_substituter().as_ref() != Some(&substituter)
+            {
+                return;
+            }
+            match res {
+                Err(substitute_err) => {
+                    render_substitute_err(state, &substituter, &substitute_err);
+                }
+                Ok(report) => {
+                    statusbar::show_msg(state, &report.summary());
+                    stack::display_substitutes(state, &report);
+                    set_missing_paths(state, &report);
+                }
+            }
+        }
+        Message::DisplayLicenses(report) => {
+            stack::display_licenses(state, &report);
+        }
+        Message::DisplayGcRoots(drv, res) => {
+            statusbar::clear(state);
+            stack::display_gc_roots(state, &drv, &res);
+        }
+        Message::DisplayReferences(parent, child, res) => {
+            statusbar::clear(state);
+            stack::display_references(state, &parent, &child, &res);
+        }
     }
 }
 
-fn app_activate(app: gtk::Application) {
+fn app_activate(app: gtk::Application, opts: &Opts) {
     let (sender, receiver) =
         glib::MainContext::channel(glib::source::PRIORITY_DEFAULT);
 
@@ -135,19 +714,54 @@ fn app_activate(app: gtk::Application) {
         }),
     );
 
-    // Do the initial search and display the results.
-    let opts = crate::opts::Opts::parse_from_args();
-    search_for(&state, &opts.nix_store_path);
+    if let Some(vuln_db_path) = &opts.vuln_db_path {
+        load_vuln_db(&state, vuln_db_path);
+    }
+
+    if let Some(license_db_path) = &opts.license_db_path {
+        load_license_db(&state, license_db_path);
+    }
+
+    if let Some(substituter_uri) = &opts.substituter {
+        state.write_substituter(substituter_uri.clone());
+    }
+
+    if let Some(path) = &opts.select {
+        state.write_pending_select(path.clone());
+    }
+
+    state.write_out_path(opts.out_path);
+
+    toolbar::set_store_uri(&state, opts.store_uri.as_deref());
+
+    // Do the initial search and display the results.  Flake references and --attr are
+    // evaluated in the background, so the window is shown right away.
+    let installable = opts.installable();
+    if installable.is_store_path() {
+        query_nix_store(
+            &state,
+            &opts.nix_store_path,
+            opts.store_uri.clone(),
+            opts.refresh,
+        );
+    } else {
+        evaluate_and_search_for(
+            &state,
+            installable,
+            opts.store_uri.clone(),
+            opts.refresh,
+        );
+    }
 }
 
-pub fn run() {
+pub fn run(opts: Opts) {
     let uiapp = gtk::Application::new(
         Some("com.github.cdepillabout.nix-query-tree-viewer"),
         gio::ApplicationFlags::FLAGS_NONE,
     )
     .expect("Application::new failed");
 
-    uiapp.connect_activate(|app| app_activate(app.clone()));
+    uiapp.connect_activate(move |app| app_activate(app.clone(), &opts));
 
     // uiapp.run(&env::args().collect::<Vec<_>>());
     uiapp.run(&[]);
@@ -2,8 +2,306 @@ use super::prelude::*;
 use glib::clone;
 
 use super::super::ui;
+use super::toolbar;
+use crate::nix_query_tree::sbom::SbomFormat;
+
+fn handle_load_vuln_db_menu_item_activated(state: &ui::State) {
+    let file_chooser = gtk::FileChooserDialog::with_buttons(
+        Some("Load Vulnerability Database"),
+        Some(&state.get_app_win()),
+        gtk::FileChooserAction::Open,
+        &[
+            ("_Cancel", gtk::ResponseType::Cancel),
+            ("_Open", gtk::ResponseType::Accept),
+        ],
+    );
+
+    let json_filter = gtk::FileFilter::new();
+    json_filter.set_name(Some("OSV or NVD JSON feed"));
+    json_filter.add_pattern("*.json");
+    file_chooser.add_filter(&json_filter);
+
+    if file_chooser.run() == gtk::ResponseType::Accept {
+        if let Some(path) = file_chooser.get_filename() {
+            ui::load_vuln_db(state, &path);
+        }
+    }
+    file_chooser.destroy();
+}
+
+fn handle_load_license_db_menu_item_activated(state: &ui::State) {
+    let file_chooser = gtk::FileChooserDialog::with_buttons(
+        Some("Load License Data"),
+        Some(&state.get_app_win()),
+        gtk::FileChooserAction::Open,
+        &[
+            ("_Cancel", gtk::ResponseType::Cancel),
+            ("_Open", gtk::ResponseType::Accept),
+        ],
+    );
+
+    let json_filter = gtk::FileFilter::new();
+    json_filter.set_name(Some("License JSON from nix eval"));
+    json_filter.add_pattern("*.json");
+    file_chooser.add_filter(&json_filter);
+
+    if file_chooser.run() == gtk::ResponseType::Accept {
+        if let Some(path) = file_chooser.get_filename() {
+            ui::load_license_db(state, &path);
+        }
+    }
+    file_chooser.destroy();
+}
+
+fn handle_check_binary_cache_menu_item_activated(state: &ui::State) {
+    let file_chooser = gtk::FileChooserDialog::with_buttons(
+        Some("Check Binary Cache"),
+        Some(&state.get_app_win()),
+        gtk::FileChooserAction::SelectFolder,
+        &[
+            ("_Cancel", gtk::ResponseType::Cancel),
+            ("_Check", gtk::ResponseType::Accept),
+        ],
+    );
+
+    if file_chooser.run() == gtk::ResponseType::Accept {
+        if let Some(path) = file_chooser.get_filename() {
+            ui::set_substituter(state, &path.to_string_lossy());
+        }
+    }
+    file_chooser.destroy();
+}
+
+fn handle_save_sbom_menu_item_activated(state: &ui::State) {
+    let root_drv = match &*state.read_nix_store_res() {
+        None => return,
+        Some(nix_store_res) => nix_store_res.tree.0.item.0.clone(),
+    };
+
+    let file_chooser = gtk::FileChooserDialog::with_buttons(
+        Some("Save SBOM"),
+        Some(&state.get_app_win()),
+        gtk::FileChooserAction::Save,
+        &[
+            ("_Cancel", gtk::ResponseType::Cancel),
+            ("_Save", gtk::ResponseType::Accept),
+        ],
+    );
+    file_chooser.set_do_overwrite_confirmation(true);
+    file_chooser.set_current_name(format!(
+        "{}.{}",
+        root_drv.drv_name(),
+        SbomFormat::CycloneDx.extension()
+    ));
+
+    let cyclonedx_filter = gtk::FileFilter::new();
+    cyclonedx_filter.set_name(Some("CycloneDX JSON"));
+    cyclonedx_filter.add_pattern("*.json");
+    file_chooser.add_filter(&cyclonedx_filter);
+
+    let spdx_filter = gtk::FileFilter::new();
+    spdx_filter.set_name(Some("SPDX JSON"));
+    spdx_filter.add_pattern("*.json");
+    file_chooser.add_filter(&spdx_filter);
+
+    if file_chooser.run() == gtk::ResponseType::Accept {
+        if let Some(path) = file_chooser.get_filename() {
+            let is_spdx = file_chooser.get_filter() == Some(spdx_filter)
+                || path
+                    .to_string_lossy()
+                    .ends_with(SbomFormat::Spdx.extension());
+            let format = if is_spdx {
+                SbomFormat::Spdx
+            } else {
+                SbomFormat::CycloneDx
+            };
+            ui::save_sbom(state, &path, format);
+        }
+    }
+    file_chooser.destroy();
+}
+
+fn handle_save_html_report_menu_item_activated(state: &ui::State) {
+    let root_drv = match &*state.read_nix_store_res() {
+        None => return,
+        Some(nix_store_res) => nix_store_res.tree.0.item.0.clone(),
+    };
+
+    let file_chooser = gtk::FileChooserDialog::with_buttons(
+        Some("Save HTML Report"),
+        Some(&state.get_app_win()),
+        gtk::FileChooserAction::Save,
+        &[
+            ("_Cancel", gtk::ResponseType::Cancel),
+            ("_Save", gtk::ResponseType::Accept),
+        ],
+    );
+    file_chooser.set_do_overwrite_confirmation(true);
+    file_chooser.set_current_name(format!("{}.html", root_drv.drv_name()));
+
+    let html_filter = gtk::FileFilter::new();
+    html_filter.set_name(Some("HTML"));
+    html_filter.add_pattern("*.html");
+    file_chooser.add_filter(&html_filter);
+
+    if file_chooser.run() == gtk::ResponseType::Accept {
+        if let Some(path) = file_chooser.get_filename() {
+            ui::save_html_report(state, &path);
+        }
+    }
+    file_chooser.destroy();
+}
+
+/// Expand to the rows matching the filter entry, or if nothing has been typed into it yet,
+/// move the focus there so something can be.
+fn handle_expand_to_matches_menu_item_activated(state: &ui::State) {
+    let filter_entry = state.get_filter_entry();
+    if filter_entry.get_buffer().get_text().trim().is_empty() {
+        filter_entry.grab_focus();
+    } else {
+        toolbar::expand_to_matches(state);
+    }
+}
+
+/// Ctrl+1 to Ctrl+9 show every row of the tree view down to that depth.
+fn handle_key_press_event(
+    state: &ui::State,
+    event_key: &gdk::EventKey,
+) -> Inhibit {
+    if !event_key
+        .get_state()
+        .contains(gdk::ModifierType::CONTROL_MASK)
+    {
+        return Inhibit(false);
+    }
+    let option_depth = gdk::keyval_to_unicode(event_key.get_keyval())
+        .and_then(|c| c.to_digit(10))
+        .filter(|depth| *depth > 0);
+    match option_depth {
+        None => Inhibit(false),
+        Some(depth) => {
+            toolbar::set_depth(state, depth);
+            Inhibit(true)
+        }
+    }
+}
+
+/// Add the keyboard shortcuts for the View menu.  These are added here instead of in the
+/// glade file, because GtkBuilder would add them to the window of the menu, not the main
+/// window.
+fn add_accelerators(state: &ui::State) {
+    let accel_group = state.get_accel_group();
+    let control = gdk::ModifierType::CONTROL_MASK;
+    let accelerators: [(gtk::MenuItem, u32, gdk::ModifierType); 4] = [
+        (
+            state.get_expand_all_menu_item(),
+            gdk::enums::key::e,
+            control,
+        ),
+        (
+            state.get_collapse_all_menu_item(),
+            gdk::enums::key::e,
+            control | gdk::ModifierType::SHIFT_MASK,
+        ),
+        (
+            state.get_expand_to_depth_menu_item(),
+            gdk::enums::key::d,
+            control,
+        ),
+        (
+            state.get_expand_to_matches_menu_item(),
+            gdk::enums::key::m,
+            control,
+        ),
+    ];
+    for (menu_item, key, modifiers) in &accelerators {
+        menu_item.add_accelerator(
+            "activate",
+            &accel_group,
+            *key,
+            *modifiers,
+            gtk::AccelFlags::VISIBLE,
+        );
+    }
+}
 
 fn connect_signals(state: &ui::State) {
+    let load_vuln_db_menu_item: gtk::MenuItem =
+        state.get_load_vuln_db_menu_item();
+
+    load_vuln_db_menu_item.connect_activate(clone!(@strong state => move |_| {
+        handle_load_vuln_db_menu_item_activated(&state);
+    }));
+
+    let load_license_db_menu_item: gtk::MenuItem =
+        state.get_load_license_db_menu_item();
+
+    load_license_db_menu_item.connect_activate(
+        clone!(@strong state => move |_| {
+            handle_load_license_db_menu_item_activated(&state);
+        }),
+    );
+
+    let check_binary_cache_menu_item: gtk::MenuItem =
+        state.get_check_binary_cache_menu_item();
+
+    check_binary_cache_menu_item.connect_activate(
+        clone!(@strong state => move |_| {
+            handle_check_binary_cache_menu_item_activated(&state);
+        }),
+    );
+
+    let save_sbom_menu_item: gtk::MenuItem = state.get_save_sbom_menu_item();
+
+    save_sbom_menu_item.connect_activate(clone!(@strong state => move |_| {
+        handle_save_sbom_menu_item_activated(&state);
+    }));
+
+    let save_html_report_menu_item: gtk::MenuItem =
+        state.get_save_html_report_menu_item();
+
+    save_html_report_menu_item.connect_activate(
+        clone!(@strong state => move |_| {
+            handle_save_html_report_menu_item_activated(&state);
+        }),
+    );
+
+    state.get_expand_all_menu_item().connect_activate(
+        clone!(@strong state => move |_| {
+            ui::expand_all(&state);
+        }),
+    );
+
+    state.get_collapse_all_menu_item().connect_activate(
+        clone!(@strong state => move |_| {
+            ui::collapse_all(&state);
+        }),
+    );
+
+    state.get_expand_to_depth_menu_item().connect_activate(
+        clone!(@strong state => move |_| {
+            ui::expand_to_depth(&state, toolbar::depth(&state));
+        }),
+    );
+
+    state.get_expand_to_matches_menu_item().connect_activate(
+        clone!(@strong state => move |_| {
+            handle_expand_to_matches_menu_item_activated(&state);
+        }),
+    );
+
+    state.get_only_missing_menu_item().connect_toggled(
+        clone!(@strong state => move |_| {
+            ui::refilter(&state);
+        }),
+    );
+
+    state.get_app_win().connect_key_press_event(
+        clone!(@strong state => move |_, event_key| {
+            handle_key_press_event(&state, event_key)
+        }),
+    );
+
     let about_menu_item: gtk::MenuItem = state.get_about_menu_item();
     let about_dialog: gtk::AboutDialog = state.get_about_dialog();
 
@@ -22,5 +320,6 @@ fn connect_signals(state: &ui::State) {
 }
 
 pub fn setup(state: &ui::State) {
+    add_accelerators(state);
     connect_signals(state);
 }
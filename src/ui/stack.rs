@@ -1,21 +1,76 @@
+mod drv;
+mod drv_diff;
+mod duplicates;
+mod files;
+mod gc_roots;
+mod licenses;
 mod raw;
+mod references;
+mod substitutes;
 mod tree;
+mod vulnerabilities;
+
+use glib::clone;
 
 use super::super::ui;
+use super::prelude::*;
+use crate::nix_query_tree::drv_diff::DrvDiff;
+use crate::nix_query_tree::gc_roots::{GcRootsErr, GcRootsRes};
+use crate::nix_query_tree::licenses::LicenseReport;
+use crate::nix_query_tree::parsing::ParseError;
+use crate::nix_query_tree::references::Reference;
+use crate::nix_query_tree::store_contents::StoreContentsErr;
+use crate::nix_query_tree::substitutes::SubstituteReport;
+use crate::nix_query_tree::NixQueryDrv;
 
 pub fn setup(state: &ui::State) {
-    tree::setup(&state);
-    raw::setup(&state);
+    tree::setup(state);
+    raw::setup(state);
+    drv::setup(state);
+    drv_diff::setup(state);
+    files::setup(state);
+    references::setup(state);
+    duplicates::setup(state);
+    vulnerabilities::setup(state);
+    licenses::setup(state);
+    substitutes::setup(state);
+    gc_roots::setup(state);
+
+    state
+        .get_stack()
+        .connect_property_visible_child_name_notify(
+            clone!(@strong state => move |_| {
+                show_files_for_selection(&state);
+            }),
+        );
 }
 
 pub fn disable(state: &ui::State) {
     tree::disable(state);
     raw::disable(state);
+    drv::disable(state);
+    drv_diff::disable(state);
+    files::disable(state);
+    references::disable(state);
+    duplicates::disable(state);
+    vulnerabilities::disable(state);
+    licenses::disable(state);
+    substitutes::disable(state);
+    gc_roots::disable(state);
 }
 
 pub fn enable(state: &ui::State) {
     tree::enable(state);
     raw::enable(state);
+    drv::enable(state);
+    drv_diff::enable(state);
+    files::enable(state);
+    references::enable(state);
+    duplicates::enable(state);
+    vulnerabilities::enable(state);
+    licenses::enable(state);
+    substitutes::enable(state);
+    gc_roots::enable(state);
 }
 
 pub fn change_sort_order(state: &ui::State) {
@@ -27,6 +82,114 @@ pub fn change_view_style(state: &ui::State) {
 }
 
 pub fn redisplay_data(state: &ui::State) {
-    tree::redisplay_data(&state);
-    raw::redisplay_data(&state);
+    tree::redisplay_data(state);
+    raw::redisplay_data(state);
+    drv::redisplay_data(state);
+    files::redisplay_data(state);
+    references::redisplay_data(state);
+    duplicates::redisplay_data(state);
+    vulnerabilities::redisplay_data(state);
+    licenses::redisplay_data(state);
+    substitutes::redisplay_data(state);
+    gc_roots::redisplay_data(state);
+}
+
+pub fn redisplay_vulnerabilities(state: &ui::State) {
+    vulnerabilities::redisplay_data(state);
+}
+
+pub fn display_licenses(state: &ui::State, report: &LicenseReport) {
+    licenses::display(state, report);
+}
+
+pub fn display_substitutes(state: &ui::State, report: &SubstituteReport) {
+    substitutes::display(state, report);
+}
+
+/// Switch to the raw page and show output from `nix-store` that couldn't be parsed.
+pub fn show_parse_error(state: &ui::State, raw: &str, parse_err: &ParseError) {
+    raw::show_parse_error(state, raw, parse_err);
+}
+
+pub fn display_drv_diff(state: &ui::State, drv_diff: &DrvDiff) {
+    drv_diff::display(state, drv_diff);
+}
+
+pub fn display_gc_roots(
+    state: &ui::State,
+    drv: &NixQueryDrv,
+    res: &Result<GcRootsRes, GcRootsErr>,
+) {
+    gc_roots::display(state, drv, res);
+}
+
+pub fn display_references(
+    state: &ui::State,
+    parent: &NixQueryDrv,
+    child: &NixQueryDrv,
+    res: &Result<Vec<Reference>, StoreContentsErr>,
+) {
+    references::display(state, parent, child, res);
+}
+
+/// Switch to the tree view and select the row at `path`.
+pub fn goto(state: &ui::State, path: &crate::tree::Path) {
+    state.get_stack().set_visible_child_name(tree::PAGE_NAME);
+    tree::goto(state, path);
+}
+
+/// Switch to the tree view and expand every row.
+pub fn expand_all(state: &ui::State) {
+    state.get_stack().set_visible_child_name(tree::PAGE_NAME);
+    tree::expand_all(state);
+}
+
+/// Switch to the tree view and collapse every row below the first one.
+pub fn collapse_all(state: &ui::State) {
+    state.get_stack().set_visible_child_name(tree::PAGE_NAME);
+    tree::collapse_all(state);
+}
+
+/// Switch to the tree view and show every row down to `depth` levels.
+pub fn expand_to_depth(state: &ui::State, depth: usize) {
+    state.get_stack().set_visible_child_name(tree::PAGE_NAME);
+    tree::expand_to_depth(state, depth);
+}
+
+/// Switch to the tree view and only show the rows leading to the store paths that match
+/// `query`.
+pub fn expand_to_matches(state: &ui::State, query: &str) {
+    state.get_stack().set_visible_child_name(tree::PAGE_NAME);
+    tree::expand_to_matches(state, query);
+}
+
+/// Show or hide the rows of the tree view for store paths that are in the binary cache.  This
+/// switches to the tree view when only the missing store paths are being shown.
+pub fn refilter(state: &ui::State) {
+    if state.get_only_missing_menu_item().get_active() {
+        state.get_stack().set_visible_child_name(tree::PAGE_NAME);
+    }
+    tree::refilter(state);
+}
+
+/// The path of the row currently selected in the tree view.
+pub fn selected_path(state: &ui::State) -> Option<crate::tree::Path> {
+    tree::selected_path(state)
+}
+
+/// Switch to the files page and show the files in `nix_query_drv`.
+pub fn show_files(state: &ui::State, nix_query_drv: &NixQueryDrv) {
+    state.get_stack().set_visible_child_name(files::PAGE_NAME);
+    files::show(state, nix_query_drv);
+}
+
+/// Show the files for the row selected in the tree view, but only if the files page is
+/// currently visible.
+pub fn show_files_for_selection(state: &ui::State) {
+    let visible_child_name = state.get_stack().get_visible_child_name();
+    if visible_child_name.as_deref() == Some(files::PAGE_NAME) {
+        if let Some(nix_query_drv) = tree::selected_nix_query_drv(state) {
+            files::show(state, &nix_query_drv);
+        }
+    }
 }
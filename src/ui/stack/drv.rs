@@ -0,0 +1,162 @@
+use glib::clone;
+
+use super::super::super::ui;
+use super::super::prelude::*;
+use super::tree;
+use crate::nix_query_tree::derivation::Derivation;
+use crate::nix_query_tree::NixQueryDrv;
+
+/// These correspond to actual columns in the `drvTreeStore`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(i32)]
+enum Column {
+    Name = 0,
+    Value,
+    /// The nix store path this row links to in the tree view.  This is empty for rows that
+    /// don't link anywhere.
+    Link,
+}
+
+const COLUMN_INDICIES: [u32; 3] = [
+    Column::Name as u32,
+    Column::Value as u32,
+    Column::Link as u32,
+];
+
+fn insert_row(
+    tree_store: &gtk::TreeStore,
+    parent: Option<&gtk::TreeIter>,
+    name: &str,
+    value: &str,
+    link: &str,
+) -> gtk::TreeIter {
+    tree_store.insert_with_values(
+        parent,
+        None,
+        &COLUMN_INDICIES,
+        &[&name, &value, &link],
+    )
+}
+
+fn insert_derivation(tree_store: &gtk::TreeStore, derivation: &Derivation) {
+    insert_row(tree_store, None, "Builder", &derivation.builder, "");
+    insert_row(tree_store, None, "Platform", &derivation.platform, "");
+
+    let args_iter = insert_row(tree_store, None, "Arguments", "", "");
+    for (i, arg) in derivation.args.iter().enumerate() {
+        insert_row(tree_store, Some(&args_iter), &i.to_string(), arg, "");
+    }
+
+    let outputs_iter = insert_row(tree_store, None, "Outputs", "", "");
+    for output in &derivation.outputs {
+        insert_row(
+            tree_store,
+            Some(&outputs_iter),
+            &output.name,
+            &output.path.to_string(),
+            "",
+        );
+    }
+
+    let input_drvs_iter =
+        insert_row(tree_store, None, "Input Derivations", "", "");
+    for input_drv in &derivation.input_drvs {
+        let drv_str = input_drv.drv.to_string();
+        insert_row(
+            tree_store,
+            Some(&input_drvs_iter),
+            &drv_str,
+            &input_drv.outputs.join(", "),
+            &drv_str,
+        );
+    }
+
+    let input_srcs_iter = insert_row(tree_store, None, "Input Sources", "", "");
+    for input_src in &derivation.input_srcs {
+        let src_str = input_src.to_string();
+        insert_row(tree_store, Some(&input_srcs_iter), &src_str, "", &src_str);
+    }
+
+    let env_iter = insert_row(tree_store, None, "Environment", "", "");
+    for (key, value) in &derivation.env {
+        insert_row(tree_store, Some(&env_iter), key, value, "");
+    }
+}
+
+/// Jump to the first instance of the nix store path `link` in the tree view.
+fn go_to_link(state: &ui::State, link: &str) {
+    tree::goto_first_instance(state, &NixQueryDrv::from(link));
+}
+
+fn toggle_row_expanded(state: &ui::State, tree_path: &gtk::TreePath) {
+    let tree_view = state.get_drv_tree_view();
+    if tree_view.row_expanded(tree_path) {
+        tree_view.collapse_row(tree_path);
+    } else {
+        tree_view.expand_row(tree_path, false);
+    }
+}
+
+fn handle_row_activated(state: &ui::State, tree_path: &gtk::TreePath) {
+    let tree_store = state.get_drv_tree_store();
+    if let Some(tree_iter) = tree_store.get_iter(tree_path) {
+        let option_link: Option<String> = tree_store
+            .get_value(&tree_iter, Column::Link as i32)
+            .get()
+            .ok()
+            .flatten();
+        match option_link {
+            Some(link) if !link.is_empty() => go_to_link(state, &link),
+            _ => toggle_row_expanded(state, tree_path),
+        }
+    }
+}
+
+pub fn setup(state: &ui::State) {
+    state.get_drv_tree_view().connect_row_activated(
+        clone!(@strong state => move |_, tree_path, _| {
+            handle_row_activated(&state, tree_path);
+        }),
+    );
+}
+
+pub fn disable(state: &ui::State) {
+    state.get_drv_tree_view().set_sensitive(false);
+}
+
+pub fn enable(state: &ui::State) {
+    state.get_drv_tree_view().set_sensitive(true);
+}
+
+pub fn redisplay_data(state: &ui::State) {
+    let tree_store = state.get_drv_tree_store();
+    tree_store.clear();
+
+    if let Some(nix_store_res) = &*state.read_nix_store_res() {
+        let root_drv: &NixQueryDrv = &nix_store_res.tree.0.item.0;
+        if root_drv.is_drv() {
+            match Derivation::from_path(root_drv) {
+                Ok(derivation) => insert_derivation(&tree_store, &derivation),
+                Err(err) => {
+                    insert_row(
+                        &tree_store,
+                        None,
+                        "Error reading derivation",
+                        &err.to_string(),
+                        "",
+                    );
+                }
+            }
+        } else {
+            insert_row(
+                &tree_store,
+                None,
+                "Not a derivation",
+                &root_drv.to_string(),
+                "",
+            );
+        }
+    }
+
+    state.get_drv_tree_view().expand_all();
+}
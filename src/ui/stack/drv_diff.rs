@@ -0,0 +1,100 @@
+use super::super::super::ui;
+use super::super::prelude::*;
+use crate::nix_query_tree::drv_diff::{DrvChange, DrvDiff};
+
+/// The name of this page in the `gtk::Stack`.
+const PAGE_NAME: &str = "page3";
+
+fn insert_row(
+    tree_store: &gtk::TreeStore,
+    parent: Option<&gtk::TreeIter>,
+    text: &str,
+) -> gtk::TreeIter {
+    tree_store.insert_with_values(parent, None, &[0], &[&text])
+}
+
+fn insert_changes(
+    tree_store: &gtk::TreeStore,
+    parent: Option<&gtk::TreeIter>,
+    drv_diff: &DrvDiff,
+) {
+    for change in &drv_diff.changes {
+        let change_iter = insert_row(tree_store, parent, &change.description());
+        for detail in change.details() {
+            insert_row(tree_store, Some(&change_iter), &detail);
+        }
+        if let DrvChange::InputDrv(input_drv_diff) = change {
+            insert_changes(tree_store, Some(&change_iter), input_drv_diff);
+        }
+    }
+}
+
+fn insert_drv_diff(tree_store: &gtk::TreeStore, drv_diff: &DrvDiff) {
+    let comparing_iter = insert_row(tree_store, None, "Comparing");
+    insert_row(
+        tree_store,
+        Some(&comparing_iter),
+        &format!("- {}", drv_diff.left),
+    );
+    insert_row(
+        tree_store,
+        Some(&comparing_iter),
+        &format!("+ {}", drv_diff.right),
+    );
+
+    if drv_diff.is_empty() {
+        insert_row(tree_store, None, "The derivations are the same");
+        return;
+    }
+
+    let divergence = drv_diff.first_divergence();
+    let first = divergence.last().unwrap_or(&drv_diff);
+    let divergence_iter =
+        insert_row(tree_store, None, "First point of divergence");
+    insert_row(
+        tree_store,
+        Some(&divergence_iter),
+        &format!("- {}", first.left),
+    );
+    insert_row(
+        tree_store,
+        Some(&divergence_iter),
+        &format!("+ {}", first.right),
+    );
+
+    let changes_iter = insert_row(tree_store, None, "Differences");
+    insert_changes(tree_store, Some(&changes_iter), drv_diff);
+}
+
+pub fn setup(_state: &ui::State) {}
+
+pub fn disable(state: &ui::State) {
+    state.get_drv_diff_tree_view().set_sensitive(false);
+}
+
+pub fn enable(state: &ui::State) {
+    state.get_drv_diff_tree_view().set_sensitive(true);
+}
+
+/// Show `drv_diff` in the diff page, and switch to it.
+pub fn display(state: &ui::State, drv_diff: &DrvDiff) {
+    let tree_store = state.get_drv_diff_tree_store();
+    tree_store.clear();
+
+    insert_drv_diff(&tree_store, drv_diff);
+
+    // Only expand the top-level rows, since the full diff can be very large.
+    let tree_view = state.get_drv_diff_tree_view();
+    if let Some(tree_iter) = tree_store.get_iter_first() {
+        loop {
+            if let Some(tree_path) = tree_store.get_path(&tree_iter) {
+                tree_view.expand_row(&tree_path, false);
+            }
+            if !tree_store.iter_next(&tree_iter) {
+                break;
+            }
+        }
+    }
+
+    state.get_stack().set_visible_child_name(PAGE_NAME);
+}
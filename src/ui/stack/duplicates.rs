@@ -0,0 +1,116 @@
+use glib::clone;
+
+use super::super::super::ui;
+use super::super::prelude::*;
+use super::tree;
+use crate::nix_query_tree::duplicates::{self, Duplicate};
+use crate::nix_query_tree::NixQueryDrv;
+
+/// These correspond to actual columns in the `duplicatesTreeStore`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(i32)]
+enum Column {
+    Name = 0,
+    Version,
+    Occurrences,
+    /// The nix store path this row links to in the tree view.  This is empty for the rows for
+    /// package names.
+    Link,
+}
+
+const COLUMN_INDICIES: [u32; 4] = [
+    Column::Name as u32,
+    Column::Version as u32,
+    Column::Occurrences as u32,
+    Column::Link as u32,
+];
+
+fn insert_duplicate(tree_store: &gtk::TreeStore, duplicate: &Duplicate) {
+    let pname_iter = tree_store.insert_with_values(
+        None,
+        None,
+        &COLUMN_INDICIES,
+        &[
+            &duplicate.pname,
+            &format!("{} variants", duplicate.variants.len()),
+            &"",
+            &"",
+        ],
+    );
+    for variant in &duplicate.variants {
+        tree_store.insert_with_values(
+            Some(&pname_iter),
+            None,
+            &COLUMN_INDICIES,
+            &[
+                &variant.drv.hash_and_drv_name(),
+                &variant.version.clone().unwrap_or_default(),
+                &variant.occurrences.to_string(),
+                &variant.drv.to_string(),
+            ],
+        );
+    }
+}
+
+fn handle_row_activated(state: &ui::State, tree_path: &gtk::TreePath) {
+    let tree_store = state.get_duplicates_tree_store();
+    if let Some(tree_iter) = tree_store.get_iter(tree_path) {
+        let option_link: Option<String> = tree_store
+            .get_value(&tree_iter, Column::Link as i32)
+            .get()
+            .ok()
+            .flatten();
+        match option_link {
+            Some(link) if !link.is_empty() => {
+                tree::goto_first_instance(state, &NixQueryDrv::from(&link));
+            }
+            _ => {
+                let tree_view = state.get_duplicates_tree_view();
+                if tree_view.row_expanded(tree_path) {
+                    tree_view.collapse_row(tree_path);
+                } else {
+                    tree_view.expand_row(tree_path, false);
+                }
+            }
+        }
+    }
+}
+
+pub fn setup(state: &ui::State) {
+    state.get_duplicates_tree_view().connect_row_activated(
+        clone!(@strong state => move |_, tree_path, _| {
+            handle_row_activated(&state, tree_path);
+        }),
+    );
+}
+
+pub fn disable(state: &ui::State) {
+    state.get_duplicates_tree_view().set_sensitive(false);
+}
+
+pub fn enable(state: &ui::State) {
+    state.get_duplicates_tree_view().set_sensitive(true);
+}
+
+pub fn redisplay_data(state: &ui::State) {
+    let tree_store = state.get_duplicates_tree_store();
+    let label = state.get_duplicates_label();
+    tree_store.clear();
+
+    if let Some(nix_store_res) = &*state.read_nix_store_res() {
+        let duplicates = duplicates::find_duplicates(&nix_store_res.map);
+        if duplicates.is_empty() {
+            label.set_text("No package appears with more than one store path.");
+        } else {
+            label.set_text(&format!(
+                "{} packages appear with more than one store path.  Activate a store path to go to its first occurrence.",
+                duplicates.len()
+            ));
+        }
+        for duplicate in &duplicates {
+            insert_duplicate(&tree_store, duplicate);
+        }
+    }
+
+    state.get_duplicates_tree_view().expand_all();
+}
@@ -0,0 +1,156 @@
+use glib::clone;
+use std::path::Path;
+
+use super::super::super::ui;
+use super::super::prelude::*;
+use crate::nix_query_tree::store_contents::{
+    self, FileEntry, FileKind, FilePreview,
+};
+use crate::nix_query_tree::NixQueryDrv;
+use crate::tree::Tree;
+
+/// The name of this page in the `gtk::Stack`.
+pub const PAGE_NAME: &str = "page4";
+
+/// Stop reading a store path after this many files, so that huge store paths don't freeze the
+/// UI.
+const MAX_ENTRIES: usize = 10_000;
+
+/// Only preview files up to this size.
+const MAX_PREVIEW_SIZE: u64 = 64 * 1024;
+
+/// These correspond to actual columns in the `filesTreeStore`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(i32)]
+enum Column {
+    Name = 0,
+    Size,
+    Info,
+    Path,
+}
+
+const COLUMN_INDICIES: [u32; 4] = [
+    Column::Name as u32,
+    Column::Size as u32,
+    Column::Info as u32,
+    Column::Path as u32,
+];
+
+fn info_str(kind: &FileKind) -> String {
+    match kind {
+        FileKind::Directory => String::from("directory"),
+        FileKind::Regular { executable: true } => String::from("executable"),
+        FileKind::Regular { executable: false } => String::new(),
+        FileKind::Symlink { target } => format!("→ {}", target.display()),
+    }
+}
+
+fn insert_file_tree(
+    tree_store: &gtk::TreeStore,
+    parent: Option<&gtk::TreeIter>,
+    file_tree: &Tree<FileEntry>,
+) {
+    let Tree { item, children } = file_tree;
+    let size_str = match item.kind {
+        FileKind::Directory => String::new(),
+        _ => store_contents::human_size(item.size),
+    };
+    let this_iter = tree_store.insert_with_values(
+        parent,
+        None,
+        &COLUMN_INDICIES,
+        &[
+            &item.name,
+            &size_str,
+            &info_str(&item.kind),
+            &item.path.to_string_lossy().into_owned(),
+        ],
+    );
+    for child in children {
+        insert_file_tree(tree_store, Some(&this_iter), child);
+    }
+}
+
+fn show_preview(state: &ui::State, path: &Path) {
+    let text = match store_contents::preview_file(path, MAX_PREVIEW_SIZE) {
+        Err(err) => err.to_string(),
+        Ok(FilePreview::Text(text) | FilePreview::Hex(text)) => text,
+        Ok(FilePreview::TooLarge(size)) => format!(
+            "{} is too large to preview ({}).",
+            path.display(),
+            store_contents::human_size(size)
+        ),
+        Ok(FilePreview::NotAFile) => String::new(),
+    };
+    state.get_files_preview_text_buffer().set_text(&text);
+}
+
+fn handle_selection_changed(
+    state: &ui::State,
+    tree_selection: &gtk::TreeSelection,
+) {
+    if let Some((tree_model, tree_iter)) = tree_selection.get_selected() {
+        let option_path: Option<String> = tree_model
+            .get_value(&tree_iter, Column::Path as i32)
+            .get()
+            .ok()
+            .flatten();
+        if let Some(path) = option_path {
+            show_preview(state, Path::new(&path));
+        }
+    }
+}
+
+/// Show the files in the store path `nix_query_drv`.
+pub fn show(state: &ui::State, nix_query_drv: &NixQueryDrv) {
+    let tree_store = state.get_files_tree_store();
+    let path_label = state.get_files_path_label();
+    tree_store.clear();
+    state.get_files_preview_text_buffer().set_text("");
+
+    match store_contents::read_store_contents(nix_query_drv, MAX_ENTRIES) {
+        Err(err) => path_label.set_text(&err.to_string()),
+        Ok(contents) => {
+            if contents.truncated {
+                path_label.set_text(&format!(
+                    "{} (only showing the first {} files)",
+                    nix_query_drv, MAX_ENTRIES
+                ));
+            } else {
+                path_label.set_text(&nix_query_drv.to_string());
+            }
+
+            if contents.tree.item.kind == FileKind::Directory {
+                for child in &contents.tree.children {
+                    insert_file_tree(&tree_store, None, child);
+                }
+            } else {
+                insert_file_tree(&tree_store, None, &contents.tree);
+            }
+        }
+    }
+}
+
+pub fn setup(state: &ui::State) {
+    state.get_files_tree_view().get_selection().connect_changed(
+        clone!(@strong state => move |tree_selection| {
+            handle_selection_changed(&state, tree_selection);
+        }),
+    );
+}
+
+pub fn disable(state: &ui::State) {
+    state.get_files_tree_view().set_sensitive(false);
+}
+
+pub fn enable(state: &ui::State) {
+    state.get_files_tree_view().set_sensitive(true);
+}
+
+pub fn redisplay_data(state: &ui::State) {
+    state.get_files_tree_store().clear();
+    state.get_files_preview_text_buffer().set_text("");
+    state
+        .get_files_path_label()
+        .set_text("Select a path in the tree view to see its files.");
+}
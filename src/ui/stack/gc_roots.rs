@@ -0,0 +1,138 @@
+use glib::clone;
+
+use super::super::super::ui;
+use super::super::prelude::*;
+use super::tree;
+use crate::nix_query_tree::gc_roots::{GcRootsErr, GcRootsRes, RootChain};
+use crate::nix_query_tree::NixQueryDrv;
+
+/// The name of this page in the `gtk::Stack`.
+const PAGE_NAME: &str = "page10";
+
+/// These correspond to actual columns in the `gcRootsTreeStore`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(i32)]
+enum Column {
+    Name = 0,
+    Details,
+    /// The nix store path this row links to in the tree view.  This is empty for the rows for
+    /// the roots themselves.
+    Link,
+}
+
+const COLUMN_INDICIES: [u32; 3] = [
+    Column::Name as u32,
+    Column::Details as u32,
+    Column::Link as u32,
+];
+
+fn insert_root_chain(tree_store: &gtk::TreeStore, root_chain: &RootChain) {
+    let root_iter = tree_store.insert_with_values(
+        None,
+        None,
+        &COLUMN_INDICIES,
+        &[
+            &root_chain.root.link,
+            &format!("chain of {} store paths", root_chain.chain.len()),
+            &"",
+        ],
+    );
+    for drv in &root_chain.chain {
+        tree_store.insert_with_values(
+            Some(&root_iter),
+            None,
+            &COLUMN_INDICIES,
+            &[&drv.hash_and_drv_name(), &"", &drv.to_string()],
+        );
+    }
+}
+
+fn handle_row_activated(state: &ui::State, tree_path: &gtk::TreePath) {
+    let tree_store = state.get_gc_roots_tree_store();
+    if let Some(tree_iter) = tree_store.get_iter(tree_path) {
+        let option_link: Option<String> = tree_store
+            .get_value(&tree_iter, Column::Link as i32)
+            .get()
+            .ok()
+            .flatten();
+        match option_link {
+            Some(link) if !link.is_empty() => {
+                tree::goto_first_instance(state, &NixQueryDrv::from(&link));
+            }
+            _ => {
+                let tree_view = state.get_gc_roots_tree_view();
+                if tree_view.row_expanded(tree_path) {
+                    tree_view.collapse_row(tree_path);
+                } else {
+                    tree_view.expand_row(tree_path, false);
+                }
+            }
+        }
+    }
+}
+
+pub fn setup(state: &ui::State) {
+    state.get_gc_roots_tree_view().connect_row_activated(
+        clone!(@strong state => move |_, tree_path, _| {
+            handle_row_activated(&state, tree_path);
+        }),
+    );
+}
+
+pub fn disable(state: &ui::State) {
+    state.get_gc_roots_tree_view().set_sensitive(false);
+}
+
+pub fn enable(state: &ui::State) {
+    state.get_gc_roots_tree_view().set_sensitive(true);
+}
+
+pub fn redisplay_data(state: &ui::State) {
+    state.get_gc_roots_tree_store().clear();
+    state.get_gc_roots_label().set_text(
+        "Right-click a path in the tree view and pick \"Why is this alive?\".",
+    );
+}
+
+/// Show the garbage collector roots keeping `drv` alive, and switch to this page.
+pub fn display(
+    state: &ui::State,
+    drv: &NixQueryDrv,
+    res: &Result<GcRootsRes, GcRootsErr>,
+) {
+    let tree_store = state.get_gc_roots_tree_store();
+    let label = state.get_gc_roots_label();
+    tree_store.clear();
+
+    match res {
+        Err(err) => label.set_text(&format!(
+            "Error finding the GC roots of {}: {}",
+            drv, err
+        )),
+        Ok(gc_roots_res) => {
+            let root_chains = &gc_roots_res.root_chains;
+            let mut text = if root_chains.is_empty() {
+                format!(
+                    "{} isn't kept alive by any GC root, so the next garbage collection will delete it.",
+                    drv
+                )
+            } else {
+                format!(
+                    "{} is kept alive by {} GC roots:",
+                    drv,
+                    root_chains.len()
+                )
+            };
+            if let Some(skipped_warning) = gc_roots_res.skipped_warning() {
+                text.push('\n');
+                text.push_str(&skipped_warning);
+            }
+            label.set_text(&text);
+            for root_chain in root_chains {
+                insert_root_chain(&tree_store, root_chain);
+            }
+        }
+    }
+
+    state.get_stack().set_visible_child_name(PAGE_NAME);
+}
@@ -0,0 +1,143 @@
+use glib::clone;
+use std::collections::HashMap;
+
+use super::super::super::ui;
+use super::super::prelude::*;
+use super::tree;
+use crate::nix_query_tree::licenses::{LicenseGroup, LicenseReport};
+use crate::nix_query_tree::NixQueryDrv;
+
+/// These correspond to actual columns in the `licensesTreeStore`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(i32)]
+enum Column {
+    Name = 0,
+    Details,
+    /// Either "unfree", "unknown", or empty.
+    Flag,
+    /// The nix store path this row links to in the tree view.  This is empty for the rows for
+    /// licenses.
+    Link,
+}
+
+const COLUMN_INDICIES: [u32; 4] = [
+    Column::Name as u32,
+    Column::Details as u32,
+    Column::Flag as u32,
+    Column::Link as u32,
+];
+
+fn insert_group(tree_store: &gtk::TreeStore, group: &LicenseGroup) {
+    let (name, full_name) = match &group.license {
+        None => (String::from("unknown"), String::new()),
+        Some(license) => (
+            license.id.clone(),
+            license.full_name.clone().unwrap_or_default(),
+        ),
+    };
+    let flag = if group.is_unknown() {
+        "unknown"
+    } else if group.is_unfree() {
+        "unfree"
+    } else {
+        ""
+    };
+    let details = if full_name.is_empty() {
+        format!("{} store paths", group.drvs.len())
+    } else {
+        format!("{} ({} store paths)", full_name, group.drvs.len())
+    };
+
+    let group_iter = tree_store.insert_with_values(
+        None,
+        None,
+        &COLUMN_INDICIES,
+        &[&name, &details, &flag, &""],
+    );
+    for drv in &group.drvs {
+        tree_store.insert_with_values(
+            Some(&group_iter),
+            None,
+            &COLUMN_INDICIES,
+            &[&drv.hash_and_drv_name(), &"", &flag, &drv.to_string()],
+        );
+    }
+}
+
+fn handle_row_activated(state: &ui::State, tree_path: &gtk::TreePath) {
+    let tree_store = state.get_licenses_tree_store();
+    if let Some(tree_iter) = tree_store.get_iter(tree_path) {
+        let option_link: Option<String> = tree_store
+            .get_value(&tree_iter, Column::Link as i32)
+            .get()
+            .ok()
+            .flatten();
+        match option_link {
+            Some(link) if !link.is_empty() => {
+                tree::goto_first_instance(state, &NixQueryDrv::from(&link));
+            }
+            _ => {
+                let tree_view = state.get_licenses_tree_view();
+                if tree_view.row_expanded(tree_path) {
+                    tree_view.collapse_row(tree_path);
+                } else {
+                    tree_view.expand_row(tree_path, false);
+                }
+            }
+        }
+    }
+}
+
+pub fn setup(state: &ui::State) {
+    state.get_licenses_tree_view().connect_row_activated(
+        clone!(@strong state => move |_, tree_path, _| {
+            handle_row_activated(&state, tree_path);
+        }),
+    );
+}
+
+pub fn disable(state: &ui::State) {
+    state.get_licenses_tree_view().set_sensitive(false);
+}
+
+pub fn enable(state: &ui::State) {
+    state.get_licenses_tree_view().set_sensitive(true);
+}
+
+/// Clear out the licenses for the previous tree.  The licenses for the new tree are found in
+/// the background, and shown with `display`.
+pub fn redisplay_data(state: &ui::State) {
+    state.get_licenses_tree_store().clear();
+    state.get_licenses_label().set_text("Finding licenses...");
+    tree::redisplay_licenses(state, &HashMap::new());
+}
+
+pub fn display(state: &ui::State, report: &LicenseReport) {
+    let tree_store = state.get_licenses_tree_store();
+    let label = state.get_licenses_label();
+    tree_store.clear();
+
+    let summary = report.summary();
+    if report.licenses.is_empty() {
+        label.set_text("No license information found.  Use File → Load License Data… or --license-data to load license information produced by `nix eval`.");
+    } else {
+        let unfree = summary.iter().filter(|group| group.is_unfree()).count();
+        label.set_text(&format!(
+            "{} packages under {} licenses.  {} unfree licenses, and {} packages without license information.",
+            report.licenses.len() + report.unknown.len(),
+            summary.len() - usize::from(!report.unknown.is_empty()),
+            unfree,
+            report.unknown.len()
+        ));
+    }
+
+    for group in &summary {
+        insert_group(&tree_store, group);
+    }
+
+    if report.licenses.is_empty() {
+        tree::redisplay_licenses(state, &HashMap::new());
+    } else {
+        tree::redisplay_licenses(state, &report.license_names());
+    }
+}
@@ -1,5 +1,10 @@
 use super::super::super::ui;
 use super::super::prelude::*;
+use crate::nix_query_tree::parsing::ParseError;
+
+const PAGE_NAME: &str = "page1";
+
+const PARSE_ERROR_TAG: &str = "parse-error";
 
 pub fn setup(_state: &ui::State) {}
 
@@ -12,6 +17,38 @@ pub fn redisplay_data(state: &ui::State) {
 
     if let Some(nix_store_res) = &*state.read_nix_store_res() {
         let text_buffer: gtk::TextBuffer = state.get_raw_text_buffer();
-        text_buffer.set_text(&nix_store_res.raw);
+        text_buffer.set_text(nix_store_res.raw.as_deref().unwrap_or(""));
     }
 }
+
+/// Get the tag used to highlight the line with a parse error, creating it the first time.
+fn parse_error_tag(text_buffer: &gtk::TextBuffer) -> gtk::TextTag {
+    let tag_table = text_buffer
+        .get_tag_table()
+        .expect("text buffer should always have a tag table");
+    tag_table.lookup(PARSE_ERROR_TAG).unwrap_or_else(|| {
+        let tag = gtk::TextTag::new(Some(PARSE_ERROR_TAG));
+        tag.set_property_paragraph_background(Some("#f8d0d0"));
+        tag.set_property_foreground(Some("#a00000"));
+        tag_table.add(&tag);
+        tag
+    })
+}
+
+/// Show output from `nix-store` that couldn't be parsed, and highlight the line with the
+/// error.
+pub fn show_parse_error(state: &ui::State, raw: &str, parse_err: &ParseError) {
+    let text_buffer: gtk::TextBuffer = state.get_raw_text_buffer();
+    text_buffer.set_text(raw);
+
+    let line = i32::try_from(parse_err.line - 1).unwrap_or(i32::MAX);
+    let mut start = text_buffer.get_iter_at_line(line);
+    let mut end = start.clone();
+    end.forward_to_line_end();
+    text_buffer.apply_tag(&parse_error_tag(&text_buffer), &start, &end);
+
+    state.get_stack().set_visible_child_name(PAGE_NAME);
+    state
+        .get_raw_text_view()
+        .scroll_to_iter(&mut start, 0.0, true, 0.0, 0.5);
+}
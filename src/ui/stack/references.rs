@@ -0,0 +1,99 @@
+use super::super::super::ui;
+use super::super::prelude::*;
+use crate::nix_query_tree::references::{Reference, ReferenceKind};
+use crate::nix_query_tree::store_contents::StoreContentsErr;
+use crate::nix_query_tree::NixQueryDrv;
+
+/// The name of this page in the `gtk::Stack`.
+const PAGE_NAME: &str = "page5";
+
+/// These correspond to actual columns in the `referencesListStore`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(i32)]
+enum Column {
+    File = 0,
+    Offset,
+    Context,
+}
+
+const COLUMN_INDICIES: [u32; 3] = [
+    Column::File as u32,
+    Column::Offset as u32,
+    Column::Context as u32,
+];
+
+fn insert_reference(
+    list_store: &gtk::ListStore,
+    parent: &NixQueryDrv,
+    reference: &Reference,
+) {
+    // Show file paths relative to the store path, since they all have the same prefix.
+    let file = reference
+        .file
+        .strip_prefix(&**parent)
+        .ok()
+        .filter(|relative| !relative.as_os_str().is_empty())
+        .map_or_else(
+            || reference.file.to_string_lossy().into_owned(),
+            |relative| relative.to_string_lossy().into_owned(),
+        );
+    let offset = match reference.kind {
+        ReferenceKind::Contents => format!("{:#x}", reference.offset),
+        ReferenceKind::SymlinkTarget => String::from("symlink target"),
+    };
+    list_store.insert_with_values(
+        None,
+        &COLUMN_INDICIES,
+        &[&file, &offset, &reference.context],
+    );
+}
+
+/// Show the places in the files of `parent` that reference `child`, and switch to this page.
+pub fn display(
+    state: &ui::State,
+    parent: &NixQueryDrv,
+    child: &NixQueryDrv,
+    res: &Result<Vec<Reference>, StoreContentsErr>,
+) {
+    let list_store = state.get_references_list_store();
+    let label = state.get_references_label();
+    list_store.clear();
+
+    match res {
+        Err(err) => label.set_text(&err.to_string()),
+        Ok(references) if references.is_empty() => label.set_text(&format!(
+            "No references to {} were found in the files of {}.",
+            child, parent
+        )),
+        Ok(references) => {
+            label.set_text(&format!(
+                "{} references to {} in {}:",
+                references.len(),
+                child,
+                parent
+            ));
+            for reference in references {
+                insert_reference(&list_store, parent, reference);
+            }
+        }
+    }
+
+    state.get_stack().set_visible_child_name(PAGE_NAME);
+}
+
+pub fn setup(_state: &ui::State) {}
+
+pub fn disable(state: &ui::State) {
+    state.get_references_tree_view().set_sensitive(false);
+}
+
+pub fn enable(state: &ui::State) {
+    state.get_references_tree_view().set_sensitive(true);
+}
+
+pub fn redisplay_data(state: &ui::State) {
+    state.get_references_list_store().clear();
+    state.get_references_label().set_text(
+        "Right-click a path in the tree view and pick \"Where is this referenced?\".",
+    );
+}
@@ -0,0 +1,94 @@
+use glib::clone;
+
+use super::super::super::ui;
+use super::super::prelude::*;
+use super::tree;
+use crate::nix_query_tree::store_contents::human_size;
+use crate::nix_query_tree::substitutes::{MissingPath, SubstituteReport};
+use crate::nix_query_tree::NixQueryDrv;
+
+/// These correspond to actual columns in the `substitutesListStore`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(i32)]
+enum Column {
+    Name = 0,
+    Size,
+    /// The missing nix store path.  Activating a row jumps to it in the tree view.
+    Link,
+}
+
+const COLUMN_INDICIES: [u32; 3] = [
+    Column::Name as u32,
+    Column::Size as u32,
+    Column::Link as u32,
+];
+
+fn insert_missing_path(list_store: &gtk::ListStore, missing: &MissingPath) {
+    let size = missing.size.map_or_else(|| String::from("?"), human_size);
+    list_store.insert_with_values(
+        None,
+        &COLUMN_INDICIES,
+        &[
+            &missing.drv.hash_and_drv_name(),
+            &size,
+            &missing.drv.to_string(),
+        ],
+    );
+}
+
+fn handle_row_activated(state: &ui::State, tree_path: &gtk::TreePath) {
+    let list_store = state.get_substitutes_list_store();
+    if let Some(tree_iter) = list_store.get_iter(tree_path) {
+        let option_link: Option<String> = list_store
+            .get_value(&tree_iter, Column::Link as i32)
+            .get()
+            .ok()
+            .flatten();
+        if let Some(link) = option_link {
+            tree::goto_first_instance(state, &NixQueryDrv::from(&link));
+        }
+    }
+}
+
+pub fn setup(state: &ui::State) {
+    state.get_substitutes_tree_view().connect_row_activated(
+        clone!(@strong state => move |_, tree_path, _| {
+            handle_row_activated(&state, tree_path);
+        }),
+    );
+}
+
+pub fn disable(state: &ui::State) {
+    state.get_substitutes_tree_view().set_sensitive(false);
+}
+
+pub fn enable(state: &ui::State) {
+    state.get_substitutes_tree_view().set_sensitive(true);
+}
+
+/// Clear out the report for the previous tree.  The new tree is checked against the binary
+/// cache in the background, and shown with `display`.
+pub fn redisplay_data(state: &ui::State) {
+    state.get_substitutes_list_store().clear();
+    let label = state.get_substitutes_label();
+    match &*state.read_substituter() {
+        None => label.set_text("Not checked against a binary cache.  Use File → Check Binary Cache… or --substituter to see which store paths would have to be uploaded."),
+        Some(substituter) => label.set_text(&format!(
+            "Checking the binary cache {}...",
+            substituter
+        )),
+    }
+    tree::redisplay_substitutes(state, None);
+}
+
+pub fn display(state: &ui::State, report: &SubstituteReport) {
+    let list_store = state.get_substitutes_list_store();
+    list_store.clear();
+    state.get_substitutes_label().set_text(&report.summary());
+
+    for missing in &report.missing {
+        insert_missing_path(&list_store, missing);
+    }
+
+    tree::redisplay_substitutes(state, Some(report));
+}
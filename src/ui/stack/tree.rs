@@ -1,4 +1,5 @@
 mod columns;
+mod expand;
 mod path;
 mod signals;
 mod store;
@@ -10,6 +11,9 @@ use glib::translate::ToGlibPtr;
 use super::super::super::ui;
 use super::super::prelude::*;
 
+/// The name of the tree view page in the `gtk::Stack`.
+pub const PAGE_NAME: &str = "page0";
+
 fn clear(state: &ui::State) {
     let tree_store = state.get_tree_store();
     tree_store.clear();
@@ -29,11 +33,37 @@ fn render_nix_store_res(state: &ui::State) {
     if let Some(res) = &*state.read_nix_store_res() {
         let tree_store = state.get_tree_store();
         store::insert(&tree_store, res);
+        // Only results read from a binary cache have `.narinfo` files to show.
+        state
+            .get_tree_view_column_nar_info()
+            .set_visible(!res.narinfos.is_empty());
+    }
+}
+
+/// Whether to show a row of the `gtk::TreeStore`.  Every row is shown unless View → Show
+/// Only Missing Paths is checked.
+fn is_row_visible(
+    state: &ui::State,
+    tree_model: &gtk::TreeModel,
+    tree_iter: &gtk::TreeIter,
+) -> bool {
+    if !state.get_only_missing_menu_item().get_active() {
+        return true;
+    }
+    match (&*state.read_missing_paths(), tree_model.get_path(tree_iter)) {
+        (Some(missing_paths), Some(tree_path)) => missing_paths
+            .contains(&path::GtkChildTreePath::new(tree_path).to_path()),
+        _ => true,
     }
 }
 
 pub fn setup(state: &ui::State) {
     signals::connect(state);
+    state.get_tree_model_filter().set_visible_func(
+        clone!(@strong state => move |tree_model, tree_iter| {
+            is_row_visible(&state, tree_model, tree_iter)
+        }),
+    );
 }
 
 /// Low-level (unsafe) function for setting the sorting function.
@@ -114,23 +144,131 @@ pub fn change_sort_order(state: &ui::State) {
                 gtk::SortType::Ascending,
             );
         }
-        ui::SortOrder::AlphabeticalHash => {
+        ui::SortOrder::AlphabeticalHash
+        | ui::SortOrder::AlphabeticalDrvName => {
             set_sort_function(state);
             tree_model_sort.set_sort_column_id(
                 gtk::SortColumn::Index(0),
                 gtk::SortType::Ascending,
             );
         }
-        ui::SortOrder::AlphabeticalDrvName => {
-            set_sort_function(state);
-            tree_model_sort.set_sort_column_id(
-                gtk::SortColumn::Index(0),
-                gtk::SortType::Ascending,
-            );
+    }
+}
+
+/// Expand, scroll to, and select the row for `nix_query_path` in the tree view.
+pub fn goto(state: &ui::State, nix_query_path: &crate::tree::Path) {
+    path::goto(state, nix_query_path);
+}
+
+/// Switch to the tree view and jump to the first instance of `nix_query_drv`.
+pub fn goto_first_instance(
+    state: &ui::State,
+    nix_query_drv: &crate::nix_query_tree::NixQueryDrv,
+) {
+    if let Some(nix_store_res) = &*state.read_nix_store_res() {
+        if let Some(first_path) = nix_store_res.map.lookup_first(nix_query_drv)
+        {
+            state.get_stack().set_visible_child_name(PAGE_NAME);
+            goto(state, first_path);
         }
     }
 }
 
+/// Fill in the vulnerabilities column, and only show it when a vulnerability database is
+/// loaded.
+pub fn redisplay_vulnerabilities(
+    state: &ui::State,
+    advisory_ids: &std::collections::HashMap<
+        crate::nix_query_tree::NixQueryDrv,
+        String,
+    >,
+) {
+    state
+        .get_tree_view_column_vulnerabilities()
+        .set_visible(state.read_vuln_db().is_some());
+    if let Some(nix_store_res) = &*state.read_nix_store_res() {
+        store::set_column(
+            &state.get_tree_store(),
+            nix_store_res,
+            columns::Column::Vulnerabilities,
+            advisory_ids,
+        );
+    }
+}
+
+/// Fill in the substitute column.  It is only shown once the tree has been checked against a
+/// binary cache.
+pub fn redisplay_substitutes(
+    state: &ui::State,
+    option_report: Option<
+        &crate::nix_query_tree::substitutes::SubstituteReport,
+    >,
+) {
+    state
+        .get_tree_view_column_substitute()
+        .set_visible(option_report.is_some());
+    if let Some(nix_store_res) = &*state.read_nix_store_res() {
+        let values = option_report.map_or_else(
+            std::collections::HashMap::new,
+            |report| {
+                nix_store_res
+                    .map
+                    .0
+                    .iter()
+                    .filter_map(|(drv, _)| {
+                        let cached = report.is_cached(drv)?;
+                        let value = if cached { "cached" } else { "missing" };
+                        Some((drv.clone(), String::from(value)))
+                    })
+                    .collect()
+            },
+        );
+        store::set_column(
+            &state.get_tree_store(),
+            nix_store_res,
+            columns::Column::Substitute,
+            &values,
+        );
+    }
+}
+
+/// Fill in the license column.  It is only shown when there are any licenses to show.
+pub fn redisplay_licenses(
+    state: &ui::State,
+    license_names: &std::collections::HashMap<
+        crate::nix_query_tree::NixQueryDrv,
+        String,
+    >,
+) {
+    state
+        .get_tree_view_column_license()
+        .set_visible(!license_names.is_empty());
+    if let Some(nix_store_res) = &*state.read_nix_store_res() {
+        store::set_column(
+            &state.get_tree_store(),
+            nix_store_res,
+            columns::Column::License,
+            license_names,
+        );
+    }
+}
+
+/// The path of the row currently selected in the tree view.
+pub fn selected_path(state: &ui::State) -> Option<crate::tree::Path> {
+    path::selected_path(state)
+}
+
+/// The nix store path for the row currently selected in the tree view.
+pub fn selected_nix_query_drv(
+    state: &ui::State,
+) -> Option<crate::nix_query_tree::NixQueryDrv> {
+    let nix_store_res = &*state.read_nix_store_res();
+    nix_store_res.as_ref().and_then(|nix_store_res| {
+        path::nix_query_entry_for_selection(state, nix_store_res)
+            .map(|nix_query_entry| nix_query_entry.0.clone())
+    })
+}
+
 pub fn change_view_style(state: &ui::State) {
     columns::change_view_style(state);
 }
@@ -143,34 +281,30 @@ fn set_sort_func_callback(
 ) -> Ordering {
     let sort_order = *state.read_sort_order();
     if let Some(nix_store_res) = &*state.read_nix_store_res() {
-        let tree_store: &gtk::TreeStore = tree_model
+        let tree_model_filter: &gtk::TreeModelFilter = tree_model
             .downcast_ref()
-            .expect("tree_model is not a tree_store");
+            .expect("tree_model is not a tree_model_filter");
+        let tree_store = state.get_tree_store();
 
-        let child_iter_a = path::GtkChildTreeIter::new(tree_model_sort_iter_a);
-        let child_iter_b = path::GtkChildTreeIter::new(tree_model_sort_iter_b);
+        let child_iter_a = path::GtkChildTreeIter::from_filter_iter(
+            tree_model_filter,
+            &tree_model_sort_iter_a,
+        );
+        let child_iter_b = path::GtkChildTreeIter::from_filter_iter(
+            tree_model_filter,
+            &tree_model_sort_iter_b,
+        );
 
         let option_nix_query_entry_a: Option<
             &crate::nix_query_tree::NixQueryEntry,
-        > = child_iter_a.nix_store_res_lookup(tree_store, &nix_store_res);
+        > = child_iter_a.nix_store_res_lookup(&tree_store, &nix_store_res);
         let option_nix_query_entry_b: Option<
             &crate::nix_query_tree::NixQueryEntry,
-        > = child_iter_b.nix_store_res_lookup(tree_store, &nix_store_res);
+        > = child_iter_b.nix_store_res_lookup(&tree_store, &nix_store_res);
 
         match (option_nix_query_entry_a, option_nix_query_entry_b) {
             (Some(nix_query_entry_a), Some(nix_query_entry_b)) => {
-                match sort_order {
-                    ui::SortOrder::NixStoreOrigOutput => {
-                        println!("The sort function should never be called when the sort order is NixStoreOrigOutput!!!");
-                        Ordering::Equal
-                    }
-                    ui::SortOrder::AlphabeticalHash => {
-                        nix_query_entry_a.cmp_hash(&nix_query_entry_b)
-                    }
-                    ui::SortOrder::AlphabeticalDrvName => {
-                        nix_query_entry_a.cmp_drv_name(&nix_query_entry_b)
-                    }
-                }
+                sort_order.compare(nix_query_entry_a, nix_query_entry_b)
             }
             _ => panic!("Not able to get an ordering for one of the nix_query_entries.  This should never happen."),
         }
@@ -190,6 +324,7 @@ pub fn set_sort_function(state: &ui::State) {
 }
 
 pub fn redisplay_data(state: &ui::State) {
+    expand::cancel(state);
     clear(state);
     enable(state);
 
@@ -200,3 +335,118 @@ pub fn redisplay_data(state: &ui::State) {
         .get_tree_view()
         .expand_row(&gtk::TreePath::new_first(), false);
 }
+
+/// Expand every row in the tree view.
+pub fn expand_all(state: &ui::State) {
+    let option_paths = state
+        .read_nix_store_res()
+        .as_ref()
+        .map(|nix_store_res| nix_store_res.tree.0.expandable_paths(None));
+    if let Some(paths) = option_paths {
+        expand::expand_paths(state, paths, |_| ());
+    }
+}
+
+/// Expand the row for `path` and every row below it.
+pub fn expand_subtree(state: &ui::State, path: &crate::tree::Path) {
+    let option_paths: Option<Vec<crate::tree::Path>> =
+        state.read_nix_store_res().as_ref().and_then(|res| {
+            let subtree = res.tree.0.lookup_tree(path.clone())?;
+            let paths =
+                subtree.expandable_paths(None).into_iter().map(|sub_path| {
+                    let mut full_path = path.clone();
+                    full_path.0.extend(sub_path.0);
+                    full_path
+                });
+            Some(paths.collect())
+        });
+    if let Some(paths) = option_paths {
+        expand::expand_paths(state, paths, |_| ());
+    }
+}
+
+/// Collapse every row in the tree view except the first one, like when a tree is first
+/// displayed.
+pub fn collapse_all(state: &ui::State) {
+    expand::cancel(state);
+    let tree_view = state.get_tree_view();
+    tree_view.collapse_all();
+    tree_view.expand_row(&gtk::TreePath::new_first(), false);
+}
+
+/// Show or hide the rows for store paths that are in the binary cache, depending on whether
+/// View → Show Only Missing Paths is checked.  When only the missing store paths are shown,
+/// they are all expanded.
+pub fn refilter(state: &ui::State) {
+    expand::cancel(state);
+    state.get_tree_model_filter().refilter();
+    collapse_all(state);
+    if !state.get_only_missing_menu_item().get_active() {
+        return;
+    }
+    let option_paths =
+        state.read_missing_paths().as_ref().map(|missing_paths| {
+            let parents: std::collections::HashSet<crate::tree::Path> =
+                missing_paths
+                    .iter()
+                    .filter_map(crate::tree::Path::parent)
+                    .collect();
+            let mut parents: Vec<crate::tree::Path> =
+                parents.into_iter().collect();
+            parents.sort_by_key(|path| path.0.len());
+            parents
+        });
+    if let Some(paths) = option_paths {
+        expand::expand_paths(state, paths, |_| ());
+    }
+}
+
+/// Collapse the tree view, then expand it so that every row down to `depth` levels below
+/// the first one is shown.
+pub fn expand_to_depth(state: &ui::State, depth: usize) {
+    let option_paths = state
+        .read_nix_store_res()
+        .as_ref()
+        .map(|res| res.tree.0.expandable_paths(Some(depth)));
+    if let Some(paths) = option_paths {
+        collapse_all(state);
+        expand::expand_paths(state, paths, |_| ());
+    }
+}
+
+/// Collapse the tree view, then expand only the rows leading to store paths whose full path
+/// contains `query`, ignoring case.  Once everything is expanded, the first match is
+/// selected and the number of matches is shown in the statusbar.
+pub fn expand_to_matches(state: &ui::State, query: &str) {
+    let lowercase_query = query.to_lowercase();
+    let is_match = |entry: &crate::nix_query_tree::NixQueryEntry| {
+        entry
+            .0
+            .to_string()
+            .to_lowercase()
+            .contains(&lowercase_query)
+    };
+    let option_matches = state
+        .read_nix_store_res()
+        .as_ref()
+        .map(|res| res.tree.0.ancestor_paths_with_matches(is_match));
+
+    collapse_all(state);
+    match option_matches {
+        Some((paths, match_count, Some(first_match))) => {
+            let msg = if match_count == 1 {
+                format!("1 row matches \"{}\"", query)
+            } else {
+                format!("{} rows match \"{}\"", match_count, query)
+            };
+            expand::expand_paths(state, paths, move |state| {
+                goto(state, &first_match);
+                ui::statusbar::show_msg(state, &msg);
+            });
+        }
+        _ => ui::statusbar::show_msg(
+            state,
+            &format!("Nothing in the tree matches \"{}\"", query),
+        ),
+    }
+}
@@ -12,6 +12,14 @@ pub enum Column {
     HashAndDrvName,
     ShortHashAndDrvName,
     OnlyDrvName,
+    /// The IDs of the advisories affecting this store path.
+    Vulnerabilities,
+    /// The licenses of this store path.
+    License,
+    /// A summary of the `.narinfo` of this store path, when it was read from a binary cache.
+    NarInfo,
+    /// Whether this store path is in the binary cache being checked against.
+    Substitute,
 }
 
 impl TryFrom<usize> for Column {
@@ -28,19 +36,27 @@ impl TryFrom<usize> for Column {
 
 impl Column {
     // Is there some way to derive these types of things?
-    const LIST: [Column; 5] = [
+    const LIST: [Column; 9] = [
         Column::FullPath,
         Column::Recurse,
         Column::HashAndDrvName,
         Column::ShortHashAndDrvName,
         Column::OnlyDrvName,
+        Column::Vulnerabilities,
+        Column::License,
+        Column::NarInfo,
+        Column::Substitute,
     ];
-    pub const INDICIES: [usize; 5] = [
+    pub const INDICIES: [usize; 9] = [
         Column::FullPath as usize,
         Column::Recurse as usize,
         Column::HashAndDrvName as usize,
         Column::ShortHashAndDrvName as usize,
         Column::OnlyDrvName as usize,
+        Column::Vulnerabilities as usize,
+        Column::License as usize,
+        Column::NarInfo as usize,
+        Column::Substitute as usize,
     ];
 }
 
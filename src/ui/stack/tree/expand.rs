@@ -0,0 +1,75 @@
+//! Expanding lots of rows in the tree view at once.  A tree can have tens of thousands of
+//! rows, so instead of expanding them all in one go and freezing the window, they are
+//! expanded a batch at a time whenever GTK is idle.
+
+use glib::clone;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use super::super::super::super::ui;
+use super::path;
+use crate::tree;
+
+/// How long to spend expanding rows before letting GTK redraw the window and handle input.
+const BATCH_TIME: Duration = Duration::from_millis(20);
+
+/// Stop any expansion that is still running, for instance because the tree is being
+/// replaced.
+pub fn cancel(state: &ui::State) {
+    state.next_expand_generation();
+}
+
+/// Expand the rows for `paths` in the background, in order.  Each `tree::Path` has to come
+/// after the `tree::Path` of its parent.  Any expansion that is still running is stopped
+/// first.
+///
+/// `then` is called once every row has been expanded, but not if the expansion is stopped.
+pub fn expand_paths<F>(state: &ui::State, paths: Vec<tree::Path>, then: F)
+where
+    F: FnOnce(&ui::State) + 'static,
+{
+    let generation = state.next_expand_generation();
+    let total = paths.len();
+    let mut paths: VecDeque<tree::Path> = paths.into();
+    let mut option_then = Some(then);
+    let mut showing_progress = false;
+    let tree_view = state.get_tree_view();
+    let tree_model_sort = state.get_tree_model_sort();
+
+    glib::idle_add_local(clone!(@strong state => move || {
+        if *state.read_expand_generation() != generation {
+            return glib::source::Continue(false);
+        }
+
+        let start = Instant::now();
+        while start.elapsed() < BATCH_TIME {
+            match paths.pop_front() {
+                None => break,
+                Some(path) => {
+                    path::expand_row(&tree_view, &tree_model_sort, &path);
+                }
+            }
+        }
+
+        if paths.is_empty() {
+            if showing_progress {
+                ui::statusbar::clear(&state);
+            }
+            if let Some(then) = option_then.take() {
+                then(&state);
+            }
+            glib::source::Continue(false)
+        } else {
+            ui::statusbar::show_msg(
+                &state,
+                &format!(
+                    "Expanding rows... ({} of {})",
+                    total - paths.len(),
+                    total
+                ),
+            );
+            showing_progress = true;
+            glib::source::Continue(true)
+        }
+    }));
+}
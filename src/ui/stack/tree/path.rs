@@ -4,7 +4,17 @@ use super::columns::Column;
 use crate::nix_query_tree::exec_nix_store::NixStoreRes;
 use crate::nix_query_tree::{NixQueryEntry, NixQueryTree, Recurse};
 use crate::tree;
-use std::collections::VecDeque;
+
+/// The `gtk::TreeModelFilter` between the `gtk::TreeStore` and `tree_model_sort`.  It hides
+/// the rows that aren't missing from the binary cache when only missing paths are shown.
+fn tree_model_filter(
+    tree_model_sort: &gtk::TreeModelSort,
+) -> gtk::TreeModelFilter {
+    tree_model_sort
+        .get_model()
+        .downcast()
+        .expect("the model of tree_model_sort should be a tree_model_filter")
+}
 
 /// This is a `gtk::TreePath` for the underlying non-sorted data.  This is the data that
 /// corresponds 1-to-1 to the actual `NixStoreRes` data.
@@ -25,36 +35,27 @@ impl GtkChildTreePath {
         &self.0
     }
 
+    /// This is `None` when the row is hidden by the filter.
     pub fn into_parent(
         &self,
         tree_model_sort: &gtk::TreeModelSort,
-    ) -> GtkParentTreePath {
+    ) -> Option<GtkParentTreePath> {
+        let filter_tree_path = tree_model_filter(tree_model_sort)
+            .convert_child_path_to_path(self.get())?;
         let parent_tree_path = tree_model_sort
-            .convert_child_path_to_path(self.get())
-            .expect("child_tree_path should always be able to be converted to a parent tree_path");
-        GtkParentTreePath::new(parent_tree_path)
+            .convert_child_path_to_path(&filter_tree_path)
+            .expect("filter_tree_path should always be able to be converted to a parent tree_path");
+        Some(GtkParentTreePath::new(parent_tree_path))
     }
 
     pub fn from_path(path: &tree::Path) -> Self {
-        let mut vec_indices: Vec<i32> =
-            path.0.iter().map(|&u| u as i32).collect();
-        vec_indices.insert(0, 0);
         let gtk_child_tree_path =
-            gtk::TreePath::new_from_indicesv(&vec_indices);
+            gtk::TreePath::new_from_indicesv(&path.to_gtk_indices());
         GtkChildTreePath::new(gtk_child_tree_path)
     }
 
     pub fn to_path(&self) -> tree::Path {
-        tree::Path(
-            self.get()
-                .get_indices()
-                .iter()
-                .map(|i| *i as usize)
-                // our tree::Path will only ever have one item at the root, so we can drop the first
-                // item from the gtk::TreePath.
-                .skip(1)
-                .collect::<VecDeque<usize>>(),
-        )
+        tree::Path::from_gtk_indices(&self.get().get_indices())
     }
 
     pub fn nix_query_tree_lookup<'a>(
@@ -72,7 +73,7 @@ impl GtkChildTreePath {
     }
 }
 
-/// This is a `gtk::TreePath` for the sorted model actually shown to the user.
+/// This is a `gtk::TreePath` for the sorted and filtered model actually shown to the user.
 ///
 /// This is just a "view" of the non-sorted data.
 pub struct GtkParentTreePath(gtk::TreePath);
@@ -100,21 +101,23 @@ impl GtkParentTreePath {
         &self,
         tree_model_sort: &gtk::TreeModelSort,
     ) -> GtkChildTreePath {
-        let parent_tree_path = tree_model_sort
+        let filter_tree_path = tree_model_sort
             .convert_path_to_child_path(self.get())
-            .expect("child_tree_path should always be able to be converted to a child_tree_path");
-        GtkChildTreePath::new(parent_tree_path)
+            .expect("parent_tree_path should always be able to be converted to a filter_tree_path");
+        let child_tree_path = tree_model_filter(tree_model_sort)
+            .convert_path_to_child_path(&filter_tree_path)
+            .expect("filter_tree_path should always be able to be converted to a child_tree_path");
+        GtkChildTreePath::new(child_tree_path)
     }
 
     #[allow(dead_code)]
     pub fn from_path(
         tree_model_sort: &gtk::TreeModelSort,
         path: &tree::Path,
-    ) -> Self {
+    ) -> Option<Self> {
         GtkChildTreePath::from_path(path).into_parent(tree_model_sort)
     }
 
-    #[allow(dead_code)]
     pub fn to_path(&self, tree_model_sort: &gtk::TreeModelSort) -> tree::Path {
         self.into_child(tree_model_sort).to_path()
     }
@@ -128,16 +131,6 @@ impl GtkParentTreePath {
         self.into_child(tree_model_sort)
             .nix_query_tree_lookup(nix_query_tree)
     }
-
-    #[allow(dead_code)]
-    pub fn nix_store_res_lookup<'a>(
-        &self,
-        tree_model_sort: &gtk::TreeModelSort,
-        nix_store_res: &'a NixStoreRes,
-    ) -> Option<&'a NixQueryEntry> {
-        self.into_child(tree_model_sort)
-            .nix_store_res_lookup(nix_store_res)
-    }
 }
 
 /// This is a `gtk::TreeIter` for the underlying non-sorted data.  This is the data that
@@ -153,6 +146,17 @@ impl GtkChildTreeIter {
         &self.0
     }
 
+    /// The `GtkChildTreeIter` for `filter_iter`, a `gtk::TreeIter` for `tree_model_sort`'s
+    /// model.
+    pub fn from_filter_iter(
+        tree_model_filter: &gtk::TreeModelFilter,
+        filter_iter: &gtk::TreeIter,
+    ) -> Self {
+        GtkChildTreeIter::new(
+            tree_model_filter.convert_iter_to_child_iter(filter_iter),
+        )
+    }
+
     pub fn nix_store_res_lookup<'a>(
         &self,
         tree_store: &gtk::TreeStore,
@@ -213,12 +217,16 @@ impl TreeViewCol {
     }
 }
 
+/// This does nothing if the row for `first_path` is hidden by the filter.
 pub fn goto(state: &ui::State, first_path: &tree::Path) {
     let tree_view = state.get_tree_view();
 
     let tree_model_sort = state.get_tree_model_sort();
     let child_tree_path = GtkChildTreePath::from_path(first_path);
-    let parent_tree_path = child_tree_path.into_parent(&tree_model_sort);
+    let parent_tree_path = match child_tree_path.into_parent(&tree_model_sort) {
+        None => return,
+        Some(parent_tree_path) => parent_tree_path,
+    };
 
     let col = tree_view.get_column(TreeViewCol::Item as i32);
 
@@ -239,6 +247,20 @@ pub fn goto(state: &ui::State, first_path: &tree::Path) {
     tree_selection.select_path(&parent_tree_path.get());
 }
 
+/// Expand the row for `path` in the tree view, but not its children.  This does nothing if
+/// the parent of the row isn't expanded, or the row is hidden by the filter.
+pub fn expand_row(
+    tree_view: &gtk::TreeView,
+    tree_model_sort: &gtk::TreeModelSort,
+    path: &tree::Path,
+) {
+    let child_tree_path = GtkChildTreePath::from_path(path);
+    if let Some(parent_tree_path) = child_tree_path.into_parent(tree_model_sort)
+    {
+        tree_view.expand_row(&parent_tree_path.get(), false);
+    }
+}
+
 fn event_button_to_parent_tree_path_column(
     state: &ui::State,
     event_button: &gdk::EventButton,
@@ -338,3 +360,34 @@ pub fn nix_query_entry_for_event_button<'a>(
 
     option_child_tree_path.and_then(|x| x.nix_store_res_lookup(nix_store_res))
 }
+
+/// Lookup the `NixQueryEntry` for the parent of the row that was clicked on.  This is `None`
+/// for the root of the tree.
+pub fn parent_nix_query_entry_for_event_button<'a>(
+    state: &ui::State,
+    event_button: &gdk::EventButton,
+    nix_store_res: &'a NixStoreRes,
+) -> Option<&'a NixQueryEntry> {
+    let mut path =
+        event_button_to_child_tree_path(state, event_button)?.to_path();
+    path.0.pop_back()?;
+    nix_store_res.tree.lookup(path)
+}
+
+/// The `tree::Path` for the row currently selected in the tree view.
+pub fn selected_path(state: &ui::State) -> Option<tree::Path> {
+    let tree_model_sort = state.get_tree_model_sort();
+    let (_, tree_iter) =
+        state.get_tree_view().get_selection().get_selected()?;
+    let parent_tree_path =
+        GtkParentTreePath::new(tree_model_sort.get_path(&tree_iter)?);
+    Some(parent_tree_path.to_path(&tree_model_sort))
+}
+
+/// Lookup the `NixQueryEntry` for the row currently selected in the tree view.
+pub fn nix_query_entry_for_selection<'a>(
+    state: &ui::State,
+    nix_store_res: &'a NixStoreRes,
+) -> Option<&'a NixQueryEntry> {
+    nix_store_res.tree.lookup(selected_path(state)?)
+}
@@ -14,11 +14,25 @@ fn toggle_row_expanded(
     let tree_view = state.get_tree_view();
     if tree_view.row_expanded(tree_path) {
         tree_view.collapse_row(tree_path);
+    } else if recurse {
+        // Expanding a big subtree in one go would freeze the window.
+        let parent_tree_path = path::GtkParentTreePath::new(tree_path.clone());
+        let nix_query_path =
+            parent_tree_path.to_path(&state.get_tree_model_sort());
+        super::expand_subtree(state, &nix_query_path);
     } else {
-        tree_view.expand_row(tree_path, recurse);
+        tree_view.expand_row(tree_path, false);
     }
 }
 
+/// Whether Shift was held down for the event being handled, which makes activating a row
+/// expand everything below it.
+fn is_shift_held() -> bool {
+    gtk::get_current_event_state().map_or(false, |modifier_type| {
+        modifier_type.contains(gdk::ModifierType::SHIFT_MASK)
+    })
+}
+
 // Warning: This function assumes that nix_query_entry actually exists in NixStoreRes
 fn go_to_path_for_query_entry(
     state: &ui::State,
@@ -64,7 +78,7 @@ fn handle_row_activated(
                 nix_store_res,
                 &nix_query_entry,
             ),
-            _ => toggle_row_expanded(state, &tree_path, false),
+            _ => toggle_row_expanded(state, &tree_path, is_shift_held()),
         }
     }
 }
@@ -162,6 +176,142 @@ fn create_goto_first_instance_menu_item(
     }
 }
 
+fn create_browse_files_menu_item(
+    state: &ui::State,
+    menu: &gtk::Menu,
+    event_button: &gdk::EventButton,
+    nix_store_res: &NixStoreRes,
+) {
+    if let Some(nix_query_entry) = path::nix_query_entry_for_event_button(
+        state,
+        event_button,
+        nix_store_res,
+    ) {
+        let browse_files_menu_item =
+            gtk::MenuItem::new_with_label("Browse files");
+
+        browse_files_menu_item.connect_activate(
+            clone!(@strong state, @strong nix_query_entry => move |_| {
+                ui::stack::show_files(&state, &nix_query_entry.0);
+            }),
+        );
+
+        menu.append(&browse_files_menu_item);
+    }
+}
+
+fn create_where_is_this_referenced_menu_item(
+    state: &ui::State,
+    menu: &gtk::Menu,
+    event_button: &gdk::EventButton,
+    nix_store_res: &NixStoreRes,
+) {
+    if let (Some(nix_query_entry), Some(parent_nix_query_entry)) = (
+        path::nix_query_entry_for_event_button(
+            state,
+            event_button,
+            nix_store_res,
+        ),
+        path::parent_nix_query_entry_for_event_button(
+            state,
+            event_button,
+            nix_store_res,
+        ),
+    ) {
+        let where_is_this_referenced_menu_item =
+            gtk::MenuItem::new_with_label("Where is this referenced?");
+
+        where_is_this_referenced_menu_item.connect_activate(
+            clone!(@strong state, @strong nix_query_entry, @strong parent_nix_query_entry => move |_| {
+                ui::find_references(&state, &parent_nix_query_entry.0, &nix_query_entry.0);
+            }),
+        );
+
+        menu.append(&where_is_this_referenced_menu_item);
+    }
+}
+
+fn create_why_is_this_alive_menu_item(
+    state: &ui::State,
+    menu: &gtk::Menu,
+    event_button: &gdk::EventButton,
+    nix_store_res: &NixStoreRes,
+) {
+    if let Some(nix_query_entry) = path::nix_query_entry_for_event_button(
+        state,
+        event_button,
+        nix_store_res,
+    ) {
+        let why_is_this_alive_menu_item =
+            gtk::MenuItem::new_with_label("Why is this alive?");
+
+        why_is_this_alive_menu_item.connect_activate(
+            clone!(@strong state, @strong nix_query_entry => move |_| {
+                ui::find_gc_roots(&state, &nix_query_entry.0);
+            }),
+        );
+
+        menu.append(&why_is_this_alive_menu_item);
+    }
+}
+
+fn create_select_for_diff_menu_item(
+    state: &ui::State,
+    menu: &gtk::Menu,
+    event_button: &gdk::EventButton,
+    nix_store_res: &NixStoreRes,
+) {
+    if let Some(nix_query_entry) = path::nix_query_entry_for_event_button(
+        state,
+        event_button,
+        nix_store_res,
+    )
+    .filter(|nix_query_entry| nix_query_entry.0.is_drv())
+    {
+        let select_for_diff_menu_item =
+            gtk::MenuItem::new_with_label("Select for diff");
+
+        select_for_diff_menu_item.connect_activate(
+            clone!(@strong state, @strong nix_query_entry => move |_| {
+                state.write_drv_diff_base(nix_query_entry.0.clone());
+            }),
+        );
+
+        menu.append(&select_for_diff_menu_item);
+    }
+}
+
+fn create_diff_against_menu_item(
+    state: &ui::State,
+    menu: &gtk::Menu,
+    event_button: &gdk::EventButton,
+    nix_store_res: &NixStoreRes,
+) {
+    if let (Some(nix_query_entry), Some(drv_diff_base)) = (
+        path::nix_query_entry_for_event_button(
+            state,
+            event_button,
+            nix_store_res,
+        )
+        .filter(|nix_query_entry| nix_query_entry.0.is_drv()),
+        &*state.read_drv_diff_base(),
+    ) {
+        if nix_query_entry.0 != *drv_diff_base {
+            let diff_against_menu_item = gtk::MenuItem::new_with_label(
+                &format!("Diff against {}", drv_diff_base.drv_name()),
+            );
+
+            diff_against_menu_item.connect_activate(
+                clone!(@strong state, @strong drv_diff_base, @strong nix_query_entry => move |_| {
+                    ui::diff_drvs(&state, &drv_diff_base, &nix_query_entry.0);
+                }),
+            );
+
+            menu.append(&diff_against_menu_item);
+        }
+    }
+}
+
 fn handle_button_press_event(
     state: &ui::State,
     tree_view: &gtk::TreeView,
@@ -194,6 +344,41 @@ fn handle_button_press_event(
                 nix_store_res,
             );
 
+            create_browse_files_menu_item(
+                state,
+                &menu,
+                event_button,
+                nix_store_res,
+            );
+
+            create_where_is_this_referenced_menu_item(
+                state,
+                &menu,
+                event_button,
+                nix_store_res,
+            );
+
+            create_why_is_this_alive_menu_item(
+                state,
+                &menu,
+                event_button,
+                nix_store_res,
+            );
+
+            create_select_for_diff_menu_item(
+                state,
+                &menu,
+                event_button,
+                nix_store_res,
+            );
+
+            create_diff_against_menu_item(
+                state,
+                &menu,
+                event_button,
+                nix_store_res,
+            );
+
             // only show the menu if there is at least one child
             if menu.get_children().len() >= 1 {
                 menu.set_property_attach_widget(Some(&tree_view.clone()));
@@ -217,6 +402,13 @@ pub fn connect(state: &ui::State) {
         }),
     );
 
+    state.get_tree_view().get_selection().connect_changed(
+        clone!(@strong state => move |_| {
+            ui::stack::show_files_for_selection(&state);
+            ui::show_selected_path(&state);
+        }),
+    );
+
     state.get_tree_view().connect_button_press_event(
         clone!(@strong state => move |tree_view_ref, event_button| {
             handle_button_press_event(
@@ -1,4 +1,5 @@
 use crate::nix_query_tree::exec_nix_store::NixStoreRes;
+use crate::nix_query_tree::narinfo::NarInfo;
 use crate::nix_query_tree::{
     NixQueryDrv, NixQueryEntry, NixQueryTree, Recurse,
 };
@@ -6,13 +7,15 @@ use crate::tree::Tree;
 
 use super::super::super::prelude::*;
 use super::columns;
+use super::path::GtkChildTreePath;
+use std::collections::HashMap;
 
-fn insert_child(
+fn insert_entry(
     tree_store: &gtk::TreeStore,
     parent: Option<&gtk::TreeIter>,
-    child: &Tree<NixQueryEntry>,
-) {
-    let Tree { item, children }: &Tree<NixQueryEntry> = child;
+    item: &NixQueryEntry,
+    nar_info: &str,
+) -> gtk::TreeIter {
     let drv: &NixQueryDrv = &item.0;
     let drv_str = drv.to_string();
     let hash_and_drv_name = drv.hash_and_drv_name();
@@ -23,7 +26,7 @@ fn insert_child(
     } else {
         ""
     };
-    let this_iter: gtk::TreeIter = tree_store.insert_with_values(
+    tree_store.insert_with_values(
         parent,
         None,
         &columns::Column::INDICIES
@@ -36,24 +39,48 @@ fn insert_child(
             &hash_and_drv_name,
             &short_hash_and_drv_name,
             &only_drv_name,
+            &"",
+            &"",
+            &nar_info,
+            &"",
         ],
-    );
-    insert_children(tree_store, &this_iter, children);
-}
-
-fn insert_children(
-    tree_store: &gtk::TreeStore,
-    parent: &gtk::TreeIter,
-    children: &[Tree<NixQueryEntry>],
-) {
-    for child in children {
-        let _: &Tree<NixQueryEntry> = child;
-        insert_child(tree_store, Some(parent), child)
-    }
+    )
 }
 
 pub fn insert(tree_store: &gtk::TreeStore, nix_store_res: &NixStoreRes) {
     let nix_query_tree: &NixQueryTree = &nix_store_res.tree;
     let tree: &Tree<NixQueryEntry> = &nix_query_tree.0;
-    insert_child(tree_store, None, tree);
+
+    // The rows for the ancestors of the current entry.  Entries come in pre-order, so the
+    // parent of an entry at depth n is always at index n - 1.
+    let mut ancestors: Vec<gtk::TreeIter> = vec![];
+    for (path, item) in tree.pre_order() {
+        ancestors.truncate(path.0.len());
+        let nar_info = nix_store_res
+            .narinfos
+            .get(&item.0)
+            .map_or_else(String::new, NarInfo::summary);
+        let this_iter =
+            insert_entry(tree_store, ancestors.last(), item, &nar_info);
+        ancestors.push(this_iter);
+    }
+}
+
+/// Fill in `column` for every row.  `values` maps each store path to the text to show for it.
+/// Rows for store paths that aren't in `values` are left empty.
+pub fn set_column(
+    tree_store: &gtk::TreeStore,
+    nix_store_res: &NixStoreRes,
+    column: columns::Column,
+    values: &HashMap<NixQueryDrv, String>,
+) {
+    tree_store.foreach(|_, tree_path, tree_iter| {
+        let value: &str = GtkChildTreePath::new(tree_path.clone())
+            .nix_store_res_lookup(nix_store_res)
+            .and_then(|nix_query_entry| values.get(&nix_query_entry.0))
+            .map_or("", String::as_str);
+        tree_store.set_value(tree_iter, column as u32, &value.to_value());
+        // Keep going through all the rows.
+        false
+    });
 }
@@ -0,0 +1,124 @@
+use glib::clone;
+
+use super::super::super::ui;
+use super::super::prelude::*;
+use super::tree;
+use crate::nix_query_tree::vulnerabilities::{self, Finding};
+use crate::nix_query_tree::NixQueryDrv;
+
+/// These correspond to actual columns in the `vulnerabilitiesTreeStore`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[repr(i32)]
+enum Column {
+    Name = 0,
+    Severity,
+    Details,
+    /// The affected nix store path.  Activating a row jumps to it in the tree view.
+    Link,
+}
+
+const COLUMN_INDICIES: [u32; 4] = [
+    Column::Name as u32,
+    Column::Severity as u32,
+    Column::Details as u32,
+    Column::Link as u32,
+];
+
+fn insert_finding(tree_store: &gtk::TreeStore, finding: &Finding) {
+    let link = finding.drv.to_string();
+    let finding_iter = tree_store.insert_with_values(
+        None,
+        None,
+        &COLUMN_INDICIES,
+        &[
+            &finding.drv.hash_and_drv_name(),
+            &"",
+            &format!("{} advisories", finding.advisories.len()),
+            &link,
+        ],
+    );
+    for advisory in &finding.advisories {
+        tree_store.insert_with_values(
+            Some(&finding_iter),
+            None,
+            &COLUMN_INDICIES,
+            &[
+                &advisory.id,
+                &advisory.severity.clone().unwrap_or_default(),
+                &advisory.summary.clone().unwrap_or_default(),
+                &link,
+            ],
+        );
+    }
+}
+
+fn handle_row_activated(state: &ui::State, tree_path: &gtk::TreePath) {
+    let tree_store = state.get_vulnerabilities_tree_store();
+    if let Some(tree_iter) = tree_store.get_iter(tree_path) {
+        let option_link: Option<String> = tree_store
+            .get_value(&tree_iter, Column::Link as i32)
+            .get()
+            .ok()
+            .flatten();
+        if let Some(link) = option_link {
+            tree::goto_first_instance(state, &NixQueryDrv::from(&link));
+        }
+    }
+}
+
+pub fn setup(state: &ui::State) {
+    state.get_vulnerabilities_tree_view().connect_row_activated(
+        clone!(@strong state => move |_, tree_path, _| {
+            handle_row_activated(&state, tree_path);
+        }),
+    );
+}
+
+pub fn disable(state: &ui::State) {
+    state.get_vulnerabilities_tree_view().set_sensitive(false);
+}
+
+pub fn enable(state: &ui::State) {
+    state.get_vulnerabilities_tree_view().set_sensitive(true);
+}
+
+/// Check every path in the tree against the vulnerability database, and show the results
+/// both in this page and in the vulnerabilities column of the tree view.
+pub fn redisplay_data(state: &ui::State) {
+    let tree_store = state.get_vulnerabilities_tree_store();
+    let label = state.get_vulnerabilities_label();
+    tree_store.clear();
+
+    let findings = match (&*state.read_vuln_db(), &*state.read_nix_store_res())
+    {
+        (None, _) => {
+            label.set_text("No vulnerability database loaded.  Use File → Load Vulnerability Database… or --vulnerability-db to load an OSV or NVD JSON feed.");
+            vec![]
+        }
+        (Some(_), None) => vec![],
+        (Some(vuln_db), Some(nix_store_res)) => {
+            let findings = vulnerabilities::scan(vuln_db, &nix_store_res.map);
+            if findings.is_empty() {
+                label.set_text(&format!(
+                    "None of the {} advisories in the vulnerability database affect this closure.",
+                    vuln_db.advisories.len()
+                ));
+            } else {
+                label.set_text(&format!(
+                    "{} store paths are affected by known vulnerabilities.",
+                    findings.len()
+                ));
+            }
+            findings
+        }
+    };
+
+    for finding in &findings {
+        insert_finding(&tree_store, finding);
+    }
+
+    tree::redisplay_vulnerabilities(
+        state,
+        &vulnerabilities::advisory_ids(&findings),
+    );
+}
@@ -1,72 +1,52 @@
+use std::collections::HashSet;
+use std::path::PathBuf;
 use std::sync::{Arc, RwLock, RwLockReadGuard};
 
+use super::super::nix_query_tree::derivation::DerivationErr;
+use super::super::nix_query_tree::drv_diff::DrvDiff;
 use super::super::nix_query_tree::exec_nix_store::{
     ExecNixStoreRes, NixStoreRes,
 };
+use super::super::nix_query_tree::gc_roots::{GcRootsErr, GcRootsRes};
+use super::super::nix_query_tree::licenses::{
+    LicenseDb, LicenseDbErr, LicenseReport,
+};
+use super::super::nix_query_tree::references::Reference;
+use super::super::nix_query_tree::store_contents::StoreContentsErr;
+use super::super::nix_query_tree::substitutes::{
+    SubstituteErr, SubstituteReport,
+};
+use super::super::nix_query_tree::vulnerabilities::{VulnDb, VulnDbErr};
+use super::super::nix_query_tree::NixQueryDrv;
+use super::super::tree;
+pub use super::super::view::{SortOrder, ViewStyle};
 use super::builder;
 use super::prelude::*;
 
-/// Sort order for the tree of nix store paths.
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-#[repr(i32)]
-pub enum SortOrder {
-    NixStoreOrigOutput = 0,
-    AlphabeticalHash,
-    AlphabeticalDrvName,
-}
-
-impl Default for SortOrder {
-    fn default() -> Self {
-        SortOrder::NixStoreOrigOutput
-    }
-}
-
-impl TryFrom<u32> for SortOrder {
-    type Error = u32;
-
-    fn try_from(value: u32) -> Result<SortOrder, u32> {
-        match value {
-            0 => Ok(SortOrder::NixStoreOrigOutput),
-            1 => Ok(SortOrder::AlphabeticalHash),
-            2 => Ok(SortOrder::AlphabeticalDrvName),
-            n => Err(n),
-        }
-    }
-}
-
-/// View style for an individual nix store path.
-#[derive(Clone, Copy, Debug, Eq, PartialEq)]
-#[repr(i32)]
-pub enum ViewStyle {
-    FullPath = 0,
-    HashAndDrvName,
-    ShortHashAndDrvName,
-    OnlyDrvName,
-}
-
-impl Default for ViewStyle {
-    fn default() -> Self {
-        ViewStyle::FullPath
-    }
-}
-
-impl TryFrom<u32> for ViewStyle {
-    type Error = u32;
-
-    fn try_from(value: u32) -> Result<ViewStyle, u32> {
-        match value {
-            0 => Ok(ViewStyle::FullPath),
-            1 => Ok(ViewStyle::HashAndDrvName),
-            2 => Ok(ViewStyle::ShortHashAndDrvName),
-            3 => Ok(ViewStyle::OnlyDrvName),
-            n => Err(n),
-        }
-    }
-}
-
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Message {
     Display(ExecNixStoreRes),
+    DisplayDrvDiff(NixQueryDrv, NixQueryDrv, Result<DrvDiff, DerivationErr>),
+    /// The places in the files of the first store path that reference the second store path.
+    DisplayReferences(
+        NixQueryDrv,
+        NixQueryDrv,
+        Result<Vec<Reference>, StoreContentsErr>,
+    ),
+    /// The garbage collector roots keeping a store path alive.
+    DisplayGcRoots(NixQueryDrv, Result<GcRootsRes, GcRootsErr>),
+    LoadVulnDb(PathBuf, Result<VulnDb, VulnDbErr>),
+    LoadLicenseDb(PathBuf, Result<LicenseDb, LicenseDbErr>),
+    DisplayLicenses(LicenseReport),
+    /// The result of writing an HTML report to a file.
+    SavedHtmlReport(PathBuf, Result<(), String>),
+    /// Which store paths in the tree for the given store path are in the binary cache at the
+    /// given URI.
+    DisplaySubstitutes(
+        PathBuf,
+        String,
+        Result<SubstituteReport, SubstituteErr>,
+    ),
 }
 
 #[derive(Clone, Debug)]
@@ -77,6 +57,31 @@ pub struct State {
     pub nix_store_res: Arc<RwLock<Option<NixStoreRes>>>,
     pub sort_order: Arc<RwLock<SortOrder>>,
     pub view_style: Arc<RwLock<ViewStyle>>,
+    /// The derivation selected with "Select for diff" in the tree view.
+    pub drv_diff_base: Arc<RwLock<Option<NixQueryDrv>>>,
+    /// The vulnerability database that every path in the tree is checked against.
+    pub vuln_db: Arc<RwLock<Option<VulnDb>>>,
+    /// License information loaded from a file, used in addition to the derivation
+    /// environments.
+    pub license_db: Arc<RwLock<Option<LicenseDb>>>,
+    /// The store path that `nix_store_res` is for, so it can be refreshed.
+    pub nix_store_path: Arc<RwLock<Option<PathBuf>>>,
+    /// The store that `nix_store_res` was queried from, if it wasn't the default one.
+    pub store_uri: Arc<RwLock<Option<String>>>,
+    /// Whether flake references and `--attr` are evaluated to their output path instead of
+    /// their `.drv` file, from `--out-path`.
+    pub out_path: Arc<RwLock<bool>>,
+    /// The row to select the next time a tree is displayed, from `--select`.
+    pub pending_select: Arc<RwLock<Option<tree::Path>>>,
+    /// The binary cache that every tree is checked against, from `--substituter` or File →
+    /// Check Binary Cache….
+    pub substituter: Arc<RwLock<Option<String>>>,
+    /// The rows of the tree view to show when View → Show Only Missing Paths is checked: the
+    /// store paths in `nix_store_res` that aren't in `substituter`, and their ancestors.
+    pub missing_paths: Arc<RwLock<Option<HashSet<tree::Path>>>>,
+    /// This goes up every time rows of the tree view start being expanded in the
+    /// background, so an older expansion can tell that it should stop.
+    pub expand_generation: Arc<RwLock<usize>>,
 }
 
 impl State {
@@ -88,6 +93,16 @@ impl State {
             nix_store_res: Arc::new(RwLock::new(None)),
             sort_order: Default::default(),
             view_style: Default::default(),
+            drv_diff_base: Arc::new(RwLock::new(None)),
+            vuln_db: Arc::new(RwLock::new(None)),
+            license_db: Arc::new(RwLock::new(None)),
+            nix_store_path: Arc::new(RwLock::new(None)),
+            store_uri: Arc::new(RwLock::new(None)),
+            out_path: Arc::new(RwLock::new(false)),
+            pending_select: Arc::new(RwLock::new(None)),
+            substituter: Arc::new(RwLock::new(None)),
+            missing_paths: Arc::new(RwLock::new(None)),
+            expand_generation: Arc::new(RwLock::new(0)),
         }
     }
 
@@ -103,6 +118,20 @@ impl State {
         self.view_style.read().unwrap()
     }
 
+    pub fn read_drv_diff_base(
+        &self,
+    ) -> RwLockReadGuard<'_, Option<NixQueryDrv>> {
+        self.drv_diff_base.read().unwrap()
+    }
+
+    pub fn read_vuln_db(&self) -> RwLockReadGuard<'_, Option<VulnDb>> {
+        self.vuln_db.read().unwrap()
+    }
+
+    pub fn read_license_db(&self) -> RwLockReadGuard<'_, Option<LicenseDb>> {
+        self.license_db.read().unwrap()
+    }
+
     pub fn write_nix_store_res(&self, new_nix_store_res: NixStoreRes) {
         let state_option_nix_store_res: &mut Option<NixStoreRes> =
             &mut *self.nix_store_res.write().unwrap();
@@ -121,6 +150,83 @@ impl State {
         *state_view_style = new_view_style;
     }
 
+    pub fn write_drv_diff_base(&self, new_drv_diff_base: NixQueryDrv) {
+        *self.drv_diff_base.write().unwrap() = Some(new_drv_diff_base);
+    }
+
+    pub fn write_vuln_db(&self, new_vuln_db: VulnDb) {
+        *self.vuln_db.write().unwrap() = Some(new_vuln_db);
+    }
+
+    pub fn write_license_db(&self, new_license_db: LicenseDb) {
+        *self.license_db.write().unwrap() = Some(new_license_db);
+    }
+
+    pub fn read_nix_store_path(&self) -> RwLockReadGuard<'_, Option<PathBuf>> {
+        self.nix_store_path.read().unwrap()
+    }
+
+    pub fn write_nix_store_path(&self, new_nix_store_path: PathBuf) {
+        *self.nix_store_path.write().unwrap() = Some(new_nix_store_path);
+    }
+
+    pub fn read_store_uri(&self) -> RwLockReadGuard<'_, Option<String>> {
+        self.store_uri.read().unwrap()
+    }
+
+    pub fn write_store_uri(&self, new_store_uri: Option<String>) {
+        *self.store_uri.write().unwrap() = new_store_uri;
+    }
+
+    pub fn read_substituter(&self) -> RwLockReadGuard<'_, Option<String>> {
+        self.substituter.read().unwrap()
+    }
+
+    pub fn write_substituter(&self, new_substituter: String) {
+        *self.substituter.write().unwrap() = Some(new_substituter);
+    }
+
+    pub fn read_missing_paths(
+        &self,
+    ) -> RwLockReadGuard<'_, Option<HashSet<tree::Path>>> {
+        self.missing_paths.read().unwrap()
+    }
+
+    pub fn write_missing_paths(
+        &self,
+        new_missing_paths: Option<HashSet<tree::Path>>,
+    ) {
+        *self.missing_paths.write().unwrap() = new_missing_paths;
+    }
+
+    pub fn read_expand_generation(&self) -> RwLockReadGuard<'_, usize> {
+        self.expand_generation.read().unwrap()
+    }
+
+    /// Start a new expansion of the tree view, returning its generation.
+    pub fn next_expand_generation(&self) -> usize {
+        let mut expand_generation = self.expand_generation.write().unwrap();
+        *expand_generation += 1;
+        *expand_generation
+    }
+
+    pub fn read_out_path(&self) -> RwLockReadGuard<'_, bool> {
+        self.out_path.read().unwrap()
+    }
+
+    pub fn write_out_path(&self, new_out_path: bool) {
+        *self.out_path.write().unwrap() = new_out_path;
+    }
+
+    pub fn write_pending_select(&self, new_pending_select: tree::Path) {
+        *self.pending_select.write().unwrap() = Some(new_pending_select);
+    }
+
+    /// Take the row to select, so it is only selected once.
+    pub fn take_pending_select(&self) -> Option<tree::Path> {
+        self.pending_select.write().unwrap().take()
+    }
+
     pub fn get_app_win(&self) -> gtk::ApplicationWindow {
         self.builder.get_object_expect("appWindow")
     }
@@ -133,6 +239,50 @@ impl State {
         self.builder.get_object_expect("quitMenuItem")
     }
 
+    pub fn get_load_vuln_db_menu_item(&self) -> gtk::MenuItem {
+        self.builder.get_object_expect("loadVulnDbMenuItem")
+    }
+
+    pub fn get_load_license_db_menu_item(&self) -> gtk::MenuItem {
+        self.builder.get_object_expect("loadLicenseDbMenuItem")
+    }
+
+    pub fn get_check_binary_cache_menu_item(&self) -> gtk::MenuItem {
+        self.builder.get_object_expect("checkBinaryCacheMenuItem")
+    }
+
+    pub fn get_save_sbom_menu_item(&self) -> gtk::MenuItem {
+        self.builder.get_object_expect("saveSbomMenuItem")
+    }
+
+    pub fn get_save_html_report_menu_item(&self) -> gtk::MenuItem {
+        self.builder.get_object_expect("saveHtmlReportMenuItem")
+    }
+
+    pub fn get_accel_group(&self) -> gtk::AccelGroup {
+        self.builder.get_object_expect("accelGroup")
+    }
+
+    pub fn get_expand_all_menu_item(&self) -> gtk::MenuItem {
+        self.builder.get_object_expect("expandAllMenuItem")
+    }
+
+    pub fn get_collapse_all_menu_item(&self) -> gtk::MenuItem {
+        self.builder.get_object_expect("collapseAllMenuItem")
+    }
+
+    pub fn get_expand_to_depth_menu_item(&self) -> gtk::MenuItem {
+        self.builder.get_object_expect("expandToDepthMenuItem")
+    }
+
+    pub fn get_expand_to_matches_menu_item(&self) -> gtk::MenuItem {
+        self.builder.get_object_expect("expandToMatchesMenuItem")
+    }
+
+    pub fn get_only_missing_menu_item(&self) -> gtk::CheckMenuItem {
+        self.builder.get_object_expect("onlyMissingMenuItem")
+    }
+
     pub fn get_about_dialog(&self) -> gtk::AboutDialog {
         self.builder.get_object_expect("aboutDialog")
     }
@@ -145,6 +295,10 @@ impl State {
         self.builder.get_object_expect("rawTextBuffer")
     }
 
+    pub fn get_raw_text_view(&self) -> gtk::TextView {
+        self.builder.get_object_expect("rawTextView")
+    }
+
     pub fn get_statusbar(&self) -> gtk::Statusbar {
         self.builder.get_object_expect("statusbar")
     }
@@ -171,6 +325,135 @@ impl State {
         self.builder.get_object_expect("cellRendererTextRepeat")
     }
 
+    pub fn get_stack(&self) -> gtk::Stack {
+        self.builder.get_object_expect("stack")
+    }
+
+    pub fn get_drv_tree_view(&self) -> gtk::TreeView {
+        self.builder.get_object_expect("drvTreeView")
+    }
+
+    pub fn get_drv_tree_store(&self) -> gtk::TreeStore {
+        self.builder.get_object_expect("drvTreeStore")
+    }
+
+    pub fn get_drv_diff_tree_view(&self) -> gtk::TreeView {
+        self.builder.get_object_expect("drvDiffTreeView")
+    }
+
+    pub fn get_drv_diff_tree_store(&self) -> gtk::TreeStore {
+        self.builder.get_object_expect("drvDiffTreeStore")
+    }
+
+    pub fn get_files_path_label(&self) -> gtk::Label {
+        self.builder.get_object_expect("filesPathLabel")
+    }
+
+    pub fn get_files_tree_view(&self) -> gtk::TreeView {
+        self.builder.get_object_expect("filesTreeView")
+    }
+
+    pub fn get_files_tree_store(&self) -> gtk::TreeStore {
+        self.builder.get_object_expect("filesTreeStore")
+    }
+
+    pub fn get_files_preview_text_buffer(&self) -> gtk::TextBuffer {
+        self.builder.get_object_expect("filesPreviewTextBuffer")
+    }
+
+    pub fn get_references_label(&self) -> gtk::Label {
+        self.builder.get_object_expect("referencesLabel")
+    }
+
+    pub fn get_references_tree_view(&self) -> gtk::TreeView {
+        self.builder.get_object_expect("referencesTreeView")
+    }
+
+    pub fn get_references_list_store(&self) -> gtk::ListStore {
+        self.builder.get_object_expect("referencesListStore")
+    }
+
+    pub fn get_duplicates_label(&self) -> gtk::Label {
+        self.builder.get_object_expect("duplicatesLabel")
+    }
+
+    pub fn get_duplicates_tree_view(&self) -> gtk::TreeView {
+        self.builder.get_object_expect("duplicatesTreeView")
+    }
+
+    pub fn get_duplicates_tree_store(&self) -> gtk::TreeStore {
+        self.builder.get_object_expect("duplicatesTreeStore")
+    }
+
+    pub fn get_tree_view_column_vulnerabilities(&self) -> gtk::TreeViewColumn {
+        self.builder
+            .get_object_expect("treeViewColumnVulnerabilities")
+    }
+
+    pub fn get_tree_view_column_license(&self) -> gtk::TreeViewColumn {
+        self.builder.get_object_expect("treeViewColumnLicense")
+    }
+
+    pub fn get_tree_view_column_nar_info(&self) -> gtk::TreeViewColumn {
+        self.builder.get_object_expect("treeViewColumnNarInfo")
+    }
+
+    pub fn get_tree_view_column_substitute(&self) -> gtk::TreeViewColumn {
+        self.builder.get_object_expect("treeViewColumnSubstitute")
+    }
+
+    pub fn get_vulnerabilities_label(&self) -> gtk::Label {
+        self.builder.get_object_expect("vulnerabilitiesLabel")
+    }
+
+    pub fn get_vulnerabilities_tree_view(&self) -> gtk::TreeView {
+        self.builder.get_object_expect("vulnerabilitiesTreeView")
+    }
+
+    pub fn get_vulnerabilities_tree_store(&self) -> gtk::TreeStore {
+        self.builder.get_object_expect("vulnerabilitiesTreeStore")
+    }
+
+    pub fn get_licenses_label(&self) -> gtk::Label {
+        self.builder.get_object_expect("licensesLabel")
+    }
+
+    pub fn get_licenses_tree_view(&self) -> gtk::TreeView {
+        self.builder.get_object_expect("licensesTreeView")
+    }
+
+    pub fn get_licenses_tree_store(&self) -> gtk::TreeStore {
+        self.builder.get_object_expect("licensesTreeStore")
+    }
+
+    pub fn get_substitutes_label(&self) -> gtk::Label {
+        self.builder.get_object_expect("substitutesLabel")
+    }
+
+    pub fn get_substitutes_tree_view(&self) -> gtk::TreeView {
+        self.builder.get_object_expect("substitutesTreeView")
+    }
+
+    pub fn get_substitutes_list_store(&self) -> gtk::ListStore {
+        self.builder.get_object_expect("substitutesListStore")
+    }
+
+    pub fn get_gc_roots_label(&self) -> gtk::Label {
+        self.builder.get_object_expect("gcRootsLabel")
+    }
+
+    pub fn get_gc_roots_tree_view(&self) -> gtk::TreeView {
+        self.builder.get_object_expect("gcRootsTreeView")
+    }
+
+    pub fn get_gc_roots_tree_store(&self) -> gtk::TreeStore {
+        self.builder.get_object_expect("gcRootsTreeStore")
+    }
+
+    pub fn get_store_entry(&self) -> gtk::Entry {
+        self.builder.get_object_expect("storeEntry")
+    }
+
     pub fn get_search_entry(&self) -> gtk::SearchEntry {
         self.builder.get_object_expect("searchEntry")
     }
@@ -179,10 +462,18 @@ impl State {
         self.builder.get_object_expect("searchButton")
     }
 
+    pub fn get_refresh_button(&self) -> gtk::Button {
+        self.builder.get_object_expect("refreshButton")
+    }
+
     pub fn get_tree_store(&self) -> gtk::TreeStore {
         self.builder.get_object_expect("treeStore")
     }
 
+    pub fn get_tree_model_filter(&self) -> gtk::TreeModelFilter {
+        self.builder.get_object_expect("treeModelFilter")
+    }
+
     pub fn get_tree_model_sort(&self) -> gtk::TreeModelSort {
         self.builder.get_object_expect("treeModelSort")
     }
@@ -194,4 +485,12 @@ impl State {
     pub fn get_view_combo_box(&self) -> gtk::ComboBoxText {
         self.builder.get_object_expect("viewComboBox")
     }
+
+    pub fn get_depth_spin_button(&self) -> gtk::SpinButton {
+        self.builder.get_object_expect("depthSpinButton")
+    }
+
+    pub fn get_filter_entry(&self) -> gtk::SearchEntry {
+        self.builder.get_object_expect("filterEntry")
+    }
 }
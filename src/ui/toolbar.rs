@@ -1,13 +1,66 @@
+use std::convert::TryFrom;
+
 use super::prelude::*;
 use glib::clone;
 
 use super::super::ui;
+use crate::nix_query_tree::installable::Installable;
 
+/// Search for what is typed into the search entry.  This can be a store path, a flake
+/// reference like `nixpkgs#hello`, or an attribute like `<nixpkgs> -A hello`.
 fn handle_search(state: &ui::State) {
     let search_entry = state.get_search_entry();
     let search_text = search_entry.get_buffer().get_text();
 
-    ui::search_for(state, std::path::Path::new(&search_text));
+    match Installable::from_input(&search_text) {
+        Installable::StorePath(nix_store_path) => {
+            ui::search_for(state, &nix_store_path);
+        }
+        installable => ui::evaluate_and_search_for(
+            state,
+            installable,
+            store_uri(state),
+            false,
+        ),
+    }
+}
+
+/// The store URI typed into the store entry, or `None` for the default store.
+pub fn store_uri(state: &ui::State) -> Option<String> {
+    let store_text = state.get_store_entry().get_buffer().get_text();
+    let store_text = store_text.trim();
+    if store_text.is_empty() {
+        None
+    } else {
+        Some(String::from(store_text))
+    }
+}
+
+/// Show `store_uri` in the store entry.
+pub fn set_store_uri(state: &ui::State, store_uri: Option<&str>) {
+    state.get_store_entry().set_text(store_uri.unwrap_or(""));
+}
+
+/// The depth in the depth spin button.
+pub fn depth(state: &ui::State) -> usize {
+    usize::try_from(state.get_depth_spin_button().get_value_as_int())
+        .unwrap_or(0)
+}
+
+/// Show `depth` in the depth spin button, and expand the tree view to it.
+pub fn set_depth(state: &ui::State, depth: u32) {
+    if self::depth(state) == depth as usize {
+        // Setting the same value again wouldn't emit value-changed.
+        ui::expand_to_depth(state, depth as usize);
+    } else {
+        state.get_depth_spin_button().set_value(f64::from(depth));
+    }
+}
+
+/// Expand the tree view to the rows matching what is typed into the filter entry.
+pub fn expand_to_matches(state: &ui::State) {
+    let filter_text = state.get_filter_entry().get_buffer().get_text();
+    ui::expand_to_matches(state, &filter_text);
 }
 
 fn handle_select_sort_order(state: &ui::State) {
@@ -31,6 +84,12 @@ fn handle_select_view_style(state: &ui::State) {
 }
 
 pub fn connect_signals(state: &ui::State) {
+    state.get_store_entry().connect_activate(
+        clone!(@strong state => move |_| {
+            handle_search(&state);
+        }),
+    );
+
     state.get_search_entry().connect_activate(
         clone!(@strong state => move |_| {
             handle_search(&state);
@@ -44,6 +103,12 @@ pub fn connect_signals(state: &ui::State) {
         }),
     );
 
+    state.get_refresh_button().connect_clicked(
+        clone!(@strong state => move |_| {
+            ui::refresh(&state);
+        }),
+    );
+
     state.get_sort_combo_box().connect_changed(
         clone!(@strong state => move |_| {
             handle_select_sort_order(&state);
@@ -55,18 +120,38 @@ pub fn connect_signals(state: &ui::State) {
             handle_select_view_style(&state);
         }),
     );
+
+    state.get_depth_spin_button().connect_value_changed(
+        clone!(@strong state => move |_| {
+            ui::expand_to_depth(&state, depth(&state));
+        }),
+    );
+
+    state.get_filter_entry().connect_activate(
+        clone!(@strong state => move |_| {
+            expand_to_matches(&state);
+        }),
+    );
 }
 
 pub fn disable(state: &ui::State) {
+    state.get_store_entry().set_sensitive(false);
     state.get_search_entry().set_sensitive(false);
     state.get_search_button().set_sensitive(false);
+    state.get_refresh_button().set_sensitive(false);
     state.get_sort_combo_box().set_sensitive(false);
+    state.get_depth_spin_button().set_sensitive(false);
+    state.get_filter_entry().set_sensitive(false);
 }
 
 pub fn enable(state: &ui::State) {
+    state.get_store_entry().set_sensitive(true);
     state.get_search_entry().set_sensitive(true);
     state.get_search_button().set_sensitive(true);
+    state.get_refresh_button().set_sensitive(true);
     state.get_sort_combo_box().set_sensitive(true);
+    state.get_depth_spin_button().set_sensitive(true);
+    state.get_filter_entry().set_sensitive(true);
 }
 
 pub fn setup(state: &ui::State) {
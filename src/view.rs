@@ -0,0 +1,155 @@
+//! Options for how the tree of nix store paths is shown.  These are shared between the GTK UI
+//! and the terminal UI.
+
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+
+use super::nix_query_tree::{NixQueryDrv, NixQueryEntry};
+
+/// Sort order for the tree of nix store paths.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[repr(i32)]
+pub enum SortOrder {
+    #[default]
+    NixStoreOrigOutput = 0,
+    AlphabeticalHash,
+    AlphabeticalDrvName,
+}
+
+impl TryFrom<u32> for SortOrder {
+    type Error = u32;
+
+    fn try_from(value: u32) -> Result<SortOrder, u32> {
+        match value {
+            0 => Ok(SortOrder::NixStoreOrigOutput),
+            1 => Ok(SortOrder::AlphabeticalHash),
+            2 => Ok(SortOrder::AlphabeticalDrvName),
+            n => Err(n),
+        }
+    }
+}
+
+impl std::fmt::Display for SortOrder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            SortOrder::NixStoreOrigOutput => "nix-store output",
+            SortOrder::AlphabeticalHash => "alphabetical by hash",
+            SortOrder::AlphabeticalDrvName => "alphabetical by name",
+        };
+        write!(f, "{}", string)
+    }
+}
+
+impl SortOrder {
+    /// The sort order after this one, wrapping around to the first.
+    pub fn next(self) -> Self {
+        SortOrder::try_from(self as u32 + 1).unwrap_or_default()
+    }
+
+    /// Compare two sibling entries in the tree.  Everything is equal in the original
+    /// `nix-store` order, so a stable sort leaves the entries as they are.
+    pub fn compare(self, a: &NixQueryEntry, b: &NixQueryEntry) -> Ordering {
+        match self {
+            SortOrder::NixStoreOrigOutput => Ordering::Equal,
+            SortOrder::AlphabeticalHash => a.cmp_hash(b),
+            SortOrder::AlphabeticalDrvName => a.cmp_drv_name(b),
+        }
+    }
+}
+
+/// View style for an individual nix store path.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+#[repr(i32)]
+pub enum ViewStyle {
+    #[default]
+    FullPath = 0,
+    HashAndDrvName,
+    ShortHashAndDrvName,
+    OnlyDrvName,
+}
+
+impl TryFrom<u32> for ViewStyle {
+    type Error = u32;
+
+    fn try_from(value: u32) -> Result<ViewStyle, u32> {
+        match value {
+            0 => Ok(ViewStyle::FullPath),
+            1 => Ok(ViewStyle::HashAndDrvName),
+            2 => Ok(ViewStyle::ShortHashAndDrvName),
+            3 => Ok(ViewStyle::OnlyDrvName),
+            n => Err(n),
+        }
+    }
+}
+
+impl std::fmt::Display for ViewStyle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let string = match self {
+            ViewStyle::FullPath => "full path",
+            ViewStyle::HashAndDrvName => "hash and name",
+            ViewStyle::ShortHashAndDrvName => "short hash and name",
+            ViewStyle::OnlyDrvName => "only name",
+        };
+        write!(f, "{}", string)
+    }
+}
+
+impl ViewStyle {
+    /// The view style after this one, wrapping around to the first.
+    pub fn next(self) -> Self {
+        ViewStyle::try_from(self as u32 + 1).unwrap_or_default()
+    }
+
+    /// Render a nix store path in this style.
+    pub fn render(self, nix_query_drv: &NixQueryDrv) -> String {
+        match self {
+            ViewStyle::FullPath => nix_query_drv.to_string(),
+            ViewStyle::HashAndDrvName => nix_query_drv.hash_and_drv_name(),
+            ViewStyle::ShortHashAndDrvName => {
+                nix_query_drv.short_hash_and_drv_name()
+            }
+            ViewStyle::OnlyDrvName => nix_query_drv.drv_name(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::nix_query_tree::Recurse;
+
+    #[test]
+    fn test_next_wraps_around() {
+        assert_eq!(
+            SortOrder::AlphabeticalDrvName.next(),
+            SortOrder::NixStoreOrigOutput
+        );
+        assert_eq!(ViewStyle::FullPath.next(), ViewStyle::HashAndDrvName);
+        assert_eq!(ViewStyle::OnlyDrvName.next(), ViewStyle::FullPath);
+    }
+
+    #[test]
+    fn test_compare() {
+        let a = NixQueryEntry::new(
+            "/nix/store/bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb-aaa-1.0",
+            Recurse::No,
+        );
+        let b = NixQueryEntry::new(
+            "/nix/store/aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-bbb-1.0",
+            Recurse::No,
+        );
+        assert_eq!(
+            SortOrder::NixStoreOrigOutput.compare(&a, &b),
+            Ordering::Equal
+        );
+        assert_eq!(
+            SortOrder::AlphabeticalHash.compare(&a, &b),
+            Ordering::Greater
+        );
+        assert_eq!(
+            SortOrder::AlphabeticalDrvName.compare(&a, &b),
+            Ordering::Less
+        );
+    }
+}
@@ -0,0 +1,111 @@
+extern crate nix_query_tree_viewer;
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use tempfile::TempDir;
+
+use nix_query_tree_viewer::nix_query_tree::installable::*;
+
+/// A directory with fake `nix` and `nix-instantiate` executables.  Each one writes its
+/// arguments to `<name>.args` and prints `output`.
+fn fake_nix_dir(output: &str) -> (TempDir, Evaluator) {
+    let dir = tempfile::tempdir().unwrap();
+    let write_fake = |program: &str| {
+        let path = dir.path().join(program);
+        std::fs::write(
+            &path,
+            format!(
+                "#!/bin/sh\nfor arg in \"$@\"; do echo \"$arg\"; done > {}.args\necho '{}'\n",
+                path.display(),
+                output
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))
+            .unwrap();
+        path
+    };
+    let evaluator = Evaluator {
+        nix: write_fake("nix"),
+        nix_instantiate: write_fake("nix-instantiate"),
+    };
+    (dir, evaluator)
+}
+
+fn args(dir: &Path, program: &str) -> Vec<String> {
+    std::fs::read_to_string(dir.join(format!("{}.args", program)))
+        .unwrap()
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn test_resolve_flake() {
+    let drv = "/nix/store/jymg0kanmlgbcv35wxd8d660rw0fawhv-hello-2.10.drv";
+    let (dir, evaluator) = fake_nix_dir(drv);
+
+    let installable = Installable::new("nixpkgs#hello", None);
+    assert_eq!(
+        evaluator.resolve(&installable, false),
+        Ok(PathBuf::from(drv))
+    );
+    assert_eq!(
+        args(dir.path(), "nix"),
+        vec![
+            "--extra-experimental-features",
+            "nix-command flakes",
+            "eval",
+            "--raw",
+            "nixpkgs#hello",
+            "--apply",
+            "pkg: pkg.drvPath"
+        ]
+    );
+}
+
+#[test]
+fn test_resolve_attr() {
+    let drv = "/nix/store/jymg0kanmlgbcv35wxd8d660rw0fawhv-hello-2.10.drv";
+    let (dir, evaluator) = fake_nix_dir(drv);
+
+    let installable = Installable::new("<nixpkgs>", Some("hello"));
+    assert_eq!(
+        evaluator.resolve(&installable, false),
+        Ok(PathBuf::from(drv))
+    );
+    assert_eq!(
+        args(dir.path(), "nix-instantiate"),
+        vec!["<nixpkgs>", "-A", "hello"]
+    );
+}
+
+#[test]
+fn test_resolve_attr_out_path() {
+    let out = "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10";
+    let (dir, evaluator) = fake_nix_dir(&format!("\"{}\"", out));
+
+    let installable = Installable::new("./default.nix", Some("hello"));
+    assert_eq!(
+        evaluator.resolve(&installable, true),
+        Ok(PathBuf::from(out))
+    );
+    assert_eq!(
+        args(dir.path(), "nix-instantiate"),
+        vec!["--eval", "--json", "./default.nix", "-A", "hello.outPath"]
+    );
+}
+
+#[test]
+fn test_resolve_not_a_store_path() {
+    let (_dir, evaluator) = fake_nix_dir("hello");
+
+    let installable = Installable::new("nixpkgs#hello", None);
+    assert_eq!(
+        evaluator.resolve(&installable, false),
+        Err(InstallableErr::EvalErr(String::from(
+            "nixpkgs#hello didn't evaluate to a store path: \"hello\""
+        )))
+    );
+}
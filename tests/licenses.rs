@@ -0,0 +1,64 @@
+extern crate nix_query_tree_viewer;
+
+use indoc::indoc;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use nix_query_tree_viewer::nix_query_tree::licenses::*;
+use nix_query_tree_viewer::nix_query_tree::*;
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name)
+}
+
+#[test]
+fn test_license_report_from_sidecar() {
+    let raw_input = indoc!(
+        "/nix/store/jymg0kanmlgbcv35wxd8d660rw0fawhv-hello-2.10.drv
+        +---/nix/store/9krlzvny65gdc8s7kpb6lkx8cd02c25b-default-builder.sh
+        +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27.drv
+        |   +---/nix/store/9krlzvny65gdc8s7kpb6lkx8cd02c25b-default-builder.sh [...]
+        +---/nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-unrar-5.9.2.drv
+        |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27.drv [...]
+        +---/nix/store/5jzbjvnrz85n454inlyxcpgap9i6k6la-mystery-1.0.drv
+        "
+    );
+    let nix_query_tree = NixQueryTree::from_str(raw_input).unwrap();
+    let license_db = LicenseDb::from_path(&fixture("licenses.json")).unwrap();
+    let report = LicenseReport::from_closure(
+        &nix_query_tree.path_map(),
+        &nix_query_tree.0.item.0,
+        Some(&license_db),
+    );
+
+    assert_eq!(
+        report.report(),
+        indoc!(
+            "GPL-3.0-or-later (1 store paths)
+                 /nix/store/jymg0kanmlgbcv35wxd8d660rw0fawhv-hello-2.10.drv
+             LGPL-2.1-or-later (1 store paths)
+                 /nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27.drv
+             UNFREE: unfreeRedistributable (1 store paths)
+                 /nix/store/9ny6szla9dg61jv8q22qbnqsz37465n0-unrar-5.9.2.drv
+             UNKNOWN: no license information (1 store paths)
+                 /nix/store/5jzbjvnrz85n454inlyxcpgap9i6k6la-mystery-1.0.drv
+            "
+        )
+    );
+    assert!(report.has_flagged());
+}
+
+#[test]
+fn test_license_db_errors() {
+    assert!(matches!(
+        LicenseDb::from_path(&fixture("does-not-exist.json")),
+        Err(LicenseDbErr::IoErr(_))
+    ));
+    assert!(matches!(
+        LicenseDb::from_str("{"),
+        Err(LicenseDbErr::JsonErr(_))
+    ));
+}
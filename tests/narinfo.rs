@@ -0,0 +1,81 @@
+extern crate nix_query_tree_viewer;
+
+use indoc::indoc;
+use std::path::{Path, PathBuf};
+
+use nix_query_tree_viewer::nix_query_tree::exec_nix_store::{
+    self, KeepRaw, NixStoreErr,
+};
+use nix_query_tree_viewer::nix_query_tree::NixQueryDrv;
+
+fn binary_cache_uri() -> String {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join("binary-cache");
+    format!("file://{}?compression=xz", dir.display())
+}
+
+#[test]
+fn test_query_binary_cache() {
+    let hello =
+        Path::new("/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10");
+    let exec_nix_store_res =
+        exec_nix_store::run(hello, Some(&binary_cache_uri()), KeepRaw::Yes);
+    assert!(!exec_nix_store_res.from_cache);
+    let nix_store_res = exec_nix_store_res.res.unwrap();
+
+    // References come after the store paths they reference, like with `nix-store`.
+    assert_eq!(
+        nix_store_res.raw.as_deref(),
+        Some(indoc!(
+            "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+            +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+            |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            +---/nix/store/0kgyxg7dd4aiaz7r6rqcncy1c1bwqpda-libidn2-2.3.0
+            |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            |   +---/nix/store/zb3ppgdksl3s9mz3s0p6hpsl6pgx1zjy-libunistring-0.9.10
+            |   |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+            |   +---/nix/store/0kgyxg7dd4aiaz7r6rqcncy1c1bwqpda-libidn2-2.3.0 [...]
+            +---/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10 [...]
+            "
+        ))
+    );
+    assert_eq!(
+        nix_store_res.raw.unwrap().parse(),
+        Ok(nix_store_res.tree.clone())
+    );
+
+    assert_eq!(nix_store_res.narinfos.len(), 4);
+    let glibc = NixQueryDrv::from(
+        "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27",
+    );
+    assert_eq!(
+        nix_store_res.narinfos[&glibc].summary(),
+        "NAR 16.2 MiB, xz 3.9 MiB, signed by cache.example.org-1"
+    );
+    let libunistring = NixQueryDrv::from(
+        "/nix/store/zb3ppgdksl3s9mz3s0p6hpsl6pgx1zjy-libunistring-0.9.10",
+    );
+    assert_eq!(
+        nix_store_res.narinfos[&libunistring].summary(),
+        "NAR 1.7 MiB, xz 488.3 KiB, unsigned"
+    );
+}
+
+#[test]
+fn test_query_binary_cache_missing_path() {
+    let missing =
+        Path::new("/nix/store/a9n6ip5d1m7b3blkxmmg0dd5z4cbqgvf-acl-2.2.53");
+    let exec_nix_store_res = exec_nix_store::refresh(
+        missing,
+        Some(&binary_cache_uri()),
+        KeepRaw::Yes,
+    );
+    assert_eq!(
+        exec_nix_store_res.res,
+        Err(NixStoreErr::BinaryCacheErr(String::from(
+            "/nix/store/a9n6ip5d1m7b3blkxmmg0dd5z4cbqgvf-acl-2.2.53 is not in the binary cache"
+        )))
+    );
+}
@@ -244,6 +244,8 @@ fn test_parse_nix_query_tree_complicated() {
                 } 
             );
     let r = nix_query_tree_parser(raw_input);
+    assert_eq!(r, Ok(actual_tree.clone()));
+    let r = stream_parsing::read_nix_query_tree(raw_input.as_bytes());
     assert_eq!(r, Ok(actual_tree));
 }
 
@@ -485,5 +487,39 @@ fn test_parse_nix_query_tree_complicated_unicode() {
                 } 
             );
     let r = nix_query_tree_parser(raw_input);
+    assert_eq!(r, Ok(actual_tree.clone()));
+    let r = stream_parsing::read_nix_query_tree(raw_input.as_bytes());
     assert_eq!(r, Ok(actual_tree));
 }
+
+#[test]
+fn test_parse_nix_query_tree_missing_final_newline() {
+    let raw_input = "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
++---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27";
+    assert!(nix_query_tree_parser(raw_input).is_err());
+    assert!(matches!(
+        stream_parsing::read_nix_query_tree(raw_input.as_bytes()),
+        Err(stream_parsing::StreamParseErr::ParseErr(2, _))
+    ));
+
+    let raw_input = "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10";
+    assert!(nix_query_tree_parser(raw_input).is_err());
+    assert!(matches!(
+        stream_parsing::read_nix_query_tree(raw_input.as_bytes()),
+        Err(stream_parsing::StreamParseErr::ParseErr(1, _))
+    ));
+}
+
+#[test]
+fn test_parse_nix_query_tree_recurse_root() {
+    let raw_input = indoc!(
+        "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10 [...]
+        +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+        "
+    );
+    assert!(nix_query_tree_parser(raw_input).is_err());
+    assert!(matches!(
+        stream_parsing::read_nix_query_tree(raw_input.as_bytes()),
+        Err(stream_parsing::StreamParseErr::ParseErr(1, _))
+    ));
+}
@@ -0,0 +1,68 @@
+extern crate nix_query_tree_viewer;
+
+use proptest::prelude::*;
+
+use nix_query_tree_viewer::nix_query_tree::parsing::nix_query_tree_parser;
+use nix_query_tree_viewer::nix_query_tree::printing::*;
+use nix_query_tree_viewer::nix_query_tree::stream_parsing::read_nix_query_tree;
+use nix_query_tree_viewer::nix_query_tree::*;
+use nix_query_tree_viewer::tree::*;
+
+fn arb_nix_query_drv() -> impl Strategy<Value = NixQueryDrv> {
+    "[a-z0-9]{32}-[a-z0-9.+-]{1,20}"
+        .prop_map(|name| NixQueryDrv::from(&format!("/nix/store/{}", name)))
+}
+
+fn arb_recurse() -> impl Strategy<Value = Recurse> {
+    prop_oneof![Just(Recurse::Yes), Just(Recurse::No)]
+}
+
+/// A branch below the root, which may have been marked with `[...]`.
+fn arb_branch() -> impl Strategy<Value = Tree<NixQueryEntry>> {
+    let leaf =
+        (arb_nix_query_drv(), arb_recurse()).prop_map(|(drv, recurse)| {
+            Tree::singleton(NixQueryEntry(drv, recurse))
+        });
+    leaf.prop_recursive(6, 64, 5, |inner| {
+        (arb_nix_query_drv(), prop::collection::vec(inner, 0..5)).prop_map(
+            |(drv, children)| {
+                Tree::new(NixQueryEntry(drv, Recurse::No), children)
+            },
+        )
+    })
+}
+
+/// A whole tree.  The root is never marked with `[...]`.
+fn arb_nix_query_tree() -> impl Strategy<Value = NixQueryTree> {
+    (
+        arb_nix_query_drv(),
+        prop::collection::vec(arb_branch(), 0..5),
+    )
+        .prop_map(|(drv, children)| {
+            NixQueryTree(Tree::new(NixQueryEntry(drv, Recurse::No), children))
+        })
+}
+
+fn arb_tree_style() -> impl Strategy<Value = TreeStyle> {
+    prop_oneof![Just(TreeStyle::Ascii), Just(TreeStyle::Unicode)]
+}
+
+proptest! {
+    #[test]
+    fn test_print_then_parse(
+        nix_query_tree in arb_nix_query_tree(),
+        style in arb_tree_style(),
+    ) {
+        let raw = print_nix_query_tree(&nix_query_tree, style);
+        prop_assert_eq!(nix_query_tree_parser(&raw), Ok(nix_query_tree));
+    }
+
+    #[test]
+    fn test_print_then_stream_parse(
+        nix_query_tree in arb_nix_query_tree(),
+        style in arb_tree_style(),
+    ) {
+        let raw = print_nix_query_tree(&nix_query_tree, style);
+        prop_assert_eq!(read_nix_query_tree(raw.as_bytes()), Ok(nix_query_tree));
+    }
+}
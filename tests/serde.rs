@@ -0,0 +1,94 @@
+#![cfg(feature = "serde")]
+
+extern crate nix_query_tree_viewer;
+
+use indoc::indoc;
+use serde_json::json;
+
+use nix_query_tree_viewer::nix_query_tree::exec_nix_store::NixStoreRes;
+use nix_query_tree_viewer::nix_query_tree::narinfo::NarInfo;
+use nix_query_tree_viewer::nix_query_tree::parsing::nix_query_tree_parser_lenient;
+use nix_query_tree_viewer::nix_query_tree::*;
+use nix_query_tree_viewer::tree::*;
+
+const RAW_INPUT: &str = indoc!(
+    "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+    +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+    |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+    +---/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10 [...]
+    "
+);
+
+#[test]
+fn test_nix_query_tree_json() {
+    let nix_query_tree: NixQueryTree = RAW_INPUT.parse().unwrap();
+
+    let expected = json!({
+        "item": ["/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10", "No"],
+        "children": [
+            {
+                "item": ["/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27", "No"],
+                "children": [
+                    {
+                        "item": ["/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27", "Yes"],
+                        "children": [],
+                    },
+                ],
+            },
+            {
+                "item": ["/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10", "Yes"],
+                "children": [],
+            },
+        ],
+    });
+    assert_eq!(serde_json::to_value(&nix_query_tree).unwrap(), expected);
+    assert_eq!(
+        serde_json::from_value::<NixQueryTree>(expected).unwrap(),
+        nix_query_tree
+    );
+}
+
+#[test]
+fn test_path_json() {
+    let path: Path = vec![2, 0, 1].into();
+
+    assert_eq!(serde_json::to_string(&path).unwrap(), "[2,0,1]");
+    assert_eq!(serde_json::from_str::<Path>("[2,0,1]").unwrap(), path);
+}
+
+#[test]
+fn test_nix_store_res_round_trip() {
+    let raw_input = format!("warning: unknown setting 'foo'\n{}", RAW_INPUT);
+    let (nix_query_tree, skipped) =
+        nix_query_tree_parser_lenient(&raw_input).unwrap();
+    let glibc_narinfo: NarInfo = indoc!(
+        "StorePath: /nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+        Compression: xz
+        NarSize: 16945456
+        "
+    )
+    .parse()
+    .unwrap();
+    let nix_store_res = NixStoreRes {
+        skipped,
+        narinfos: vec![(
+            NixQueryDrv::from(&glibc_narinfo.store_path),
+            glibc_narinfo,
+        )]
+        .into_iter()
+        .collect(),
+        ..NixStoreRes::new(&raw_input, nix_query_tree)
+    };
+
+    let json = serde_json::to_string(&nix_store_res).unwrap();
+    let round_tripped: NixStoreRes = serde_json::from_str(&json).unwrap();
+
+    assert_eq!(round_tripped, nix_store_res);
+    assert_eq!(
+        round_tripped.lookup_first_query_entry(&NixQueryEntry::new(
+            "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27",
+            Recurse::Yes
+        )),
+        Some(&vec![0].into())
+    );
+}
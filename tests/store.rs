@@ -0,0 +1,86 @@
+#![cfg(feature = "serde")]
+
+extern crate nix_query_tree_viewer;
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use nix_query_tree_viewer::nix_query_tree::cache::{self, Cache};
+use nix_query_tree_viewer::nix_query_tree::exec_nix_store::{self, KeepRaw};
+
+const HELLO: &str = "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10";
+
+/// Write a fake `nix-store` to `dir` that appends its arguments to `nix-store.args`, one run
+/// per line, and prints a small tree.
+fn write_fake_nix_store(dir: &Path) {
+    let path = dir.join("nix-store");
+    std::fs::write(
+        &path,
+        format!(
+            "#!/bin/sh\necho \"$@\" >> {}\necho '{}'\necho '+---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27'\n",
+            dir.join("nix-store.args").display(),
+            HELLO
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))
+        .unwrap();
+}
+
+fn runs(dir: &Path) -> Vec<String> {
+    std::fs::read_to_string(dir.join("nix-store.args"))
+        .unwrap_or_default()
+        .lines()
+        .map(String::from)
+        .collect()
+}
+
+// This is the only test in this file, since it changes `PATH` and `XDG_CACHE_HOME` for the
+// whole process.
+#[test]
+fn test_store_uri_is_queried_and_cached() {
+    let bin_dir = tempfile::tempdir().unwrap();
+    let cache_dir = tempfile::tempdir().unwrap();
+    write_fake_nix_store(bin_dir.path());
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let mut paths = vec![bin_dir.path().to_path_buf()];
+    paths.extend(std::env::split_paths(&path));
+    std::env::set_var("PATH", std::env::join_paths(paths).unwrap());
+    std::env::set_var("XDG_CACHE_HOME", cache_dir.path());
+
+    let hello = Path::new(HELLO);
+    let res = exec_nix_store::run(hello, Some("ssh://builder"), KeepRaw::No);
+    assert_eq!(res.store_uri, Some(String::from("ssh://builder")));
+    assert!(!res.from_cache);
+    let nix_store_res = res.res.unwrap();
+    assert_eq!(nix_store_res.tree.0.children.len(), 1);
+    assert_eq!(nix_store_res.raw, None);
+    assert_eq!(
+        runs(bin_dir.path()),
+        vec![format!("--query --tree {} --store ssh://builder", HELLO)]
+    );
+
+    // The result is cached under a key that includes the store URI.
+    let key = cache::cache_key(hello, Some("ssh://builder")).unwrap();
+    assert!(key.contains("ssh://builder"));
+    assert!(Cache::open_default().unwrap().get(&key).unwrap().is_some());
+
+    // The same store URI uses the cached result.
+    let res = exec_nix_store::run(hello, Some("ssh://builder"), KeepRaw::No);
+    assert!(res.from_cache);
+    assert_eq!(runs(bin_dir.path()).len(), 1);
+
+    // The cached result doesn't have the raw output, so asking for it runs `nix-store` again.
+    let res = exec_nix_store::run(hello, Some("ssh://builder"), KeepRaw::Yes);
+    assert!(!res.from_cache);
+    assert!(res.res.unwrap().raw.is_some());
+    assert_eq!(runs(bin_dir.path()).len(), 2);
+
+    // A different store URI doesn't.
+    let res = exec_nix_store::run(hello, Some("ssh://other"), KeepRaw::No);
+    assert!(!res.from_cache);
+    assert_eq!(
+        runs(bin_dir.path())[2],
+        format!("--query --tree {} --store ssh://other", HELLO)
+    );
+}
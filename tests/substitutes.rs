@@ -0,0 +1,199 @@
+extern crate nix_query_tree_viewer;
+
+use indoc::indoc;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use nix_query_tree_viewer::nix_query_tree::exec_nix_store::NixStoreRes;
+use nix_query_tree_viewer::nix_query_tree::narinfo::NarInfo;
+use nix_query_tree_viewer::nix_query_tree::substitutes::*;
+use nix_query_tree_viewer::nix_query_tree::*;
+
+fn binary_cache_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join("binary-cache")
+}
+
+/// A tree where `acl` isn't in the binary cache, and `attr` isn't either and has no known
+/// size.
+fn nix_store_res() -> NixStoreRes {
+    let raw = indoc!(
+        "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+        +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+        +---/nix/store/a9n6ip5d1m7b3blkxmmg0dd5z4cbqgvf-acl-2.2.53
+        |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+        |   +---/nix/store/xa0pvm3qfhvgmjm1rmnk8z3pz9i05l9j-attr-2.4.48
+        +---/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10 [...]
+        "
+    );
+    let acl_narinfo = NarInfo {
+        store_path: PathBuf::from(
+            "/nix/store/a9n6ip5d1m7b3blkxmmg0dd5z4cbqgvf-acl-2.2.53",
+        ),
+        nar_size: Some(3 * 1024 * 1024),
+        ..NarInfo::default()
+    };
+    NixStoreRes {
+        narinfos: vec![(
+            NixQueryDrv::from(&acl_narinfo.store_path),
+            acl_narinfo,
+        )]
+        .into_iter()
+        .collect(),
+        ..NixStoreRes::new(raw, NixQueryTree::from_str(raw).unwrap())
+    }
+}
+
+fn check_report(report: &SubstituteReport) {
+    assert_eq!(report.cached.len(), 2);
+    assert_eq!(
+        report.missing,
+        vec![
+            MissingPath {
+                drv: NixQueryDrv::from(
+                    "/nix/store/a9n6ip5d1m7b3blkxmmg0dd5z4cbqgvf-acl-2.2.53"
+                ),
+                size: Some(3 * 1024 * 1024),
+            },
+            MissingPath {
+                drv: NixQueryDrv::from(
+                    "/nix/store/xa0pvm3qfhvgmjm1rmnk8z3pz9i05l9j-attr-2.4.48"
+                ),
+                size: None,
+            },
+        ]
+    );
+    assert_eq!(
+        report.report(),
+        indoc!(
+            "2 of 4 store paths are missing from the binary cache, 3.0 MiB to upload, plus 1 store paths of unknown size.
+            /nix/store/a9n6ip5d1m7b3blkxmmg0dd5z4cbqgvf-acl-2.2.53  3.0 MiB
+            /nix/store/xa0pvm3qfhvgmjm1rmnk8z3pz9i05l9j-attr-2.4.48  ?
+            "
+        )
+    );
+}
+
+#[test]
+fn test_check_local_binary_cache() {
+    let uri = format!("file://{}", binary_cache_dir().display());
+    let substituter = Substituter::from_uri(&uri).unwrap();
+    check_report(&check(&substituter, &nix_store_res()).unwrap());
+
+    let substituter =
+        Substituter::from_uri(&binary_cache_dir().display().to_string())
+            .unwrap();
+    check_report(&check(&substituter, &nix_store_res()).unwrap());
+}
+
+/// What `serve_binary_cache` has seen so far.
+#[derive(Debug, Default)]
+struct Requests {
+    connections: usize,
+    /// The `Host` header of every request.
+    hosts: Vec<String>,
+}
+
+/// Serve the `.narinfo` files of the fixture binary cache under `/cache`, like a minimal
+/// HTTP binary cache.
+///
+/// With `keep_alive`, this answers in HTTP/1.1 with a `Content-Length` and keeps serving
+/// requests on the same connection.  Otherwise it answers in HTTP/1.0 and closes the
+/// connection after each response.
+fn serve_binary_cache(keep_alive: bool) -> (String, Arc<Mutex<Requests>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let requests = Arc::new(Mutex::new(Requests::default()));
+    let server_requests = Arc::clone(&requests);
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            server_requests.lock().unwrap().connections += 1;
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            loop {
+                let mut request_line = String::new();
+                if reader.read_line(&mut request_line).unwrap() == 0 {
+                    break;
+                }
+                let mut header = String::new();
+                while reader.read_line(&mut header).unwrap() > 2 {
+                    if let Some(host) = header.strip_prefix("Host: ") {
+                        server_requests
+                            .lock()
+                            .unwrap()
+                            .hosts
+                            .push(String::from(host.trim_end()));
+                    }
+                    header.clear();
+                }
+                let path = request_line.split_whitespace().nth(1).unwrap();
+                let file = path
+                    .strip_prefix("/cache/")
+                    .map(|file_name| binary_cache_dir().join(file_name));
+                let (status, body) = match file.map(std::fs::read_to_string) {
+                    Some(Ok(body)) => ("200 OK", body),
+                    _ => ("404 Not Found", String::new()),
+                };
+                let response = if keep_alive {
+                    format!(
+                        "HTTP/1.1 {}\r\nContent-Type: text/x-nix-narinfo\r\nContent-Length: {}\r\n\r\n{}",
+                        status,
+                        body.len(),
+                        body
+                    )
+                } else {
+                    format!(
+                        "HTTP/1.0 {}\r\nContent-Type: text/x-nix-narinfo\r\n\r\n{}",
+                        status, body
+                    )
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+                if !keep_alive {
+                    break;
+                }
+            }
+        }
+    });
+    (format!("http://127.0.0.1:{}/cache/", port), requests)
+}
+
+#[test]
+fn test_check_http_binary_cache() {
+    let (uri, requests) = serve_binary_cache(false);
+    let substituter = Substituter::from_uri(&uri).unwrap();
+    check_report(&check(&substituter, &nix_store_res()).unwrap());
+
+    let glibc = substituter
+        .lookup(&NixQueryDrv::from(
+            "/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27",
+        ))
+        .unwrap()
+        .unwrap();
+    assert_eq!(glibc.nar_size, Some(16_945_456));
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.connections, 5);
+    let host = uri
+        .trim_start_matches("http://")
+        .trim_end_matches("/cache/");
+    assert!(requests
+        .hosts
+        .iter()
+        .all(|request_host| request_host == host));
+}
+
+#[test]
+fn test_check_http_binary_cache_keep_alive() {
+    let (uri, requests) = serve_binary_cache(true);
+    let substituter = Substituter::from_uri(&uri).unwrap();
+    check_report(&check(&substituter, &nix_store_res()).unwrap());
+
+    let requests = requests.lock().unwrap();
+    assert_eq!(requests.connections, 1);
+    assert_eq!(requests.hosts.len(), 4);
+}
@@ -0,0 +1,103 @@
+extern crate nix_query_tree_viewer;
+
+use indoc::indoc;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use nix_query_tree_viewer::nix_query_tree::vulnerabilities::*;
+use nix_query_tree_viewer::nix_query_tree::*;
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("fixtures")
+        .join(name)
+}
+
+fn nix_query_tree() -> NixQueryTree {
+    let raw_input = indoc!(
+        "/nix/store/qy93dp4a3rqyn2mz63fbxjg228hffwyw-hello-2.10
+        +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27
+        |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+        +---/nix/store/i6vl5lwlz5jbkg4r6p340dwmj6fha3xq-openssl-1.1.1d
+        |   +---/nix/store/pnd2kl27sag76h23wa5kl95a76n3k9i3-glibc-2.27 [...]
+        +---/nix/store/a6d5lyc3bzakyvxigwbms4lsh97pw3hn-openssl-1.1.1g-dev
+        +---/nix/store/9ic5wcjfn6jn7vyc3ncli3gd2wl5fkm8-libidn2-2.1.1a
+        +---/nix/store/c8v2ylrkfvha3k2ivg8x6kfzpcmkcpwb-bash-4.4-p23
+        "
+    );
+    NixQueryTree::from_str(raw_input).unwrap()
+}
+
+#[test]
+fn test_scan_osv_feed() {
+    let vuln_db = VulnDb::from_path(&fixture("osv-feed.json")).unwrap();
+    assert_eq!(vuln_db.advisories.len(), 3);
+
+    let findings = scan(&vuln_db, &nix_query_tree().path_map());
+    let summary: Vec<(&str, &str, Vec<&str>)> = findings
+        .iter()
+        .map(|finding| {
+            (
+                finding.pname.as_str(),
+                finding.version.as_str(),
+                finding.advisories.iter().map(|a| a.id.as_str()).collect(),
+            )
+        })
+        .collect();
+
+    assert_eq!(
+        summary,
+        vec![
+            ("glibc", "2.27", vec!["CVE-2019-9169"]),
+            ("openssl", "1.1.1d", vec!["CVE-2020-1967"]),
+        ]
+    );
+    assert_eq!(
+        findings[1].advisories[0].severity,
+        Some(String::from("HIGH"))
+    );
+}
+
+#[test]
+fn test_scan_nvd_feed() {
+    let vuln_db = VulnDb::from_path(&fixture("nvd-feed.json")).unwrap();
+    assert_eq!(vuln_db.advisories.len(), 2);
+    // The linux_kernel CPE is not vulnerable itself, so it is not included.
+    assert_eq!(vuln_db.advisories[1].affected.len(), 1);
+
+    let findings = scan(&vuln_db, &nix_query_tree().path_map());
+    let summary: Vec<(&str, &str, &str, Option<&str>)> = findings
+        .iter()
+        .map(|finding| {
+            let advisory = &finding.advisories[0];
+            (
+                finding.pname.as_str(),
+                finding.version.as_str(),
+                advisory.id.as_str(),
+                advisory.severity.as_deref(),
+            )
+        })
+        .collect();
+
+    assert_eq!(
+        summary,
+        vec![("libidn2", "2.1.1a", "CVE-2019-18224", Some("CRITICAL"))]
+    );
+}
+
+#[test]
+fn test_vuln_db_errors() {
+    assert!(matches!(
+        VulnDb::from_path(&fixture("does-not-exist.json")),
+        Err(VulnDbErr::IoErr(_))
+    ));
+    assert!(matches!(
+        VulnDb::from_str("{ not json"),
+        Err(VulnDbErr::JsonErr(_))
+    ));
+    assert!(matches!(
+        VulnDb::from_str("{\"vulns\": 3}"),
+        Err(VulnDbErr::FormatErr(_))
+    ));
+}